@@ -0,0 +1,98 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use protobuf::{parse_from_bytes, Message};
+use sc2_proto::sc2api::{Request, Response};
+
+use sc2_proxy::bench_support::*;
+use sc2_proxy::delta;
+use sc2_proxy::refine::RefinerKind;
+
+/// Observation of 200 units, see `bench_support::observation_response`
+const OBSERVATION: &[u8] = include_bytes!("../tests/data/observation_response.pb");
+/// Action request of 20 unit commands, see `bench_support::action_request`
+const ACTION: &[u8] = include_bytes!("../tests/data/action_request.pb");
+
+fn observation_relay(c: &mut Criterion) {
+    let mut group = c.benchmark_group("observation");
+    group.bench_function("passthrough", |b| b.iter(|| black_box(OBSERVATION).to_vec()));
+    group.bench_function("parse_reserialize", |b| {
+        b.iter(|| {
+            let response = parse_from_bytes::<Response>(black_box(OBSERVATION)).unwrap();
+            response.write_to_bytes().unwrap()
+        })
+    });
+    group.bench_function("parse_classify_reserialize", |b| {
+        b.iter(|| {
+            let response = parse_from_bytes::<Response>(black_box(OBSERVATION)).unwrap();
+            black_box(ResponseEvent::from_response(&response));
+            response.write_to_bytes().unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn observation_delta(c: &mut Criterion) {
+    let next = observation_response(200, 1).write_to_bytes().unwrap();
+    let ops = delta::diff(OBSERVATION, &next);
+    let mut group = c.benchmark_group("observation_delta");
+    group.bench_function("diff", |b| b.iter(|| delta::diff(black_box(OBSERVATION), black_box(&next))));
+    group.bench_function("apply", |b| b.iter(|| delta::apply(black_box(OBSERVATION), black_box(&ops))));
+    group.finish();
+}
+
+fn request_limits(c: &mut Criterion) {
+    let limits = match_config(&[]).request_limits;
+    let action = parse_from_bytes::<Request>(ACTION).unwrap();
+    let step = step_request(8);
+    let mut group = c.benchmark_group("request_limits");
+    group.bench_function("action", |b| b.iter(|| limits.is_request_allowed(black_box(&action))));
+    group.bench_function("step", |b| b.iter(|| limits.is_request_allowed(black_box(&step))));
+    group.finish();
+}
+
+fn refiner_pipeline(c: &mut Criterion) {
+    let several = [
+        RefinerKind::ClampStep,
+        RefinerKind::TagChat,
+        RefinerKind::StripCheats,
+        RefinerKind::ForceStepMode,
+        RefinerKind::LimitDebugDraw,
+    ];
+    let requests = [
+        ("action", parse_from_bytes::<Request>(ACTION).unwrap()),
+        ("debug_draw", debug_draw_request(50)),
+    ];
+    let mut group = c.benchmark_group("refiners");
+    for (name, refiners) in &[("zero", &several[..0]), ("several", &several[..])] {
+        for (kind, req) in &requests {
+            let mut filter = RequestFilter::new(&match_config(refiners), refine_context());
+            group.bench_function(format!("{}/{}", name, kind), |b| {
+                b.iter(|| filter.decide(black_box(req.clone())))
+            });
+        }
+        let mut filter = RequestFilter::new(&match_config(refiners), refine_context());
+        group.bench_function(format!("{}/action_bytes", name), |b| {
+            b.iter(|| filter.decide_bytes(black_box(ACTION)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn remote_json(c: &mut Criterion) {
+    let mut group = c.benchmark_group("remote_json");
+    for response in remote_responses(32) {
+        let name = format!("{:?}", response);
+        let name = name.split('(').next().unwrap().to_owned();
+        group.bench_function(name, |b| b.iter(|| encode_remote_response(black_box(&response))));
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    observation_relay,
+    observation_delta,
+    request_limits,
+    refiner_pipeline,
+    remote_json
+);
+criterion_main!(benches);
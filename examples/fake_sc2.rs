@@ -0,0 +1,270 @@
+//! Fake SC2 process used by the integration tests.
+//! Accepts the same command line arguments as SC2, and answers the API
+//! requests needed to create, join, play and leave a game.
+//!
+//! Like the real SC2, keeps running until killed or quit is requested.
+//!
+//! Records its process id to `<dataDir>/pids/<pid>`, so that tests can
+//! check that processes are cleaned up.
+//!
+//! Environment variables:
+//! * `FAKE_SC2_GAME_LOOPS`: game length in loops, default 100
+//! * `FAKE_SC2_MARKER`: written to the pid file, to check the process environment
+//! * `FAKE_SC2_CREATE_GAME_DELAY_MS`: time it takes to create a game, default 0
+//! * `FAKE_SC2_CREATE_GAME_FAILURES`: number of create_game requests to fail first, default 0
+//! * `FAKE_SC2_CREATE_GAME_ERROR`: error code for the failures, default 3 (InvalidMapData)
+//! * `FAKE_SC2_CREATE_GAME_FAILURES_ONCE`: file created by the first process; if it exists,
+//!   `FAKE_SC2_CREATE_GAME_FAILURES` is ignored, so that only the first process fails
+//! * `FAKE_SC2_JOIN_GAME_FAILURES`: number of join_game requests to fail with LaunchError first, default 0
+//! * `FAKE_SC2_REQUEST_LOG`: file to append the type of each received request to, one per line
+//! * `FAKE_SC2_PLAYER_IDS`: player ids by player name, e.g. `alpha:2,beta:1`, default 1 for every player
+//! * `FAKE_SC2_INVALID_RESPONSE_ONCE`: file created by the first process to get an observation request;
+//!   that process answers it with bytes that are not a valid response
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use protobuf::{parse_from_bytes, Message, ProtobufEnum, RepeatedField};
+use sc2_proto::common::Race;
+use sc2_proto::sc2api::{
+    PlayerInfo, PlayerResult, PlayerType, Request, Response, ResponseCreateGame, ResponseCreateGame_Error,
+    ResponseGameInfo, ResponseJoinGame, ResponseJoinGame_Error, ResponseLeaveGame, ResponseObservation,
+    ResponsePing, ResponseQuit, ResponseStep, Result as GameResult, Status,
+};
+use websocket::sync::Server;
+use websocket::OwnedMessage;
+
+/// Value of a command line argument
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    let index = args.iter().position(|a| a == name)?;
+    args.get(index + 1).cloned()
+}
+
+/// Numeric value of an environment variable
+fn env_number<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Type of a request, for the request log
+fn request_name(req: &Request) -> &'static str {
+    if req.has_join_game() {
+        "join_game"
+    } else if req.has_step() {
+        "step"
+    } else if req.has_observation() {
+        "observation"
+    } else if req.has_leave_game() {
+        "leave_game"
+    } else if req.has_quit() {
+        "quit"
+    } else {
+        "other"
+    }
+}
+
+/// Checks if this is the first process using the `FAKE_SC2_CREATE_GAME_FAILURES_ONCE` file
+fn first_process() -> bool {
+    match env::var("FAKE_SC2_CREATE_GAME_FAILURES_ONCE") {
+        Ok(path) => claim_once(&path),
+        Err(_) => true,
+    }
+}
+
+/// Creates the file at `path`, returns false if it already exists
+fn claim_once(path: &str) -> bool {
+    fs::OpenOptions::new().write(true).create_new(true).open(path).is_ok()
+}
+
+/// Contents of saved replays
+const FAKE_REPLAY: &[u8] = b"fake replay";
+
+/// Fake game state
+struct State {
+    status: Status,
+    game_loop: u32,
+    game_loops: u32,
+    player_id: u32,
+    create_game_delay: Duration,
+    create_game_failures: u32,
+    create_game_error: ResponseCreateGame_Error,
+    join_game_failures: u32,
+}
+impl State {
+    fn respond(&mut self, req: &Request) -> Response {
+        let mut resp = Response::new();
+
+        if req.has_ping() {
+            resp.set_ping(ResponsePing::new());
+        } else if req.has_create_game() {
+            thread::sleep(self.create_game_delay);
+            let mut create = ResponseCreateGame::new();
+            if self.create_game_failures > 0 {
+                self.create_game_failures -= 1;
+                create.set_error(self.create_game_error);
+            } else {
+                self.status = Status::init_game;
+            }
+            resp.set_create_game(create);
+        } else if req.has_join_game() && self.join_game_failures > 0 {
+            self.join_game_failures -= 1;
+            let mut join = ResponseJoinGame::new();
+            join.set_error(ResponseJoinGame_Error::LaunchError);
+            resp.set_join_game(join);
+        } else if req.has_join_game() {
+            self.status = Status::in_game;
+            self.game_loop = 0;
+            self.player_id = player_id_of(req.get_join_game().get_player_name());
+            let mut join = ResponseJoinGame::new();
+            join.set_player_id(self.player_id);
+            resp.set_join_game(join);
+        } else if req.has_game_info() {
+            let mut info = ResponseGameInfo::new();
+            info.set_map_name("Fake map".to_owned());
+            info.mut_start_raw().mut_map_size().set_x(64);
+            info.mut_start_raw().mut_map_size().set_y(48);
+            let mut player = PlayerInfo::new();
+            player.set_player_id(self.player_id);
+            player.set_field_type(PlayerType::Participant);
+            player.set_race_requested(Race::Terran);
+            info.mut_player_info().push(player);
+            resp.set_game_info(info);
+        } else if req.has_step() {
+            self.game_loop += req.get_step().get_count().max(1);
+            let mut step = ResponseStep::new();
+            step.set_simulation_loop(self.game_loop);
+            resp.set_step(step);
+        } else if req.has_observation() {
+            let mut obs = ResponseObservation::new();
+            obs.mut_observation().set_game_loop(self.game_loop);
+            // Score grows with the game loop, so that tests can tell observations apart
+            let score = obs.mut_observation().mut_score();
+            score.set_score(self.game_loop as i32);
+            score.mut_score_details().set_collected_minerals(50.0 + self.game_loop as f32);
+            if self.game_loop >= self.game_loops {
+                self.status = Status::ended;
+                let results = (1..=2)
+                    .map(|player_id| {
+                        let mut r = PlayerResult::new();
+                        r.set_player_id(player_id);
+                        r.set_result(if player_id == 1 {
+                            GameResult::Victory
+                        } else {
+                            GameResult::Defeat
+                        });
+                        r
+                    })
+                    .collect();
+                obs.set_player_result(RepeatedField::from_vec(results));
+            }
+            resp.set_observation(obs);
+        } else if req.has_save_replay() {
+            resp.mut_save_replay().set_data(FAKE_REPLAY.to_vec());
+        } else if req.has_leave_game() {
+            self.status = Status::launched;
+            resp.set_leave_game(ResponseLeaveGame::new());
+        } else if req.has_quit() {
+            self.status = Status::quit;
+            resp.set_quit(ResponseQuit::new());
+        } else {
+            resp.set_error(RepeatedField::from_vec(vec!["Unsupported by fake SC2".to_owned()]));
+        }
+
+        resp.set_status(self.status);
+        resp
+    }
+}
+
+/// Player id of a player name from `FAKE_SC2_PLAYER_IDS`
+fn player_id_of(name: &str) -> u32 {
+    let ids = env::var("FAKE_SC2_PLAYER_IDS").unwrap_or_default();
+    ids.split(',')
+        .find_map(|pair| {
+            let mut parts = pair.splitn(2, ':');
+            if parts.next() == Some(name) {
+                parts.next()?.parse().ok()
+            } else {
+                None
+            }
+        })
+        .unwrap_or(1)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let host = arg_value(&args, "-listen").expect("Missing -listen");
+    let port = arg_value(&args, "-port").expect("Missing -port");
+
+    if let Some(data_dir) = arg_value(&args, "-dataDir") {
+        let pid_dir = Path::new(&data_dir).join("pids");
+        fs::create_dir_all(&pid_dir).expect("Could not create pid dir");
+        let marker = env::var("FAKE_SC2_MARKER").unwrap_or_default();
+        fs::write(pid_dir.join(process::id().to_string()), marker).expect("Could not write pid file");
+    }
+
+    let game_loops = env_number("FAKE_SC2_GAME_LOOPS", 100);
+    let mut request_log = env::var("FAKE_SC2_REQUEST_LOG").ok().map(|path| {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .expect("Could not open request log")
+    });
+
+    let mut server = Server::bind(format!("{}:{}", host, port)).expect("Could not bind");
+    let mut client = match server.accept() {
+        Ok(upgrade) => upgrade.accept().expect("Could not accept"),
+        Err(_) => process::exit(1),
+    };
+
+    let mut state = State {
+        status: Status::launched,
+        game_loop: 0,
+        game_loops,
+        player_id: 1,
+        create_game_delay: Duration::from_millis(env_number("FAKE_SC2_CREATE_GAME_DELAY_MS", 0)),
+        create_game_failures: if first_process() {
+            env_number("FAKE_SC2_CREATE_GAME_FAILURES", 0)
+        } else {
+            0
+        },
+        create_game_error: ResponseCreateGame_Error::from_i32(env_number("FAKE_SC2_CREATE_GAME_ERROR", 3))
+            .expect("Invalid create_game error code"),
+        join_game_failures: env_number("FAKE_SC2_JOIN_GAME_FAILURES", 0),
+    };
+    let mut invalid_response_once = env::var("FAKE_SC2_INVALID_RESPONSE_ONCE").ok();
+
+    while let Ok(msg) = client.recv_message() {
+        let req = match msg {
+            OwnedMessage::Binary(bytes) => parse_from_bytes::<Request>(&bytes).expect("Invalid request"),
+            OwnedMessage::Close(_) => break,
+            _ => continue,
+        };
+        if let Some(log) = &mut request_log {
+            writeln!(log, "{}", request_name(&req)).expect("Could not write request log");
+        }
+
+        let invalid = req.has_observation() && invalid_response_once.take().is_some_and(|p| claim_once(&p));
+        let bytes = if invalid {
+            vec![0xff; 3]
+        } else {
+            state.respond(&req).write_to_bytes().expect("Invalid response")
+        };
+        if client.send_message(&OwnedMessage::Binary(bytes)).is_err() {
+            break;
+        }
+
+        if req.has_quit() {
+            return;
+        }
+    }
+
+    // Like the real SC2, keep running after the connection closes
+    loop {
+        thread::sleep(Duration::from_secs(60));
+    }
+}
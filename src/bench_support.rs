@@ -0,0 +1,137 @@
+//! Constructors for exercising the relay without live sockets or SC2 processes
+//! Used by the benchmarks in `benches/`, and the fixtures in `tests/data/`
+
+use sc2_proto::common::Point;
+use sc2_proto::debug::DebugCommand;
+use sc2_proto::raw::{ActionRaw, Alliance, Unit, UnitOrder};
+use sc2_proto::sc2api::{Action, Request, Response};
+
+use crate::config::{Config, LimitAction, MatchConfig};
+use crate::refine::{RefineContext, RefinerKind};
+use crate::remote_control::message::{GameInfo, Response as RemoteResponse};
+use crate::results::GameStats;
+use crate::sc2::SessionStatus;
+use crate::supervisor::GameId;
+
+pub use crate::relay::{RequestDecision, RequestFilter, ResponseEvent};
+
+/// Observation response of a game loop, with `units` units spread over the map
+pub fn observation_response(units: usize, game_loop: u32) -> Response {
+    let mut response = Response::new();
+    let obs = response.mut_observation().mut_observation();
+    obs.set_game_loop(game_loop);
+    let raw = obs.mut_raw_data();
+    for i in 0..units {
+        let mut unit = Unit::new();
+        unit.set_tag(0x1_0000_0000 + i as u64);
+        unit.set_unit_type(48 + (i % 8) as u32);
+        unit.set_alliance(if i % 2 == 0 { Alliance::value_Self } else { Alliance::Enemy });
+        unit.set_owner(1 + (i % 2) as i32);
+        let mut pos = Point::new();
+        pos.set_x((i % 128) as f32 + 0.5);
+        pos.set_y((i / 128) as f32 + 0.5);
+        pos.set_z(10.0);
+        unit.set_pos(pos);
+        unit.set_facing(1.5);
+        unit.set_radius(0.375);
+        unit.set_build_progress(1.0);
+        unit.set_health(45.0);
+        unit.set_health_max(45.0);
+        if i % 4 == 0 {
+            let mut order = UnitOrder::new();
+            order.set_ability_id(23);
+            order.set_target_unit_tag(0x1_0000_0000 + ((i + 1) % units) as u64);
+            unit.mut_orders().push(order);
+        }
+        raw.mut_units().push(unit);
+    }
+    response
+}
+
+/// Action request with `commands` raw unit commands
+pub fn action_request(commands: usize) -> Request {
+    let mut req = Request::new();
+    let actions = req.mut_action().mut_actions();
+    for i in 0..commands {
+        let mut raw = ActionRaw::new();
+        let command = raw.mut_unit_command();
+        command.set_ability_id(23);
+        command.mut_unit_tags().push(0x1_0000_0000 + i as u64);
+        let target = command.mut_target_world_space_pos();
+        target.set_x((i % 128) as f32);
+        target.set_y((i / 128) as f32);
+        let mut action = Action::new();
+        action.set_action_raw(raw);
+        actions.push(action);
+    }
+    req
+}
+
+/// Debug request drawing `texts` texts
+pub fn debug_draw_request(texts: usize) -> Request {
+    let mut req = Request::new();
+    let mut command = DebugCommand::new();
+    for i in 0..texts {
+        let mut text = sc2_proto::debug::DebugText::new();
+        text.set_text(format!("Unit {}", i));
+        command.mut_draw().mut_text().push(text);
+    }
+    req.mut_debug().mut_debug().push(command);
+    req
+}
+
+/// Step request advancing `count` game loops
+pub fn step_request(count: u32) -> Request {
+    let mut req = Request::new();
+    req.mut_step().set_count(count);
+    req
+}
+
+/// Match settings with exactly the given refiners, and every request limit enabled
+/// Step requests over the limit are rejected, unless `ClampStep` is given
+pub fn match_config(refiners: &[RefinerKind]) -> MatchConfig {
+    let mut config = MatchConfig::default();
+    config.game.request_refiners = refiners.to_vec();
+    let limits = &mut config.request_limits;
+    limits.disable_cheats = true;
+    limits.disable_save_replay = true;
+    limits.max_step_count = Some(16);
+    if !refiners.contains(&RefinerKind::ClampStep) {
+        limits.step_limit_action = LimitAction::Reject;
+    }
+    if refiners.contains(&RefinerKind::LimitDebugDraw) {
+        limits.max_debug_draw_per_step = Some(100);
+    }
+    config
+}
+
+/// Participant in the first slot
+pub fn refine_context() -> RefineContext {
+    RefineContext {
+        slot: 0,
+        player_name: Some("benchbot".to_owned()),
+    }
+}
+
+/// Remote controller responses of a proxy running `games` games
+pub fn remote_responses(games: usize) -> Vec<RemoteResponse> {
+    let list = (0..games)
+        .map(|i| GameInfo {
+            id: GameId::from_raw(i as u64),
+            external_id: Some(format!("match-{}", i)),
+            running: i % 4 != 0,
+            player_statuses: vec![Some(SessionStatus::InGame), None],
+        })
+        .collect();
+    vec![
+        RemoteResponse::Ping(1),
+        RemoteResponse::GetGames(list),
+        RemoteResponse::GetStats(GameStats::default()),
+        RemoteResponse::GetConfig(Config::new()),
+    ]
+}
+
+/// Encode a remote controller response, as sent over the connection
+pub fn encode_remote_response(response: &RemoteResponse) -> Vec<u8> {
+    crate::remote_control::to_json_line(response)
+}
@@ -1,15 +1,29 @@
 #![allow(missing_docs)]
 
+mod replay_name;
 mod request_limits;
+mod socket_options;
 
+use log::info;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::maps::find_map;
+use sc2_proto::sc2api::InterfaceOptions;
+
+use crate::maps::find_map_in;
+use crate::refine::RefinerKind;
+use crate::remote_control::audit::AuditLog;
 
 pub use crate::sc2::{BuiltinAI, Difficulty, Race};
-pub use crate::sc2process::ProcessOptions;
+pub use crate::sc2process::{CpuAffinity, ProcessOptions, Renderer};
 
+pub use self::replay_name::*;
 pub use self::request_limits::*;
+pub use self::socket_options::*;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct Config {
@@ -19,9 +33,19 @@ pub struct Config {
     pub process: ProcessOptions,
     #[serde(default)]
     pub matchmaking: Matchmaking,
+    #[serde(default)]
     pub match_defaults: MatchConfig,
     #[serde(default)]
     pub remote_controller: RemoteController,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// Upstream proxy this one is federated with, see `remote_control::federation`
+    #[serde(default)]
+    pub upstream: Option<UpstreamConfig>,
+    /// Named matchmaking queues, selected by the websocket path bots connect to,
+    /// e.g. `ws://host:8642/ladder`. Other paths use the default queue configured above.
+    #[serde(default)]
+    pub queues: HashMap<String, QueueConfig>,
 }
 impl Config {
     /// New default config
@@ -29,12 +53,134 @@ impl Config {
         Self { ..Default::default() }
     }
 
+    /// Applies presets, overriding the individual settings they control
+    pub fn normalize(&mut self) {
+        self.match_defaults.apply_integrity();
+        for queue in self.queues.values_mut() {
+            queue.match_defaults.apply_integrity();
+        }
+    }
+
+    /// Queue selected by a websocket path, None for the default queue
+    /// Returns an error if the path is rejected by `proxy.reject_unknown_paths`
+    pub fn queue_for_path(&self, path: &str) -> Result<Option<String>, String> {
+        let name = path.trim_start_matches('/');
+        if self.queues.contains_key(name) {
+            Ok(Some(name.to_owned()))
+        } else if self.proxy.reject_unknown_paths && !name.is_empty() && name != "sc2api" {
+            Err(format!("Unknown queue path {:?}", path))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Matchmaking settings of a queue, the default ones if `queue` is None or not configured
+    pub fn queue_matchmaking(&self, queue: Option<&str>) -> &Matchmaking {
+        match queue.and_then(|name| self.queues.get(name)) {
+            Some(q) => &q.matchmaking,
+            None => &self.matchmaking,
+        }
+    }
+
+    /// Match settings of a queue, the default ones if `queue` is None or not configured
+    pub fn queue_match_defaults(&self, queue: Option<&str>) -> &MatchConfig {
+        match queue.and_then(|name| self.queues.get(name)) {
+            Some(q) => &q.match_defaults,
+            None => &self.match_defaults,
+        }
+    }
+
+    /// Config for the games of a queue, with its matchmaking and match settings
+    pub fn queue_config(&self, queue: Option<&str>) -> Config {
+        let mut config = self.clone();
+        config.matchmaking = self.queue_matchmaking(queue).clone();
+        config.match_defaults = self.queue_match_defaults(queue).clone();
+        config
+    }
+
+    /// Copy without the secrets only admins may see: the remote controller tokens and the upstream token
+    pub fn redacted(mut self) -> Self {
+        self.remote_controller.tokens.clear();
+        if let Some(upstream) = &mut self.upstream {
+            upstream.token = None;
+        }
+        self
+    }
+
+    /// Checks if the default queue and every named queue have the same matchmaking modes here and in `other`
+    pub fn same_matchmaking_modes(&self, other: &Config) -> bool {
+        self.matchmaking.mode == other.matchmaking.mode
+            && self.queues.len() == other.queues.len()
+            && self.queues.iter().all(|(name, queue)| {
+                other.queues.get(name).map(|q| q.matchmaking.mode) == Some(queue.matchmaking.mode)
+            })
+    }
+
+    /// Checks if any queue uses the remote controller for matchmaking
+    pub fn requires_remote_controller(&self) -> bool {
+        self.matchmaking.mode == MatchmakingMode::RemoteController
+            || self
+                .queues
+                .values()
+                .any(|q| q.matchmaking.mode == MatchmakingMode::RemoteController)
+    }
+
+    /// Checks the constraints between settings that would make the proxy unusable,
+    /// before the server is started. Unlike `check`, this does not require a map,
+    /// as one can still be set by the remote controller.
+    pub fn check_startup(&self) -> Result<(), String> {
+        if !self.remote_controller.enabled && self.requires_remote_controller() {
+            return Err("Remote controller disabled, but required for matchmaking".to_owned());
+        }
+
+        self.matchmaking.check_computers()?;
+        for (name, queue) in &self.queues {
+            queue.matchmaking.check_computers().map_err(|e| format!("Queue {}: {}", name, e))?;
+        }
+
+        // Check that results can be recorded
+        if let Some(path) = &self.matchmaking.standings_path {
+            let dir = Path::new(path).parent().filter(|d| !d.as_os_str().is_empty());
+            check_writable_dir(dir.unwrap_or_else(|| Path::new(".")))?;
+        }
+        let record = &self.match_defaults.record_results;
+        for dir in record.game_info_dir.iter().chain(&record.results_dir) {
+            check_writable_dir(Path::new(dir))?;
+        }
+
+        Ok(())
+    }
+
     /// Checks if the config is valid for use, and returns possible error
     /// Checked before creating a lobby, as in that point it cannot anymore
     /// be changed by the remote controller
     pub fn check(&self) -> Result<(), String> {
+        self.matchmaking.check_computers()?;
+
+        // Check that bots can use at least one interface to play
+        if !self.match_defaults.game.allowed_interfaces.is_playable() {
+            return Err("Allowed interfaces must include raw or feature_layer".to_owned());
+        }
+
+        if let Some(sim) = &self.match_defaults.game.network_sim {
+            if !(0.0..=1.0).contains(&sim.drop_probability) {
+                return Err("Network simulation drop probability must be between 0 and 1".to_owned());
+            }
+        }
+
+        if self.match_defaults.time_limits.announce_checkpoints.iter().any(|&p| p == 0 || p >= 100) {
+            return Err("Time limit announcement checkpoints must be between 1 and 99 percent".to_owned());
+        }
+
+        if let Some(stall) = &self.match_defaults.time_limits.stall_detection {
+            if stall.stall_window_loops == 0 {
+                return Err("Stall detection window must be at least one game loop".to_owned());
+            }
+        }
+
         // Check that map is defined and exists
-        find_map(
+        find_map_in(
+            self.process.map_dir.as_deref(),
             self.match_defaults
                 .game
                 .map_name
@@ -51,12 +197,28 @@ impl Config {
 pub struct Proxy {
     pub host: String,
     pub port: u16,
+    /// Close connections to paths that are not a queue name, "/" or "/sc2api",
+    /// instead of using the default queue for them
+    #[serde(default)]
+    pub reject_unknown_paths: bool,
+    /// Length of the queue of connections not yet accepted, the OS default (128 on Linux) if not set
+    /// Raise it if bots connecting in a burst, e.g. at the start of a tournament round, are refused.
+    /// Linux caps it at `net.core.somaxconn`.
+    #[serde(default)]
+    pub listen_backlog: Option<u32>,
+    /// TCP options of the bot and SC2 connections
+    /// Tables must come after plain values for TOML serialization
+    #[serde(default)]
+    pub socket: SocketOptions,
 }
 impl Default for Proxy {
     fn default() -> Self {
         Self {
             host: "127.0.0.1".to_owned(),
             port: 8642,
+            reject_unknown_paths: false,
+            listen_backlog: None,
+            socket: SocketOptions::default(),
         }
     }
 }
@@ -71,6 +233,24 @@ pub struct RemoteController {
     pub enabled: bool,
     pub host: String,
     pub port: u16,
+    /// While the listener is disabled, creating this file starts it again.
+    /// The file is removed when the listener is started.
+    #[serde(default)]
+    pub enable_flag_path: Option<String>,
+    /// Append-only log of every request, see `remote_control::audit`
+    #[serde(default)]
+    pub audit_log: Option<String>,
+    /// The audit log is rotated to `<audit_log>.1` when it would grow over this size
+    #[serde(default = "RemoteController::default_audit_log_max_bytes")]
+    pub audit_log_max_bytes: u64,
+    /// Size of the replay chunks sent for FetchReplay, before base64 encoding
+    #[serde(default = "RemoteController::default_replay_chunk_bytes")]
+    pub replay_chunk_bytes: usize,
+    /// Access tokens and their roles, used with the Authenticate request.
+    /// If empty, every controller connection has full access.
+    /// Tables must come after plain values for TOML serialization
+    #[serde(default)]
+    pub tokens: HashMap<String, RemoteRole>,
 }
 impl Default for RemoteController {
     fn default() -> Self {
@@ -78,25 +258,198 @@ impl Default for RemoteController {
             enabled: true,
             host: "127.0.0.1".to_owned(),
             port: 2468,
+            enable_flag_path: None,
+            audit_log: None,
+            audit_log_max_bytes: Self::default_audit_log_max_bytes(),
+            replay_chunk_bytes: Self::default_replay_chunk_bytes(),
+            tokens: HashMap::new(),
         }
     }
 }
 
+/// Connection of a downstream proxy to its upstream proxy, see `remote_control::federation`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UpstreamConfig {
+    /// Address of the remote controller endpoint of the upstream proxy, e.g. `10.0.0.1:2468`
+    pub addr: String,
+    /// Name of this proxy on the upstream, prefixed to the ids of its clients there
+    /// Must be unique among the proxies of the upstream, and cannot contain `/`
+    pub name: String,
+    /// Admin access token of the upstream proxy, if it has tokens configured
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
 impl RemoteController {
     pub fn addr(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
+
+    /// Audit log writer, if configured
+    pub fn audit_log(&self) -> Option<AuditLog> {
+        let path = self.audit_log.as_ref()?;
+        Some(AuditLog::new(path, self.audit_log_max_bytes))
+    }
+
+    fn default_audit_log_max_bytes() -> u64 {
+        10 * 1024 * 1024
+    }
+
+    fn default_replay_chunk_bytes() -> usize {
+        64 * 1024
+    }
+}
+
+/// Access level of a remote controller connection
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteRole {
+    /// Full access
+    Admin,
+    /// Read-only access, e.g. for tournament dashboards
+    Spectator,
 }
 
+/// Matchmaking queue with its own settings, see `Config::queues`
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct QueueConfig {
+    #[serde(default)]
+    pub matchmaking: Matchmaking,
+    #[serde(default)]
+    pub match_defaults: MatchConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Matchmaking {
     pub mode: MatchmakingMode,
+    /// Number of participants in a game, used with Pairs mode
+    #[serde(default = "Matchmaking::default_players_per_game")]
+    pub players_per_game: usize,
     /// Builtin AI difficulty, used with some modes
     #[serde(default)]
     pub cpu_difficulty: Difficulty,
     /// Builtin AI race, used with some modes
     #[serde(default)]
     pub cpu_race: Race,
+    /// Drop older connections of a bot when it joins again with the same identifier
+    #[serde(default)]
+    pub deduplicate_clients: bool,
+    /// Never pair two connections with the same bot identifier against each other
+    #[serde(default)]
+    pub forbid_self_match: bool,
+    /// Allow clients to host their own single-participant games using create_game
+    #[serde(default)]
+    pub allow_client_hosting: bool,
+    /// Allow clients to analyze replays using replay_info and start_replay
+    #[serde(default)]
+    pub allow_replay_clients: bool,
+    /// Allow computer players, i.e. `filler_ai` or `cpu_process`, with Singleplayer matchmaking
+    #[serde(default)]
+    pub allow_singleplayer_computers: bool,
+    /// Lobbies that haven't started in this time are closed
+    #[serde(default)]
+    pub max_lobby_age_secs: Option<u64>,
+    /// File where the win/loss records of bots are kept across restarts
+    #[serde(default)]
+    pub standings_path: Option<String>,
+    /// Race for clients whose join request has no race set.
+    /// If not set, such requests are rejected with an error.
+    #[serde(default)]
+    pub default_race: Option<Race>,
+    /// Place computer players in random slots instead of after the participants,
+    /// picked from `random_seed` if it is set
+    #[serde(default = "Matchmaking::default_randomize_slots")]
+    pub randomize_slots: bool,
+    /// Builtin AI opponent for bots without a partner, used in Pairs mode
+    #[serde(default)]
+    pub filler_ai: FillerAI,
+    /// Options of a separate SC2 process for the builtin AI, e.g. with a modded `executable`.
+    /// If set, games with computer players are created and hosted by a dedicated SC2 process
+    /// launched with these options, which runs the computer players, see `HostSelection::Dedicated`.
+    #[serde(default)]
+    pub cpu_process: Option<ProcessOptions>,
+}
+impl Matchmaking {
+    fn default_players_per_game() -> usize {
+        2
+    }
+
+    fn default_randomize_slots() -> bool {
+        true
+    }
+
+    /// Checks that computer players are only configured for modes that allow them
+    fn check_computers(&self) -> Result<(), String> {
+        let computers = self.filler_ai.enabled || self.cpu_process.is_some();
+        if self.mode == MatchmakingMode::Singleplayer && computers && !self.allow_singleplayer_computers {
+            return Err("Singleplayer matchmaking has computer players, \
+                        but allow_singleplayer_computers is not set"
+                .to_owned());
+        }
+        Ok(())
+    }
+}
+impl Default for Matchmaking {
+    fn default() -> Self {
+        Self {
+            mode: MatchmakingMode::default(),
+            players_per_game: Self::default_players_per_game(),
+            cpu_difficulty: Difficulty::default(),
+            cpu_race: Race::default(),
+            deduplicate_clients: false,
+            forbid_self_match: false,
+            allow_client_hosting: false,
+            allow_replay_clients: false,
+            allow_singleplayer_computers: false,
+            max_lobby_age_secs: None,
+            standings_path: None,
+            default_race: None,
+            randomize_slots: Self::default_randomize_slots(),
+            filler_ai: FillerAI::default(),
+            cpu_process: None,
+        }
+    }
+}
+
+/// Log output of the proxy itself, see `logging::init_logging`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Log to this file in addition to stderr, which is then only used when it's a terminal
+    pub file: Option<String>,
+    /// The file is rotated when it would grow over this size
+    pub max_size_mb: u64,
+    /// Number of rotated files kept, as `<file>.1` (newest) to `<file>.<keep_files>`
+    pub keep_files: usize,
+}
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            max_size_mb: 100,
+            keep_files: 5,
+        }
+    }
+}
+
+/// Builtin AI started against a bot that has waited too long for a partner
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct FillerAI {
+    pub enabled: bool,
+    /// Time to wait for a partner before starting against the filler
+    pub wait_secs: u64,
+    pub race: Race,
+    pub difficulty: Difficulty,
+}
+impl Default for FillerAI {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            wait_secs: 60,
+            race: Race::default(),
+            difficulty: Difficulty::default(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
@@ -118,6 +471,10 @@ impl Default for MatchmakingMode {
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct MatchConfig {
+    /// Fair play preset, see `apply_integrity`
+    /// Tables must come after plain values for TOML serialization
+    #[serde(default)]
+    pub integrity: bool,
     #[serde(default)]
     pub game: GameConfig,
     #[serde(default)]
@@ -127,6 +484,37 @@ pub struct MatchConfig {
     #[serde(default)]
     pub record_results: RecordConfig,
 }
+impl MatchConfig {
+    /// If `integrity` is set, enforce settings for fair games: cheats are disabled,
+    /// fog of war is enabled, the score interface (with the opponent's score) is not
+    /// allowed, bots cannot save replays, and the network is not simulated.
+    /// Logs every setting that was changed.
+    pub fn apply_integrity(&mut self) {
+        if !self.integrity {
+            return;
+        }
+        if !self.request_limits.disable_cheats {
+            info!("Integrity preset: disabling cheats");
+            self.request_limits.disable_cheats = true;
+        }
+        if self.game.disable_fog {
+            info!("Integrity preset: enabling fog of war");
+            self.game.disable_fog = false;
+        }
+        if self.game.allowed_interfaces.score {
+            info!("Integrity preset: disallowing the score interface");
+            self.game.allowed_interfaces.score = false;
+        }
+        if !self.request_limits.disable_save_replay {
+            info!("Integrity preset: disallowing save_replay requests from bots");
+            self.request_limits.disable_save_replay = true;
+        }
+        if self.game.network_sim.is_some() {
+            info!("Integrity preset: disabling network simulation");
+            self.game.network_sim = None;
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct GameConfig {
@@ -138,9 +526,108 @@ pub struct GameConfig {
     pub random_seed: Option<u32>,
     #[serde(default)]
     pub realtime: bool,
+    /// Never run games in realtime, even if `realtime` is set or a client hosted game requests it
+    #[serde(default)]
+    pub force_step_mode: bool,
+    /// Run the SC2 processes of the match fullscreen or windowed, overriding `process.fullscreen`
+    #[serde(default)]
+    pub fullscreen: Option<bool>,
+    /// How to resolve participants requesting a random race
+    #[serde(default)]
+    pub random_race: RandomRace,
+    /// Force races of participants by lobby slot, regardless of the requested race.
+    /// Must have exactly one entry per participant. Entries with `None` keep the
+    /// requested race, but TOML cannot express them, so they are only available
+    /// through the remote controller.
+    #[serde(default)]
+    pub overwrite_races: Option<Vec<Option<Race>>>,
+    /// Minimum number of participants required to start a game
+    #[serde(default = "GameConfig::default_min_participants")]
+    pub min_participants: usize,
+    /// Number of player slots on the map, including computers.
+    /// Map files are not inspected, so the capacity is not checked unless set here.
+    #[serde(default)]
+    pub map_capacity: Option<usize>,
+    /// Whose SC2 process creates and hosts the game
+    #[serde(default)]
+    pub host_selection: HostSelection,
+    /// Extra attempts for creating and joining a game after a transient SC2 error
+    #[serde(default = "GameConfig::default_start_retries")]
+    pub start_retries: u32,
+    /// Extra attempts for starting a lobby whose game could not be created or joined,
+    /// relaunching the SC2 processes of all participants before each attempt
+    #[serde(default)]
+    pub lobby_start_retries: u32,
+    /// Abort the start if a participant disconnects before its join response is sent,
+    /// instead of starting without it. The start is retried if `lobby_start_retries` allows.
+    #[serde(default)]
+    pub abort_start_on_join_disconnect: bool,
+    /// Wait this long after sending the join responses before relaying any other requests,
+    /// e.g. for bots that analyse the map before their first step. Not counted in `time_limits`
+    /// or latency measurements. In realtime games, SC2 keeps running during the wait.
+    #[serde(default)]
+    pub post_join_delay_ms: Option<u64>,
+    /// Answer repeated observation requests on the same game loop without asking SC2.
+    /// Always disabled in realtime games, where the game advances between requests.
+    #[serde(default)]
+    pub cache_observations: bool,
+    /// Answer observation requests arriving faster than this with the previous observation.
+    /// In step mode, only repeated observations of the same game loop are answered so.
+    #[serde(default)]
+    pub max_observations_per_sec: Option<u32>,
+    /// Send observations as delta frames to clients that ask for them with the `delta` query parameter,
+    /// see `delta`
+    #[serde(default = "GameConfig::default_allow_observation_delta")]
+    pub allow_observation_delta: bool,
+    /// How to score games where every participant disconnected before the game was over
+    #[serde(default)]
+    pub simultaneous_disconnect: DisconnectScoring,
+    /// Measure the latency the proxy adds to relayed requests, included in the player stats
+    #[serde(default)]
+    pub measure_latency: bool,
+    /// Count the unit commands of each player by game minute, included in the player stats as APM
+    #[serde(default)]
+    pub record_apm: bool,
+    /// Log errors in SC2 responses, with the game, player and request they belong to
+    #[serde(default = "GameConfig::default_log_sc2_errors")]
+    pub log_sc2_errors: bool,
+    /// End the game for a player after this many consecutive SC2 error responses, as a defeat.
+    /// Errors are always forwarded to the client and counted in the player stats.
+    #[serde(default)]
+    pub max_consecutive_sc2_errors: Option<u32>,
+    /// Refiners applied to client requests, see `refine::Pipeline`
+    #[serde(default)]
+    pub request_refiners: Vec<RefinerKind>,
     /// These interfaces are allowed for the client
+    /// Tables must come after plain values for TOML serialization
     #[serde(default)]
     pub allowed_interfaces: AllowedInterfaces,
+    /// Simulate a laggy connection to the clients, for testing bots. Never use for real matches,
+    /// the results are marked with it, see `GameResult::network_sim`.
+    #[serde(default)]
+    pub network_sim: Option<NetworkSim>,
+}
+impl GameConfig {
+    /// Checks if games run in realtime, taking `force_step_mode` into account
+    pub fn is_realtime(&self) -> bool {
+        self.realtime && !self.force_step_mode
+    }
+
+    fn default_min_participants() -> usize {
+        1
+    }
+
+    fn default_start_retries() -> u32 {
+        2
+    }
+
+    fn default_log_sc2_errors() -> bool {
+        true
+    }
+
+    fn default_allow_observation_delta() -> bool {
+        true
+    }
 }
 impl Default for GameConfig {
     fn default() -> Self {
@@ -149,15 +636,253 @@ impl Default for GameConfig {
             disable_fog: false,
             random_seed: None,
             realtime: false,
+            force_step_mode: false,
+            fullscreen: None,
+            random_race: RandomRace::default(),
+            overwrite_races: None,
+            min_participants: Self::default_min_participants(),
+            map_capacity: None,
+            host_selection: HostSelection::default(),
+            start_retries: Self::default_start_retries(),
+            lobby_start_retries: 0,
+            abort_start_on_join_disconnect: false,
+            post_join_delay_ms: None,
+            cache_observations: false,
+            max_observations_per_sec: None,
+            allow_observation_delta: Self::default_allow_observation_delta(),
+            simultaneous_disconnect: DisconnectScoring::default(),
+            measure_latency: false,
+            record_apm: false,
+            log_sc2_errors: Self::default_log_sc2_errors(),
+            max_consecutive_sc2_errors: None,
+            request_refiners: Vec::new(),
             allowed_interfaces: AllowedInterfaces::default(),
+            network_sim: None,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+/// Simulated network conditions between the proxy and a client, see `refine::NetworkSimRequests`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NetworkSim {
+    /// Delay added to each relayed request, split between the request and its response
+    #[serde(default)]
+    pub added_latency_ms: u64,
+    /// Random extra delay of up to this much, split like `added_latency_ms`
+    #[serde(default)]
+    pub jitter_ms: u64,
+    /// Probability, from 0 to 1, of replacing the response to a non-essential request with an error,
+    /// see `refine::is_droppable`
+    #[serde(default)]
+    pub drop_probability: f64,
+}
+
+/// Resolution of `Race::Random` requests at join time
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RandomRace {
+    /// Let SC2 pick the race
+    Keep,
+    /// Always use Protoss
+    Protoss,
+    /// Always use Terran
+    Terran,
+    /// Always use Zerg
+    Zerg,
+    /// Pick deterministically from `random_seed` and the lobby slot
+    Seeded,
+}
+impl RandomRace {
+    /// Resolve a requested race for the participant in `slot`
+    pub fn resolve(self, requested: Race, seed: u32, slot: usize) -> Race {
+        if requested != Race::Random {
+            return requested;
+        }
+
+        match self {
+            RandomRace::Keep => Race::Random,
+            RandomRace::Protoss => Race::Protoss,
+            RandomRace::Terran => Race::Terran,
+            RandomRace::Zerg => Race::Zerg,
+            RandomRace::Seeded => {
+                let z = mix(u64::from(seed) << 32 | slot as u64);
+                [Race::Protoss, Race::Terran, Race::Zerg][(z % 3) as usize]
+            },
+        }
+    }
+}
+impl Default for RandomRace {
+    fn default() -> Self {
+        RandomRace::Keep
+    }
+}
+
+/// Scoring of games where every participant disconnected
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectScoring {
+    /// Every participant is defeated
+    Defeat,
+    /// The game ends as a no contest, and every participant gets a tie
+    NoContest,
+}
+impl Default for DisconnectScoring {
+    fn default() -> Self {
+        DisconnectScoring::Defeat
+    }
+}
+
+/// Checks that files can be created in a directory, by creating and removing one
+fn check_writable_dir(dir: &Path) -> Result<(), String> {
+    let probe = dir.join(format!(".sc2-proxy-write-check-{}", process::id()));
+    fs::write(&probe, b"").map_err(|e| format!("Cannot write to {:?}: {}", dir, e))?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
+/// SplitMix64 finalizer, so that consecutive inputs give uncorrelated outputs
+pub(crate) fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// The seed if given, otherwise a seed from the current time
+pub(crate) fn seed_or_now(seed: Option<u32>) -> u64 {
+    seed.map(u64::from).unwrap_or_else(|| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        now.as_nanos() as u64
+    })
+}
+
+/// Shuffle items, deterministically if `seed` is given
+pub(crate) fn shuffle<T>(items: &mut [T], seed: Option<u32>) {
+    let seed = seed_or_now(seed);
+    for i in (1..items.len()).rev() {
+        let j = (mix(seed.wrapping_add(i as u64)) % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// Selection of the SC2 process that creates and hosts a game
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HostSelection {
+    /// The first participant to join
+    First,
+    /// A random participant, picked from `random_seed` if it is set
+    Random,
+    /// An extra SC2 process launched by the proxy, joined as an observer
+    Dedicated,
+}
+impl HostSelection {
+    /// Slot of the participant hosting a game with `participants` participants,
+    /// or None if the game has a dedicated host
+    pub fn host_slot(self, participants: usize, seed: Option<u32>) -> Option<usize> {
+        match self {
+            HostSelection::First => Some(0),
+            HostSelection::Random => Some((mix(seed_or_now(seed)) % participants.max(1) as u64) as usize),
+            HostSelection::Dedicated => None,
+        }
+    }
+}
+impl Default for HostSelection {
+    fn default() -> Self {
+        HostSelection::First
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct TimeLimits {
+    /// End games as a tie for the participants still playing on this game loop
     #[serde(default)]
     pub game_loops: Option<u64>,
+    /// Abort starting a game, i.e. creating and joining it, if it takes longer than this
+    #[serde(default)]
+    pub game_start_timeout_secs: Option<u64>,
+    /// Tell the bots in chat how many game loops remain, when `game_loops` is set
+    /// Observations have no field for it, so the message is added to their received chat.
+    #[serde(default)]
+    pub announce_remaining: bool,
+    /// Percentages of `game_loops` after which `announce_remaining` announces, each once
+    #[serde(default = "TimeLimits::default_announce_checkpoints")]
+    pub announce_checkpoints: Vec<u8>,
+    /// End games where the participants stop making progress, off if not set
+    #[serde(default)]
+    pub stall_detection: Option<StallDetection>,
+}
+
+impl TimeLimits {
+    fn default_announce_checkpoints() -> Vec<u8> {
+        vec![75, 90, 95]
+    }
+}
+impl Default for TimeLimits {
+    fn default() -> Self {
+        Self {
+            game_loops: None,
+            game_start_timeout_secs: None,
+            announce_remaining: false,
+            announce_checkpoints: Self::default_announce_checkpoints(),
+            stall_detection: None,
+        }
+    }
+}
+
+/// Detection of stalemates from the scores of the participants, see `game::StallDetector`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StallDetection {
+    /// End the game when no participant has made progress for this many game loops
+    #[serde(default = "StallDetection::default_stall_window_loops")]
+    pub stall_window_loops: u32,
+    /// Changes of the army value up to this, in resources, are not progress
+    #[serde(default = "StallDetection::default_army_value_threshold")]
+    pub army_value_threshold: f32,
+    /// Changes of the value of structures up to this, in resources, are not progress
+    #[serde(default = "StallDetection::default_structures_value_threshold")]
+    pub structures_value_threshold: f32,
+    /// Results of the participants still playing when a stalemate is detected
+    #[serde(default)]
+    pub outcome: StalemateOutcome,
+}
+impl StallDetection {
+    fn default_stall_window_loops() -> u32 {
+        // Five minutes of game time
+        6720
+    }
+
+    fn default_army_value_threshold() -> f32 {
+        100.0
+    }
+
+    fn default_structures_value_threshold() -> f32 {
+        100.0
+    }
+}
+impl Default for StallDetection {
+    fn default() -> Self {
+        Self {
+            stall_window_loops: Self::default_stall_window_loops(),
+            army_value_threshold: Self::default_army_value_threshold(),
+            structures_value_threshold: Self::default_structures_value_threshold(),
+            outcome: StalemateOutcome::default(),
+        }
+    }
+}
+
+/// Results of a game ended as a stalemate
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StalemateOutcome {
+    /// Every participant still playing gets a tie
+    Tie,
+    /// The participant with the highest score wins and the others are defeated,
+    /// a tie if the highest score is shared
+    ScoreTiebreak,
+}
+impl Default for StalemateOutcome {
+    fn default() -> Self {
+        StalemateOutcome::Tie
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
@@ -168,6 +893,78 @@ pub struct RecordConfig {
     end_score: bool,
     #[serde(default)]
     score_history: bool,
+    /// Gzip saved replays on write, adding a `.gz` extension
+    #[serde(default)]
+    pub compress: bool,
+    /// File name of replays saved with SaveReplay, whose path is then a directory
+    #[serde(default)]
+    pub replay_name_pattern: Option<ReplayNamePattern>,
+    /// Fetch the SC2 game info when a game starts, and send it to the remote controller
+    #[serde(default)]
+    pub game_info: bool,
+    /// Also write the raw game info protobuf to `<game_info_dir>/<game id>.SC2GameInfo`
+    #[serde(default)]
+    pub game_info_dir: Option<String>,
+    /// Write the result of each game to `<results_dir>/<game id>.json`
+    #[serde(default)]
+    pub results_dir: Option<String>,
+    /// Schema of the result files written to `results_dir`
+    #[serde(default)]
+    pub result_format: ResultFormat,
+    /// Rules for voiding finished games, which are then left out of the standings
+    #[serde(default)]
+    pub validity: ValidityRules,
+}
+impl RecordConfig {
+    /// Path a recording requested to be saved to `path` is written to
+    pub fn output_path(&self, path: &str) -> String {
+        if self.compress && !path.ends_with(".gz") {
+            format!("{}.gz", path)
+        } else {
+            path.to_owned()
+        }
+    }
+}
+
+/// Schema of result files, see `results::write_result`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultFormat {
+    /// `GameResult` as is
+    Native,
+    /// Results of the AI Arena ladder, see `results::AiArenaResult`
+    Aiarena,
+}
+impl Default for ResultFormat {
+    fn default() -> Self {
+        ResultFormat::Native
+    }
+}
+
+/// Rules for voiding a finished game, see `results::void_reason`
+/// Defaults void games that were not really played, i.e. crashed SC2 processes and
+/// clients that disconnected before their first observation
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ValidityRules {
+    /// Void games where an SC2 process closed its connection unexpectedly
+    pub sc2_crash: bool,
+    /// Void games where a client disconnected before its first observation
+    pub disconnect_before_start: bool,
+    /// Void games where a client disconnected before this game loop
+    pub disconnect_before_loop: u32,
+    /// Void games that ended before this many game seconds, 0 to allow any length
+    pub min_duration_secs: u32,
+}
+impl Default for ValidityRules {
+    fn default() -> Self {
+        Self {
+            sc2_crash: true,
+            disconnect_before_start: true,
+            disconnect_before_loop: 0,
+            min_duration_secs: 0,
+        }
+    }
 }
 
 /// All implmented interfaces allowed by default,
@@ -175,10 +972,38 @@ pub struct RecordConfig {
 /// relevant limitation fields.
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub struct AllowedInterfaces {
-    raw: bool,
-    score: bool,
-    feature_layer: bool,
-    render: bool, // NOTE: Unimplemented in the SC2 api
+    /// Raw unit data
+    pub raw: bool,
+    /// Score data, including the opponent's score
+    pub score: bool,
+    /// Feature layer images
+    pub feature_layer: bool,
+    /// Rendered images
+    pub render: bool, // NOTE: Unimplemented in the SC2 api
+}
+impl AllowedInterfaces {
+    /// Checks if at least one interface bots can play with is allowed
+    pub fn is_playable(&self) -> bool {
+        self.raw || self.feature_layer
+    }
+
+    /// Names of the interfaces requested in the options, but not allowed here
+    pub fn disallowed(&self, ifopts: &InterfaceOptions) -> Vec<&'static str> {
+        let mut result = Vec::new();
+        if ifopts.get_raw() && !self.raw {
+            result.push("raw");
+        }
+        if ifopts.get_score() && !self.score {
+            result.push("score");
+        }
+        if ifopts.has_feature_layer() && !self.feature_layer {
+            result.push("feature_layer");
+        }
+        if ifopts.has_render() && !self.render {
+            result.push("render");
+        }
+        result
+    }
 }
 impl Default for AllowedInterfaces {
     fn default() -> Self {
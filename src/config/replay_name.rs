@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::SystemTime;
+
+use crate::supervisor::GameId;
+
+/// Placeholders of a replay name pattern, without the braces
+pub const REPLAY_NAME_PLACEHOLDERS: &[&str] =
+    &["game_id", "timestamp", "map", "p1", "p2", "winner", "external_id"];
+
+/// Replaces placeholders that have no value, e.g. `{winner}` while the game is running
+const MISSING_VALUE: &str = "none";
+
+/// Reason why a replay name pattern is invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayNameError {
+    /// A placeholder is not in `REPLAY_NAME_PLACEHOLDERS`
+    UnknownPlaceholder(String),
+    /// A `{` is not closed
+    Unclosed,
+    /// A `}` was not opened
+    Unopened,
+}
+impl fmt::Display for ReplayNameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownPlaceholder(name) => write!(
+                f,
+                "Unknown placeholder {{{}}} in replay name pattern, expected one of {}",
+                name,
+                REPLAY_NAME_PLACEHOLDERS.join(", ")
+            ),
+            Self::Unclosed => write!(f, "Unclosed {{ in replay name pattern"),
+            Self::Unopened => write!(f, "Unopened }} in replay name pattern"),
+        }
+    }
+}
+
+/// Part of a replay name pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment<'a> {
+    Literal(&'a str),
+    Placeholder(&'a str),
+}
+
+/// Split a pattern into literal text and placeholders, checking that the placeholders are known
+fn parse(pattern: &str) -> Result<Vec<Segment>, ReplayNameError> {
+    let mut segments = Vec::new();
+    let mut rest = pattern;
+    while !rest.is_empty() {
+        let open = rest.find('{');
+        let close = rest.find('}');
+        match (open, close) {
+            (None, None) => {
+                segments.push(Segment::Literal(rest));
+                rest = "";
+            },
+            (None, Some(_)) => return Err(ReplayNameError::Unopened),
+            (Some(_), None) => return Err(ReplayNameError::Unclosed),
+            (Some(o), Some(c)) if c < o => return Err(ReplayNameError::Unopened),
+            (Some(o), Some(c)) => {
+                let name = &rest[o + 1..c];
+                if name.contains('{') {
+                    return Err(ReplayNameError::Unclosed);
+                }
+                if !REPLAY_NAME_PLACEHOLDERS.contains(&name) {
+                    return Err(ReplayNameError::UnknownPlaceholder(name.to_owned()));
+                }
+                if o > 0 {
+                    segments.push(Segment::Literal(&rest[..o]));
+                }
+                segments.push(Segment::Placeholder(name));
+                rest = &rest[c + 1..];
+            },
+        }
+    }
+    Ok(segments)
+}
+
+/// Make a value safe to use in a file name
+/// Letters, digits, `-` and `_` are kept, and everything else, including path separators
+/// and dots, is replaced with `_`. Empty values are replaced with `none`.
+pub fn sanitize_name_value(value: &str) -> String {
+    if value.is_empty() {
+        return MISSING_VALUE.to_owned();
+    }
+    value
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// Timestamp in a compact form without separators, e.g. `20190307T154512Z`
+fn compact_timestamp(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time)
+        .to_string()
+        .replace(|c: char| c == '-' || c == ':', "")
+}
+
+/// Values of the placeholders of a replay name
+#[derive(Debug, Clone)]
+pub struct ReplayNameFields<'a> {
+    /// Value of `{game_id}`
+    pub game_id: GameId,
+    /// Value of `{timestamp}`, in UTC
+    pub timestamp: SystemTime,
+    /// Value of `{map}`, the map name from the config
+    pub map: Option<&'a str>,
+    /// Names of the participants in join order, for `{p1}` and `{p2}`
+    pub players: &'a [Option<String>],
+    /// Value of `{winner}`, the name of the winning participant
+    pub winner: Option<&'a str>,
+    /// Value of `{external_id}`
+    pub external_id: Option<&'a str>,
+}
+impl ReplayNameFields<'_> {
+    /// Unsanitized value of a placeholder, None if it has no value
+    fn value(&self, placeholder: &str) -> Option<String> {
+        let player = |index: usize| self.players.get(index).map(|name| name.as_deref().unwrap_or("unnamed"));
+        match placeholder {
+            "game_id" => Some(self.game_id.to_string()),
+            "timestamp" => Some(compact_timestamp(self.timestamp)),
+            "map" => self.map.map(str::to_owned),
+            "p1" => player(0).map(str::to_owned),
+            "p2" => player(1).map(str::to_owned),
+            "winner" => self.winner.map(str::to_owned),
+            "external_id" => self.external_id.map(str::to_owned),
+            other => unreachable!("Unknown placeholder {:?} (checked when parsing)", other),
+        }
+    }
+}
+
+/// File name pattern of saved replays, with placeholders in braces,
+/// e.g. `{timestamp}_{game_id}_{p1}_vs_{p2}.SC2Replay`
+/// Unknown placeholders are rejected when the config is loaded. The literal text is used as is,
+/// so it can contain directories, but placeholder values are sanitized, see `sanitize_name_value`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ReplayNamePattern(String);
+impl ReplayNamePattern {
+    /// Validate a pattern
+    pub fn new(pattern: &str) -> Result<Self, ReplayNameError> {
+        parse(pattern)?;
+        Ok(Self(pattern.to_owned()))
+    }
+
+    /// The pattern as written in the config
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// File name with the placeholders replaced by the sanitized values in `fields`
+    /// Placeholders without a value are replaced with `none`
+    pub fn expand(&self, fields: &ReplayNameFields) -> String {
+        let segments = parse(&self.0).expect("Replay name pattern was validated when created");
+        segments
+            .into_iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.to_owned(),
+                Segment::Placeholder(name) => match fields.value(name) {
+                    Some(value) => sanitize_name_value(&value),
+                    None => MISSING_VALUE.to_owned(),
+                },
+            })
+            .collect()
+    }
+}
+impl TryFrom<String> for ReplayNamePattern {
+    type Error = ReplayNameError;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        parse(&pattern)?;
+        Ok(Self(pattern))
+    }
+}
+impl From<ReplayNamePattern> for String {
+    fn from(pattern: ReplayNamePattern) -> Self {
+        pattern.0
+    }
+}
@@ -8,6 +8,21 @@ pub struct RequestLimits {
     /// Cheats (all debug commands except drawing)
     #[serde(default)]
     pub disable_cheats: bool,
+    /// Save replay requests from bots
+    #[serde(default)]
+    pub disable_save_replay: bool,
+    /// Maximum number of game loops a single step request can advance
+    #[serde(default)]
+    pub max_step_count: Option<u32>,
+    /// What to do with step requests over `max_step_count`
+    #[serde(default)]
+    pub step_limit_action: LimitAction,
+    /// Maximum number of debug texts and shapes drawn per step, the excess is removed
+    #[serde(default)]
+    pub max_debug_draw_per_step: Option<u32>,
+    /// Forfeit a player after this many SC2 error responses in total during a game
+    #[serde(default)]
+    pub max_sc2_errors: Option<u64>,
 }
 impl RequestLimits {
     /// Checks if the limits here allow a particular request
@@ -19,6 +34,30 @@ impl RequestLimits {
             }
         }
 
+        if self.disable_save_replay && req.has_save_replay() {
+            return false;
+        }
+
+        if let (Some(max), LimitAction::Reject) = (self.max_step_count, self.step_limit_action) {
+            if req.has_step() && req.get_step().get_count() > max {
+                return false;
+            }
+        }
+
         true
     }
 }
+
+/// Action taken on requests over a limit
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LimitAction {
+    /// Rewrite the request to the limit
+    Clamp,
+    /// Deny the request
+    Reject,
+}
+impl Default for LimitAction {
+    fn default() -> Self {
+        LimitAction::Clamp
+    }
+}
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::TcpStream;
+
+/// TCP options of the client and SC2 connections
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SocketOptions {
+    /// Disable Nagle's algorithm, so that small requests and responses are sent immediately
+    #[serde(default = "SocketOptions::default_nodelay")]
+    pub nodelay: bool,
+    /// Size of the kernel send buffer in bytes, the OS default if not set
+    #[serde(default)]
+    pub send_buffer_bytes: Option<usize>,
+    /// Size of the kernel receive buffer in bytes, the OS default if not set
+    #[serde(default)]
+    pub recv_buffer_bytes: Option<usize>,
+}
+impl SocketOptions {
+    fn default_nodelay() -> bool {
+        true
+    }
+
+    /// Set these options on a connected stream
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        if let Some(bytes) = self.send_buffer_bytes {
+            set_buffer_size(stream, Buffer::Send, bytes)?;
+        }
+        if let Some(bytes) = self.recv_buffer_bytes {
+            set_buffer_size(stream, Buffer::Recv, bytes)?;
+        }
+        Ok(())
+    }
+}
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            nodelay: Self::default_nodelay(),
+            send_buffer_bytes: None,
+            recv_buffer_bytes: None,
+        }
+    }
+}
+
+/// Kernel buffer of a socket
+#[derive(Debug, Clone, Copy)]
+enum Buffer {
+    Send,
+    Recv,
+}
+impl Buffer {
+    /// Socket option of the buffer size
+    #[cfg(unix)]
+    fn option(self) -> libc::c_int {
+        match self {
+            Buffer::Send => libc::SO_SNDBUF,
+            Buffer::Recv => libc::SO_RCVBUF,
+        }
+    }
+}
+
+/// Kernel send and receive buffer sizes of a stream, in bytes
+/// Linux reports double the size that was set, as it includes bookkeeping overhead
+#[cfg(unix)]
+pub fn buffer_sizes(stream: &TcpStream) -> io::Result<(usize, usize)> {
+    Ok((get_buffer_size(stream, Buffer::Send)?, get_buffer_size(stream, Buffer::Recv)?))
+}
+
+/// Set a buffer size socket option
+#[cfg(unix)]
+fn set_buffer_size(stream: &TcpStream, buffer: Buffer, bytes: usize) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let value = bytes.min(libc::c_int::max_value() as usize) as libc::c_int;
+    // Safety: the value is a plain integer, and its size is passed along with it
+    let ret = unsafe {
+        libc::setsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            buffer.option(),
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Read a buffer size socket option
+#[cfg(unix)]
+fn get_buffer_size(stream: &TcpStream, buffer: Buffer) -> io::Result<usize> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut value: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // Safety: the value is a plain integer, and its size is passed along with it
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            buffer.option(),
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(value as usize)
+}
+
+/// Set a buffer size socket option. Not supported on this platform.
+#[cfg(not(unix))]
+fn set_buffer_size(_stream: &TcpStream, _buffer: Buffer, _bytes: usize) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "Socket buffer sizes are only supported on Unix"))
+}
@@ -0,0 +1,268 @@
+//! Observation delta frames, for clients on slow links
+//!
+//! A client opts in by connecting with the `delta` query parameter,
+//! e.g. `ws://127.0.0.1:8642/sc2api?delta=32`.
+//! Responses to its observation requests are then sent as frames instead of plain protobuf messages:
+//! either a keyframe with the full serialized response, or a delta against the previous observation
+//! response sent to the client. Every `keyframe_interval`th frame is a keyframe.
+//! Other responses are sent unchanged. Clients reconstruct the responses with `Decoder`.
+//!
+//! Frame format: one kind byte, `FRAME_KEY` or `FRAME_DELTA`, followed by the full response bytes,
+//! or by delta operations: `OP_COPY` with varint offset and length of a range of the base,
+//! or `OP_INSERT` with varint length and literal bytes.
+
+use protobuf::parse_from_bytes;
+use sc2_proto::sc2api::Response;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Keyframe interval used when the `delta` query parameter has no value
+pub const DEFAULT_KEYFRAME_INTERVAL: u32 = 32;
+
+/// Frame kind: full serialized response
+pub const FRAME_KEY: u8 = 0;
+/// Frame kind: delta operations against the previous response
+pub const FRAME_DELTA: u8 = 1;
+
+/// Delta operation: copy a range of the base
+const OP_COPY: u8 = 0;
+/// Delta operation: literal bytes
+const OP_INSERT: u8 = 1;
+
+/// Length of the base blocks matched against the target
+/// Long enough that a copy operation is always smaller than the bytes it replaces
+const BLOCK: usize = 16;
+
+/// Reason why a frame cannot be decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeltaError {
+    /// The frame has no kind byte
+    Empty,
+    /// The kind byte is neither `FRAME_KEY` nor `FRAME_DELTA`
+    UnknownFrame(u8),
+    /// A delta frame was received before any keyframe
+    NoBase,
+    /// A delta operation is unknown or ends early
+    Malformed,
+    /// A copy operation reaches past the end of the base
+    CopyOutOfRange {
+        /// Start of the copied range
+        offset: usize,
+        /// Length of the copied range
+        len: usize,
+    },
+    /// The reconstructed bytes are not a valid response
+    InvalidResponse(String),
+}
+impl fmt::Display for DeltaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Empty frame"),
+            Self::UnknownFrame(kind) => write!(f, "Unknown frame kind {}", kind),
+            Self::NoBase => write!(f, "Delta frame before any keyframe"),
+            Self::Malformed => write!(f, "Malformed delta operations"),
+            Self::CopyOutOfRange { offset, len } => {
+                write!(f, "Copy of {} bytes at {} is outside the base", len, offset)
+            },
+            Self::InvalidResponse(e) => write!(f, "Invalid response: {}", e),
+        }
+    }
+}
+
+/// Append a varint to `out`
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read a varint from the start of `input`, advancing it
+fn read_varint(input: &mut &[u8]) -> Result<usize, DeltaError> {
+    let mut value = 0usize;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first().ok_or(DeltaError::Malformed)?;
+        *input = rest;
+        value |= ((byte & 0x7f) as usize).checked_shl(shift).ok_or(DeltaError::Malformed)?;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(DeltaError::Malformed)
+}
+
+/// Append an insert operation of `bytes` to `out`, if there are any
+fn write_insert(out: &mut Vec<u8>, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        out.push(OP_INSERT);
+        write_varint(out, bytes.len());
+        out.extend_from_slice(bytes);
+    }
+}
+
+/// Delta operations that turn `base` into `target`
+/// Blocks of the base are located anywhere in the target, so inserted and removed fields,
+/// which shift the rest of a protobuf message, still leave most of it copied
+pub fn diff(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut index: HashMap<&[u8], usize> = HashMap::with_capacity(base.len() / BLOCK);
+    for (i, block) in base.chunks_exact(BLOCK).enumerate() {
+        index.entry(block).or_insert(i * BLOCK);
+    }
+
+    let mut out = Vec::new();
+    // Start of the target bytes not yet covered by an operation
+    let mut literal = 0;
+    let mut pos = 0;
+    while pos + BLOCK <= target.len() {
+        let offset = match index.get(&target[pos..pos + BLOCK]) {
+            Some(&offset) => offset,
+            None => {
+                pos += 1;
+                continue;
+            },
+        };
+
+        // Extend the match backwards over pending literal bytes, and forwards as far as it goes
+        let back = (1..=offset.min(pos - literal))
+            .take_while(|&k| base[offset - k] == target[pos - k])
+            .last()
+            .unwrap_or(0);
+        let forward = base[offset..]
+            .iter()
+            .zip(&target[pos..])
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        write_insert(&mut out, &target[literal..pos - back]);
+        out.push(OP_COPY);
+        write_varint(&mut out, offset - back);
+        write_varint(&mut out, back + forward);
+        pos += forward;
+        literal = pos;
+    }
+    write_insert(&mut out, &target[literal..]);
+    out
+}
+
+/// Apply delta operations from `diff` to `base`
+pub fn apply(base: &[u8], mut ops: &[u8]) -> Result<Vec<u8>, DeltaError> {
+    let mut out = Vec::with_capacity(base.len());
+    while let Some((&op, rest)) = ops.split_first() {
+        ops = rest;
+        match op {
+            OP_COPY => {
+                let offset = read_varint(&mut ops)?;
+                let len = read_varint(&mut ops)?;
+                let range = offset
+                    .checked_add(len)
+                    .and_then(|end| base.get(offset..end))
+                    .ok_or(DeltaError::CopyOutOfRange { offset, len })?;
+                out.extend_from_slice(range);
+            },
+            OP_INSERT => {
+                let len = read_varint(&mut ops)?;
+                if len > ops.len() {
+                    return Err(DeltaError::Malformed);
+                }
+                let (bytes, rest) = ops.split_at(len);
+                out.extend_from_slice(bytes);
+                ops = rest;
+            },
+            _ => return Err(DeltaError::Malformed),
+        }
+    }
+    Ok(out)
+}
+
+/// Proxy side of a delta connection, turning serialized observation responses into frames
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    keyframe_interval: u32,
+    /// Previous response sent to the client
+    base: Option<Vec<u8>>,
+    /// Frames sent since the last keyframe
+    since_keyframe: u32,
+    /// Bytes of the responses, and of the frames sent instead
+    full_bytes: u64,
+    frame_bytes: u64,
+}
+impl Encoder {
+    /// Encoder sending a keyframe every `keyframe_interval` frames, at least every frame
+    pub fn new(keyframe_interval: u32) -> Self {
+        Self {
+            keyframe_interval: keyframe_interval.max(1),
+            base: None,
+            since_keyframe: 0,
+            full_bytes: 0,
+            frame_bytes: 0,
+        }
+    }
+
+    /// Frame to send instead of the serialized response `data`
+    /// A keyframe is sent when it's due, or when the delta would not be smaller
+    pub fn encode(&mut self, data: Vec<u8>) -> Vec<u8> {
+        let delta = match &self.base {
+            Some(base) if self.since_keyframe + 1 < self.keyframe_interval => Some(diff(base, &data)),
+            _ => None,
+        };
+
+        let frame = match delta.filter(|ops| ops.len() < data.len()) {
+            Some(ops) => {
+                self.since_keyframe += 1;
+                let mut frame = Vec::with_capacity(ops.len() + 1);
+                frame.push(FRAME_DELTA);
+                frame.extend(ops);
+                frame
+            },
+            None => {
+                self.since_keyframe = 0;
+                let mut frame = Vec::with_capacity(data.len() + 1);
+                frame.push(FRAME_KEY);
+                frame.extend_from_slice(&data);
+                frame
+            },
+        };
+        self.full_bytes += data.len() as u64;
+        self.frame_bytes += frame.len() as u64;
+        self.base = Some(data);
+        frame
+    }
+
+    /// Bytes saved by sending frames instead of the full responses so far
+    /// Negative if the kind bytes of keyframes outweigh the savings
+    pub fn bytes_saved(&self) -> i64 {
+        self.full_bytes as i64 - self.frame_bytes as i64
+    }
+}
+
+/// Client side of a delta connection, reconstructing observation responses from frames
+#[derive(Debug, Clone, Default)]
+pub struct Decoder {
+    /// Previous reconstructed response
+    base: Option<Vec<u8>>,
+}
+impl Decoder {
+    /// Decoder waiting for the first keyframe
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reconstruct the serialized response of a frame
+    pub fn decode(&mut self, frame: &[u8]) -> Result<Vec<u8>, DeltaError> {
+        let (&kind, payload) = frame.split_first().ok_or(DeltaError::Empty)?;
+        let data = match kind {
+            FRAME_KEY => payload.to_vec(),
+            FRAME_DELTA => apply(self.base.as_ref().ok_or(DeltaError::NoBase)?, payload)?,
+            other => return Err(DeltaError::UnknownFrame(other)),
+        };
+        self.base = Some(data.clone());
+        Ok(data)
+    }
+
+    /// Reconstruct and parse the response of a frame
+    pub fn decode_response(&mut self, frame: &[u8]) -> Result<Response, DeltaError> {
+        let data = self.decode(frame)?;
+        parse_from_bytes::<Response>(&data).map_err(|e| DeltaError::InvalidResponse(e.to_string()))
+    }
+}
@@ -0,0 +1,44 @@
+//! Error type for the proxy server
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+/// Errors that prevent the proxy server from running
+#[derive(Debug)]
+pub enum Error {
+    /// Could not bind a listening socket
+    Bind {
+        /// Name of the listener, e.g. "proxy"
+        listener: &'static str,
+        /// Address the bind was attempted on
+        addr: String,
+        /// Underlying IO error
+        source: io::Error,
+    },
+    /// Could not set up logging
+    Logging(String),
+    /// The config has settings that cannot work together, see `Config::check_startup`
+    Config(String),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Bind {
+                listener,
+                addr,
+                source,
+            } => write!(f, "Could not bind {} listener to {}: {}", listener, addr, source),
+            Error::Logging(msg) => write!(f, "Could not set up logging: {}", msg),
+            Error::Config(msg) => write!(f, "Invalid config: {}", msg),
+        }
+    }
+}
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Bind { source, .. } => Some(source),
+            Error::Logging(_) | Error::Config(_) => None,
+        }
+    }
+}
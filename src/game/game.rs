@@ -1,30 +1,242 @@
 //! Game manages a single game, including configuration and result gathering
 
 use crossbeam::channel::{select, Receiver, Sender};
-use log::{debug, warn};
+use log::{debug, info, warn};
+use sc2_proto::sc2api::ResponseGameInfo;
+use serde::{Deserialize, Serialize};
+use std::net::Shutdown;
 use std::thread;
+use std::time::Duration;
 
-use crate::config::Config;
-use crate::sc2::PlayerResult;
+use crate::config::{Config, DisconnectScoring, NetworkSim};
+use crate::portconfig::PortConfig;
+use crate::proxy::ClientConnection;
+use crate::results::void_reason;
+use crate::sc2::{Difficulty, PlayerResult, Race};
+use crate::supervisor::GameId;
 
 use super::any_panic_to_string;
-use super::messaging::{create_channels, FromSupervisor, ToGame, ToGameContent, ToSupervisor};
-use super::player::Player;
+use super::messaging::{create_channels, FromSupervisor, ToGame, ToGameContent, ToPlayer, ToSupervisor};
+use super::host::Host;
+use super::player::{Player, PlayerStats};
+use super::stall::StallDetector;
 
 /// Game result data
-#[derive(Debug, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct GameResult {
+    /// Identifier given by an external system, if any
+    pub external_id: Option<String>,
+    /// Races of participants in join order, after resolving random races
+    pub player_races: Vec<Race>,
+    /// Races requested by participants in join order
+    pub requested_races: Vec<Race>,
+    /// Names of participants in join order, None if not given
+    pub player_names: Vec<Option<String>>,
+    /// Metadata given by participants when connecting, in join order, None if not given
+    #[serde(default)]
+    pub player_metadata: Vec<Option<String>>,
+    /// Slot of the participant whose SC2 process hosted the game, None for a dedicated host
+    pub host_slot: Option<usize>,
+    /// Player setup the game was created with, see `Matchmaking::randomize_slots`
+    /// Empty if the game was created by the client
+    #[serde(default)]
+    pub slot_assignment: Vec<SlotAssignment>,
+    /// Why the game ended
     pub end_reason: GameEndReason,
+    /// SC2 player ids of participants in join order, None if not known
+    #[serde(default)]
+    pub player_ids: Vec<Option<u32>>,
+    /// Result for each player, ordered by player id
+    /// Includes computer players only if SC2 reported the results at the end of the game
     pub player_results: Vec<PlayerResult>,
+    /// How each participant's game ended, in join order
+    #[serde(default)]
+    pub player_details: Vec<PlayerOutcomeDetail>,
+    /// Result category of each participant in join order, telling crashes, timeouts and surrenders
+    /// apart from games played to the end
+    #[serde(default)]
+    pub player_categories: Vec<ResultCategory>,
+    /// Request counters of participants in join order
+    pub player_stats: Vec<PlayerStats>,
+    /// False if the game was voided by `record_results.validity`
+    #[serde(default = "GameResult::default_valid")]
+    pub valid: bool,
+    /// Why the game was voided
+    #[serde(default)]
+    pub void_reason: Option<VoidReason>,
+    /// Network conditions simulated with `game.network_sim`, None for normal games
+    /// Such games are not counted in the standings
+    #[serde(default)]
+    pub network_sim: Option<NetworkSim>,
+}
+
+/// Entry of the player setup a game is created with
+/// SC2 assigns participants to the participant entries in join order
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SlotAssignment {
+    /// Participant
+    Participant {
+        /// Join order slot of the participant
+        slot: usize,
+    },
+    /// Builtin AI
+    Computer {
+        /// Race of the computer player
+        race: Race,
+        /// Difficulty of the computer player
+        difficulty: Difficulty,
+    },
+    /// Dedicated host
+    Observer,
+}
+
+impl GameResult {
+    fn default_valid() -> bool {
+        true
+    }
+
+    /// Last game loop observed by any participant
+    pub fn game_loop(&self) -> u32 {
+        self.player_stats.iter().map(|s| s.game_loop).max().unwrap_or(0)
+    }
+
+    /// Result of each participant in join order, None if not known
+    pub fn participant_results(&self) -> Vec<Option<PlayerResult>> {
+        let count = self.player_races.len();
+        let ids: Option<Vec<u32>> = self.player_ids.iter().copied().collect();
+        let ids = match ids {
+            Some(ids) if ids.len() == count => ids,
+            // Results without player ids are in join order
+            _ => return (0..count).map(|slot| self.player_results.get(slot).copied()).collect(),
+        };
+        ids.iter()
+            .enumerate()
+            .map(|(slot, &id)| {
+                let index = if self.player_results.len() == count {
+                    // Participants only, ordered by player id, and by join order for equal ids
+                    ids.iter().enumerate().filter(|&(s, &other)| (other, s) < (id, slot)).count()
+                } else {
+                    // Every player of the game, with ids starting from 1
+                    (id as usize).checked_sub(1)?
+                };
+                self.player_results.get(index).copied()
+            })
+            .collect()
+    }
+}
+
+/// Why a game was voided, see `results::void_reason`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VoidReason {
+    /// SC2 process of a participant crashed
+    SC2Crashed {
+        /// Join order slot of the participant
+        slot: usize,
+    },
+    /// Client of a participant disconnected too early
+    EarlyDisconnect {
+        /// Join order slot of the participant
+        slot: usize,
+        /// Game loop of the last observation of the participant, None if there was none
+        game_loop: Option<u32>,
+    },
+    /// Game ended before `min_duration_secs`
+    TooShort {
+        /// Last game loop of the game
+        game_loop: u32,
+    },
 }
 
 /// Why this game ended
-#[derive(Debug, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
 pub enum GameEndReason {
     /// Game ended naturally
     Normal,
     /// Supervisor requested game quit
     QuitRequest,
+    /// Every participant disconnected before the game was over,
+    /// with `simultaneous_disconnect` set to `NoContest`
+    NoContest,
+    /// Game reached `time_limits.game_loops`, unfinished participants tie
+    TimeLimit,
+    /// No participant made progress during `time_limits.stall_detection`,
+    /// unfinished participants get the stalemate outcome
+    Stalemate,
+}
+
+/// How the game of a participant ended, telling apart the reasons behind a `PlayerResult`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayerOutcomeDetail {
+    /// Played until SC2 gave the result, or until the time limit
+    NormalResult,
+    /// Left or quit the game before it was over
+    LeftEarly,
+    /// Client closed the connection during the game
+    ClientDisconnected {
+        /// Game loop of the last observation
+        game_loop: u32,
+    },
+    /// Client closed the connection before its first observation
+    ClientCrashedBeforeStart,
+    /// SC2 process closed the connection unexpectedly
+    SC2Crashed,
+    /// Forfeited after too many SC2 errors,
+    /// see `max_consecutive_sc2_errors` and `request_limits.max_sc2_errors`
+    SC2ErrorLimit,
+    /// Removed from the game by the proxy, e.g. on shutdown
+    Kicked,
+}
+impl PlayerOutcomeDetail {
+    /// Detail given by a message from the player, None for messages that don't end the game
+    pub fn from_message(content: &ToGameContent) -> Option<Self> {
+        match content {
+            ToGameContent::GameOver(_) | ToGameContent::TimeLimitReached => Some(Self::NormalResult),
+            ToGameContent::LeftGame | ToGameContent::QuitBeforeLeave => Some(Self::LeftEarly),
+            ToGameContent::SC2UnexpectedConnectionClose => Some(Self::SC2Crashed),
+            ToGameContent::SC2ErrorLimitReached => Some(Self::SC2ErrorLimit),
+            ToGameContent::UnexpectedConnectionClose(Some(game_loop)) => {
+                Some(Self::ClientDisconnected {
+                    game_loop: *game_loop,
+                })
+            },
+            ToGameContent::UnexpectedConnectionClose(None) => Some(Self::ClientCrashedBeforeStart),
+            ToGameContent::StatusChanged(_) | ToGameContent::Score(_) => None,
+        }
+    }
+}
+
+/// Category of the result of a participant, telling apart the results that should be scored
+/// differently from a game played to the end
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultCategory {
+    /// Played until SC2 gave the result, or removed from the game by the proxy
+    Normal,
+    /// The client or its SC2 process stopped unexpectedly, or the client was removed
+    /// for making too many errors
+    Crash,
+    /// Game ended by the time limit
+    Timeout,
+    /// Gave up by leaving or quitting the game before it was over
+    SurrenderDisconnect,
+}
+impl ResultCategory {
+    /// Category given by a message from the player, None for messages that don't end the game
+    pub fn from_message(content: &ToGameContent) -> Option<Self> {
+        match content {
+            ToGameContent::GameOver(_) => Some(Self::Normal),
+            ToGameContent::TimeLimitReached => Some(Self::Timeout),
+            ToGameContent::LeftGame | ToGameContent::QuitBeforeLeave => Some(Self::SurrenderDisconnect),
+            ToGameContent::SC2UnexpectedConnectionClose
+            | ToGameContent::SC2ErrorLimitReached
+            | ToGameContent::UnexpectedConnectionClose(_) => Some(Self::Crash),
+            ToGameContent::StatusChanged(_) | ToGameContent::Score(_) => None,
+        }
+    }
 }
 
 /// A running game
@@ -34,17 +246,58 @@ pub struct Game {
     pub(super) config: Config,
     /// Player participants
     pub(super) players: Vec<Player>,
+    /// Dedicated host, if any
+    pub(super) host: Option<Host>,
+    /// Slot of the participant hosting the game, None for a dedicated host
+    pub(super) host_slot: Option<usize>,
+    /// Player setup the game was created with
+    pub(super) slot_assignment: Vec<SlotAssignment>,
+    /// Ports used by the game, leased until it ends
+    pub(super) ports: Option<PortConfig>,
+    /// Identifier given by an external system, if any
+    pub(super) external_id: Option<String>,
+    /// SC2 game info fetched at start, if enabled in the config
+    pub(super) game_info: Option<ResponseGameInfo>,
+    /// Wait before relaying requests, None if the participants did not join through the proxy
+    pub(super) post_join_delay: Option<Duration>,
 }
 impl Game {
+    /// Take the SC2 game info fetched when the game was started, if any
+    pub fn take_game_info(&mut self) -> Option<ResponseGameInfo> {
+        self.game_info.take()
+    }
+
     /// Process a messsage from player thread
-    fn process_msg(msg: ToGame, player_results: &mut Vec<Option<PlayerResult>>) {
+    /// Records which players got their result by disconnecting
+    /// Results reported by SC2 are stored to `reported`, and override the results by join order
+    fn process_msg(
+        msg: ToGame, player_ids: &[Option<u32>], player_results: &mut [Option<PlayerResult>],
+        reported: &mut Option<Vec<PlayerResult>>, disconnected: &mut [bool],
+        details: &mut [Option<PlayerOutcomeDetail>], categories: &mut [Option<ResultCategory>],
+    ) {
         let ToGame {
             player_index,
             content,
         } = msg;
+        let detail = PlayerOutcomeDetail::from_message(&content);
+        let category = ResultCategory::from_message(&content);
         match content {
             ToGameContent::GameOver(results) => {
-                player_results.splice(.., results.into_iter().map(Some));
+                // Results are ordered by player id, which can differ from the join order
+                for (slot, result) in player_results.iter_mut().enumerate() {
+                    let index = player_ids[slot].map_or(slot, |id| (id as usize).saturating_sub(1));
+                    *result = Some(results.get(index).copied().unwrap_or(PlayerResult::Tie));
+                }
+                *reported = Some(results);
+                disconnected.iter_mut().for_each(|d| *d = false);
+                // Participants that already left keep their detail
+                for d in details.iter_mut().filter(|d| d.is_none()) {
+                    *d = detail;
+                }
+                for c in categories.iter_mut().filter(|c| c.is_none()) {
+                    *c = category;
+                }
+                return;
             },
             ToGameContent::LeftGame => {
                 debug!("Player left game before it was over");
@@ -57,23 +310,55 @@ impl Game {
             ToGameContent::SC2UnexpectedConnectionClose => {
                 warn!("SC2 process closed connection unexpectedly");
                 player_results[player_index] = Some(PlayerResult::Defeat);
+                disconnected[player_index] = true;
             },
-            ToGameContent::UnexpectedConnectionClose => {
+            ToGameContent::SC2ErrorLimitReached => {
+                warn!("Player removed after too many SC2 errors");
+                player_results[player_index] = Some(PlayerResult::Defeat);
+            },
+            ToGameContent::UnexpectedConnectionClose(_) => {
                 warn!("Unexpected connection close");
                 player_results[player_index] = Some(PlayerResult::Defeat);
+                disconnected[player_index] = true;
+            },
+            ToGameContent::StatusChanged(_)
+            | ToGameContent::Score(_)
+            | ToGameContent::TimeLimitReached => {
+                unreachable!("Handled by the game loop")
             },
         }
+        details[player_index] = detail;
+        categories[player_index] = category;
     }
 
     /// Run the game, spawns thread for each participant player
     /// Returns the non-disconnected player instances, so they can be returned to the playlist
+    /// If the game cannot be finished, the clients still connected are sent to `recovered` before panicking
     pub fn run(
-        self, result_tx: Sender<GameResult>, from_sv: Receiver<FromSupervisor>, _to_sv: Sender<ToSupervisor>,
+        self, id: GameId, result_tx: Sender<GameResult>, from_sv: Receiver<FromSupervisor>,
+        to_sv: Sender<ToSupervisor>, recovered: Sender<ClientConnection>,
     ) -> Vec<Player> {
-        let mut handles: Vec<thread::JoinHandle<Option<Player>>> = Vec::new();
+        let mut handles: Vec<thread::JoinHandle<(Option<Player>, PlayerStats)>> = Vec::new();
 
-        let (rx, mut _to_player_channels, player_channels) = create_channels(self.players.len());
+        let (rx, mut to_player_channels, player_channels) =
+            create_channels(id, self.players.len(), &recovered);
         let mut player_results: Vec<Option<PlayerResult>> = vec![None; self.players.len()];
+        let mut reported: Option<Vec<PlayerResult>> = None;
+        let mut disconnected: Vec<bool> = vec![false; self.players.len()];
+        let mut details: Vec<Option<PlayerOutcomeDetail>> = vec![None; self.players.len()];
+        let mut categories: Vec<Option<ResultCategory>> = vec![None; self.players.len()];
+        let player_races: Vec<Race> = self.players.iter().map(|p| p.data.race).collect();
+        let requested_races: Vec<Race> = self.players.iter().map(|p| p.data.requested_race).collect();
+        let player_names: Vec<Option<String>> = self.players.iter().map(|p| p.data.name.clone()).collect();
+        let player_ids: Vec<Option<u32>> = self.players.iter().map(|p| p.data.player_id).collect();
+        let player_metadata: Vec<Option<String>> =
+            self.players.iter().map(|p| p.bot_metadata().map(str::to_owned)).collect();
+
+        // Requests sent during the wait are buffered, and read only after it
+        if let Some(delay) = self.post_join_delay {
+            debug!("Waiting {:?} after the join responses", delay);
+            thread::sleep(delay);
+        }
 
         // Run games
         for (p, c) in self.players.into_iter().zip(player_channels) {
@@ -82,26 +367,89 @@ impl Game {
             handles.push(handle);
         }
 
-        while player_results.contains(&None) {
+        // Keep the dedicated host following the game until the players are done
+        let realtime = self.config.match_defaults.game.is_realtime();
+        let host = self.host.map(|host| {
+            let stream = host.sc2_stream();
+            (stream, thread::spawn(move || host.run(realtime)))
+        });
+
+        let stall_rules = self.config.match_defaults.time_limits.stall_detection.clone();
+        let mut stall = stall_rules.map(|rules| StallDetector::new(rules, player_ids.len()));
+        let mut end_reason = GameEndReason::Normal;
+        while end_reason != GameEndReason::QuitRequest && player_results.contains(&None) {
             select! {
                 // A client ended the game
                 recv(rx) -> r => match r {
-                    Ok(msg) => Self::process_msg(msg, &mut player_results),
-                    Err(_) => panic!("Player channel closed without sending results"),
+                    Ok(ToGame { player_index, content: ToGameContent::StatusChanged(status) }) => {
+                        // The supervisor may be gone already, e.g. when shutting down
+                        let _ = to_sv.send(ToSupervisor::PlayerStatus(player_index, status));
+                    },
+                    Ok(ToGame { player_index, content: ToGameContent::Score(score) }) => {
+                        let _ = to_sv.send(ToSupervisor::PlayerScore(player_index, score));
+                        if let Some(detector) = &mut stall {
+                            if detector.record(player_index, &score) && end_reason == GameEndReason::Normal {
+                                info!("Stalemate on game loop {}", score.game_loop);
+                                end_reason = GameEndReason::Stalemate;
+                                for (index, outcome) in detector.results().into_iter().enumerate() {
+                                    if player_results[index].is_none() {
+                                        player_results[index] = Some(outcome);
+                                        details[index] = Some(PlayerOutcomeDetail::NormalResult);
+                                        categories[index] = Some(ResultCategory::Normal);
+                                        to_player_channels[index].send(ToPlayer::Quit);
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    Ok(ToGame { player_index, content: ToGameContent::TimeLimitReached }) => {
+                        info!("Time limit reached");
+                        end_reason = GameEndReason::TimeLimit;
+                        for (index, result) in player_results.iter_mut().enumerate() {
+                            if result.is_none() {
+                                *result = Some(PlayerResult::Tie);
+                                details[index] = Some(PlayerOutcomeDetail::NormalResult);
+                                categories[index] = Some(ResultCategory::Timeout);
+                                // The player that reached the limit has already left
+                                if index != player_index {
+                                    to_player_channels[index].send(ToPlayer::Quit);
+                                }
+                            }
+                        }
+                    },
+                    Ok(msg) => Self::process_msg(
+                        msg,
+                        &player_ids,
+                        &mut player_results,
+                        &mut reported,
+                        &mut disconnected,
+                        &mut details,
+                        &mut categories,
+                    ),
+                    Err(_) => {
+                        // Every player thread has ended, some of them without a result
+                        let players = handles.drain(..).filter_map(|h| h.join().ok()?.0);
+                        recover_clients(players, &recovered);
+                        panic!("Player channel closed without sending results");
+                    },
                 },
                 recv(from_sv) -> r => match r {
                     Ok(FromSupervisor::Quit) => {
-                        // Game quit requested
+                        // Game quit requested, players still in the game leave it
                         debug!("Supervisor requested game quit");
-
-                        result_tx
-                            .send(GameResult {
-                                end_reason: GameEndReason::QuitRequest,
-                                player_results: Vec::new(),
-                            })
-                            .expect("Could not send results to the supervisor");
-
-                        unimplemented!(); // TODO
+                        end_reason = GameEndReason::QuitRequest;
+                        for (index, result) in player_results.iter().enumerate() {
+                            if result.is_none() {
+                                to_player_channels[index].send(ToPlayer::Quit);
+                            }
+                        }
+                    },
+                    Ok(FromSupervisor::SaveReplay(path)) => {
+                        // Any participant still in the game can save the replay
+                        match player_results.iter().position(Option::is_none) {
+                            Some(index) => to_player_channels[index].send(ToPlayer::SaveReplay(path)),
+                            None => warn!("Cannot save replay, no participants left"),
+                        }
                     },
                     Err(_) => panic!("Supervisor channel closed unexpectedly"),
                 }
@@ -112,27 +460,102 @@ impl Game {
 
         // Wait until the games are ready
         let mut result_players: Vec<Player> = Vec::new();
+        let mut player_stats: Vec<PlayerStats> = Vec::new();
+        let mut player_panic = None;
         for handle in handles {
             match handle.join() {
-                Ok(Some(player)) => result_players.push(player),
-                Ok(None) => {},
+                Ok((player, stats)) => {
+                    result_players.extend(player);
+                    player_stats.push(stats);
+                },
                 Err(panic_msg) => {
-                    panic!(
-                        "Could not join game-client thread: {:?}",
-                        any_panic_to_string(panic_msg)
-                    );
+                    player_panic.get_or_insert(any_panic_to_string(panic_msg));
                 },
             }
         }
+        if let Some(panic_msg) = player_panic {
+            recover_clients(result_players, &recovered);
+            panic!("Could not join game-client thread: {:?}", panic_msg);
+        }
+
+        if let Some((stream, handle)) = host {
+            // Interrupt the host in case the game is still running without players
+            if let Ok(stream) = stream {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            if let Err(panic_msg) = handle.join() {
+                warn!("Dedicated host thread panicked: {}", any_panic_to_string(panic_msg));
+            }
+        }
 
-        // Send game result to the supervisor
-        result_tx
-            .send(GameResult {
-                end_reason: GameEndReason::Normal,
-                player_results: player_results.into_iter().map(Option::unwrap).collect(),
-            })
-            .expect("Could not send results to the supervisor");
+        // The ports can be reused by other games now
+        drop(self.ports);
+
+        let mut player_results: Vec<PlayerResult> = if end_reason == GameEndReason::QuitRequest {
+            Vec::new()
+        } else if let Some(reported) = reported {
+            reported
+        } else {
+            // Ordered by player id like the results reported by SC2, in join order for unknown or equal ids
+            let mut by_id: Vec<(Option<u32>, PlayerResult)> =
+                player_ids.iter().copied().zip(player_results.into_iter().map(Option::unwrap)).collect();
+            by_id.sort_by_key(|&(id, _)| id.unwrap_or(u32::MAX));
+            by_id.into_iter().map(|(_, result)| result).collect()
+        };
+        // Participants without a detail were still playing when the game was quit
+        let player_details = details.into_iter().map(|d| d.unwrap_or(PlayerOutcomeDetail::Kicked)).collect();
+        let player_categories =
+            categories.into_iter().map(|c| c.unwrap_or(ResultCategory::Normal)).collect();
+        let all_disconnected = disconnected.len() > 1 && disconnected.iter().all(|&d| d);
+        let scoring = self.config.match_defaults.game.simultaneous_disconnect;
+        let no_contest = scoring == DisconnectScoring::NoContest;
+        if end_reason == GameEndReason::Normal && all_disconnected && no_contest {
+            info!("Every participant disconnected, no contest");
+            end_reason = GameEndReason::NoContest;
+            player_results = vec![PlayerResult::Tie; player_results.len()];
+        }
+
+        // Send game result to the supervisor, which is gone if it requested the quit on shutdown
+        let mut result = GameResult {
+            external_id: self.external_id,
+            player_races,
+            requested_races,
+            player_names,
+            player_metadata,
+            player_ids,
+            host_slot: self.host_slot,
+            slot_assignment: self.slot_assignment,
+            end_reason,
+            player_results,
+            player_details,
+            player_categories,
+            player_stats,
+            valid: true,
+            void_reason: None,
+            network_sim: self.config.match_defaults.game.network_sim.clone(),
+        };
+        result.void_reason = void_reason(&self.config.match_defaults.record_results.validity, &result);
+        if let Some(reason) = result.void_reason {
+            info!("Game voided: {:?}", reason);
+            result.valid = false;
+        }
+        let sent = result_tx.send(result);
+        if sent.is_err() {
+            warn!("Supervisor is gone, game result discarded");
+        }
 
         result_players
     }
 }
+
+/// Return the clients of players that are still connected to the supervisor,
+/// when the game cannot be finished to return them with the result
+fn recover_clients(players: impl IntoIterator<Item = Player>, recovered: &Sender<ClientConnection>) {
+    for player in players {
+        if let Some(client) = player.extract_client() {
+            if recovered.send(client).is_err() {
+                warn!("Unable to return the client, the supervisor is gone");
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Dedicated SC2 process that hosts a game, isolating hosting from the participants
+
+use log::{debug, error};
+use std::fmt;
+use std::io;
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use websocket::OwnedMessage;
+
+use protobuf::parse_from_bytes;
+use protobuf::Message;
+use sc2_proto::sc2api::{Request, Response, Status};
+
+use crate::config::{Config, ProcessOptions};
+use crate::proxy::Client;
+use crate::sc2process::Process;
+
+use super::player::launch_sc2;
+
+/// Delay between observations in realtime games, where the observer cannot step
+const REALTIME_OBSERVE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Dedicated host, whose SC2 process is being launched in a background thread
+pub struct PendingHost {
+    /// Launcher thread, returns the process and its websocket connection
+    launch: thread::JoinHandle<Option<(Process, Client)>>,
+}
+impl PendingHost {
+    /// Start launching the SC2 process, with `process.spectator_defaults` if set
+    pub fn new(mut config: Config) -> Self {
+        config.process = config.process.spectator();
+        Self {
+            launch: launch_sc2(config),
+        }
+    }
+
+    /// Start launching the SC2 process with the given options, e.g. `matchmaking.cpu_process`
+    pub fn with_process(mut config: Config, process: ProcessOptions) -> Self {
+        config.process = process;
+        Self {
+            launch: launch_sc2(config),
+        }
+    }
+
+    /// Checks if the launch has finished, successfully or not
+    pub fn is_launched(&self) -> bool {
+        self.launch.is_finished()
+    }
+
+    /// Wait for the launch to finish
+    /// Returns None if the process could not be started
+    pub fn into_host(self) -> Option<Host> {
+        match self.launch.join() {
+            Ok(Some((process, sc2_ws))) => Some(Host { process, sc2_ws }),
+            _ => {
+                error!("Could not launch the SC2 process of the dedicated host");
+                None
+            },
+        }
+    }
+
+    /// Wait for the launch to finish, then close like `Host::close`
+    pub fn close(self) {
+        if let Some(host) = self.into_host() {
+            host.close();
+        }
+    }
+}
+impl fmt::Debug for PendingHost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PendingHost {{ ... }}")
+    }
+}
+
+/// Dedicated host process, joined to its game as a silent observer
+pub struct Host {
+    /// SC2 process
+    process: Process,
+    /// SC2 websocket connection
+    sc2_ws: Client,
+}
+impl Host {
+    /// Send a request to SC2 and return the reponse
+    /// Returns None if the connection is already closed
+    #[must_use]
+    pub fn sc2_query(&mut self, r: Request) -> Option<Response> {
+        let bytes = r.write_to_bytes().expect("Invalid protobuf message");
+        self.sc2_ws.send_message(&OwnedMessage::Binary(bytes)).ok()?;
+        match self.sc2_ws.recv_message().ok()? {
+            OwnedMessage::Binary(bytes) => Some(parse_from_bytes::<Response>(&bytes).expect("Invalid data")),
+            OwnedMessage::Close(_) => None,
+            other => panic!("Expected binary message, got {:?}", other),
+        }
+    }
+
+    /// Clone of the SC2 websocket stream, can be used to interrupt the connection
+    pub fn sc2_stream(&self) -> io::Result<TcpStream> {
+        self.sc2_ws.stream_ref().try_clone()
+    }
+
+    /// Ask SC2 to quit, then terminate the process
+    pub fn close(mut self) {
+        self.process.quit(&mut self.sc2_ws);
+    }
+
+    /// Follow the game until it ends or the connection is closed, then kill the process
+    /// Non-realtime games only advance when every process steps, including this one
+    pub fn run(mut self, realtime: bool) {
+        loop {
+            if realtime {
+                thread::sleep(REALTIME_OBSERVE_INTERVAL);
+            } else {
+                let mut req = Request::new();
+                req.mut_step().set_count(1);
+                if self.sc2_query(req).is_none() {
+                    break;
+                }
+            }
+
+            let mut req = Request::new();
+            req.mut_observation();
+            match self.sc2_query(req) {
+                Some(ref resp) if resp.get_status() == Status::in_game => {},
+                _ => break,
+            }
+        }
+
+        debug!("Game over, killing the dedicated host process");
+        self.process.kill();
+    }
+}
+impl fmt::Debug for Host {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Host {{ ... }}")
+    }
+}
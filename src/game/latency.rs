@@ -0,0 +1,155 @@
+//! Measurement of the latency the proxy adds to relayed requests, see `GameConfig::measure_latency`
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Current time if `MEASURE` is set, otherwise None
+/// The check is done at compile time, so disabled measurement costs nothing
+#[inline(always)]
+pub(crate) fn now<const MEASURE: bool>() -> Option<Instant> {
+    if MEASURE {
+        Some(Instant::now())
+    } else {
+        None
+    }
+}
+
+/// Sub-buckets per power of two, so buckets are at most 25% wide
+const SUB_BUCKETS: u32 = 4;
+
+/// Number of buckets, the last one counts everything over about an hour
+const BUCKETS: usize = 128;
+
+/// Histogram of durations with logarithmic microsecond buckets
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+    max_us: u64,
+}
+impl LatencyHistogram {
+    /// Create an empty histogram
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; BUCKETS],
+            total: 0,
+            max_us: 0,
+        }
+    }
+
+    /// Bucket of a value in microseconds
+    fn bucket(us: u64) -> usize {
+        if us < u64::from(SUB_BUCKETS) {
+            return us as usize;
+        }
+        let octave = 63 - us.leading_zeros();
+        let sub = (us >> (octave - 2)) & u64::from(SUB_BUCKETS - 1);
+        ((octave - 1) * SUB_BUCKETS + sub as u32).min(BUCKETS as u32 - 1) as usize
+    }
+
+    /// Largest value in microseconds counted into a bucket
+    fn bucket_limit(bucket: usize) -> u64 {
+        let bucket = bucket as u32;
+        if bucket < SUB_BUCKETS {
+            return u64::from(bucket);
+        }
+        let octave = bucket / SUB_BUCKETS + 1;
+        let sub = u64::from(bucket % SUB_BUCKETS);
+        ((SUB_BUCKETS as u64 + sub + 1) << (octave - 2)) - 1
+    }
+
+    /// Count a duration
+    pub fn record(&mut self, duration: Duration) {
+        let us = duration.as_micros() as u64;
+        self.counts[Self::bucket(us)] += 1;
+        self.total += 1;
+        self.max_us = self.max_us.max(us);
+    }
+
+    /// Number of durations counted
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// Upper bound in microseconds of the given percentile (0-100), 0 if empty
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        let rank = ((percentile / 100.0) * self.total as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::bucket_limit(bucket).min(self.max_us);
+            }
+        }
+        0
+    }
+
+    /// Percentiles of the counted durations
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            p50_us: self.percentile(50.0),
+            p95_us: self.percentile(95.0),
+            p99_us: self.percentile(99.0),
+            max_us: self.max_us,
+        }
+    }
+}
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Percentiles of proxy-added latency, in microseconds
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySummary {
+    /// Median
+    pub p50_us: u64,
+    /// 95th percentile
+    pub p95_us: u64,
+    /// 99th percentile
+    pub p99_us: u64,
+    /// Largest measured value
+    pub max_us: u64,
+}
+
+/// Latency the proxy added to the requests of a player relayed to SC2
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// Requests measured, cached and denied requests are not included
+    pub requests: u64,
+    /// From receiving a client request to forwarding it to SC2
+    pub to_sc2: LatencySummary,
+    /// From receiving an SC2 response to sending it to the client
+    pub to_client: LatencySummary,
+}
+
+/// Histograms of both directions of relayed requests
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LatencyRecorder {
+    to_sc2: LatencyHistogram,
+    to_client: LatencyHistogram,
+}
+impl LatencyRecorder {
+    /// Count a relayed request from its timestamps, if all of them were taken
+    pub fn record(
+        &mut self, arrived: Option<Instant>, forwarded: Option<Instant>, sc2_responded: Option<Instant>,
+        sent: Option<Instant>,
+    ) {
+        if let (Some(arrived), Some(forwarded), Some(sc2_responded), Some(sent)) =
+            (arrived, forwarded, sc2_responded, sent)
+        {
+            self.to_sc2.record(forwarded - arrived);
+            self.to_client.record(sent - sc2_responded);
+        }
+    }
+
+    /// Percentiles of both directions
+    pub fn stats(&self) -> LatencyStats {
+        LatencyStats {
+            requests: self.to_sc2.count(),
+            to_sc2: self.to_sc2.summary(),
+            to_client: self.to_client.summary(),
+        }
+    }
+}
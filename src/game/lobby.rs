@@ -1,18 +1,30 @@
 //! Game manages a single unstarted game, including its configuration
 
-use log::{debug, error};
+use log::{debug, error, info, warn};
+use std::fmt;
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use protobuf::RepeatedField;
-use sc2_proto::sc2api::RequestJoinGame;
+use sc2_proto::sc2api::{
+    Request, RequestJoinGame, ResponseCreateGame_Error, ResponseGameInfo, ResponseJoinGame_Error,
+};
 
-use crate::config::Config;
-use crate::maps::find_map;
+use crate::config::{shuffle, Config, HostSelection, ProcessOptions};
+use crate::maps::find_map_in;
 use crate::portconfig::PortConfig;
-use crate::proxy::Client;
+use crate::proxy::ClientConnection;
+use crate::refine::{Pipeline, RefineContext};
 use crate::sc2::{Difficulty, Race};
 
-use super::game::Game;
-use super::player::{Player, PlayerData};
+use super::game::{Game, SlotAssignment};
+use super::host::{Host, PendingHost};
+use super::player::{PendingPlayer, Player, PlayerData};
+
+/// Delay before retrying after a transient error, multiplied by the attempt number
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
 
 /// An unstarted game
 #[derive(Debug)]
@@ -21,45 +33,360 @@ pub struct GameLobby {
     config: Config,
     /// Player participants
     players: Vec<Player>,
+    /// Participants whose SC2 process is still launching, joined after `players`
+    pending: Vec<PendingPlayer>,
     /// Computeer players
     computer_players: Vec<(Race, Difficulty)>,
+    /// Identifier given by an external system, if any
+    external_id: Option<String>,
+    /// Creation time
+    created: Instant,
+    /// Start even with fewer participants than the configured minimum
+    forced: bool,
+    /// Start automatically when this many players have joined and are ready
+    autostart: Option<usize>,
+    /// Dedicated host whose SC2 process is still launching
+    pending_host: Option<PendingHost>,
+    /// Dedicated host, if `host_selection` is `dedicated`
+    host: Option<Host>,
+    /// Ports leased for the game when joining it
+    ports: Option<PortConfig>,
+    /// Failed start attempts, see `relaunch`
+    start_attempts: u32,
+    /// Player setup of the created game
+    slot_assignment: Vec<SlotAssignment>,
+    /// Matchmaking queue the lobby belongs to, None for the default queue
+    queue: Option<String>,
 }
 impl GameLobby {
     /// Create new empty game lobby from config
-    pub fn new(config: Config) -> Self {
+    /// The `fullscreen` setting of the match overrides the one of the process options
+    pub fn new(mut config: Config, external_id: Option<String>) -> Self {
+        if let Some(fullscreen) = config.match_defaults.game.fullscreen {
+            config.process.fullscreen = fullscreen;
+        }
         Self {
             config,
             players: Vec::new(),
+            pending: Vec::new(),
             computer_players: Vec::new(),
+            external_id,
+            created: Instant::now(),
+            forced: false,
+            autostart: None,
+            pending_host: None,
+            host: None,
+            ports: None,
+            start_attempts: 0,
+            slot_assignment: Vec::new(),
+            queue: None,
         }
     }
 
-    /// Checks if this lobby has any player participants
-    pub fn is_valid(&self) -> bool {
-        !self.players.is_empty()
+    /// Time since the lobby was created
+    pub fn age(&self) -> Duration {
+        self.created.elapsed()
     }
 
-    /// Add a new client to the game
-    pub fn join(&mut self, connection: Client, join_req: RequestJoinGame) {
-        self.players.push(Player::new(
-            self.config.clone(),
-            connection,
-            PlayerData::from_join_request(join_req),
-        ));
+    /// Identifier given by an external system, if any
+    pub fn external_id(&self) -> Option<&str> {
+        self.external_id.as_deref()
+    }
+
+    /// Configuration of the lobby, a snapshot taken when it was created
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Matchmaking queue the lobby belongs to, None for the default queue
+    pub fn queue(&self) -> Option<&str> {
+        self.queue.as_deref()
+    }
+
+    /// Set the matchmaking queue the lobby belongs to
+    pub fn set_queue(&mut self, queue: Option<String>) {
+        self.queue = queue;
+    }
+
+    /// Launch SC2 processes of participants joining after this fullscreen or windowed
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.config.match_defaults.game.fullscreen = Some(fullscreen);
+        self.config.process.fullscreen = fullscreen;
+    }
+
+    /// Allow starting with fewer participants than the configured minimum
+    pub fn force(&mut self) {
+        self.forced = true;
+    }
+
+    /// Start the game automatically when `slots` players, including computers,
+    /// have joined and all their SC2 processes are ready
+    pub fn start_when_full(&mut self, slots: usize) {
+        self.autostart = Some(slots);
+    }
+
+    /// Number of failed start attempts of this lobby
+    pub fn start_attempts(&self) -> u32 {
+        self.start_attempts
+    }
+
+    /// Checks if the game should be started automatically now
+    pub fn should_start(&self) -> bool {
+        self.autostart.is_some_and(|slots| self.is_full(slots) && self.is_ready())
+    }
+
+    /// Checks if this lobby has no player participants
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty() && self.pending.is_empty()
+    }
+
+    /// Checks if the SC2 processes of all participants, and the dedicated host, are ready
+    pub fn is_ready(&self) -> bool {
+        self.pending.is_empty() && self.pending_host.is_none()
+    }
+
+    /// Names of participants, the command lines of their SC2 processes,
+    /// and the process options they were launched with, in join order
+    /// The command is None while the process is still being launched
+    pub fn participants(&self) -> Vec<(Option<&str>, Option<&[String]>, &ProcessOptions)> {
+        let ready = self.players.iter().map(|p| {
+            (p.data.name.as_deref(), Some(p.launch_command()), self.process_options(&p.data))
+        });
+        let pending =
+            self.pending.iter().map(|p| (p.data.name.as_deref(), None, self.process_options(&p.data)));
+        ready.chain(pending).collect()
+    }
+
+    /// Process options of a participant, its override if any, otherwise the ones of the lobby
+    fn process_options<'a>(&'a self, data: &'a PlayerData) -> &'a ProcessOptions {
+        data.process_options.as_ref().unwrap_or(&self.config.process)
+    }
+
+    /// Move participants whose SC2 process has launched from pending to players,
+    /// keeping the join order. Participants whose launch failed are disconnected.
+    pub fn update_pending(&mut self) {
+        if self.pending_host.as_ref().is_some_and(PendingHost::is_launched) {
+            self.host = self.pending_host.take().and_then(PendingHost::into_host);
+        }
+
+        while self.pending.first().is_some_and(PendingPlayer::is_launched) {
+            let pending = self.pending.remove(0);
+            if let Some(player) = pending.into_player() {
+                self.players.push(player);
+            }
+        }
+    }
+
+    /// Checks that the game can be started, pinging the SC2 process of every participant
+    /// Returns all problems found
+    pub fn is_valid(&mut self) -> Result<(), Vec<LobbyProblem>> {
+        if self.is_empty() {
+            return Err(vec![LobbyProblem::NoParticipants]);
+        }
+
+        let game_config = &self.config.match_defaults.game;
+        let participants = self.players.len() + self.pending.len();
+        let mut problems = Vec::new();
+
+        if !self.pending.is_empty() {
+            problems.push(LobbyProblem::StillLaunching {
+                count: self.pending.len(),
+            });
+        }
+
+        if participants < game_config.min_participants && !self.forced {
+            problems.push(LobbyProblem::TooFewParticipants {
+                required: game_config.min_participants,
+                actual: participants,
+            });
+        }
+
+        if self.has_dedicated_host() && self.host.is_none() {
+            problems.push(LobbyProblem::HostNotReady);
+        }
+
+        if let Some(capacity) = game_config.map_capacity {
+            let players = participants + self.computer_players.len();
+            if players > capacity {
+                problems.push(LobbyProblem::OverCapacity { capacity, players });
+            }
+        }
+
+        if let Some(overwrites) = &game_config.overwrite_races {
+            if overwrites.len() != participants {
+                problems.push(LobbyProblem::RaceOverwriteCount {
+                    overwrites: overwrites.len(),
+                    participants,
+                });
+            }
+        }
+
+        let datas = self.players.iter().map(|p| &p.data);
+        let datas = datas.chain(self.pending.iter().map(|p| &p.data));
+        for (slot, data) in datas.enumerate() {
+            for interface in game_config.allowed_interfaces.disallowed(&data.ifopts) {
+                problems.push(LobbyProblem::InterfaceNotAllowed { slot, interface });
+            }
+        }
+
+        for (slot, player) in self.players.iter_mut().enumerate() {
+            if !player.sc2_ping() {
+                problems.push(LobbyProblem::ProcessNotResponding { slot });
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Checks if this lobby has at least `slots` players, including computers
+    pub fn is_full(&self, slots: usize) -> bool {
+        self.players.len() + self.pending.len() + self.computer_players.len() >= slots
     }
 
     /// Add a new client to the game
+    /// The SC2 process is launched in the background, see `update_pending`
+    pub fn join(&mut self, connection: ClientConnection, join_req: RequestJoinGame) {
+        self.join_with_options(connection, join_req, None);
+    }
+
+    /// Add a new client to the game, launching its SC2 process with `process_options`
+    /// instead of the ones of the lobby, if given
+    pub fn join_with_options(
+        &mut self, connection: ClientConnection, join_req: RequestJoinGame,
+        process_options: Option<ProcessOptions>,
+    ) {
+        self.launch_host();
+
+        let mut config = self.config.clone();
+        let mut data = PlayerData::from_join_request(join_req);
+        if let Some(options) = process_options {
+            config.process = options.clone();
+            data.process_options = Some(options);
+        }
+        self.pending.push(PendingPlayer::new(config, connection, data));
+    }
+
+    /// Checks if a participant with the given player name is in this lobby
+    pub fn has_player_named(&self, name: &str) -> bool {
+        self.players.iter().any(|p| p.data.name.as_deref() == Some(name))
+            || self.pending.iter().any(|p| p.data.name.as_deref() == Some(name))
+    }
+
+    /// Removes participants with the given player name, closing their connections
+    /// Returns the number of players removed
+    pub fn remove_players_named(&mut self, name: &str) -> usize {
+        self.remove_players_where(
+            |p| p.data.name.as_deref() == Some(name),
+            |p| p.data.name.as_deref() == Some(name),
+            "Reconnected from another connection",
+        )
+    }
+
+    /// Removes participants whose client has disconnected, closing their processes
+    /// Returns the number of players removed
+    pub fn remove_disconnected(&mut self) -> usize {
+        self.remove_players_where(|p| !p.is_connected(), |p| !p.is_connected(), "Disconnected")
+    }
+
+    /// Removes players and pending players matching the filters, closing their connections with `reason`
+    /// Returns the number of players removed
+    fn remove_players_where(
+        &mut self, player_filter: impl Fn(&Player) -> bool, pending_filter: impl Fn(&PendingPlayer) -> bool,
+        reason: &str,
+    ) -> usize {
+        let (removed, kept): (Vec<Player>, Vec<Player>) = self.players.drain(..).partition(player_filter);
+        self.players = kept;
+
+        let (removed_pending, kept_pending): (Vec<PendingPlayer>, Vec<PendingPlayer>) =
+            self.pending.drain(..).partition(pending_filter);
+        self.pending = kept_pending;
+
+        let count = removed.len() + removed_pending.len();
+        for player in removed {
+            player.close(reason);
+        }
+        if !removed_pending.is_empty() {
+            // Waiting for the launches to finish would block the caller
+            let reason = reason.to_owned();
+            thread::spawn(move || {
+                for pending in removed_pending {
+                    pending.close(&reason);
+                }
+            });
+        }
+        count
+    }
+
+    /// Add a computer player to the game
+    /// With `matchmaking.cpu_process`, this launches the dedicated host that runs the computers
     pub fn add_computer(&mut self, race: Race, difficulty: Difficulty) {
+        let first = self.computer_players.is_empty();
         self.computer_players.push((race, difficulty));
+        if first && self.config.matchmaking.cpu_process.is_some() {
+            // A host launched for `host_selection` has the spectator options, replace it
+            let host = self.host.take();
+            let pending_host = self.pending_host.take();
+            if host.is_some() || pending_host.is_some() {
+                thread::spawn(move || {
+                    if let Some(host) = host {
+                        host.close();
+                    }
+                    if let Some(pending_host) = pending_host {
+                        pending_host.close();
+                    }
+                });
+            }
+        }
+        self.launch_host();
+    }
+
+    /// Checks if the computer players run on their own SC2 process, see `Matchmaking::cpu_process`
+    fn has_cpu_host(&self) -> bool {
+        self.config.matchmaking.cpu_process.is_some() && !self.computer_players.is_empty()
+    }
+
+    /// Checks if the game is created and hosted by a dedicated SC2 process,
+    /// because of `host_selection` or the computer players
+    fn has_dedicated_host(&self) -> bool {
+        self.config.match_defaults.game.host_selection == HostSelection::Dedicated || self.has_cpu_host()
+    }
+
+    /// Start launching the dedicated host if the game needs one and it's not launched yet
+    /// It uses `matchmaking.cpu_process` if it runs computer players
+    fn launch_host(&mut self) {
+        if !self.has_dedicated_host() || self.host.is_some() || self.pending_host.is_some() {
+            return;
+        }
+        let config = self.config.clone();
+        self.pending_host = Some(match &self.config.matchmaking.cpu_process {
+            Some(options) if self.has_cpu_host() => PendingHost::with_process(config, options.clone()),
+            _ => PendingHost::new(config),
+        });
+    }
+
+    /// Fill the lobby with computer players, until it has `slots` players
+    /// Returns the number of computer players added
+    pub fn fill_with_computers(&mut self, slots: usize, race: Race, difficulty: Difficulty) -> usize {
+        let joined = self.players.len() + self.pending.len() + self.computer_players.len();
+        let count = slots.saturating_sub(joined);
+        for _ in 0..count {
+            self.add_computer(race, difficulty);
+        }
+        count
     }
 
     /// Protobuf to create a new game
-    fn proto_create_game(&self, players: Vec<CreateGamePlayer>) -> sc2_proto::sc2api::Request {
+    fn proto_create_game(&self, players: &[SlotAssignment]) -> sc2_proto::sc2api::Request {
         use sc2_proto::sc2api::{LocalMap, Request, RequestCreateGame};
 
         let mut r_local_map = LocalMap::new();
         r_local_map.set_map_path(
-            find_map(
+            find_map_in(
+                self.config.process.map_dir.as_deref(),
                 self.config
                     .match_defaults
                     .game
@@ -72,13 +399,13 @@ impl GameLobby {
 
         let mut r_create_game = RequestCreateGame::new();
         r_create_game.set_local_map(r_local_map);
-        r_create_game.set_realtime(self.config.match_defaults.game.realtime);
+        r_create_game.set_realtime(self.config.match_defaults.game.is_realtime());
         r_create_game.set_disable_fog(self.config.match_defaults.game.disable_fog);
         if let Some(realtime) = self.config.match_defaults.game.random_seed.clone() {
             r_create_game.set_random_seed(realtime);
         }
 
-        let p_cfgs: Vec<_> = players.iter().map(CreateGamePlayer::to_proto).collect();
+        let p_cfgs: Vec<_> = players.iter().map(player_setup).collect();
         r_create_game.set_player_setup(RepeatedField::from_vec(p_cfgs));
 
         let mut request = Request::new();
@@ -86,54 +413,91 @@ impl GameLobby {
         request
     }
 
-    /// Create the game using the first client
+    /// Create the game using the SC2 process of the participant in `host_slot`,
+    /// or the dedicated host if it's None
     /// Returns None iff game join fails (connection close or sc2 process close)
     #[must_use]
-    pub fn create_game(&mut self) -> Option<()> {
+    pub fn create_game(&mut self, host_slot: Option<usize>) -> Option<()> {
         assert!(self.players.len() > 0);
 
         // Craft CrateGame request
-        let mut player_configs: Vec<CreateGamePlayer> = Vec::new();
-
-        // Participant players first
-        for _ in &self.players {
-            player_configs.push(CreateGamePlayer::Participant);
+        // Participants are assigned to the participant entries in join order,
+        // so only the positions of the computer players need to be randomized
+        let mut computers = vec![false; self.players.len()];
+        computers.resize(self.players.len() + self.computer_players.len(), true);
+        if self.config.matchmaking.randomize_slots {
+            shuffle(&mut computers, self.config.match_defaults.game.random_seed);
         }
+        let mut participants = 0..self.players.len();
+        let mut computer_players = self.computer_players.iter();
+        let mut player_configs: Vec<SlotAssignment> = computers
+            .into_iter()
+            .map(|computer| {
+                if computer {
+                    let &(race, difficulty) = computer_players.next().unwrap();
+                    SlotAssignment::Computer { race, difficulty }
+                } else {
+                    SlotAssignment::Participant {
+                        slot: participants.next().unwrap(),
+                    }
+                }
+            })
+            .collect();
 
-        // Then computer players
-        for (race, difficulty) in self.computer_players.clone() {
-            player_configs.push(CreateGamePlayer::Computer(race, difficulty));
+        // Dedicated host observes the game
+        if self.host.is_some() {
+            player_configs.push(SlotAssignment::Observer);
         }
 
         // TODO: Human players?
-        // TODO: Observers?
 
-        // Send CreateGame request to first process
-        let proto = self.proto_create_game(player_configs);
-        let response = self.players[0].sc2_query(proto)?;
+        // Send CreateGame request to the hosting process
+        debug!("Creating game with player setup {:?}", player_configs);
+        let proto = self.proto_create_game(&player_configs);
+        self.slot_assignment = player_configs;
+        let retries = self.config.match_defaults.game.start_retries;
+        for attempt in 0..=retries {
+            let response = match (host_slot, self.host.as_mut()) {
+                (Some(slot), _) => self.players[slot].sc2_query(proto.clone())?,
+                (None, Some(host)) => host.sc2_query(proto.clone())?,
+                (None, None) => panic!("Dedicated host missing (GameLobby::is_valid?)"),
+            };
+
+            assert!(response.has_create_game());
+            let resp_create_game = response.get_create_game();
+            if !resp_create_game.has_error() {
+                debug!("Game created succesfully");
+                return Some(());
+            }
 
-        assert!(response.has_create_game());
-        let resp_create_game = response.get_create_game();
-        if resp_create_game.has_error() {
-            error!("Could not create game: {:?}", resp_create_game.get_error());
-            return None;
-        } else {
-            debug!("Game created succesfully");
+            let error = resp_create_game.get_error();
+            if !is_transient_create_error(error) || attempt == retries {
+                error!("Could not create game: {:?}", error);
+                return None;
+            }
+            warn!(
+                "Could not create game: {:?}, retrying ({}/{})",
+                error,
+                attempt + 1,
+                retries
+            );
+            thread::sleep(RETRY_BACKOFF * (attempt + 1));
         }
-
-        Some(())
+        unreachable!()
     }
 
-    /// Protobuf to join a game
+    /// Protobuf for the SC2 process with index `process` to join a game
     fn proto_join_game_participant(
-        &self, portconfig: PortConfig, player_data: PlayerData,
+        &self, portconfig: &PortConfig, process: usize, player_data: PlayerData,
     ) -> sc2_proto::sc2api::Request {
         use sc2_proto::sc2api::{Request, RequestJoinGame};
 
         let mut r_join_game = RequestJoinGame::new();
         r_join_game.set_options(player_data.ifopts);
         r_join_game.set_race(player_data.race.to_proto());
-        portconfig.apply_proto(&mut r_join_game, self.players.len() == 1);
+        portconfig
+            .apply_proto(&mut r_join_game, process)
+            .expect("Port config has ports for every process");
 
         if let Some(name) = player_data.name {
             r_join_game.set_player_name(name);
@@ -143,83 +507,481 @@ impl GameLobby {
         request
     }
 
+    /// Protobuf for the dedicated host to join a game as an observer
+    /// The host is the last process, after the participants
+    fn proto_join_game_host(&self, portconfig: &PortConfig) -> sc2_proto::sc2api::Request {
+        use sc2_proto::sc2api::{InterfaceOptions, Request, RequestJoinGame};
+
+        let mut ifopts = InterfaceOptions::new();
+        ifopts.set_raw(true);
+
+        let mut r_join_game = RequestJoinGame::new();
+        r_join_game.set_options(ifopts);
+        r_join_game.set_observed_player_id(0);
+        portconfig
+            .apply_proto(&mut r_join_game, portconfig.processes() - 1)
+            .expect("Port config has ports for every process");
+
+        let mut request = Request::new();
+        request.set_join_game(r_join_game);
+        request
+    }
+
+    /// Apply race overwrites and resolve random race requests according to the config
+    fn resolve_races(&mut self) {
+        let game_config = &self.config.match_defaults.game;
+        let seed = game_config.random_seed.unwrap_or(0);
+        let overwrites = game_config.overwrite_races.as_ref();
+        for (slot, player) in self.players.iter_mut().enumerate() {
+            if let Some(&Some(race)) = overwrites.and_then(|o| o.get(slot)) {
+                if race != player.data.race {
+                    info!(
+                        "Overwriting race of slot {} from {:?} to {:?}",
+                        slot, player.data.race, race
+                    );
+                    player.data.race = race;
+                }
+            }
+
+            let resolved = game_config.random_race.resolve(player.data.race, seed, slot);
+            if resolved != player.data.race {
+                info!("Resolved random race of slot {} to {:?}", slot, resolved);
+                player.data.race = resolved;
+            }
+        }
+    }
+
     /// Joins all participants to games
-    /// Returns None iff game join fails (connection close or sc2 process close)
+    /// Returns None iff game join fails (no free ports, connection close or sc2 process close)
     #[must_use]
     pub fn join_all_game(&mut self) -> Option<()> {
-        let pc = PortConfig::new().expect("Unable to find free ports");
+        self.resolve_races();
+
+        let processes = self.players.len() + self.host.iter().count();
+        let pc = match PortConfig::new(processes) {
+            Some(pc) => pc,
+            None => {
+                error!("Cannot start game: {}", LobbyProblem::NoFreePorts { processes });
+                return None;
+            },
+        };
 
         let protos: Vec<_> = self
             .players
             .iter()
-            .map(|p| self.proto_join_game_participant(pc.clone(), p.data.clone()))
+            .enumerate()
+            .map(|(process, p)| self.proto_join_game_participant(&pc, process, p.data.clone()))
             .collect();
+        let host_proto = self.proto_join_game_host(&pc);
+        self.ports = Some(pc);
+
+        // The host joins in the background, as joining blocks until all players have joined
+        let host_join = self.host.take().map(|mut host| {
+            thread::spawn(move || {
+                let response = host.sc2_query(host_proto)?;
+                assert!(response.has_join_game());
+                if response.get_join_game().has_error() {
+                    error!("Dedicated host could not join game: {:?}", response.get_join_game().get_error());
+                    return None;
+                }
+                Some(host)
+            })
+        });
+
+        for (player, proto) in self.players.iter_mut().zip(protos.iter()) {
+            player.sc2_request(proto.clone())?;
+        }
 
+        let retries = self.config.match_defaults.game.start_retries;
+        let mut responses = Vec::new();
         for (player, proto) in self.players.iter_mut().zip(protos) {
-            player.sc2_request(proto)?;
+            let mut attempt = 0;
+            let response = loop {
+                let response = player.sc2_recv()?;
+                assert!(response.has_join_game());
+                let resp_join_game = response.get_join_game();
+                if !resp_join_game.has_error() {
+                    debug!("Game join succesful");
+                    break response;
+                }
+
+                let error = resp_join_game.get_error();
+                if !is_transient_join_error(error) || attempt == retries {
+                    error!("Could not join game: {:?}", error);
+                    return None;
+                }
+                attempt += 1;
+                warn!("Could not join game: {:?}, retrying ({}/{})", error, attempt, retries);
+                thread::sleep(RETRY_BACKOFF * attempt);
+                player.sc2_request(proto.clone())?;
+            };
+
+            responses.push(response);
         }
 
-        for player in self.players.iter_mut() {
-            let response = player.sc2_recv()?;
-            assert!(response.has_join_game());
-            let resp_join_game = response.get_join_game();
-            if resp_join_game.has_error() {
-                error!("Could not join game: {:?}", resp_join_game.get_error());
+        if let Some(handle) = host_join {
+            self.host = Some(handle.join().ok()??);
+            debug!("Dedicated host joined succesfully");
+        }
+
+        // Responses are passed through only after everyone has joined, so that a failed start can be retried
+        let abort = self.config.match_defaults.game.abort_start_on_join_disconnect;
+        if abort {
+            if let Some(slot) = self.players.iter().position(|p| !p.is_connected()) {
+                error!("Participant {} disconnected while joining, aborting the start", slot);
                 return None;
-            } else {
-                debug!("Game join succesful");
             }
-
-            // No error, pass through the response
-            player.client_respond(response);
+        }
+        for (slot, (player, response)) in self.players.iter_mut().zip(responses).enumerate() {
+            player.data.player_id = Some(response.get_join_game().get_player_id());
+            if player.respond_join(response).is_none() {
+                if abort {
+                    error!("Participant {} disconnected while joining, aborting the start", slot);
+                    return None;
+                }
+                warn!("Participant {} disconnected while joining, starting without it", slot);
+            }
         }
 
         // TODO: Human players?
-        // TODO: Observers?
 
         Some(())
     }
 
     /// Start the game, and send responses to join requests
-    /// Returns None iff game create or join fails (connection close or sc2 process close)
-    /// In that case, the connections are dropped (closed).
+    /// Returns `Err(None)` if the lobby is not valid, and `Err(Some(self))` if game create or join
+    /// fails (connection close or sc2 process close), so that the start can be retried with `relaunch`.
+    /// If the error is dropped, the connections are dropped (closed).
+    pub fn start(mut self) -> Result<Game, Option<Box<Self>>> {
+        if let Err(problems) = self.is_valid() {
+            for problem in problems {
+                error!("Cannot start game: {}", problem);
+            }
+            return Err(None);
+        }
+
+        let game_config = &self.config.match_defaults.game;
+        let host_slot = if self.has_cpu_host() {
+            info!("Game hosted by the SC2 process of the computer players");
+            None
+        } else {
+            game_config
+                .host_selection
+                .host_slot(self.players.len(), game_config.random_seed)
+        };
+        if let Some(slot) = host_slot {
+            info!("Game hosted by the SC2 process of participant {}", slot);
+        }
+
+        if self.create_game(host_slot).is_none() || self.join_all_game().is_none() {
+            self.start_attempts += 1;
+            return Err(Some(Box::new(self)));
+        }
+        let post_join_delay = self.config.match_defaults.game.post_join_delay_ms.map(Duration::from_millis);
+        let game_info = if self.config.match_defaults.record_results.game_info {
+            self.fetch_game_info()
+        } else {
+            None
+        };
+        Ok(Game {
+            config: self.config,
+            players: self.players,
+            host: self.host,
+            host_slot,
+            slot_assignment: self.slot_assignment,
+            ports: self.ports,
+            external_id: self.external_id,
+            game_info,
+            post_join_delay,
+        })
+    }
+
+    /// Ask SC2 for the game info of the joined game, before the participants send any requests
+    fn fetch_game_info(&mut self) -> Option<ResponseGameInfo> {
+        let mut req = Request::new();
+        req.mut_game_info();
+        let mut response = self.players[0].sc2_query(req)?;
+        if response.has_game_info() {
+            Some(response.take_game_info())
+        } else {
+            warn!("Could not fetch game info: {:?}", response.get_error());
+            None
+        }
+    }
+
+    /// Replace the SC2 processes of a lobby whose start failed with new ones,
+    /// keeping the connections, computer players and failed attempt count.
+    /// The new lobby starts automatically when all its processes are ready.
+    pub fn relaunch(mut self) -> Self {
+        // The old dedicated host quits before its replacement is launched
+        if let Some(host) = self.host.take() {
+            host.close();
+        }
+        let mut lobby = Self::new(self.config.clone(), self.external_id.clone());
+        lobby.computer_players = self.computer_players.clone();
+        lobby.forced = self.forced;
+        lobby.start_attempts = self.start_attempts;
+        lobby.queue = self.queue.clone();
+        // Same order as `into_clients`
+        let datas = self.players.iter().map(|p| &p.data);
+        let datas = datas.chain(self.pending.iter().map(|p| &p.data));
+        let overrides: Vec<_> = datas.map(|data| data.process_options.clone()).collect();
+        let clients = self.into_clients();
+        lobby.start_when_full(clients.len() + lobby.computer_players.len());
+        for ((connection, join_req), process_options) in clients.into_iter().zip(overrides) {
+            lobby.join_with_options(connection, join_req, process_options);
+        }
+        lobby
+    }
+
+    /// Start a session on a dedicated SC2 process, relaying the client's first request,
+    /// e.g. create_game for client hosted games or start_replay for replay analysis.
+    /// The rest of the session is relayed like in a normal game.
+    /// Returns None iff the SC2 process cannot be launched, the first request fails,
+    /// or the client disconnects, closing the connection.
+    /// Blocks until SC2 is launched, so it's run in a start thread, see `spawn_dedicated`.
+    /// The launched process is registered with `abort`, so a stalled first request can be interrupted.
     #[must_use]
-    pub fn start(mut self) -> Option<Game> {
-        self.create_game()?;
-        self.join_all_game()?;
+    pub fn start_dedicated(
+        mut self, connection: ClientConnection, mut first_req: Request, abort: &AbortHandle,
+    ) -> Option<Game> {
+        assert!(self.players.is_empty());
+        Pipeline::new(&self.config.match_defaults).refine(&mut first_req, &RefineContext::default());
+        match PendingPlayer::new(self.config.clone(), connection, PlayerData::default()).launched() {
+            Ok(player) => {
+                match player.sc2_stream() {
+                    Ok(stream) => abort.register(stream),
+                    Err(e) => warn!("Could not clone the SC2 connection of a dedicated session: {}", e),
+                }
+                self.players.push(player);
+            },
+            Err(connection) => {
+                connection.close("Could not launch SC2");
+                return None;
+            },
+        }
+
+        let response = match self.players[0].sc2_query(first_req) {
+            Some(response) => response,
+            None => {
+                error!("SC2 did not respond to the first request of a dedicated session");
+                self.close("Could not start the session");
+                return None;
+            },
+        };
+
+        let failed = !response.get_error().is_empty()
+            || (response.has_create_game() && response.get_create_game().has_error())
+            || (response.has_start_replay() && response.get_start_replay().has_error())
+            || (response.has_replay_info() && response.get_replay_info().has_error());
+        if self.players[0].respond_join(response.clone()).is_none() {
+            warn!("Client disconnected while starting a dedicated session");
+            self.close("Client disconnected");
+            return None;
+        }
+        if failed {
+            error!("Could not start dedicated session: {:?}", response);
+            self.close("Could not start the session");
+            return None;
+        } else {
+            debug!("Dedicated session started succesfully");
+        }
+
         Some(Game {
             config: self.config,
             players: self.players,
+            host: None,
+            host_slot: Some(0),
+            slot_assignment: Vec::new(),
+            ports: None,
+            external_id: self.external_id,
+            game_info: None,
+            post_join_delay: None,
         })
     }
 
-    /// Destroy the lobby, closing all the connections
-    pub fn close(self) {}
+    /// Destroy the lobby, killing the processes and returning
+    /// the connections with their original join requests
+    pub fn into_clients(self) -> Vec<(ClientConnection, RequestJoinGame)> {
+        let players = self.players.into_iter().map(|p| {
+            let req = p.data.to_join_request();
+            (p.into_client(), req)
+        });
+        let pending = self.pending.into_iter().map(|p| {
+            let req = p.data.to_join_request();
+            (p.into_client(), req)
+        });
+        players.chain(pending).collect()
+    }
+
+    /// Handle that can be used to interrupt starting the game from another thread
+    /// Participants still launching at this point are not covered, unless registered later
+    pub fn abort_handle(&self) -> AbortHandle {
+        let streams = self.players.iter().map(Player::sc2_stream);
+        let streams = streams.chain(self.host.iter().map(Host::sc2_stream));
+        let streams = streams.filter_map(Result::ok).collect();
+        AbortHandle {
+            state: Arc::new(Mutex::new((streams, false))),
+        }
+    }
+
+    /// Destroy the lobby, asking the SC2 processes to quit before terminating them,
+    /// and closing all the connections with a Close frame giving `reason`
+    /// Waits for the SC2 processes still being launched, so that none of them are left running
+    pub fn close(self, reason: &str) {
+        for player in self.players {
+            player.close(reason);
+        }
+        for pending in self.pending {
+            pending.close(reason);
+        }
+        if let Some(host) = self.host {
+            host.close();
+        }
+        if let Some(pending_host) = self.pending_host {
+            pending_host.close();
+        }
+    }
+
+    /// Destroy the lobby like `close`, but return the connections
+    /// with their original join requests instead of closing them
+    pub fn cancel(self) -> Vec<(ClientConnection, RequestJoinGame)> {
+        if let Some(host) = self.host {
+            host.close();
+        }
+        if let Some(pending_host) = self.pending_host {
+            pending_host.close();
+        }
+        let players = self.players.into_iter().map(|p| {
+            let req = p.data.to_join_request();
+            (p.cancel(), req)
+        });
+        let pending = self.pending.into_iter().map(|p| {
+            let req = p.data.to_join_request();
+            (p.cancel(), req)
+        });
+        players.chain(pending).collect()
+    }
+}
+
+/// Interrupts the SC2 connections of a lobby, making a blocked start fail
+/// Clones share the connections, so the start thread can register processes it launches
+#[derive(Debug, Clone)]
+pub struct AbortHandle {
+    /// SC2 connections, and whether the start has been aborted
+    state: Arc<Mutex<(Vec<TcpStream>, bool)>>,
 }
+impl AbortHandle {
+    /// Shut down the SC2 connections
+    pub fn abort(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        for stream in &state.0 {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        state.1 = true;
+    }
 
-/// Used to pass player setup info to CreateGame
-enum CreateGamePlayer {
-    Participant,
-    Computer(Race, Difficulty),
-    Observer,
+    /// Add the SC2 connection of a process launched after the handle was created
+    /// The connection is shut down right away if the start was already aborted
+    pub fn register(&self, stream: TcpStream) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.1 {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        state.0.push(stream);
+    }
 }
-impl CreateGamePlayer {
-    fn to_proto(&self) -> sc2_proto::sc2api::PlayerSetup {
-        use sc2_proto::sc2api::{PlayerSetup, PlayerType};
-        let mut ps = PlayerSetup::new();
+
+/// Reason why a lobby cannot be started
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LobbyProblem {
+    /// The lobby has no participants
+    NoParticipants,
+    /// Fewer participants than the configured minimum
+    TooFewParticipants { required: usize, actual: usize },
+    /// More players, including computers, than the map has slots
+    OverCapacity { capacity: usize, players: usize },
+    /// Race overwrites don't match the number of participants
+    RaceOverwriteCount { overwrites: usize, participants: usize },
+    /// A participant requested an interface that is not allowed
+    InterfaceNotAllowed { slot: usize, interface: &'static str },
+    /// SC2 processes of some participants are still launching
+    StillLaunching { count: usize },
+    /// SC2 process of a participant did not answer to a ping
+    ProcessNotResponding { slot: usize },
+    /// Dedicated host SC2 process is still launching or could not be launched
+    HostNotReady,
+    /// Not enough free ports for the game between the SC2 processes
+    NoFreePorts { processes: usize },
+}
+impl fmt::Display for LobbyProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::Participant => {
-                ps.set_field_type(PlayerType::Participant);
+            Self::NoParticipants => write!(f, "The lobby has no participants"),
+            Self::TooFewParticipants { required, actual } => {
+                write!(f, "Requires at least {} participants, has {}", required, actual)
+            },
+            Self::OverCapacity { capacity, players } => {
+                write!(f, "Map has {} slots, but the game has {} players", capacity, players)
             },
-            Self::Computer(race, difficulty) => {
-                ps.set_field_type(PlayerType::Computer);
-                ps.set_race(race.to_proto());
-                ps.set_difficulty(difficulty.to_proto());
+            Self::RaceOverwriteCount {
+                overwrites,
+                participants,
+            } => write!(
+                f,
+                "Race overwrites configured for {} participants, but the game has {}",
+                overwrites, participants
+            ),
+            Self::InterfaceNotAllowed { slot, interface } => write!(
+                f,
+                "Participant {} requested the {} interface, which is not allowed",
+                slot, interface
+            ),
+            Self::StillLaunching { count } => {
+                write!(f, "SC2 processes of {} participants are still launching", count)
             },
-            Self::Observer => {
-                ps.set_field_type(PlayerType::Observer);
+            Self::ProcessNotResponding { slot } => {
+                write!(f, "SC2 process of participant {} is not responding", slot)
+            },
+            Self::HostNotReady => write!(f, "SC2 process of the dedicated host is not ready"),
+            Self::NoFreePorts { processes } => {
+                write!(f, "Not enough free ports for {} SC2 processes", processes)
             },
         }
-        ps
     }
 }
+
+/// Checks if a CreateGame error may go away by retrying, e.g. the map is not loaded yet
+fn is_transient_create_error(error: ResponseCreateGame_Error) -> bool {
+    error == ResponseCreateGame_Error::InvalidMapData
+}
+
+/// Checks if a JoinGame error may go away by retrying, e.g. the ports are not ready yet
+fn is_transient_join_error(error: ResponseJoinGame_Error) -> bool {
+    matches!(
+        error,
+        ResponseJoinGame_Error::LaunchError
+            | ResponseJoinGame_Error::CannotOpenMap
+            | ResponseJoinGame_Error::NetworkError
+    )
+}
+
+/// Player setup entry of CreateGame
+fn player_setup(slot: &SlotAssignment) -> sc2_proto::sc2api::PlayerSetup {
+    use sc2_proto::sc2api::{PlayerSetup, PlayerType};
+    let mut ps = PlayerSetup::new();
+    match slot {
+        SlotAssignment::Participant { .. } => {
+            ps.set_field_type(PlayerType::Participant);
+        },
+        SlotAssignment::Computer { race, difficulty } => {
+            ps.set_field_type(PlayerType::Computer);
+            ps.set_race(race.to_proto());
+            ps.set_difficulty(difficulty.to_proto());
+        },
+        SlotAssignment::Observer => {
+            ps.set_field_type(PlayerType::Observer);
+        },
+    }
+    ps
+}
@@ -1,20 +1,33 @@
 #![allow(dead_code)]
 
 use crossbeam::channel::{self, Receiver, Sender, TryRecvError};
+use log::warn;
 
-use crate::sc2::PlayerResult;
+use crate::proxy::ClientConnection;
+use crate::sc2::{PlayerResult, ScoreSnapshot, SessionStatus};
+use crate::supervisor::GameId;
 
 /// Request from the supervisor
 pub enum FromSupervisor {
     Quit,
+    /// Save the replay to a path, without ending the game
+    SaveReplay(String),
 }
 
 /// Response to the supervisor
-pub enum ToSupervisor {}
+pub enum ToSupervisor {
+    /// Status of the SC2 session of a player changed
+    PlayerStatus(usize, SessionStatus),
+    /// Score of a player from a new game loop
+    PlayerScore(usize, ScoreSnapshot),
+}
 
 /// Create one receiver for the game, send connections to players,
 /// and corresponding two-way connections to players
-pub fn create_channels(count: usize) -> (Receiver<ToGame>, Vec<ChannelToPlayer>, Vec<ChannelToGame>) {
+/// Players return their clients through `recovered` if the game is gone
+pub fn create_channels(
+    game_id: GameId, count: usize, recovered: &Sender<ClientConnection>,
+) -> (Receiver<ToGame>, Vec<ChannelToPlayer>, Vec<ChannelToGame>) {
     let mut to_player_channels = Vec::new();
     let mut to_game_channels = Vec::new();
 
@@ -25,9 +38,12 @@ pub fn create_channels(count: usize) -> (Receiver<ToGame>, Vec<ChannelToPlayer>,
         to_player_channels.push(ChannelToPlayer { tx });
 
         to_game_channels.push(ChannelToGame {
+            game_id,
             player_index,
             tx: tx_to_game.clone(),
             rx,
+            recovered: recovered.clone(),
+            game_lost: false,
         });
     }
 
@@ -36,19 +52,37 @@ pub fn create_channels(count: usize) -> (Receiver<ToGame>, Vec<ChannelToPlayer>,
 
 /// Channel from a player to the game
 pub struct ChannelToGame {
+    game_id: GameId,
     player_index: usize,
     tx: Sender<ToGame>,
     rx: Receiver<ToPlayer>,
+    /// Returns clients to the supervisor directly, when the game cannot collect them
+    recovered: Sender<ClientConnection>,
+    /// Whether `recv` has found the game gone
+    game_lost: bool,
 }
 impl ChannelToGame {
+    /// Id of the game
+    pub fn game_id(&self) -> GameId {
+        self.game_id
+    }
+
+    /// Index of the player in the game
+    pub fn player_index(&self) -> usize {
+        self.player_index
+    }
+
     /// Sends a message to the game
+    /// If the game is gone, the message is discarded, and
+    /// `recv` will return `ToPlayer::GameDisconnected`
     pub fn send(&mut self, content: ToGameContent) {
-        self.tx
-            .send(ToGame {
-                player_index: self.player_index,
-                content,
-            })
-            .expect("Unable to send to the game");
+        let msg = ToGame {
+            player_index: self.player_index,
+            content,
+        };
+        if self.tx.send(msg).is_err() {
+            warn!("Unable to send to the game, it has already ended");
+        }
     }
 
     /// Receives message from game, nonblocking: None if not available
@@ -56,7 +90,22 @@ impl ChannelToGame {
         match self.rx.try_recv() {
             Ok(msg) => Some(msg),
             Err(TryRecvError::Empty) => None,
-            Err(TryRecvError::Disconnected) => panic!("Disconnected"),
+            Err(TryRecvError::Disconnected) => {
+                self.game_lost = true;
+                Some(ToPlayer::GameDisconnected)
+            },
+        }
+    }
+
+    /// Checks if `recv` has found the game gone, e.g. because its thread panicked
+    pub fn is_game_lost(&self) -> bool {
+        self.game_lost
+    }
+
+    /// Return a client to the supervisor without going through the game
+    pub fn recover(&mut self, client: ClientConnection) {
+        if self.recovered.send(client).is_err() {
+            warn!("Unable to return the client, the supervisor is gone");
         }
     }
 }
@@ -69,7 +118,7 @@ pub struct ToGame {
 }
 
 /// Message from a player to the game
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ToGameContent {
     /// Game ended normally
     GameOver(Vec<PlayerResult>),
@@ -79,8 +128,18 @@ pub enum ToGameContent {
     QuitBeforeLeave,
     /// SC2 unexpectedly closed connection, usually user clicking the window close button
     SC2UnexpectedConnectionClose,
-    /// Client unexpectedly closed connection
-    UnexpectedConnectionClose,
+    /// Client unexpectedly closed connection,
+    /// with the game loop of the last observation, None if there was none yet
+    UnexpectedConnectionClose(Option<u32>),
+    /// Status of the SC2 session changed
+    StatusChanged(SessionStatus),
+    /// Score from an observation of a new game loop
+    Score(ScoreSnapshot),
+    /// Game reached the game loop limit, the player has left
+    TimeLimitReached,
+    /// SC2 responded with errors `max_consecutive_sc2_errors` times in a row,
+    /// or `request_limits.max_sc2_errors` times in total, the player has left
+    SC2ErrorLimitReached,
 }
 
 /// Channel from the game to a player
@@ -90,14 +149,22 @@ pub struct ChannelToPlayer {
 }
 impl ChannelToPlayer {
     /// Sends a message to the player
+    /// The player may have left the game at the same time, so this doesn't fail
     pub fn send(&mut self, content: ToPlayer) {
-        self.tx.send(content).expect("Unable to send to the game");
+        if self.tx.send(content).is_err() {
+            warn!("Unable to send to the player, it has already left the game");
+        }
     }
 }
 
 /// Message from a player to the game
 #[derive(Debug, Clone)]
 pub enum ToPlayer {
-    /// Game over, kill the client
+    /// Game over, leave the game and close the client
     Quit,
+    /// Save the replay to a path after the current request
+    SaveReplay(String),
+    /// Game thread has ended unexpectedly, e.g. by a panic.
+    /// Not sent by the game, but returned by the channel when it's disconnected.
+    GameDisconnected,
 }
@@ -2,21 +2,37 @@
 //! which in turn run own thread for each client
 
 mod game;
+mod host;
+mod latency;
 mod lobby;
 mod messaging;
 mod player;
+pub mod relay;
+mod stall;
 
 use crossbeam::channel::{self, Receiver, Sender, TryRecvError};
+use log::error;
+use sc2_proto::sc2api::Request;
 use std::any::Any;
 use std::thread;
+use std::time::{Duration, Instant};
 
 use self::player::Player;
+use crate::config::Config;
+use crate::proxy::ClientConnection;
+use crate::sc2::{ScoreSnapshot, SessionStatus};
+use crate::supervisor::GameId;
 
-pub use self::game::{Game, GameResult};
-pub use self::lobby::GameLobby;
+pub use self::game::{
+    Game, GameEndReason, GameResult, PlayerOutcomeDetail, ResultCategory, SlotAssignment, VoidReason,
+};
+pub use self::lobby::{AbortHandle, GameLobby, LobbyProblem};
+pub use self::latency::{LatencyHistogram, LatencyStats, LatencySummary};
+pub use self::player::{ApmStats, PlayerStats};
 pub use self::messaging::{FromSupervisor, ToSupervisor};
+pub use self::stall::StallDetector;
 
-fn any_panic_to_string(panic_msg: Box<Any>) -> String {
+pub(crate) fn any_panic_to_string(panic_msg: Box<Any>) -> String {
     panic_msg
         .downcast_ref::<String>()
         .unwrap_or(&"Panic message was not a String".to_owned())
@@ -32,20 +48,85 @@ pub struct Handle {
     /// Message connection sender
     msg_tx: Sender<FromSupervisor>,
     /// Message connection receiver
-    _msg_rx: Receiver<ToSupervisor>,
+    msg_rx: Receiver<ToSupervisor>,
+    /// Latest SC2 session status of each player, None if not known
+    /// Updated by `check`
+    statuses: Vec<Option<SessionStatus>>,
+    /// Latest score of each player, None before the first observation with a score
+    /// Updated by `check`
+    scores: Vec<Option<ScoreSnapshot>>,
     /// Result or error, if the game is over
     /// Updated by `poll`
     result: Option<Result<GameResult, ()>>,
+    /// Identifier given by an external system, if any
+    external_id: Option<String>,
+    /// Names of participants in join order, None if not given
+    player_names: Vec<Option<String>>,
+    /// Command lines of the SC2 processes of participants in join order
+    launch_commands: Vec<Vec<String>>,
+    /// Configuration the game was created with
+    config: Config,
+    /// When the game was started
+    started: Instant,
 }
 impl Handle {
+    /// Identifier given by an external system, if any
+    pub fn external_id(&self) -> Option<&str> {
+        self.external_id.as_deref()
+    }
+
     /// Send message to the game
     /// Panics if the game is not running, i.e. the channel is disconnected
     pub fn send(&mut self, msg: FromSupervisor) {
         self.msg_tx.send(msg).expect("Could not send");
     }
 
-    /// Checks if the game is over
+    /// Send message to the game
+    /// Returns None if the game has already ended
+    #[must_use]
+    pub fn try_send(&mut self, msg: FromSupervisor) -> Option<()> {
+        self.msg_tx.send(msg).ok()
+    }
+
+    /// Names of participants in join order, None if not given
+    pub fn player_names(&self) -> &[Option<String>] {
+        &self.player_names
+    }
+
+    /// Command lines of the SC2 processes of participants in join order
+    pub fn launch_commands(&self) -> &[Vec<String>] {
+        &self.launch_commands
+    }
+
+    /// Configuration the game was created with
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Time since the game was started
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Latest SC2 session status of each player in join order, None if not known
+    pub fn player_statuses(&self) -> &[Option<SessionStatus>] {
+        &self.statuses
+    }
+
+    /// Latest score of each player in join order, None before the first observation with a score
+    pub fn player_scores(&self) -> &[Option<ScoreSnapshot>] {
+        &self.scores
+    }
+
+    /// Checks if the game is over, and updates player statuses and scores
     pub fn check(&mut self) -> bool {
+        while let Ok(msg) = self.msg_rx.try_recv() {
+            match msg {
+                ToSupervisor::PlayerStatus(index, status) => self.statuses[index] = Some(status),
+                ToSupervisor::PlayerScore(index, score) => self.scores[index] = Some(score),
+            }
+        }
+
         match self.result_rx.try_recv() {
             Err(TryRecvError::Empty) => false,
             Ok(result) => {
@@ -81,18 +162,138 @@ impl Handle {
 }
 
 /// Run game in a thread, returning handle
-pub fn spawn(game: Game) -> Handle {
+/// If the game thread panics, the clients still connected are sent to `recovered`
+pub fn spawn(id: GameId, game: Game, recovered: Sender<ClientConnection>) -> Handle {
     let (result_tx, result_rx) = channel::unbounded::<GameResult>();
     let (fr_msg_tx, fr_msg_rx) = channel::unbounded::<FromSupervisor>();
     let (to_msg_tx, to_msg_rx) = channel::unbounded::<ToSupervisor>();
+    let external_id = game.external_id.clone();
+    let statuses = game.players.iter().map(Player::status).collect();
+    let scores = vec![None; game.players.len()];
+    let player_names = game.players.iter().map(|p| p.data.name.clone()).collect();
+    let launch_commands = game.players.iter().map(|p| p.launch_command().to_vec()).collect();
+    let config = game.config.clone();
 
-    let handle = thread::spawn(move || game.run(result_tx, fr_msg_rx, to_msg_tx));
+    let handle = thread::spawn(move || game.run(id, result_tx, fr_msg_rx, to_msg_tx, recovered));
 
     Handle {
         handle,
         result_rx,
         msg_tx: fr_msg_tx,
-        _msg_rx: to_msg_rx,
+        msg_rx: to_msg_rx,
+        statuses,
+        scores,
         result: None,
+        external_id,
+        player_names,
+        launch_commands,
+        config,
+        started: Instant::now(),
+    }
+}
+
+/// Handle for a game being created and joined in a thread
+pub struct StartHandle {
+    /// Handle for the start thread, returns the game if it was started,
+    /// or the lobby if the start can be retried
+    handle: thread::JoinHandle<Result<Game, Option<Box<GameLobby>>>>,
+    /// Interrupts the start
+    abort: AbortHandle,
+    /// When the start began
+    started: Instant,
+    /// Whether the start was aborted
+    aborted: bool,
+    /// Identifier given by an external system, if any
+    external_id: Option<String>,
+    /// Configuration of the lobby being started
+    config: Config,
+    /// Client id of a dedicated session being started, see `spawn_dedicated`
+    dedicated_client: Option<String>,
+}
+impl StartHandle {
+    /// Identifier given by an external system, if any
+    pub fn external_id(&self) -> Option<&str> {
+        self.external_id.as_deref()
+    }
+
+    /// Configuration of the lobby being started
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Time since the start began
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Client id of the connection, if this is a dedicated session
+    pub fn dedicated_client(&self) -> Option<&str> {
+        self.dedicated_client.as_deref()
+    }
+
+    /// Checks if the start thread has finished
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Abort the start, the thread finishes without a game
+    pub fn abort(&mut self) {
+        self.abort.abort();
+        self.aborted = true;
+    }
+
+    /// Checks if the start was aborted
+    pub fn is_aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Wait for the start to finish
+    /// Returns the game, or the lobby if it could not be started but the start can be retried
+    pub fn collect(self) -> Result<Game, Option<Box<GameLobby>>> {
+        match self.handle.join() {
+            Ok(result) => result,
+            Err(panic_msg) => {
+                error!("Game start panicked: {}", any_panic_to_string(panic_msg));
+                Err(None)
+            },
+        }
+    }
+}
+
+/// Start a game from lobby in a thread, returning handle
+pub fn spawn_start(lobby: GameLobby) -> StartHandle {
+    let abort = lobby.abort_handle();
+    let external_id = lobby.external_id().map(str::to_owned);
+    let config = lobby.config().clone();
+    let handle = thread::spawn(move || lobby.start());
+    StartHandle {
+        handle,
+        abort,
+        started: Instant::now(),
+        aborted: false,
+        external_id,
+        config,
+        dedicated_client: None,
+    }
+}
+
+/// Start a dedicated session, i.e. a client hosted game or a replay, in a thread, returning handle
+/// The start cannot be retried, and the connection is closed if it fails
+pub fn spawn_dedicated(lobby: GameLobby, connection: ClientConnection, first_req: Request) -> StartHandle {
+    let abort = lobby.abort_handle();
+    let thread_abort = abort.clone();
+    let external_id = lobby.external_id().map(str::to_owned);
+    let config = lobby.config().clone();
+    let dedicated_client = Some(connection.meta.peer_addr.clone());
+    let handle =
+        thread::spawn(move || lobby.start_dedicated(connection, first_req, &thread_abort).ok_or(None));
+    StartHandle {
+        handle,
+        abort,
+        started: Instant::now(),
+        aborted: false,
+        external_id,
+        config,
+        dedicated_client,
     }
 }
@@ -1,22 +1,42 @@
 //! Bot player participant
 
-use log::{debug, error, trace, warn};
+use log::{debug, error, info, trace, warn};
 use std::fmt;
-use std::io::ErrorKind::{ConnectionAborted, ConnectionReset};
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::io::ErrorKind::{ConnectionAborted, ConnectionReset, WouldBlock};
+use std::net::TcpStream;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use websocket::result::WebSocketError;
 use websocket::OwnedMessage;
 
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
 use protobuf::parse_from_bytes;
-use protobuf::{Message, RepeatedField};
-use sc2_proto::sc2api::{Request, RequestJoinGame, Response, Status};
+use protobuf::Message;
+use sc2_proto::sc2api::{Request, RequestJoinGame, RequestObservation, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, RecordConfig};
+use crate::delta::Encoder;
+use crate::proxy::{Client, ClientConnection};
+use crate::refine::RefineContext;
+use crate::sc2::{Race, SessionStatus};
+use crate::sc2process::{Process, ProcessOptions};
+
+use super::latency::{now, LatencyRecorder, LatencyStats};
+use super::messaging::{ChannelToGame, ToPlayer};
+use super::relay::{Engine, ObservationRate, RequestDecision, ResponseActions, SessionStep};
 
-use crate::config::Config;
-use crate::proxy::Client;
-use crate::sc2::{PlayerResult, Race};
-use crate::sc2process::Process;
+/// Maximum time to wait for the SC2 process to answer a ping
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
 
-use super::messaging::{ChannelToGame, ToGameContent, ToPlayer};
+/// Time SC2 has to confirm leaving a game before its process is killed
+const LEAVE_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Player process, connection and details
 pub struct Player {
@@ -25,25 +45,56 @@ pub struct Player {
     /// SC2 websocket connection
     sc2_ws: Client,
     /// Proxy connection to connected client
-    connection: Client,
-    /// Status of the connected sc2 process
-    sc2_status: Option<Status>,
+    connection: ClientConnection,
+    /// Whether the client disconnected while joining, after which its connection is treated as closed
+    client_failed: bool,
+    /// Status of the connected sc2 process, from the latest response
+    sc2_status: Option<SessionStatus>,
+    /// Last observation request and its response, valid until the next other request
+    obs_cache: Option<(RequestObservation, Response)>,
+    /// Delta encoder of observation responses, if the client asked for them in this game
+    obs_delta: Option<Encoder>,
+    /// Latency histograms, if `measure_latency` is set
+    latency: LatencyRecorder,
+    /// When the last request was forwarded to SC2 and its response arrived, if measured
+    sc2_marks: (Option<Instant>, Option<Instant>),
     /// Additonal data
     pub data: PlayerData,
 }
 
 impl Player {
-    /// Creates new player instance and initializes sc2 process for it
-    pub fn new(config: Config, connection: Client, data: PlayerData) -> Self {
-        let process = Process::new(config.process);
-        let sc2_ws = process.connect().expect("Could not connect");
-        Self {
-            process,
-            sc2_ws,
-            connection,
-            sc2_status: None,
-            data,
+    /// Command line the SC2 process was launched with
+    pub fn launch_command(&self) -> &[String] {
+        self.process.command()
+    }
+
+    /// Metadata given by the bot when connecting, see `ConnectionMeta::bot_metadata`
+    pub fn bot_metadata(&self) -> Option<&str> {
+        self.connection.meta.bot_metadata()
+    }
+
+    /// Checks that the client connection is still open, without consuming any data
+    pub fn is_connected(&self) -> bool {
+        !self.client_failed && is_connected(&self.connection)
+    }
+
+    /// Send the response to the join request, or to the first request of a dedicated session,
+    /// unless the client has disconnected while joining
+    /// Returns None if the client is gone, marking it as failed, so that its session ends
+    /// with an unexpected connection close as soon as it starts
+    #[must_use]
+    pub fn respond_join(&mut self, r: Response) -> Option<()> {
+        if !self.is_connected() {
+            self.client_failed = true;
+            return None;
+        }
+        let bytes = r.write_to_bytes().expect("Invalid protobuf message");
+        if let Err(e) = self.connection.send_message(&OwnedMessage::Binary(bytes)) {
+            warn!("Could not send join response to {}: {}", self.connection.meta.peer_addr, e);
+            self.client_failed = true;
+            return None;
         }
+        Some(())
     }
 
     /// Send message to the client
@@ -63,10 +114,25 @@ impl Player {
         ));
     }
 
+    /// Send the response of a forwarded request to the client,
+    /// as a delta frame if it's an observation and the client asked for them
+    fn client_forward(&mut self, r: &Response) {
+        match self.obs_delta.as_mut() {
+            Some(encoder) if r.has_observation() => {
+                let frame = encoder.encode(r.write_to_bytes().expect("Invalid protobuf message"));
+                self.client_send(&OwnedMessage::Binary(frame));
+            },
+            _ => self.client_respond(r.clone()),
+        }
+    }
+
     /// Receive a message from the client
     /// Returns None if the connection is already closed
     #[must_use]
     fn client_recv(&mut self) -> Option<OwnedMessage> {
+        if self.client_failed {
+            return None;
+        }
         trace!("Waiting for a message from the client");
         match self.connection.recv_message() {
             Ok(msg) => {
@@ -75,41 +141,59 @@ impl Player {
             },
             Err(WebSocketError::NoDataAvailable) => {
                 warn!(
-                    "Client {:?} closed connection unexpectedly (ws disconnect)",
-                    self.connection.peer_addr().expect("PeerAddr")
+                    "Client {} closed connection unexpectedly (ws disconnect)",
+                    self.connection.meta.peer_addr
                 );
                 None
             },
             Err(WebSocketError::IoError(ref e)) if e.kind() == ConnectionReset => {
                 warn!(
-                    "Client {:?} closed connection unexpectedly (connection reset)",
-                    self.connection.peer_addr().expect("PeerAddr")
+                    "Client {} closed connection unexpectedly (connection reset)",
+                    self.connection.meta.peer_addr
                 );
                 None
             },
             Err(WebSocketError::IoError(ref e)) if e.kind() == ConnectionAborted => {
                 warn!(
-                    "Client {:?} closed connection unexpectedly (connection abort)",
-                    self.connection.peer_addr().expect("PeerAddr")
+                    "Client {} closed connection unexpectedly (connection abort)",
+                    self.connection.meta.peer_addr
+                );
+                None
+            },
+            Err(err) => {
+                warn!(
+                    "Client {} closed connection unexpectedly ({:?})",
+                    self.connection.meta.peer_addr, err
                 );
                 None
             },
-            Err(err) => panic!("Could not receive: {:?}", err),
         }
     }
 
-    /// Get a protobuf request from the client
-    /// Returns None if the connection is already closed
+    /// Get a protobuf request from the client, with its arrival time if `MEASURE` is set
+    /// Returns None if the connection is already closed, or the client sent something
+    /// other than a protobuf request, which is handled like closing the connection
     #[must_use]
-    pub fn client_get_request(&mut self) -> Option<Request> {
-        match self.client_recv()? {
-            OwnedMessage::Binary(bytes) => {
-                let resp = parse_from_bytes::<Request>(&bytes).expect("Invalid protobuf message");
-                trace!("Request from the client: {:?}", resp);
-                Some(resp)
+    fn client_get_request<const MEASURE: bool>(&mut self) -> Option<(Request, Option<Instant>)> {
+        let msg = self.client_recv()?;
+        let arrived = now::<MEASURE>();
+        match msg {
+            OwnedMessage::Binary(bytes) => match parse_from_bytes::<Request>(&bytes) {
+                Ok(req) => {
+                    trace!("Request from the client: {:?}", req);
+                    Some((req, arrived))
+                },
+                Err(e) => {
+                    warn!("Invalid request from client {}: {}", self.connection.meta.peer_addr, e);
+                    None
+                },
             },
             OwnedMessage::Close(_) => None,
-            other => panic!("Expected binary message, got {:?}", other),
+            other => {
+                let peer_addr = &self.connection.meta.peer_addr;
+                warn!("Expected binary message from client {}, got {:?}", peer_addr, other);
+                None
+            },
         }
     }
 
@@ -133,11 +217,55 @@ impl Player {
     /// Returns None if the connection is already closed
     #[must_use]
     pub fn sc2_recv(&mut self) -> Option<Response> {
-        match self.sc2_ws.recv_message().ok()? {
-            OwnedMessage::Binary(bytes) => Some(parse_from_bytes::<Response>(&bytes).expect("Invalid data")),
-            OwnedMessage::Close(_) => None,
+        let msg = self.sc2_ws.recv_message().ok()?;
+        self.sc2_parse(msg)
+    }
+
+    /// Parse a protobuf response from sc2, recording the session status
+    /// Returns None if the message closes the connection
+    fn sc2_parse(&mut self, msg: OwnedMessage) -> Option<Response> {
+        let response = match msg {
+            OwnedMessage::Binary(bytes) => parse_from_bytes::<Response>(&bytes).expect("Invalid data"),
+            OwnedMessage::Close(_) => return None,
             other => panic!("Expected binary message, got {:?}", other),
+        };
+        if response.has_status() {
+            self.set_status(SessionStatus::from_proto(response.get_status()));
         }
+        Some(response)
+    }
+
+    /// Record the status of the SC2 session, logging transitions
+    fn set_status(&mut self, status: SessionStatus) {
+        if self.sc2_status != Some(status) {
+            debug!("SC2 status changed from {:?} to {:?}", self.sc2_status, status);
+            self.sc2_status = Some(status);
+        }
+    }
+
+    /// Status of the SC2 session from the latest response, None before any responses
+    pub fn status(&self) -> Option<SessionStatus> {
+        self.sc2_status
+    }
+
+    /// Clone of the SC2 websocket stream, can be used to interrupt the connection
+    pub fn sc2_stream(&self) -> io::Result<TcpStream> {
+        self.sc2_ws.stream_ref().try_clone()
+    }
+
+    /// Checks that the SC2 process answers to a ping in time
+    #[must_use]
+    pub fn sc2_ping(&mut self) -> bool {
+        let mut req = Request::new();
+        req.mut_ping();
+
+        let stream = self.sc2_ws.stream_ref();
+        stream.set_read_timeout(Some(PING_TIMEOUT)).expect("Could not set timeout");
+        let response = self.sc2_query(req);
+        let stream = self.sc2_ws.stream_ref();
+        stream.set_read_timeout(None).expect("Could not set timeout");
+
+        response.is_some_and(|r| r.has_ping())
     }
 
     /// Send a request to SC2 and return the reponse
@@ -148,84 +276,297 @@ impl Player {
         self.sc2_recv()
     }
 
-    /// Run game communication loop
-    /// Returns self it iff not disconnected, so that it can be returned to the playlist
+    /// Send a request to SC2 and return the reponse, recording in `sc2_marks`
+    /// when it was forwarded and when the response arrived if `MEASURE` is set
+    /// Returns None if the connection is already closed
     #[must_use]
-    pub fn run(mut self, config: Config, mut gamec: ChannelToGame) -> Option<Self> {
-        while let Some(req) = self.client_get_request() {
-            if !config.match_defaults.request_limits.is_request_allowed(&req) {
-                warn!("AC: Request denied");
-                let mut response = Response::new();
-                response.set_error(RepeatedField::from_vec(vec!["Proxy: Request denied".to_owned()]));
-                self.client_respond(response.clone());
+    fn sc2_query_measured<const MEASURE: bool>(&mut self, r: Request) -> Option<Response> {
+        let msg = OwnedMessage::Binary(r.write_to_bytes().expect("Invalid protobuf message"));
+        let forwarded = now::<MEASURE>();
+        self.sc2_send(&msg)?;
+        let msg = self.sc2_ws.recv_message().ok()?;
+        self.sc2_marks = (forwarded, now::<MEASURE>());
+        self.sc2_parse(msg)
+    }
+
+    /// Answer an observation request from the cache, or from SC2 caching the response
+    /// Returns the response, and whether it was answered from the cache
+    /// Returns None if the connection is already closed
+    #[must_use]
+    fn sc2_query_cached<const MEASURE: bool>(&mut self, req: Request) -> Option<(Response, bool)> {
+        // Requests waiting for a specific game loop are not repeated
+        let cacheable = req.has_observation() && !req.get_observation().has_game_loop();
+        if !cacheable {
+            self.obs_cache = None;
+            return Some((self.sc2_query_measured::<MEASURE>(req)?, false));
+        }
+
+        if let Some((cached_req, response)) = &self.obs_cache {
+            if cached_req == req.get_observation() {
+                return Some((response.clone(), true));
+            }
+        }
+
+        let obs_req = req.get_observation().clone();
+        let response = self.sc2_query_measured::<MEASURE>(req)?;
+        self.obs_cache = Some((obs_req, response.clone()));
+        Some((response, false))
+    }
+
+    /// Ask SC2 for the replay of the current game, and write it to `path`
+    fn save_replay(&mut self, path: &str, record: &RecordConfig) {
+        let mut req = Request::new();
+        req.mut_save_replay();
+        let response = match self.sc2_query(req) {
+            Some(d) => d,
+            None => {
+                error!("SC2 closed the connection while saving a replay");
+                return;
+            },
+        };
+
+        if !response.has_save_replay() {
+            error!("Could not save replay: {:?}", response.get_error());
+        } else {
+            let path = record.output_path(path);
+            match write_recording(&path, response.get_save_replay().get_data(), record.compress) {
+                Ok(()) => info!("Replay saved to {:?}", path),
+                Err(e) => error!("Could not write replay to {:?}: {}", path, e),
+            }
+        }
+    }
+
+    /// Run game communication loop
+    /// Returns self it iff not disconnected, so that it can be returned to the playlist,
+    /// and the request counters of the game
+    pub fn run(mut self, config: Config, mut gamec: ChannelToGame) -> (Option<Self>, PlayerStats) {
+        let ctx = RefineContext {
+            slot: gamec.player_index(),
+            player_name: self.data.name.clone(),
+        };
+        let mut engine = Engine::new(&config.match_defaults, ctx);
+        self.obs_delta = if config.match_defaults.game.allow_observation_delta {
+            self.connection.meta.observation_delta().map(Encoder::new)
+        } else {
+            None
+        };
+        let connected = if config.match_defaults.game.measure_latency {
+            self.relay::<true>(&mut engine, &config, &mut gamec)
+        } else {
+            self.relay::<false>(&mut engine, &config, &mut gamec)
+        };
+
+        if let Some(encoder) = self.obs_delta.take() {
+            info!(
+                "Observation delta frames of player {:?} saved {} bytes",
+                self.data.name,
+                encoder.bytes_saved()
+            );
+        }
+
+        let mut stats = engine.stats();
+        if config.match_defaults.game.measure_latency {
+            let latency = self.latency.stats();
+            info!(
+                "Proxy latency of player {:?} over {} requests, to SC2: {:?}, to client: {:?}",
+                self.data.name, latency.requests, latency.to_sc2, latency.to_client
+            );
+            stats.latency = Some(latency);
+        }
+
+        // Nobody collects the players of a game that is gone, so the client is returned directly
+        if connected && gamec.is_game_lost() {
+            if let Some(client) = self.extract_client() {
+                gamec.recover(client);
             }
+            return (None, stats);
+        }
+        (if connected { Some(self) } else { None }, stats)
+    }
+
+    /// Relay requests between the client and SC2 until the game is over,
+    /// executing the decisions of the engine
+    /// If `MEASURE` is set, the latency added by the proxy is recorded
+    /// Returns false if disconnected
+    fn relay<const MEASURE: bool>(
+        &mut self, engine: &mut Engine, config: &Config, gamec: &mut ChannelToGame,
+    ) -> bool {
+        let game_config = &config.match_defaults.game;
+        let use_cache = game_config.cache_observations && !game_config.is_realtime();
+        let mut obs_rate = game_config
+            .max_observations_per_sec
+            .map(|rate| ObservationRate::new(rate, game_config.is_realtime()));
+        while let Some((req, arrived)) = self.client_get_request::<MEASURE>() {
+            let req = match engine.on_request(req) {
+                RequestDecision::Forward(req) => req,
+                RequestDecision::Respond(response) => {
+                    self.client_respond(response);
+                    continue;
+                },
+            };
 
-            let response = match self.sc2_query(req) {
+            self.sc2_marks = (None, None);
+            let coalesced = obs_rate.as_mut().and_then(|r| r.coalesce(&req, Instant::now()));
+            let response = if let Some(previous) = coalesced {
+                Some((previous, true))
+            } else if use_cache {
+                self.sc2_query_cached::<MEASURE>(req)
+            } else {
+                self.sc2_query_measured::<MEASURE>(req).map(|r| (r, false))
+            };
+            let (response, cached) = match response {
                 Some(d) => d,
                 None => {
                     error!("SC2 unexpectedly closed the connection");
-                    gamec.send(ToGameContent::SC2UnexpectedConnectionClose);
-                    debug!("Killing the process");
-                    self.process.kill();
-                    return None;
+                    let actions = engine.on_sc2_closed();
+                    return self.execute(actions, gamec);
                 },
             };
-            self.sc2_status = Some(response.get_status());
 
-            // TODO: request refining, e.g. pathing gird fix
+            if let (Some(rate), false) = (obs_rate.as_mut(), cached) {
+                rate.on_response(&response, Instant::now());
+            }
 
-            self.client_respond(response.clone());
+            match engine.refine_response(&response) {
+                Some(refined) => self.client_forward(&refined),
+                None => self.client_forward(&response),
+            }
+            if MEASURE {
+                let (forwarded, sc2_responded) = self.sc2_marks;
+                self.latency.record(arrived, forwarded, sc2_responded, now::<MEASURE>());
+            }
 
-            if response.has_quit() {
-                debug!("SC2 is shutting down");
-                gamec.send(ToGameContent::QuitBeforeLeave);
-                debug!("Waiting for the process");
-                self.process.wait();
-                return None;
-            } else if response.has_leave_game() {
-                debug!("Client left the game");
-                gamec.send(ToGameContent::LeftGame);
-                return Some(self);
-            } else if response.has_observation() {
-                let obs = response.get_observation();
-                let obs_results = obs.get_player_result();
-                if !obs_results.is_empty() {
-                    // Game is over nad results available
-                    let mut results_by_id: Vec<(u32, PlayerResult)> = obs_results
-                        .iter()
-                        .map(|r| (r.get_player_id(), PlayerResult::from_proto(r.get_result())))
-                        .collect();
-                    results_by_id.sort();
-                    let results: Vec<_> = results_by_id.into_iter().map(|(_, v)| v).collect();
-                    gamec.send(ToGameContent::GameOver(results));
-                }
-                // TODO: config time_limit.game_loops
+            let actions = engine.on_response(&response, cached);
+            if !response.get_error().is_empty() && game_config.log_sc2_errors {
+                let ctx = engine.context();
+                warn!(
+                    "SC2 error in game {}, player {} ({}), game loop {}, {} request: {}",
+                    gamec.game_id(),
+                    ctx.slot,
+                    ctx.player_name.as_deref().unwrap_or("unnamed"),
+                    engine.game_loop(),
+                    engine.last_request().unwrap_or("unknown"),
+                    response.get_error().join("; ")
+                );
+            }
+            if actions.step != SessionStep::Continue {
+                return self.execute(actions, gamec);
+            }
+            for msg in actions.messages {
+                gamec.send(msg);
             }
 
             if let Some(msg) = gamec.recv() {
                 match msg {
                     ToPlayer::Quit => {
-                        debug!("Killing the process by request from the game");
-                        self.process.kill();
-                        return None;
+                        debug!("Ending the session by request from the game");
+                        self.shutdown_session();
+                        return false;
+                    },
+                    ToPlayer::SaveReplay(path) => {
+                        self.save_replay(&path, &config.match_defaults.record_results)
+                    },
+                    ToPlayer::GameDisconnected => {
+                        error!("Game ended unexpectedly, returning the client");
+                        return true;
                     },
                 }
             }
         }
 
         // Connection already closed
-        gamec.send(ToGameContent::UnexpectedConnectionClose);
-        debug!("Killing process after unexpected connection close");
-        self.process.kill();
-        None
+        debug!("Ending the session after unexpected connection close");
+        self.execute(engine.on_client_closed(), gamec)
     }
 
-    /// Terminate the process, and return the client
-    pub fn extract_client(mut self) -> Client {
-        assert_eq!(self.sc2_status, Some(Status::launched));
+    /// Send the messages of the engine to the game, and end the session as decided
+    /// Returns false if disconnected
+    fn execute(&mut self, actions: ResponseActions, gamec: &mut ChannelToGame) -> bool {
+        for msg in actions.messages {
+            gamec.send(msg);
+        }
+        match actions.step {
+            SessionStep::Continue => true,
+            SessionStep::WaitForExit => {
+                debug!("SC2 is shutting down, waiting for the process");
+                self.process.wait();
+                false
+            },
+            SessionStep::Leave => {
+                debug!("Client left the game");
+                true
+            },
+            SessionStep::Shutdown => {
+                self.shutdown_session();
+                false
+            },
+            SessionStep::Kill => {
+                debug!("Killing the process");
+                self.process.kill();
+                false
+            },
+        }
+    }
+
+    /// Ask SC2 to quit, then terminate the process, and close the client connection
+    /// with a Close frame giving `reason`
+    pub fn close(self, reason: &str) {
+        self.cancel().close(reason);
+    }
+
+    /// Ask SC2 to quit, then terminate the process, and return the client
+    pub fn cancel(mut self) -> ClientConnection {
+        self.process.quit(&mut self.sc2_ws);
+        self.connection
+    }
+
+    /// Terminate the process of a player that hasn't joined a game yet, and return the client
+    pub fn into_client(mut self) -> ClientConnection {
         self.process.kill();
         self.connection
     }
+
+    /// Leave the game on behalf of the client
+    /// Returns true if SC2 confirmed leaving in time
+    fn leave_game(&mut self) -> bool {
+        let mut req = Request::new();
+        req.mut_leave_game();
+
+        let stream = self.sc2_ws.stream_ref();
+        stream.set_read_timeout(Some(LEAVE_TIMEOUT)).expect("Could not set timeout");
+        let left = self.sc2_query(req).is_some_and(|r| r.has_leave_game());
+        let stream = self.sc2_ws.stream_ref();
+        let _ = stream.set_read_timeout(None);
+        left
+    }
+
+    /// End the SC2 session cleanly, leaving the game first if SC2 is still in one,
+    /// so that the replay is intact, and terminate the process
+    /// Returns false if SC2 did not confirm leaving, and was killed in the game
+    pub fn shutdown_session(&mut self) -> bool {
+        let in_game = self.sc2_status.is_some_and(SessionStatus::is_in_game);
+        let left = !in_game || {
+            debug!("Leaving the game on behalf of the client");
+            self.leave_game()
+        };
+        if !left {
+            warn!("SC2 did not confirm leaving the game, killing it");
+        }
+        self.process.kill();
+        left
+    }
+
+    /// Terminate the process, and return the client
+    /// If the client is still in a game, leaves it first, and returns None if SC2
+    /// does not confirm leaving, closing the connection
+    pub fn extract_client(mut self) -> Option<ClientConnection> {
+        if self.shutdown_session() {
+            Some(self.connection)
+        } else {
+            warn!("SC2 did not confirm leaving the game, dropping the client");
+            None
+        }
+    }
 }
 
 impl fmt::Debug for Player {
@@ -234,23 +575,214 @@ impl fmt::Debug for Player {
     }
 }
 
+/// Connected client, whose SC2 process is being launched in a background thread
+pub struct PendingPlayer {
+    /// Proxy connection to connected client
+    connection: ClientConnection,
+    /// Launcher thread, returns the process and its websocket connection
+    launch: thread::JoinHandle<Option<(Process, Client)>>,
+    /// Additonal data
+    pub data: PlayerData,
+}
+impl PendingPlayer {
+    /// Start launching the SC2 process
+    pub fn new(config: Config, connection: ClientConnection, data: PlayerData) -> Self {
+        Self {
+            connection,
+            launch: launch_sc2(config),
+            data,
+        }
+    }
+
+    /// Checks if the launch has finished, successfully or not
+    pub fn is_launched(&self) -> bool {
+        self.launch.is_finished()
+    }
+
+    /// Checks that the client connection is still open, without consuming any data
+    pub fn is_connected(&self) -> bool {
+        is_connected(&self.connection)
+    }
+
+    /// Wait for the launch to finish
+    /// Returns None if the process could not be started, closing the connection
+    pub fn into_player(self) -> Option<Player> {
+        self.launched().ok()
+    }
+
+    /// Wait for the launch to finish, then close like `Player::close`
+    pub fn close(self, reason: &str) {
+        match self.launched() {
+            Ok(player) => player.close(reason),
+            Err(connection) => connection.close(reason),
+        }
+    }
+
+    /// Wait for the launch to finish, then cancel like `Player::cancel`
+    pub fn cancel(self) -> ClientConnection {
+        match self.launched() {
+            Ok(player) => player.cancel(),
+            Err(connection) => connection,
+        }
+    }
+
+    /// Wait for the launch to finish
+    /// Returns the client if the process could not be started
+    pub(super) fn launched(self) -> Result<Player, ClientConnection> {
+        match self.launch.join() {
+            Ok(Some((process, sc2_ws))) => Ok(Player {
+                process,
+                sc2_ws,
+                connection: self.connection,
+                client_failed: false,
+                sc2_status: None,
+                obs_cache: None,
+                obs_delta: None,
+                latency: LatencyRecorder::default(),
+                sc2_marks: (None, None),
+                data: self.data,
+            }),
+            _ => {
+                error!("Could not launch SC2 process");
+                Err(self.connection)
+            },
+        }
+    }
+
+    /// Abandon the launch and return the client
+    /// The process is killed as soon as the launch finishes
+    pub fn into_client(self) -> ClientConnection {
+        self.connection
+    }
+}
+impl fmt::Debug for PendingPlayer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PendingPlayer {{ ... }}")
+    }
+}
+
+/// Write a replay or other recording to `path`, streaming it through a gzip encoder if `compress` is set
+/// Missing parent directories are created, e.g. for the directories of `replay_name_pattern`
+fn write_recording(path: &str, data: &[u8], compress: bool) -> io::Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    if compress {
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(data)?;
+        encoder.finish()?.flush()
+    } else {
+        file.write_all(data)?;
+        file.flush()
+    }
+}
+
+/// Launch an SC2 process and connect to it in a background thread
+pub(super) fn launch_sc2(config: Config) -> thread::JoinHandle<Option<(Process, Client)>> {
+    thread::spawn(move || {
+        let process = Process::new(config.process.clone());
+        let sc2_ws = connect_sc2(&process, &config)?;
+        Some((process, sc2_ws))
+    })
+}
+
+/// Connect to an SC2 process, setting the socket options from `proxy.socket`
+fn connect_sc2(process: &Process, config: &Config) -> Option<Client> {
+    let sc2_ws = process.connect()?;
+    if let Err(e) = config.proxy.socket.apply(sc2_ws.stream_ref()) {
+        warn!("Could not set socket options of the SC2 connection: {}", e);
+    }
+    Some(sc2_ws)
+}
+
+/// Checks that a client connection is still open, without consuming any data
+fn is_connected(connection: &Client) -> bool {
+    let stream = connection.stream_ref();
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let mut buf = [0u8; 1];
+    let connected = match stream.peek(&mut buf) {
+        Ok(0) => false,
+        Ok(_) => true,
+        Err(ref e) => e.kind() == WouldBlock,
+    };
+    stream.set_nonblocking(false).is_ok() && connected
+}
+
+/// Request counters of a player in a game
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlayerStats {
+    /// Requests sent to SC2
+    pub sc2_requests: u64,
+    /// Observation requests answered from the cache or coalesced by `max_observations_per_sec`,
+    /// saving a round-trip to SC2
+    pub cached_observations: u64,
+    /// Responses from SC2 with the error field set
+    pub sc2_errors: u64,
+    /// Debug texts and shapes removed for exceeding `max_debug_draw_per_step`
+    pub stripped_debug_draws: u64,
+    /// Latency added by the proxy, if `measure_latency` is set
+    #[serde(default)]
+    pub latency: Option<LatencyStats>,
+    /// Game loop of the last observation
+    #[serde(default)]
+    pub game_loop: u32,
+    /// Actions per minute, if `record_apm` is set
+    #[serde(default)]
+    pub apm: Option<ApmStats>,
+}
+
+/// Unit commands of a player, counted by game minute
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ApmStats {
+    /// Unit commands sent during the game
+    pub actions: u64,
+    /// Actions per game minute, on average
+    pub average_apm: u64,
+    /// Actions in the busiest game minute
+    pub peak_apm: u64,
+}
+
 /// Player data, like join parameters
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct PlayerData {
     pub race: Race,
+    /// Race in the original join request, before any overwrites
+    pub requested_race: Race,
     pub name: Option<String>,
     pub ifopts: sc2_proto::sc2api::InterfaceOptions,
+    /// SC2 player id, known after joining the game
+    pub player_id: Option<u32>,
+    /// Process options overriding the ones of the lobby, given with `AddToLobby`
+    pub process_options: Option<ProcessOptions>,
 }
 impl PlayerData {
     pub fn from_join_request(req: RequestJoinGame) -> Self {
+        let race = Race::from_proto(req.get_race());
         Self {
-            race: Race::from_proto(req.get_race()),
+            race,
+            requested_race: race,
             name: if req.has_player_name() {
                 Some(req.get_player_name().to_owned())
             } else {
                 None
             },
             ifopts: req.get_options().clone(),
+            player_id: None,
+            process_options: None,
+        }
+    }
+
+    /// Reconstruct a join request, without port configuration
+    pub fn to_join_request(&self) -> RequestJoinGame {
+        let mut req = RequestJoinGame::new();
+        req.set_race(self.requested_race.to_proto());
+        if let Some(name) = &self.name {
+            req.set_player_name(name.clone());
         }
+        req.set_options(self.ifopts.clone());
+        req
     }
 }
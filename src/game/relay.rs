@@ -0,0 +1,438 @@
+//! Decisions of the relay loop of a player, separated from its sockets, see `Player::run`
+
+use log::{info, warn};
+use std::time::{Duration, Instant};
+
+use protobuf::{parse_from_bytes, ProtobufError, RepeatedField};
+use sc2_proto::sc2api::{Action, Request, RequestAction, Response};
+
+use crate::config::{MatchConfig, RequestLimits};
+use crate::refine::{debug_draw_count, Pipeline, RefineContext};
+use crate::sc2::{request_kind, PlayerResult, ScoreSnapshot, SessionStatus};
+
+use super::player::{ApmStats, PlayerStats};
+
+pub use super::messaging::ToGameContent;
+
+/// What to do with a request from the client
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestDecision {
+    /// Forward the refined request to SC2
+    Forward(Request),
+    /// Respond to the client without contacting SC2
+    Respond(Response),
+}
+
+/// Refines client requests of a participant and the responses to them,
+/// and checks the requests against the request limits
+pub struct RequestFilter {
+    refiners: Pipeline,
+    limits: RequestLimits,
+    ctx: RefineContext,
+    /// Debug texts and shapes removed by the refiners so far
+    stripped_debug_draws: u64,
+}
+impl RequestFilter {
+    /// Filter using the refiners and request limits of a match
+    pub fn new(config: &MatchConfig, ctx: RefineContext) -> Self {
+        Self {
+            refiners: Pipeline::new(config),
+            limits: config.request_limits.clone(),
+            ctx,
+            stripped_debug_draws: 0,
+        }
+    }
+
+    /// Participant whose requests are filtered
+    pub fn context(&self) -> &RefineContext {
+        &self.ctx
+    }
+
+    /// Debug texts and shapes removed by the refiners so far
+    pub fn stripped_debug_draws(&self) -> u64 {
+        self.stripped_debug_draws
+    }
+
+    /// Refine a request, and decide whether it is forwarded or denied
+    pub fn decide(&mut self, mut req: Request) -> RequestDecision {
+        let draws = debug_draw_count(&req);
+        self.refiners.refine(&mut req, &self.ctx);
+        self.stripped_debug_draws += (draws - debug_draw_count(&req)) as u64;
+
+        if self.limits.is_request_allowed(&req) {
+            RequestDecision::Forward(req)
+        } else {
+            warn!("AC: Request denied");
+            let mut response = Response::new();
+            response.set_error(RepeatedField::from_vec(vec!["Proxy: Request denied".to_owned()]));
+            RequestDecision::Respond(response)
+        }
+    }
+
+    /// Refine a response before it is forwarded to the client
+    /// Returns None if no response refiners are active, so that the response is forwarded as is
+    pub fn refine_response(&mut self, response: &Response) -> Option<Response> {
+        if !self.refiners.refines_responses() {
+            return None;
+        }
+        let mut refined = response.clone();
+        self.refiners.refine_response(&mut refined, &self.ctx);
+        Some(refined)
+    }
+
+    /// Parse a serialized request from the client, and decide on it
+    /// Fails if the bytes are not a valid request
+    pub fn decide_bytes(&mut self, bytes: &[u8]) -> Result<RequestDecision, ProtobufError> {
+        Ok(self.decide(parse_from_bytes::<Request>(bytes)?))
+    }
+}
+
+/// What a response from SC2 means for the session
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseEvent {
+    /// SC2 is shutting down
+    Quit,
+    /// The client left the game
+    LeftGame,
+    /// Observation of a game loop, with the results if the game is over
+    Observation {
+        /// Game loop of the observation
+        game_loop: u32,
+        /// Results in player id order, None while the game is running
+        results: Option<Vec<PlayerResult>>,
+    },
+    /// Any other response
+    Other,
+}
+impl ResponseEvent {
+    /// Classify a response from SC2
+    pub fn from_response(response: &Response) -> Self {
+        if response.has_quit() {
+            ResponseEvent::Quit
+        } else if response.has_leave_game() {
+            ResponseEvent::LeftGame
+        } else if response.has_observation() {
+            let obs = response.get_observation();
+            let obs_results = obs.get_player_result();
+            let results = if obs_results.is_empty() {
+                None
+            } else {
+                let mut results_by_id: Vec<(u32, PlayerResult)> = obs_results
+                    .iter()
+                    .map(|r| (r.get_player_id(), PlayerResult::from_proto(r.get_result())))
+                    .collect();
+                results_by_id.sort();
+                Some(results_by_id.into_iter().map(|(_, v)| v).collect())
+            };
+            ResponseEvent::Observation {
+                game_loop: obs.get_observation().get_game_loop(),
+                results,
+            }
+        } else {
+            ResponseEvent::Other
+        }
+    }
+}
+
+/// What the relay loop does after handling a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionStep {
+    /// Wait for the next client request
+    Continue,
+    /// SC2 is shutting down, wait for the process to exit
+    WaitForExit,
+    /// The client left the game, and can be returned to the playlist
+    Leave,
+    /// End the session, leaving the game first
+    Shutdown,
+    /// SC2 closed the connection, kill the process
+    Kill,
+}
+
+/// Reaction to a response from SC2
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseActions {
+    /// Messages to the game, in sending order
+    pub messages: Vec<ToGameContent>,
+    /// Next step of the relay loop
+    pub step: SessionStep,
+}
+
+/// Decision logic of the relay loop of a participant, without any IO
+/// `Player::run` receives and sends the messages, and executes the decisions
+pub struct Engine {
+    requests: RequestFilter,
+    /// Game loop limit from `time_limits.game_loops`
+    time_limit: Option<u64>,
+    /// Consecutive SC2 error limit from `max_consecutive_sc2_errors`
+    error_limit: Option<u32>,
+    /// Total SC2 error limit from `request_limits.max_sc2_errors`
+    total_error_limit: Option<u64>,
+    /// SC2 error responses since the last successful one
+    consecutive_errors: u32,
+    stats: PlayerStats,
+    /// Status last reported to the game
+    reported_status: Option<SessionStatus>,
+    /// Game loop of the last observation
+    game_loop: u32,
+    /// Whether any observation has been received
+    observed: bool,
+    /// Game loop of the last score reported to the game
+    score_loop: Option<u32>,
+    /// Type of the last request forwarded to SC2
+    last_request: Option<&'static str>,
+    /// Action counts, if `record_apm` is set
+    apm: Option<ApmRecorder>,
+}
+impl Engine {
+    /// Engine for a participant of a match
+    pub fn new(config: &MatchConfig, ctx: RefineContext) -> Self {
+        Self {
+            requests: RequestFilter::new(config, ctx),
+            time_limit: config.time_limits.game_loops,
+            error_limit: config.game.max_consecutive_sc2_errors,
+            total_error_limit: config.request_limits.max_sc2_errors,
+            consecutive_errors: 0,
+            stats: PlayerStats::default(),
+            reported_status: None,
+            game_loop: 0,
+            observed: false,
+            score_loop: None,
+            last_request: None,
+            apm: if config.game.record_apm { Some(ApmRecorder::new()) } else { None },
+        }
+    }
+
+    /// Participant whose session is relayed
+    pub fn context(&self) -> &RefineContext {
+        self.requests.context()
+    }
+
+    /// Request counters so far, including the APM up to the last observation if recorded
+    pub fn stats(&self) -> PlayerStats {
+        let mut stats = self.stats;
+        stats.apm = self.apm.as_ref().map(|apm| apm.stats(self.game_loop));
+        stats
+    }
+
+    /// Game loop of the last observation
+    pub fn game_loop(&self) -> u32 {
+        self.game_loop
+    }
+
+    /// Type of the last request forwarded to SC2, None before any
+    pub fn last_request(&self) -> Option<&'static str> {
+        self.last_request
+    }
+
+    /// Decide on a request from the client
+    pub fn on_request(&mut self, req: Request) -> RequestDecision {
+        let decision = self.requests.decide(req);
+        self.stats.stripped_debug_draws = self.requests.stripped_debug_draws();
+        if let RequestDecision::Forward(req) = &decision {
+            self.last_request = Some(request_kind(req));
+            if let (Some(apm), true) = (self.apm.as_mut(), req.has_action()) {
+                apm.record(self.game_loop, action_count(req.get_action()));
+            }
+        }
+        decision
+    }
+
+    /// Response as forwarded to the client, see `RequestFilter::refine_response`
+    /// The engine itself reacts to the unrefined response in `on_response`
+    pub fn refine_response(&mut self, response: &Response) -> Option<Response> {
+        self.requests.refine_response(response)
+    }
+
+    /// React to the response to a forwarded request, after it was sent to the client
+    /// `cached` tells if the response was answered from the observation cache,
+    /// or with a coalesced observation, instead of SC2
+    pub fn on_response(&mut self, response: &Response, cached: bool) -> ResponseActions {
+        if cached {
+            self.stats.cached_observations += 1;
+        } else {
+            self.stats.sc2_requests += 1;
+        }
+        if response.get_error().is_empty() {
+            self.consecutive_errors = 0;
+        } else {
+            self.stats.sc2_errors += 1;
+            self.consecutive_errors += 1;
+            let consecutive = self.error_limit.is_some_and(|limit| self.consecutive_errors >= limit);
+            let total = self.total_error_limit.is_some_and(|limit| self.stats.sc2_errors >= limit);
+            if consecutive || total {
+                warn!(
+                    "Ending the game after {} SC2 errors, {} of them consecutive",
+                    self.stats.sc2_errors, self.consecutive_errors
+                );
+                return ResponseActions {
+                    messages: vec![ToGameContent::SC2ErrorLimitReached],
+                    step: SessionStep::Shutdown,
+                };
+            }
+        }
+
+        let mut messages = Vec::new();
+        if response.has_status() {
+            let status = SessionStatus::from_proto(response.get_status());
+            if self.reported_status != Some(status) {
+                self.reported_status = Some(status);
+                messages.push(ToGameContent::StatusChanged(status));
+            }
+        }
+
+        let step = match ResponseEvent::from_response(response) {
+            ResponseEvent::Quit => {
+                messages.push(ToGameContent::QuitBeforeLeave);
+                SessionStep::WaitForExit
+            },
+            ResponseEvent::LeftGame => {
+                messages.push(ToGameContent::LeftGame);
+                SessionStep::Leave
+            },
+            ResponseEvent::Observation { game_loop, results } => {
+                self.game_loop = game_loop;
+                self.stats.game_loop = game_loop;
+                self.observed = true;
+                // Repeated observations of the same game loop have the same score
+                if self.score_loop != Some(game_loop) {
+                    let obs = response.get_observation().get_observation();
+                    if let Some(score) = ScoreSnapshot::from_proto(obs) {
+                        self.score_loop = Some(game_loop);
+                        messages.push(ToGameContent::Score(score));
+                    }
+                }
+                if let Some(results) = results {
+                    messages.push(ToGameContent::GameOver(results));
+                }
+                if self.time_limit.is_some_and(|limit| u64::from(game_loop) >= limit) {
+                    info!("Time limit reached on game loop {}", game_loop);
+                    messages.push(ToGameContent::TimeLimitReached);
+                    SessionStep::Shutdown
+                } else {
+                    SessionStep::Continue
+                }
+            },
+            ResponseEvent::Other => SessionStep::Continue,
+        };
+        ResponseActions { messages, step }
+    }
+
+    /// React to SC2 closing the connection instead of responding to a forwarded request
+    pub fn on_sc2_closed(&mut self) -> ResponseActions {
+        self.stats.sc2_requests += 1;
+        ResponseActions {
+            messages: vec![ToGameContent::SC2UnexpectedConnectionClose],
+            step: SessionStep::Kill,
+        }
+    }
+
+    /// React to the client closing the connection instead of sending a request
+    pub fn on_client_closed(&mut self) -> ResponseActions {
+        let game_loop = if self.observed { Some(self.game_loop) } else { None };
+        ResponseActions {
+            messages: vec![ToGameContent::UnexpectedConnectionClose(game_loop)],
+            step: SessionStep::Shutdown,
+        }
+    }
+}
+
+/// Game loops in a game minute, at the faster game speed used by the API
+const LOOPS_PER_MINUTE: u32 = 1344;
+
+/// Checks if an action commands units, as opposed to e.g. moving the camera or chatting
+fn is_unit_command(action: &Action) -> bool {
+    action.get_action_raw().has_unit_command()
+        || action.get_action_feature_layer().has_unit_command()
+        || action.get_action_render().has_unit_command()
+}
+
+/// Number of unit commands in an action request, counted for APM
+pub fn action_count(req: &RequestAction) -> u64 {
+    req.get_actions().iter().filter(|a| is_unit_command(a)).count() as u64
+}
+
+/// Counts the actions of a player by game minute, see `GameConfig::record_apm`
+#[derive(Debug, Clone, Default)]
+pub struct ApmRecorder {
+    /// Actions sent during each game minute
+    minutes: Vec<u64>,
+}
+impl ApmRecorder {
+    /// Recorder without any actions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count `actions` sent when the last observation was of `game_loop`
+    pub fn record(&mut self, game_loop: u32, actions: u64) {
+        let minute = (game_loop / LOOPS_PER_MINUTE) as usize;
+        if self.minutes.len() <= minute {
+            self.minutes.resize(minute + 1, 0);
+        }
+        self.minutes[minute] += actions;
+    }
+
+    /// Totals of a game that lasted until `game_loop`
+    /// The average is taken over every started game minute
+    pub fn stats(&self, game_loop: u32) -> ApmStats {
+        let actions: u64 = self.minutes.iter().sum();
+        let minutes = self.minutes.len().max((game_loop / LOOPS_PER_MINUTE) as usize + 1);
+        ApmStats {
+            actions,
+            average_apm: actions / minutes as u64,
+            peak_apm: self.minutes.iter().copied().max().unwrap_or(0),
+        }
+    }
+}
+
+/// Coalesces observation requests arriving faster than `max_observations_per_sec`,
+/// answering them with the previous observation instead of asking SC2
+/// In step mode, observations are only coalesced until the next step or other request,
+/// so that the client never misses a game loop it stepped to
+pub struct ObservationRate {
+    /// Minimum time between observations forwarded to SC2
+    interval: Duration,
+    realtime: bool,
+    /// Latest observation from SC2, and when it arrived
+    last: Option<(Instant, Response)>,
+    /// Whether the game may have advanced in step mode since the latest observation
+    stepped: bool,
+}
+impl ObservationRate {
+    /// Rate limit of `per_sec` observations per second, at least one
+    pub fn new(per_sec: u32, realtime: bool) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / per_sec.max(1),
+            realtime,
+            last: None,
+            stepped: false,
+        }
+    }
+
+    /// Previous observation to answer a request arriving at `now` with, if it's coalesced
+    /// Requests waiting for a specific game loop are never coalesced
+    pub fn coalesce(&mut self, req: &Request, now: Instant) -> Option<Response> {
+        if !req.has_observation() {
+            self.stepped = true;
+            return None;
+        }
+        if req.get_observation().has_game_loop() || (self.stepped && !self.realtime) {
+            return None;
+        }
+        let (arrived, response) = self.last.as_ref()?;
+        if now.saturating_duration_since(*arrived) < self.interval {
+            Some(response.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Record a response from SC2 arriving at `now`
+    pub fn on_response(&mut self, response: &Response, now: Instant) {
+        if response.has_observation() {
+            self.last = Some((now, response.clone()));
+            self.stepped = false;
+        }
+    }
+}
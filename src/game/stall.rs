@@ -0,0 +1,75 @@
+//! Detection of stalemates from the scores of the participants, see `TimeLimits::stall_detection`
+
+use crate::config::{StalemateOutcome, StallDetection};
+use crate::sc2::{PlayerResult, ScoreSnapshot};
+
+/// Detects games where no participant makes progress, i.e. where neither the army value
+/// nor the value of structures of any participant changes more than the thresholds
+/// during the window. Fed with the scores of the observations of each participant.
+#[derive(Debug, Clone)]
+pub struct StallDetector {
+    rules: StallDetection,
+    /// Score of each participant when it last made progress, None before its first score
+    baselines: Vec<Option<ScoreSnapshot>>,
+    /// Latest score of each participant, None before its first score
+    latest: Vec<Option<ScoreSnapshot>>,
+}
+impl StallDetector {
+    /// Create a detector for a game with `players` participants
+    pub fn new(rules: StallDetection, players: usize) -> Self {
+        Self {
+            rules,
+            baselines: vec![None; players],
+            latest: vec![None; players],
+        }
+    }
+
+    /// Checks if the score differs enough from the baseline to count as progress
+    fn is_progress(&self, baseline: &ScoreSnapshot, score: &ScoreSnapshot) -> bool {
+        (score.army_value - baseline.army_value).abs() > self.rules.army_value_threshold
+            || (score.structures_value - baseline.structures_value).abs()
+                > self.rules.structures_value_threshold
+    }
+
+    /// Record a score of a participant, returns true if the game is stalled after it
+    pub fn record(&mut self, player_index: usize, score: &ScoreSnapshot) -> bool {
+        let progress = match &self.baselines[player_index] {
+            Some(baseline) => self.is_progress(baseline, score),
+            None => true,
+        };
+        if progress {
+            self.baselines[player_index] = Some(*score);
+        }
+        self.latest[player_index] = Some(*score);
+        self.is_stalled()
+    }
+
+    /// Checks if every participant has gone the whole window without progress
+    pub fn is_stalled(&self) -> bool {
+        let window = self.rules.stall_window_loops;
+        self.baselines.iter().zip(&self.latest).all(|(baseline, latest)| match (baseline, latest) {
+            (Some(baseline), Some(latest)) => latest.game_loop.saturating_sub(baseline.game_loop) >= window,
+            _ => false,
+        })
+    }
+
+    /// Results of the participants in join order when the game ends as a stalemate
+    pub fn results(&self) -> Vec<PlayerResult> {
+        let scores: Vec<Option<i32>> = self.latest.iter().map(|s| s.map(|s| s.score)).collect();
+        let best = scores.iter().flatten().copied().max();
+        let best_count = scores.iter().filter(|&&s| s.is_some() && s == best).count();
+        scores
+            .iter()
+            .map(|&score| match self.rules.outcome {
+                StalemateOutcome::ScoreTiebreak if best_count == 1 => {
+                    if score == best {
+                        PlayerResult::Victory
+                    } else {
+                        PlayerResult::Defeat
+                    }
+                },
+                _ => PlayerResult::Tie,
+            })
+            .collect()
+    }
+}
@@ -9,23 +9,37 @@
 use crossbeam::channel::{self, TryRecvError};
 use log::{error, info, warn};
 use std::env::var;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::prelude::*;
+use std::path::Path;
 use std::thread;
 
+mod error;
 mod game;
 mod paths;
-mod portconfig;
-mod proxy;
 mod sc2process;
 
+pub mod bench_support;
 pub mod config;
+pub mod delta;
+pub mod logging;
 pub mod maps;
+pub mod portconfig;
+pub mod proxy;
+pub mod refine;
 pub mod remote_control;
+pub mod results;
 pub mod sc2;
+pub mod selftest;
 pub mod supervisor;
 
-use self::config::Config;
+pub use self::game::relay;
+pub use self::error::Error;
+pub use self::logging::init_logging;
+
+use self::config::{Config, RemoteController};
+use self::remote_control::federation::UpstreamLink;
+use self::remote_control::Remote;
 use self::supervisor::{RemoteUpdateStatus, Supervisor};
 
 /// Default config file path
@@ -55,7 +69,7 @@ pub fn load_config(path: String) -> Option<Config> {
 }
 
 /// Run a proxy server, loading the config any available
-pub fn run_server(config_path: Option<String>) {
+pub fn run_server(config_path: Option<String>) -> Result<(), Error> {
     let path = config_path.unwrap_or_else(|| default_config_path());
     let config = load_config(path).unwrap_or_else(|| {
         warn!("Config file not found, using default config");
@@ -64,30 +78,96 @@ pub fn run_server(config_path: Option<String>) {
     run_server_config(config)
 }
 
+/// Start the remote controller listener again if it's disabled and the enable flag file exists,
+/// removing the file
+fn enable_requested_remote(config: &RemoteController) -> Option<Remote> {
+    let flag = Path::new(config.enable_flag_path.as_ref()?);
+    if !flag.exists() {
+        return None;
+    }
+    if let Err(e) = fs::remove_file(flag) {
+        error!("Could not remove remote controller enable flag {:?}: {}", flag, e);
+        return None;
+    }
+    match remote_control::run_server(&config.addr(), config.audit_log()) {
+        Ok(remote) => {
+            info!("Remote controller listener enabled by flag file");
+            Some(remote)
+        },
+        Err(e) => {
+            error!("Could not bind remote controller listener to {}: {}", config.addr(), e);
+            None
+        },
+    }
+}
+
+/// Start a new remote controller listener on the address of one that stopped unexpectedly
+fn restart_remote(remote: Remote, config: &RemoteController) -> Option<Remote> {
+    let addr = remote.addr().to_owned();
+    warn!("Remote controller listener stopped unexpectedly, restarting it on {}", addr);
+    if remote.handle.join().is_err() {
+        error!("Remote controller listener thread panicked");
+    }
+    match remote_control::run_server(&addr, config.audit_log()) {
+        Ok(remote) => Some(remote),
+        Err(e) => {
+            error!("Could not bind remote controller listener to {}: {}", addr, e);
+            None
+        },
+    }
+}
+
+/// Request a running proxy to start its disabled remote controller listener again,
+/// by creating the enable flag file set in `config`
+pub fn request_remote_enable(config: &Config) -> Result<(), String> {
+    let path = config
+        .remote_controller
+        .enable_flag_path
+        .as_ref()
+        .ok_or("remote_controller.enable_flag_path is not set in the config")?;
+    File::create(path).map_err(|e| format!("Could not create {:?}: {}", path, e))?;
+    Ok(())
+}
+
 /// Run a proxy server using `config`
-pub fn run_server_config(config: Config) {
+/// Returns an error if the config is not usable, see `Config::check_startup`,
+/// or if the listeners cannot be bound
+pub fn run_server_config(config: Config) -> Result<(), Error> {
+    config.check_startup().map_err(Error::Config)?;
+
     let (proxy_sender, proxy_receiver) = channel::unbounded();
 
+    let addr = config.proxy.addr();
+    let server = proxy::bind(&addr, config.proxy.listen_backlog).map_err(|source| Error::Bind {
+        listener: "proxy",
+        addr,
+        source,
+    })?;
+
     let mut remote = if config.remote_controller.enabled {
-        Some(remote_control::run_server(&config.remote_controller.addr()))
-    } else if config.matchmaking.mode == self::config::MatchmakingMode::RemoteController {
-        error!("Remote controller disabled in config, but required for matchmaking");
-        return;
+        let addr = config.remote_controller.addr();
+        let audit_log = config.remote_controller.audit_log();
+        Some(remote_control::run_server(&addr, audit_log).map_err(|source| Error::Bind {
+            listener: "remote controller",
+            addr,
+            source,
+        })?)
     } else {
         None
     };
 
-    let addr = config.proxy.addr();
     thread::spawn(move || {
-        proxy::run(&addr, proxy_sender);
+        proxy::run(server, proxy_sender);
     });
 
+    let remote_config = config.remote_controller.clone();
+    let mut upstream = config.upstream.clone().map(UpstreamLink::connect);
     let mut sv = Supervisor::new(config);
 
     loop {
         match proxy_receiver.try_recv() {
-            Ok(client) => {
-                sv.add_client(client);
+            Ok(conn) => {
+                sv.add_connection(conn);
             },
             Err(TryRecvError::Empty) => {},
             Err(TryRecvError::Disconnected) => break,
@@ -95,13 +175,39 @@ pub fn run_server_config(config: Config) {
 
         sv.update_playlist();
 
+        sv.update_lobbies();
+
         sv.update_games();
 
+        if let Some(ref mut link) = upstream {
+            sv.update_upstream(link);
+        }
+
+        if remote.as_ref().is_some_and(Remote::is_closed) {
+            remote = restart_remote(remote.take().unwrap(), &remote_config);
+        }
+
         if let Some(ref mut r) = remote {
-            if sv.update_remote(r) == RemoteUpdateStatus::Quit {
-                sv.close();
-                break;
+            match sv.update_remote(r) {
+                RemoteUpdateStatus::Quit => {
+                    sv.close();
+                    break;
+                },
+                RemoteUpdateStatus::Disabled => {
+                    info!("Remote controller listener closed");
+                    remote.take().unwrap().handle.join().unwrap();
+                },
+                RemoteUpdateStatus::Processed | RemoteUpdateStatus::NoAction => {},
             }
+        } else if let Some(r) = enable_requested_remote(&remote_config) {
+            remote = Some(r);
+        }
+
+        if sv.is_drained() {
+            info!("Drained, quitting");
+            sv.close();
+            // The remote controller thread is still waiting for requests, so it's not joined
+            return Ok(());
         }
 
         thread::sleep(::std::time::Duration::from_millis(100));
@@ -112,4 +218,6 @@ pub fn run_server_config(config: Config) {
     if let Some(r) = remote {
         r.handle.join().unwrap();
     }
+
+    Ok(())
 }
@@ -0,0 +1,165 @@
+//! Log output of the proxy: stderr, and optionally a file rotated by size
+
+use log::{Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+
+use crate::config::LoggingConfig;
+use crate::Error;
+
+/// Append-only file, rotated to `<path>.1` .. `<path>.<keep>` when it would grow over `max_bytes`.
+/// The file is opened on the first write, and again after a failed write.
+#[derive(Debug)]
+pub(crate) struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: Option<File>,
+    size: u64,
+}
+impl RotatingFile {
+    pub(crate) fn new(path: impl Into<PathBuf>, max_bytes: u64, keep: usize) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes,
+            keep,
+            file: None,
+            size: 0,
+        }
+    }
+
+    /// Path of the file, for error messages
+    pub(crate) fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Append a line, flushing it immediately
+    pub(crate) fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let result = self.try_write_line(line);
+        if result.is_err() {
+            self.file = None;
+        }
+        result
+    }
+
+    fn try_write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.file = None;
+            self.rotate()?;
+            self.open()?;
+        }
+
+        let file = self.file.as_mut().unwrap();
+        file.write_all(line)?;
+        file.flush()?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Open the file for appending
+    pub(crate) fn open(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Shift the rotated files by one, dropping the oldest
+    fn rotate(&self) -> io::Result<()> {
+        if self.keep == 0 {
+            return fs::remove_file(&self.path);
+        }
+        for i in (1..self.keep).rev() {
+            let from = self.rotated(i);
+            if from.exists() {
+                fs::rename(from, self.rotated(i + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))
+    }
+
+    /// Path of the `n`th newest rotated file
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+}
+
+/// Logs to stderr and to the log file, using the filter from the `RUST_LOG` environment variable
+struct Logger {
+    /// Formats and filters stderr output
+    stderr: env_logger::Logger,
+    /// Whether to write to stderr
+    use_stderr: bool,
+    file: Option<Mutex<RotatingFile>>,
+}
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.stderr.matches(record) {
+            return;
+        }
+        if self.use_stderr {
+            self.stderr.log(record);
+        }
+        if let Some(file) = &self.file {
+            let line = format!(
+                "{} {:<5} {} > {}\n",
+                humantime::format_rfc3339_seconds(SystemTime::now()),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            let mut file = file.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Err(e) = file.write_line(line.as_bytes()) {
+                // The logger cannot log its own errors
+                eprintln!("Could not write to the log file {:?}: {}", file.path(), e);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+    }
+}
+
+/// Install the global logger, for running the proxy with `run_server_config`.
+/// Without a log file, everything is logged to stderr, like `pretty_env_logger`.
+/// Returns an error if the log file cannot be opened, or a logger is already installed.
+pub fn init_logging(config: &LoggingConfig) -> Result<(), Error> {
+    let mut builder = pretty_env_logger::formatted_builder();
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse(&filters);
+    }
+    let stderr = builder.build();
+
+    let file = match &config.file {
+        Some(path) => {
+            let mut file = RotatingFile::new(path, config.max_size_mb * 1024 * 1024, config.keep_files);
+            file.open()
+                .map_err(|e| Error::Logging(format!("Could not open {:?}: {}", path, e)))?;
+            Some(Mutex::new(file))
+        },
+        None => None,
+    };
+
+    let logger = Logger {
+        use_stderr: file.is_none() || io::stderr().is_terminal(),
+        file,
+        stderr,
+    };
+    let max_level = logger.stderr.filter();
+    log::set_boxed_logger(Box::new(logger)).map_err(|e| Error::Logging(e.to_string()))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
@@ -1,21 +1,59 @@
-use sc2_proxy::run_server;
+use sc2_proxy::config::Config;
+use sc2_proxy::{
+    default_config_path, init_logging, load_config, request_remote_enable, run_server_config, selftest,
+};
 
 use std::env;
 
 use dotenv::dotenv;
-use pretty_env_logger;
+use log::warn;
 
 fn main() -> Result<(), String> {
     dotenv().ok();
-    pretty_env_logger::init();
 
-    let args: Vec<_> = env::args().skip(1).collect();
+    let mut args: Vec<_> = env::args().skip(1).collect();
+    let enable_remote = args.first().map(String::as_str) == Some("--enable-remote");
+    let selftest = args.first().map(String::as_str) == Some("--selftest");
+    if enable_remote || selftest {
+        args.remove(0);
+    }
 
     if args.len() > 1 {
-        println!("Usage: {} [config.toml]", env::args().nth(0).unwrap());
-        Err("Too many arguments".to_owned())
+        println!(
+            "Usage: {} [--enable-remote | --selftest] [config.toml]",
+            env::args().nth(0).unwrap()
+        );
+        return Err("Too many arguments".to_owned());
+    }
+
+    // The config is loaded before logging is set up, as it contains the logging options
+    let path = args.first().cloned().unwrap_or_else(default_config_path);
+    let config = load_config(path);
+    let logging = config.as_ref().map(|c| c.logging.clone()).unwrap_or_default();
+    init_logging(&logging).map_err(|e| e.to_string())?;
+
+    if enable_remote {
+        // Tell the running proxy to start its remote controller listener again
+        let config = config.ok_or("Config file not found")?;
+        request_remote_enable(&config)
+    } else if selftest {
+        // Launch SC2 and run a game between builtin AIs, to check that the installation works
+        let config = config.unwrap_or_else(|| {
+            warn!("Config file not found, using default config");
+            Config::new()
+        });
+        match selftest::run(&config) {
+            Ok(report) => {
+                println!("Self-test passed: {}", report);
+                Ok(())
+            },
+            Err(e) => Err(format!("Self-test failed: {}", e)),
+        }
     } else {
-        run_server(args.first().cloned());
-        Ok(())
+        let config = config.unwrap_or_else(|| {
+            warn!("Config file not found, using default config");
+            Config::new()
+        });
+        run_server_config(config).map_err(|e| e.to_string())
     }
 }
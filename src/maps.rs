@@ -1,37 +1,94 @@
 //! Map file finder
+//!
+//! Maps are searched in the SC2 map directory, or in `process.map_dir` if it's set.
+//! Map files can be directly in the directory or in its subdirectories, e.g. `Ladder2019Season1`.
 
 use std::fs;
+use std::path::{Path, PathBuf};
 
 use crate::paths::map_dir;
 
-/// Find a map file, returning its relative path to the sc2 map directory
-pub fn find_map(mut name: String) -> Option<String> {
+/// Directory maps are searched in, the SC2 map directory if `custom` is None
+fn search_dir(custom: Option<&str>) -> PathBuf {
+    match custom {
+        Some(dir) => PathBuf::from(shellexpand::tilde(dir).into_owned()),
+        None => map_dir(),
+    }
+}
+
+/// All map files in `dir` and its subdirectories
+/// Returns an empty list if the directory cannot be read
+fn map_files(dir: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut files = Vec::new();
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            files.extend(map_files_flat(&path));
+        } else if is_map_file(&path) {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Map files directly in `dir`
+fn map_files_flat(dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| is_map_file(path))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Checks if the path has the map file extension
+fn is_map_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("SC2Map"))
+}
+
+/// Path of a map file as given to SC2
+/// Maps in the SC2 map directory are relative to it, and maps in `process.map_dir` are absolute,
+/// as SC2 accepts both
+fn sc2_path(path: &Path, dir: &Path, custom: Option<&str>) -> Option<String> {
+    let path = match custom {
+        Some(_) => fs::canonicalize(path).ok()?,
+        None => path.strip_prefix(dir).unwrap().to_path_buf(),
+    };
+    path.to_str().map(str::to_owned)
+}
+
+/// Find a map file in the SC2 map directory, returning its path relative to it
+pub fn find_map(name: String) -> Option<String> {
+    find_map_in(None, name)
+}
+
+/// Find a map file in `custom`, or in the SC2 map directory if None, see `sc2_path`
+/// Spaces in the name are ignored, and the `.SC2Map` extension is optional
+pub fn find_map_in(custom: Option<&str>, mut name: String) -> Option<String> {
     name = name.replace(" ", "");
     if !name.ends_with(".SC2Map") {
         name.push_str(".SC2Map");
     }
 
-    let mapdir = map_dir();
-    for outer in fs::read_dir(mapdir.clone()).expect("Could not iterate map directory") {
-        let outer_path = outer.unwrap().path();
-
-        if !outer_path.is_dir() {
-            continue;
-        }
+    let dir = search_dir(custom);
+    let path = map_files(&dir).into_iter().find(|path| {
+        path.file_name()
+            .and_then(|f| f.to_str())
+            .is_some_and(|f| f.eq_ignore_ascii_case(&name))
+    })?;
+    sc2_path(&path, &dir, custom)
+}
 
-        for inner in fs::read_dir(outer_path).expect("Could not iterate map subdirectory") {
-            let path = inner.unwrap().path();
-            let current = path
-                .file_name()
-                .unwrap()
-                .to_str()
-                .expect("Invalid unicode in poath");
-            if current.to_ascii_lowercase() == name.to_ascii_lowercase() {
-                let relative = path.strip_prefix(mapdir).unwrap();
-                let relative_str = relative.to_str().unwrap();
-                return Some(relative_str.to_owned());
-            }
-        }
-    }
-    None
+/// Any map in `custom`, or in the SC2 map directory if None, in path order, see `sc2_path`
+pub fn first_map(custom: Option<&str>) -> Option<String> {
+    let dir = search_dir(custom);
+    let mut files = map_files(&dir);
+    files.sort();
+    sc2_path(files.first()?, &dir, custom)
 }
@@ -132,6 +132,17 @@ pub fn executable() -> PathBuf {
     latest_executeble_path(base_dir().join(Path::new("Versions")))
 }
 
+/// Base build of the SC2 version `executable` launches, from the name of its version folder
+/// None if no versions are installed
+pub fn base_build() -> Option<u32> {
+    fs::read_dir(base_dir().join(Path::new("Versions")))
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().to_str()?.strip_prefix("Base")?.parse().ok())
+        .max()
+}
+
 /// Directory to switch to before starting SC2
 pub fn cwd_dir() -> PathBuf {
     let base = base_dir();
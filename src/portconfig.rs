@@ -1,45 +1,257 @@
-//! Full port configuration
+//! Full port configuration, and process-wide port allocation
 
 use portpicker::pick_unused_port;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
 
 use protobuf::RepeatedField;
 use sc2_proto::sc2api::{PortSet, RequestJoinGame};
 
+/// Ports leased to games and SC2 processes, so that concurrent allocations never overlap
+static LEASED_PORTS: Mutex<BTreeSet<u16>> = Mutex::new(BTreeSet::new());
+
+/// Maximum number of free port probes per requested port
+const PROBES_PER_PORT: usize = 100;
+
+/// Free ports reserved from the process-wide allocator, released when dropped
+#[derive(Debug)]
+pub(crate) struct PortLease {
+    ports: Vec<u16>,
+}
+impl PortLease {
+    /// Lease `count` free ports that are not leased by anyone else
+    pub fn new(count: usize) -> Option<Self> {
+        let mut leased = LEASED_PORTS.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut ports = Vec::with_capacity(count);
+        for _ in 0..count * PROBES_PER_PORT {
+            if ports.len() == count {
+                break;
+            }
+            let port = pick_unused_port()?;
+            if leased.insert(port) {
+                ports.push(port);
+            }
+        }
+
+        if ports.len() < count {
+            for port in &ports {
+                leased.remove(port);
+            }
+            return None;
+        }
+        Some(Self { ports })
+    }
+
+    /// The leased ports
+    pub fn ports(&self) -> &[u16] {
+        &self.ports
+    }
+}
+impl Drop for PortLease {
+    fn drop(&mut self) {
+        let mut leased = LEASED_PORTS.lock().unwrap_or_else(PoisonError::into_inner);
+        for port in &self.ports {
+            leased.remove(port);
+        }
+    }
+}
+
+/// Game and base port of one SC2 process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortPair {
+    /// Game port
+    pub game: u16,
+    /// Base port
+    pub base: u16,
+}
+impl PortPair {
+    fn to_proto(self) -> PortSet {
+        let mut ps = PortSet::new();
+        ps.set_game_port(self.game as i32);
+        ps.set_base_port(self.base as i32);
+        ps
+    }
+}
+
+/// Reason why a port config cannot be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortConfigError {
+    /// A game needs at least one SC2 process
+    NoProcesses,
+    /// Games with multiple SC2 processes need server ports
+    MissingServerPorts,
+    /// Every process except the server needs a client port pair
+    NotEnoughClientPorts {
+        /// Number of SC2 processes in the game
+        processes: usize,
+        /// Number of client port pairs
+        client_ports: usize,
+    },
+    /// The same port is used twice
+    DuplicatePort(u16),
+    /// Process index is not below the number of processes
+    ProcessOutOfRange {
+        /// Index of the process
+        process: usize,
+        /// Number of SC2 processes in the game
+        processes: usize,
+    },
+}
+impl fmt::Display for PortConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoProcesses => write!(f, "A game needs at least one SC2 process"),
+            Self::MissingServerPorts => write!(f, "Server ports are required with multiple SC2 processes"),
+            Self::NotEnoughClientPorts {
+                processes,
+                client_ports,
+            } => write!(
+                f,
+                "{} SC2 processes need {} client port pairs, got {}",
+                processes,
+                processes - 1,
+                client_ports
+            ),
+            Self::DuplicatePort(port) => write!(f, "Port {} is used twice", port),
+            Self::ProcessOutOfRange { process, processes } => {
+                write!(f, "Process {} out of range, the game has {} processes", process, processes)
+            },
+        }
+    }
+}
+
 /// Full set of ports needed by SC2
+/// Games with a single SC2 process only use the shared port.
+/// Otherwise the process creating the game acts as the server, and every other process is a client.
+/// The ports stay leased until the last clone is dropped
 #[derive(Debug, Clone)]
 pub struct PortConfig {
+    /// Number of SC2 processes joining the game
+    processes: usize,
+    /// Shared port, given to every process
     shared: u16,
-    server_game: u16,
-    server_base: u16,
-    client_game: u16,
-    client_base: u16,
+    /// Ports of the server, None if the game has a single SC2 process
+    server: Option<PortPair>,
+    /// Ports of each client, i.e. every process except the server
+    clients: Vec<PortPair>,
+    /// Lease of all the ports above, empty if they were given with `from_ports`
+    lease: Arc<PortLease>,
 }
 impl PortConfig {
-    /// Create a set of random ports
-    pub fn new() -> Option<Self> {
+    /// Lease a set of free ports for a game with the given number of SC2 processes,
+    /// including a dedicated host
+    pub fn new(processes: usize) -> Option<Self> {
+        let processes = processes.max(1);
+        let client_count = processes - 1;
+        let count = if processes == 1 { 1 } else { 3 + 2 * client_count };
+        let lease = PortLease::new(count)?;
+        let ports = lease.ports().to_vec();
+
         Some(Self {
-            shared: pick_unused_port()?,
-            server_game: pick_unused_port()?,
-            server_base: pick_unused_port()?,
-            client_game: pick_unused_port()?,
-            client_base: pick_unused_port()?,
+            processes,
+            shared: ports[0],
+            server: ports.get(1..3).map(|p| PortPair { game: p[0], base: p[1] }),
+            clients: ports
+                .get(3..)
+                .unwrap_or_default()
+                .chunks(2)
+                .map(|c| PortPair { game: c[0], base: c[1] })
+                .collect(),
+            lease: Arc::new(lease),
         })
     }
 
-    /// Apply port config to a game join request
-    pub fn apply_proto(&self, req: &mut RequestJoinGame, singleplayer: bool) {
-        req.set_shared_port(self.shared as i32);
+    /// Use the given ports for a game with `processes` SC2 processes, without leasing them
+    /// Returns an error if the ports don't meet the requirements of SC2, see `validate`
+    pub fn from_ports(
+        processes: usize, shared: u16, server: Option<PortPair>, clients: Vec<PortPair>,
+    ) -> Result<Self, PortConfigError> {
+        let config = Self {
+            processes,
+            shared,
+            server,
+            clients,
+            lease: Arc::new(PortLease { ports: Vec::new() }),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check the requirements of SC2: server ports and a client port pair for every process
+    /// except the server in multi-process games, and no port used twice
+    pub fn validate(&self) -> Result<(), PortConfigError> {
+        if self.processes == 0 {
+            return Err(PortConfigError::NoProcesses);
+        }
+        if self.processes > 1 {
+            if self.server.is_none() {
+                return Err(PortConfigError::MissingServerPorts);
+            }
+            if self.clients.len() < self.processes - 1 {
+                return Err(PortConfigError::NotEnoughClientPorts {
+                    processes: self.processes,
+                    client_ports: self.clients.len(),
+                });
+            }
+        }
+
+        let mut seen = BTreeSet::new();
+        for port in self.ports() {
+            if !seen.insert(port) {
+                return Err(PortConfigError::DuplicatePort(port));
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of SC2 processes joining the game
+    pub fn processes(&self) -> usize {
+        self.processes
+    }
+
+    /// Shared port, given to every process
+    pub fn shared_port(&self) -> u16 {
+        self.shared
+    }
 
-        if !singleplayer {
-            let mut server_ps = PortSet::new();
-            server_ps.set_game_port(self.server_game as i32);
-            server_ps.set_base_port(self.server_base as i32);
-            req.set_server_ports(server_ps);
+    /// Ports of the server, None if the game has a single SC2 process
+    pub fn server_ports(&self) -> Option<PortPair> {
+        self.server
+    }
+
+    /// Ports of the clients, used by the processes that join without creating the game
+    pub fn client_ports(&self) -> &[PortPair] {
+        &self.clients[..self.processes.saturating_sub(1).min(self.clients.len())]
+    }
 
-            let mut client_ps = PortSet::new();
-            client_ps.set_game_port(self.client_game as i32);
-            client_ps.set_base_port(self.client_base as i32);
-            req.set_client_ports(RepeatedField::from_vec(vec![client_ps]));
+    /// All ports in this config
+    pub fn ports(&self) -> Vec<u16> {
+        let pairs = self.server.iter().chain(self.client_ports());
+        Some(self.shared)
+            .into_iter()
+            .chain(pairs.flat_map(|p| vec![p.game, p.base]))
+            .collect()
+    }
+
+    /// Fill the ports of the join request of the SC2 process with index `process`
+    /// SC2 requires every process of a multi-process game to get the same server and client ports,
+    /// so the index is only checked to be within the configured number of processes
+    pub fn apply_proto(&self, req: &mut RequestJoinGame, process: usize) -> Result<(), PortConfigError> {
+        if process >= self.processes {
+            return Err(PortConfigError::ProcessOutOfRange {
+                process,
+                processes: self.processes,
+            });
+        }
+        self.validate()?;
+
+        req.set_shared_port(self.shared as i32);
+        if let Some(server) = self.server.filter(|_| self.processes > 1) {
+            req.set_server_ports(server.to_proto());
+            let client_ps = self.client_ports().iter().map(|p| p.to_proto()).collect();
+            req.set_client_ports(RepeatedField::from_vec(client_ps));
         }
+        Ok(())
     }
 }
@@ -1,32 +1,241 @@
 //! Proxy WebSocket receiver
 
 use crossbeam::channel::Sender;
-use log::debug;
-use std::net::ToSocketAddrs;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::ops::{Deref, DerefMut};
+use std::time::SystemTime;
 
 use websocket::client::sync::Client as GenericClient;
-use websocket::server::sync::Server as GenericServer;
-use websocket::server::NoTlsAcceptor;
+use websocket::message::CloseData;
+use websocket::server::upgrade::sync::IntoWs;
 use websocket::stream::sync::TcpStream;
+use websocket::OwnedMessage;
+
+use crate::delta::DEFAULT_KEYFRAME_INTERVAL;
 
-/// Server socket
-pub type Server = GenericServer<NoTlsAcceptor>;
 /// Client socket
 pub type Client = GenericClient<TcpStream>;
 
-/// Accept a new connection
-fn get_connection(server: &mut Server) -> Option<Client> {
-    Some(server.accept().ok()?.accept().expect("Unable to accept"))
+/// Close frame status code for connections closed by the proxy, e.g. when shutting down
+const CLOSE_GOING_AWAY: u16 = 1001;
+
+/// Longest accepted bot metadata string, in bytes
+pub const BOT_METADATA_MAX_LEN: usize = 256;
+
+/// Details of a client connection, captured from the websocket handshake
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ConnectionMeta {
+    /// Peer address, read when the connection was accepted
+    pub peer_addr: String,
+    /// Requested URL path, without the query string
+    pub path: String,
+    /// Query string of the requested URL, without the leading `?`
+    pub query: Option<String>,
+    /// Origin header, if present
+    pub origin: Option<String>,
+    /// User-Agent header, if present
+    pub user_agent: Option<String>,
+    /// When the connection was accepted
+    pub connected_at: SystemTime,
 }
+impl ConnectionMeta {
+    /// Metadata of a connection accepted now, without handshake details
+    pub fn new(peer_addr: String) -> Self {
+        Self {
+            peer_addr,
+            path: "/".to_owned(),
+            query: None,
+            origin: None,
+            user_agent: None,
+            connected_at: SystemTime::now(),
+        }
+    }
+
+    /// Metadata of a connection accepted now, from the request URI and headers of the handshake
+    /// The URI can be either an absolute path, or an absolute URL
+    pub fn from_handshake(
+        peer_addr: String, uri: &str, origin: Option<&str>, user_agent: Option<&str>,
+    ) -> Self {
+        // Strip the scheme and authority of absolute URLs
+        let target = match uri.find("://") {
+            Some(i) => uri[i + 3..].find('/').map_or("/", |j| &uri[i + 3 + j..]),
+            None => uri,
+        };
+        let (path, query) = match target.find('?') {
+            Some(i) => (&target[..i], Some(target[i + 1..].to_owned())),
+            None => (target, None),
+        };
+        Self {
+            path: if path.is_empty() { "/" } else { path }.to_owned(),
+            query,
+            origin: origin.map(str::to_owned),
+            user_agent: user_agent.map(str::to_owned),
+            ..Self::new(peer_addr)
+        }
+    }
+
+    /// Value of a query string parameter, if present
+    /// Values are not percent-decoded
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query.as_ref()?.split('&').find_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            if parts.next() == Some(name) {
+                Some(parts.next().unwrap_or(""))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Metadata the bot gave in the `meta` query parameter, e.g. its version, recorded in game results
+    /// Values longer than `BOT_METADATA_MAX_LEN` are ignored
+    pub fn bot_metadata(&self) -> Option<&str> {
+        self.query_param("meta").filter(|m| m.len() <= BOT_METADATA_MAX_LEN)
+    }
 
-/// Run the proxy server
-pub fn run<A: ToSocketAddrs>(addr: A, channel_out: Sender<Client>) -> ! {
-    let mut server = Server::bind(addr).expect("Unable to bind");
+    /// SC2 base build the bot was built against, from the `base_build` query parameter,
+    /// e.g. `75689`. Join requests are rejected if it's not the one the proxy launches.
+    /// Invalid values are ignored
+    pub fn expected_base_build(&self) -> Option<u32> {
+        self.query_param("base_build")?.parse().ok()
+    }
+
+    /// Keyframe interval of observation delta frames the client asked for in the `delta` query parameter,
+    /// see `delta`. Without a value, `DEFAULT_KEYFRAME_INTERVAL` is used. Invalid values are ignored
+    pub fn observation_delta(&self) -> Option<u32> {
+        match self.query_param("delta")? {
+            "" => Some(DEFAULT_KEYFRAME_INTERVAL),
+            value => value.parse().ok().filter(|&interval| interval > 0),
+        }
+    }
+}
+
+/// Client socket with the details of its connection
+pub struct ClientConnection {
+    /// Websocket connection
+    pub client: Client,
+    /// Handshake details
+    pub meta: ConnectionMeta,
+}
+impl ClientConnection {
+    /// Wrap a client socket accepted now, without handshake details
+    /// Fails if the peer address cannot be read
+    pub fn new(client: Client) -> io::Result<Self> {
+        let peer_addr = client.peer_addr()?.to_string();
+        Ok(Self {
+            client,
+            meta: ConnectionMeta::new(peer_addr),
+        })
+    }
+
+    /// Close the connection, sending a Close frame that gives `reason` to the client
+    /// Errors are ignored, as the client may already be gone
+    pub fn close(mut self, reason: &str) {
+        let data = CloseData::new(CLOSE_GOING_AWAY, reason.to_owned());
+        if let Err(e) = self.client.send_message(&OwnedMessage::Close(Some(data))) {
+            debug!("Could not send close frame to {}: {}", self.meta.peer_addr, e);
+        }
+        let _ = self.client.shutdown();
+    }
+}
+impl Deref for ClientConnection {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+impl DerefMut for ClientConnection {
+    fn deref_mut(&mut self) -> &mut Client {
+        &mut self.client
+    }
+}
+
+/// Single value of a handshake header, if present and valid UTF-8
+fn header_value<'a>(headers: &'a websocket::header::Headers, name: &str) -> Option<&'a str> {
+    let raw = headers.get_raw(name)?;
+    std::str::from_utf8(raw.first()?).ok()
+}
+
+/// Server socket
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+}
+impl Server {
+    /// Local address the server is listening on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+/// Accept a new connection, capturing the handshake details
+/// Connections that fail during the handshake are dropped
+fn get_connection(server: &mut Server) -> Option<ClientConnection> {
+    let (stream, _) = server.listener.accept().ok()?;
+    let upgrade = stream.into_ws().ok()?;
+    let uri = upgrade.uri();
+    let origin = upgrade.origin().map(str::to_owned);
+    let user_agent = header_value(&upgrade.request.headers, "User-Agent").map(str::to_owned);
+
+    let client = match upgrade.accept() {
+        Ok(client) => client,
+        Err((_, e)) => {
+            warn!("Could not accept connection: {}", e);
+            return None;
+        },
+    };
+    let peer_addr = match client.peer_addr() {
+        Ok(addr) => addr.to_string(),
+        Err(e) => {
+            warn!("Could not get peer address of a new connection, dropping it: {}", e);
+            return None;
+        },
+    };
+    let meta = ConnectionMeta::from_handshake(peer_addr, &uri, origin.as_deref(), user_agent.as_deref());
+    Some(ClientConnection { client, meta })
+}
+
+/// Bind the proxy server socket
+/// The listen backlog is the OS default if `backlog` is None, see `Proxy::listen_backlog`
+pub fn bind<A: ToSocketAddrs>(addr: A, backlog: Option<u32>) -> io::Result<Server> {
+    let listener = TcpListener::bind(addr)?;
+    if let Some(backlog) = backlog {
+        set_listen_backlog(&listener, backlog)?;
+    }
+    Ok(Server { listener })
+}
+
+/// Change the backlog of a listening socket by listening again,
+/// which updates the queue length of an already listening socket
+#[cfg(unix)]
+fn set_listen_backlog(listener: &TcpListener, backlog: u32) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let backlog = backlog.min(libc::c_int::max_value() as u32) as libc::c_int;
+    // Safety: the descriptor is owned by the listener, which outlives the call
+    let ret = unsafe { libc::listen(listener.as_raw_fd(), backlog) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Change the backlog of a listening socket. Not supported on this platform.
+#[cfg(not(unix))]
+fn set_listen_backlog(_listener: &TcpListener, _backlog: u32) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "Listen backlog is only supported on Unix"))
+}
 
+/// Run the proxy server, sending accepted connections with their handshake details
+pub fn run(mut server: Server, channel_out: Sender<ClientConnection>) -> ! {
     loop {
         debug!("Waiting for connection");
         if let Some(conn) = get_connection(&mut server) {
-            debug!("Connection accepted: {:?}", conn.peer_addr().unwrap());
+            debug!("Connection accepted: {} {}", conn.meta.peer_addr, conn.meta.path);
             channel_out.send(conn).expect("Send failed");
         }
     }
@@ -0,0 +1,447 @@
+//! Refiners rewrite client requests before they are checked and forwarded to SC2,
+//! and SC2 responses before they are forwarded to the client
+
+use log::warn;
+use protobuf::RepeatedField;
+use sc2_proto::sc2api::{ChatReceived, Request, Response};
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::{mix, seed_or_now, LimitAction, MatchConfig, NetworkSim};
+use crate::sc2::Race;
+
+/// Participant whose request is being refined
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RefineContext {
+    /// Lobby slot of the participant
+    pub slot: usize,
+    /// Player name from the join request
+    pub player_name: Option<String>,
+}
+
+/// Rewrites a client request in place
+pub trait RequestRefiner {
+    /// Refine a request from the participant described by `ctx`
+    fn refine(&mut self, req: &mut Request, ctx: &RefineContext);
+}
+
+/// Rewrites an SC2 response in place, only the copy forwarded to the client
+pub trait ResponseRefiner {
+    /// Refine a response to the participant described by `ctx`
+    fn refine(&mut self, resp: &mut Response, ctx: &RefineContext);
+}
+
+/// Built-in refiners, selected with `game.request_refiners`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RefinerKind {
+    /// Clamp step requests to `request_limits.max_step_count`
+    ClampStep,
+    /// Apply `game.overwrite_races` to join requests
+    OverrideRace,
+    /// Prefix chat actions with the player name
+    TagChat,
+    /// Remove debug commands other than drawing
+    StripCheats,
+    /// Turn realtime game creation requests into step mode
+    ForceStepMode,
+    /// Limit debug drawing to `request_limits.max_debug_draw_per_step`
+    LimitDebugDraw,
+    /// Delay requests and responses, and drop responses, as configured in `game.network_sim`
+    NetworkSim,
+    /// Tell the remaining game loops in chat, as configured in `time_limits.announce_remaining`
+    AnnounceRemaining,
+}
+
+/// Limits step requests to a number of game loops
+#[derive(Debug, Clone, Copy)]
+pub struct ClampStep {
+    /// Maximum step count
+    pub max: u32,
+}
+impl RequestRefiner for ClampStep {
+    fn refine(&mut self, req: &mut Request, _ctx: &RefineContext) {
+        if req.has_step() && req.get_step().get_count() > self.max {
+            req.mut_step().set_count(self.max);
+        }
+    }
+}
+
+/// Forces races of join requests by lobby slot
+#[derive(Debug, Clone)]
+pub struct OverrideRace {
+    /// Forced race for each slot, `None` keeps the requested race
+    pub races: Vec<Option<Race>>,
+}
+impl RequestRefiner for OverrideRace {
+    fn refine(&mut self, req: &mut Request, ctx: &RefineContext) {
+        if !req.has_join_game() {
+            return;
+        }
+        if let Some(Some(race)) = self.races.get(ctx.slot) {
+            req.mut_join_game().set_race(race.to_proto());
+        }
+    }
+}
+
+/// Prepends "[BotName] " to chat messages, so that replays show the sender
+#[derive(Debug, Clone, Copy)]
+pub struct TagChat;
+impl RequestRefiner for TagChat {
+    fn refine(&mut self, req: &mut Request, ctx: &RefineContext) {
+        let name = match (&ctx.player_name, req.has_action()) {
+            (Some(name), true) => name,
+            _ => return,
+        };
+        for action in req.mut_action().mut_actions().iter_mut() {
+            if action.has_action_chat() {
+                let chat = action.mut_action_chat();
+                let message = format!("[{}] {}", name, chat.get_message());
+                chat.set_message(message);
+            }
+        }
+    }
+}
+
+/// Removes cheats from debug requests instead of denying the whole request
+#[derive(Debug, Clone, Copy)]
+pub struct StripCheats;
+impl RequestRefiner for StripCheats {
+    fn refine(&mut self, req: &mut Request, _ctx: &RefineContext) {
+        if req.has_debug() {
+            let commands = req.mut_debug().take_debug().into_vec();
+            let draws = commands.into_iter().filter(|c| c.has_draw()).collect();
+            req.mut_debug().set_debug(draws);
+        }
+    }
+}
+
+/// Number of texts and shapes drawn by a debug request
+pub fn debug_draw_count(req: &Request) -> usize {
+    if !req.has_debug() {
+        return 0;
+    }
+    let draws = req.get_debug().get_debug().iter().filter(|c| c.has_draw());
+    draws
+        .map(|c| {
+            let draw = c.get_draw();
+            draw.get_text().len() + draw.get_lines().len() + draw.get_boxes().len() + draw.get_spheres().len()
+        })
+        .sum()
+}
+
+/// Truncate `items` to at most `left` items, reducing `left` by the number kept
+/// Returns the number of items removed
+fn keep_at_most<T>(items: &mut RepeatedField<T>, left: &mut usize) -> usize {
+    let kept = items.len().min(*left);
+    let removed = items.len() - kept;
+    items.truncate(kept);
+    *left -= kept;
+    removed
+}
+
+/// Removes texts and shapes drawn over a limit between two step requests.
+/// Realtime games do not step, so there the limit applies to the whole game.
+#[derive(Debug, Clone)]
+pub struct LimitDebugDraw {
+    /// Maximum number of texts and shapes per step
+    max: u32,
+    /// Drawn since the last step
+    drawn: u32,
+    /// Stripping is logged only once
+    warned: bool,
+}
+impl LimitDebugDraw {
+    /// Limit drawing to `max` texts and shapes per step
+    pub fn new(max: u32) -> Self {
+        Self {
+            max,
+            drawn: 0,
+            warned: false,
+        }
+    }
+}
+impl RequestRefiner for LimitDebugDraw {
+    fn refine(&mut self, req: &mut Request, ctx: &RefineContext) {
+        if req.has_step() {
+            self.drawn = 0;
+        }
+        if !req.has_debug() {
+            return;
+        }
+
+        let mut left = self.max.saturating_sub(self.drawn) as usize;
+        let before = left;
+        let mut removed = 0;
+        for command in req.mut_debug().mut_debug().iter_mut().filter(|c| c.has_draw()) {
+            let draw = command.mut_draw();
+            removed += keep_at_most(draw.mut_text(), &mut left);
+            removed += keep_at_most(draw.mut_lines(), &mut left);
+            removed += keep_at_most(draw.mut_boxes(), &mut left);
+            removed += keep_at_most(draw.mut_spheres(), &mut left);
+        }
+        self.drawn += (before - left) as u32;
+
+        if removed > 0 && !self.warned {
+            self.warned = true;
+            warn!(
+                "Player {} ({}) exceeded the debug draw limit of {} per step, stripping the excess",
+                ctx.slot,
+                ctx.player_name.as_deref().unwrap_or("unnamed"),
+                self.max
+            );
+        }
+    }
+}
+
+/// Disables realtime mode in game creation requests
+#[derive(Debug, Clone, Copy)]
+pub struct ForceStepMode;
+impl RequestRefiner for ForceStepMode {
+    fn refine(&mut self, req: &mut Request, _ctx: &RefineContext) {
+        if req.has_create_game() && req.get_create_game().get_realtime() {
+            req.mut_create_game().set_realtime(false);
+        }
+    }
+}
+
+/// Pseudo-random numbers of the network simulation
+#[derive(Debug, Clone)]
+struct SimRng(u64);
+impl SimRng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(1);
+        mix(self.0)
+    }
+
+    /// Uniform value from 0 to `max`, inclusive
+    fn up_to(&mut self, max: u64) -> u64 {
+        self.next() % (max + 1)
+    }
+
+    /// True with the given probability
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+/// Checks if the response can be dropped by the network simulation
+/// Only observations, actions, queries and debug commands can be, as bots are expected to retry them.
+/// Observations with the game results are never dropped, so that the bot sees the end of the game.
+pub fn is_droppable(resp: &Response) -> bool {
+    if resp.has_observation() {
+        return resp.get_observation().get_player_result().is_empty();
+    }
+    resp.has_action() || resp.has_obs_action() || resp.has_query() || resp.has_debug()
+}
+
+/// Delays requests before they are forwarded to SC2, by half of `added_latency_ms` and `jitter_ms`
+/// Paired with `NetworkSimResponses`, which delays the responses by the other half.
+#[derive(Debug, Clone)]
+pub struct NetworkSimRequests {
+    latency_ms: u64,
+    jitter_ms: u64,
+    rng: SimRng,
+}
+impl NetworkSimRequests {
+    /// Request side of the simulation, random delays seeded from `seed` if given
+    pub fn new(sim: &NetworkSim, seed: Option<u32>) -> Self {
+        Self {
+            latency_ms: sim.added_latency_ms / 2,
+            jitter_ms: sim.jitter_ms / 2,
+            rng: SimRng(seed_or_now(seed)),
+        }
+    }
+}
+impl RequestRefiner for NetworkSimRequests {
+    fn refine(&mut self, _req: &mut Request, _ctx: &RefineContext) {
+        let delay = self.latency_ms + self.rng.up_to(self.jitter_ms);
+        if delay > 0 {
+            thread::sleep(Duration::from_millis(delay));
+        }
+    }
+}
+
+/// Delays responses before they are forwarded to the client, and replaces some of them with errors
+/// The request has still been processed by SC2, as if only the response was lost.
+#[derive(Debug, Clone)]
+pub struct NetworkSimResponses {
+    latency_ms: u64,
+    jitter_ms: u64,
+    drop_probability: f64,
+    rng: SimRng,
+}
+impl NetworkSimResponses {
+    /// Response side of the simulation, random delays and drops seeded from `seed` if given
+    pub fn new(sim: &NetworkSim, seed: Option<u32>) -> Self {
+        Self {
+            latency_ms: sim.added_latency_ms - sim.added_latency_ms / 2,
+            jitter_ms: sim.jitter_ms - sim.jitter_ms / 2,
+            drop_probability: sim.drop_probability,
+            // Different numbers than the request side, even with the same seed
+            rng: SimRng(!seed_or_now(seed)),
+        }
+    }
+}
+impl ResponseRefiner for NetworkSimResponses {
+    fn refine(&mut self, resp: &mut Response, _ctx: &RefineContext) {
+        let delay = self.latency_ms + self.rng.up_to(self.jitter_ms);
+        if delay > 0 {
+            thread::sleep(Duration::from_millis(delay));
+        }
+        if is_droppable(resp) && self.rng.chance(self.drop_probability) {
+            let mut dropped = Response::new();
+            dropped.set_error(RepeatedField::from_vec(vec!["Proxy: Simulated drop".to_owned()]));
+            if resp.has_status() {
+                dropped.set_status(resp.get_status());
+            }
+            *resp = dropped;
+        }
+    }
+}
+
+/// Adds "Proxy: N loops remaining" to the received chat of the first observation
+/// past each checkpoint of the game loop limit. Each checkpoint is announced once,
+/// and checkpoints passed in a single step are announced together as the latest one.
+#[derive(Debug, Clone)]
+pub struct AnnounceRemaining {
+    /// Game loop limit
+    limit: u64,
+    /// Game loops of the checkpoints not yet announced, ascending
+    checkpoints: Vec<u64>,
+}
+impl AnnounceRemaining {
+    /// Announce at `percentages` of `limit` game loops
+    pub fn new(limit: u64, percentages: &[u8]) -> Self {
+        let mut checkpoints: Vec<u64> = percentages.iter().map(|&p| limit * u64::from(p) / 100).collect();
+        checkpoints.sort_unstable();
+        checkpoints.dedup();
+        Self { limit, checkpoints }
+    }
+}
+impl ResponseRefiner for AnnounceRemaining {
+    fn refine(&mut self, resp: &mut Response, _ctx: &RefineContext) {
+        if !resp.has_observation() || !resp.get_observation().get_player_result().is_empty() {
+            return;
+        }
+        let game_loop = u64::from(resp.get_observation().get_observation().get_game_loop());
+        let passed = self.checkpoints.iter().take_while(|&&c| c <= game_loop).count();
+        if passed == 0 || game_loop >= self.limit {
+            return;
+        }
+        self.checkpoints.drain(..passed);
+
+        let mut chat = ChatReceived::new();
+        chat.set_player_id(0);
+        chat.set_message(format!("Proxy: {} loops remaining", self.limit - game_loop));
+        resp.mut_observation().mut_chat().push(chat);
+    }
+}
+
+/// Refiner pipeline of a match, applied in order to every client request and SC2 response
+pub struct Pipeline {
+    refiners: Vec<Box<dyn RequestRefiner>>,
+    response_refiners: Vec<Box<dyn ResponseRefiner>>,
+}
+impl Pipeline {
+    /// Refiners from `game.request_refiners`, followed by the ones implied by
+    /// `request_limits.max_step_count`, `game.force_step_mode`, `game.network_sim`
+    /// and `time_limits.announce_remaining` if not listed
+    pub fn new(config: &MatchConfig) -> Self {
+        let mut kinds = config.game.request_refiners.clone();
+        let limits = &config.request_limits;
+        if limits.max_step_count.is_some() && limits.step_limit_action == LimitAction::Clamp {
+            kinds.push(RefinerKind::ClampStep);
+        }
+        if config.game.force_step_mode {
+            kinds.push(RefinerKind::ForceStepMode);
+        }
+        if limits.max_debug_draw_per_step.is_some() {
+            kinds.push(RefinerKind::LimitDebugDraw);
+        }
+        if config.game.network_sim.is_some() {
+            kinds.push(RefinerKind::NetworkSim);
+        }
+        if config.time_limits.announce_remaining {
+            kinds.push(RefinerKind::AnnounceRemaining);
+        }
+
+        let mut refiners: Vec<Box<dyn RequestRefiner>> = Vec::new();
+        let mut response_refiners: Vec<Box<dyn ResponseRefiner>> = Vec::new();
+        let mut used = Vec::new();
+        for kind in kinds {
+            if used.contains(&kind) {
+                continue;
+            }
+            used.push(kind);
+            match kind {
+                RefinerKind::ClampStep => {
+                    if let Some(max) = limits.max_step_count {
+                        refiners.push(Box::new(ClampStep { max }));
+                    }
+                },
+                RefinerKind::OverrideRace => {
+                    if let Some(races) = &config.game.overwrite_races {
+                        refiners.push(Box::new(OverrideRace { races: races.clone() }));
+                    }
+                },
+                RefinerKind::TagChat => refiners.push(Box::new(TagChat)),
+                RefinerKind::StripCheats => refiners.push(Box::new(StripCheats)),
+                RefinerKind::ForceStepMode => refiners.push(Box::new(ForceStepMode)),
+                RefinerKind::LimitDebugDraw => {
+                    if let Some(max) = limits.max_debug_draw_per_step {
+                        refiners.push(Box::new(LimitDebugDraw::new(max)));
+                    }
+                },
+                RefinerKind::NetworkSim => {
+                    if let Some(sim) = &config.game.network_sim {
+                        let seed = config.game.random_seed;
+                        refiners.push(Box::new(NetworkSimRequests::new(sim, seed)));
+                        response_refiners.push(Box::new(NetworkSimResponses::new(sim, seed)));
+                    }
+                },
+                RefinerKind::AnnounceRemaining => {
+                    let time_limits = &config.time_limits;
+                    if let Some(limit) = time_limits.game_loops {
+                        let announce = AnnounceRemaining::new(limit, &time_limits.announce_checkpoints);
+                        response_refiners.push(Box::new(announce));
+                    }
+                },
+            }
+        }
+        Self {
+            refiners,
+            response_refiners,
+        }
+    }
+
+    /// Number of active refiners, of both requests and responses
+    pub fn len(&self) -> usize {
+        self.refiners.len() + self.response_refiners.len()
+    }
+
+    /// Checks if no refiners are active
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Checks if any refiners of responses are active
+    pub fn refines_responses(&self) -> bool {
+        !self.response_refiners.is_empty()
+    }
+
+    /// Apply every request refiner to the request
+    pub fn refine(&mut self, req: &mut Request, ctx: &RefineContext) {
+        for refiner in &mut self.refiners {
+            refiner.refine(req, ctx);
+        }
+    }
+
+    /// Apply every response refiner to the response
+    pub fn refine_response(&mut self, resp: &mut Response, ctx: &RefineContext) {
+        for refiner in &mut self.response_refiners {
+            refiner.refine(resp, ctx);
+        }
+    }
+}
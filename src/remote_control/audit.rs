@@ -0,0 +1,88 @@
+//! Append-only audit log of remote controller requests, one JSON object per line
+
+use log::warn;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::RemoteRole;
+use crate::logging::RotatingFile;
+
+use super::message::{Request, Response};
+
+/// A single audit log line
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Milliseconds since the Unix epoch
+    pub time_ms: u64,
+    /// Peer address of the controller connection
+    pub connection: String,
+    /// Role the connection had authenticated as when the request was received, if any
+    pub role: Option<RemoteRole>,
+    /// Redacted summary of the request, `None` if it could not be parsed
+    pub request: Option<String>,
+    /// Kind of the response, with the message for errors
+    pub response: String,
+}
+
+/// Writes audit entries to a file, rotating it to `<path>.1` when it grows over `max_bytes`.
+/// Write failures are logged and otherwise ignored, so that they never block remote control.
+#[derive(Debug)]
+pub struct AuditLog {
+    file: RotatingFile,
+}
+impl AuditLog {
+    /// Create a writer for the audit log at `path`, the file is opened on the first write
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Self {
+            file: RotatingFile::new(path, max_bytes, 1),
+        }
+    }
+
+    /// Record a processed request
+    pub fn record(
+        &mut self, connection: &str, role: Option<RemoteRole>, request: Option<&Request>, response: &Response,
+    ) {
+        let time_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        let entry = AuditEntry {
+            time_ms,
+            connection: connection.to_owned(),
+            role,
+            request: request.map(summarize_request),
+            response: response_kind(response),
+        };
+        let mut line = serde_json::to_vec(&entry).expect("JSON writing failed");
+        line.push(b'\n');
+        if let Err(e) = self.file.write_line(&line) {
+            warn!("Could not write to the audit log {:?}: {}", self.file.path(), e);
+        }
+    }
+}
+
+/// Name of an externally tagged enum variant, as serialized
+fn variant_name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        Ok(serde_json::Value::Object(map)) => map.keys().next().cloned().unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// Summary of a request, leaving out tokens and configs
+fn summarize_request(req: &Request) -> String {
+    match req {
+        Request::Authenticate(_) | Request::SetConfig(_) => variant_name(req),
+        _ => serde_json::to_string(req).expect("JSON writing failed"),
+    }
+}
+
+/// Kind of a response, with the message for errors
+fn response_kind(resp: &Response) -> String {
+    match resp {
+        Response::Error(msg) => format!("Error: {}", msg),
+        _ => variant_name(resp),
+    }
+}
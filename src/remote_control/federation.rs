@@ -0,0 +1,439 @@
+//! Federation of two proxies, so that one remote controller can schedule games on several machines
+//!
+//! A downstream proxy with `[upstream]` in its config connects to the remote controller endpoint
+//! of the upstream proxy, and keeps it informed of its playlist. The upstream lists those clients
+//! in GetPlaylist with the name of the downstream as a prefix, e.g. `machine-b/10.0.0.2:51234`.
+//! Only the metadata of the clients is sent: the games of the downstream clients run on the
+//! downstream proxy. Adding a downstream client to an empty lobby moves the lobby there.
+//! Requests about the lobby are then forwarded to the downstream proxy, and its updates are
+//! forwarded back, always using the lobby id of the upstream. A lobby cannot have clients of
+//! both proxies, so such AddToLobby requests are rejected.
+//!
+//! The connection starts with `PeerMessage::Hello`, which the upstream answers with
+//! `UpstreamMessage::Welcome` or `UpstreamMessage::Rejected`. After that, both sides send
+//! JSON lines: the upstream sends requests, and the downstream sends their responses,
+//! updates about the games created through the upstream, and its playlist when it changes.
+//! The downstream reconnects automatically if the connection is lost.
+
+use bufstream::BufStream;
+use crossbeam::channel::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use log::{debug, error, info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::UpstreamConfig;
+use crate::supervisor::{GameId, PlaylistEntry};
+
+use super::message::{Request, Response, Update};
+use super::to_json_line;
+
+/// Separates the name of a downstream proxy from the client id in the client ids of the upstream
+pub const PEER_ID_SEPARATOR: char = '/';
+
+/// Time the upstream waits for the response to a forwarded request
+const PEER_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time the downstream waits for the upstream to accept the connection
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time between connection attempts of the downstream
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often the downstream checks whether the connection is still open while idle
+const LINK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Message from a downstream proxy to its upstream
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum PeerMessage {
+    /// First message of the connection
+    Hello {
+        /// Name of the downstream proxy, see `UpstreamConfig::name`
+        name: String,
+        /// Admin access token, if the upstream has tokens configured
+        token: Option<String>,
+    },
+    /// Clients in the playlist of the downstream proxy, sent when it changes
+    Playlist(Vec<PlaylistEntry>),
+    /// Response to the forwarded request with the same sequence number
+    Response(u64, Response),
+    /// Update about a lobby or game created through the upstream, using the id of the downstream
+    Update(Update),
+}
+
+/// Message from an upstream proxy to a downstream one
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum UpstreamMessage {
+    /// The downstream proxy was accepted
+    Welcome,
+    /// The downstream proxy was rejected, and the connection is closed
+    Rejected(String),
+    /// Request forwarded from the remote controller, with a sequence number for the response
+    Request(u64, Request),
+}
+
+/// Checks if a request can be forwarded to a downstream proxy
+/// Requests that change the downstream itself, e.g. Quit or SetConfig, are reserved for its own controller
+pub fn is_forwardable(request: &Request) -> bool {
+    match request {
+        Request::Ping(_)
+        | Request::GetEffectiveConfig(_)
+        | Request::CreateLobby(_)
+        | Request::CreateQueueLobby(_, _)
+        | Request::SetLobbyFullscreen(_, _)
+        | Request::AddToLobby(_, _, _)
+        | Request::GetLobby(_)
+        | Request::CancelLobby(_)
+        | Request::StartGame(_)
+        | Request::ForceStart(_)
+        | Request::GetGames
+        | Request::GetScore(_)
+        | Request::GetLaunchCommands(_)
+        | Request::SaveReplay(_, _) => true,
+        _ => false,
+    }
+}
+
+/// Checks that a downstream proxy name can be used as a client id prefix
+pub fn check_peer_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        Err("Peer name is empty".to_owned())
+    } else if name.contains(PEER_ID_SEPARATOR) {
+        Err(format!("Peer name {:?} contains {:?}", name, PEER_ID_SEPARATOR))
+    } else {
+        Ok(())
+    }
+}
+
+/// Split a client id of the upstream into the name of a downstream proxy and its client id
+/// Returns None for ids of local clients, which never contain the separator
+pub fn split_peer_client(client_id: &str) -> Option<(&str, &str)> {
+    let sep = client_id.find(PEER_ID_SEPARATOR)?;
+    Some((&client_id[..sep], &client_id[sep + 1..]))
+}
+
+/// Lobby or game moved to a downstream proxy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    /// Name of the downstream proxy
+    pub peer: String,
+    /// Id of the lobby or game on the downstream proxy
+    pub id: GameId,
+}
+
+/// Read JSON lines from a connection until it closes or sends an invalid message
+fn read_messages<T: DeserializeOwned>(mut stream: impl BufRead, tx: &Sender<T>) -> io::Result<()> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if stream.read_until(b'\n', &mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let msg = serde_json::from_slice(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        if tx.send(msg).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Connection of a downstream proxy, passed from the listener to the supervisor of the upstream
+pub struct PeerSession {
+    /// Name of the downstream proxy
+    pub name: String,
+    /// Access token given by the downstream proxy, if any
+    pub token: Option<String>,
+    /// Messages from the downstream proxy, disconnected when the connection closes
+    incoming: Receiver<PeerMessage>,
+    /// Messages to the downstream proxy, the connection is closed when this is dropped
+    outgoing: Sender<UpstreamMessage>,
+}
+impl PeerSession {
+    /// Reject the downstream proxy, closing the connection
+    pub fn reject(self, reason: &str) {
+        let _ = self.outgoing.send(UpstreamMessage::Rejected(reason.to_owned()));
+    }
+
+    /// Accept the downstream proxy
+    pub fn accept(self) -> Peer {
+        let _ = self.outgoing.send(UpstreamMessage::Welcome);
+        Peer {
+            session: self,
+            playlist: Vec::new(),
+            next_seq: 0,
+        }
+    }
+}
+
+/// Serve a downstream proxy connection after its hello message, until it closes
+/// The session is passed to the supervisor through `sessions`
+pub(crate) fn serve_peer(
+    stream: BufStream<TcpStream>, name: String, token: Option<String>, sessions: &Sender<PeerSession>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(stream.get_ref().try_clone()?);
+    stream.get_ref().set_read_timeout(None)?;
+
+    let (tx_incoming, rx_incoming) = channel::unbounded();
+    let (tx_outgoing, rx_outgoing) = channel::unbounded();
+    let session = PeerSession {
+        name,
+        token,
+        incoming: rx_incoming,
+        outgoing: tx_outgoing,
+    };
+    if sessions.send(session).is_err() {
+        return Err(io::ErrorKind::BrokenPipe.into());
+    }
+
+    thread::spawn(move || {
+        if let Err(e) = read_messages(stream, &tx_incoming) {
+            debug!("Peer connection closed: {}", e);
+        }
+    });
+
+    // Ends when the supervisor drops the peer, e.g. after the reader above has stopped
+    for msg in rx_outgoing.iter() {
+        let rejected = matches!(msg, UpstreamMessage::Rejected(_));
+        writer.write_all(&to_json_line(&msg))?;
+        writer.flush()?;
+        if rejected {
+            break;
+        }
+    }
+    writer.get_ref().shutdown(Shutdown::Both)
+}
+
+/// Downstream proxy connected to this one, kept by the supervisor
+pub struct Peer {
+    session: PeerSession,
+    /// Latest playlist of the downstream proxy, with its own client ids
+    playlist: Vec<PlaylistEntry>,
+    /// Sequence number of the next forwarded request
+    next_seq: u64,
+}
+impl Peer {
+    /// Name of the downstream proxy
+    pub fn name(&self) -> &str {
+        &self.session.name
+    }
+
+    /// Latest playlist of the downstream proxy, with client ids of the upstream
+    pub fn playlist(&self) -> Vec<PlaylistEntry> {
+        self.playlist
+            .iter()
+            .cloned()
+            .map(|mut entry| {
+                entry.id = format!("{}{}{}", self.name(), PEER_ID_SEPARATOR, entry.id);
+                entry
+            })
+            .collect()
+    }
+
+    /// Receive the messages that have arrived, except playlists, which are stored instead
+    /// Returns false if the connection has closed
+    pub fn receive(&mut self, messages: &mut Vec<PeerMessage>) -> bool {
+        loop {
+            match self.session.incoming.try_recv() {
+                Ok(msg) => self.handle(msg, messages),
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+
+    /// Store a playlist, or pass the message on
+    fn handle(&mut self, msg: PeerMessage, messages: &mut Vec<PeerMessage>) {
+        match msg {
+            PeerMessage::Playlist(playlist) => self.playlist = playlist,
+            PeerMessage::Response(seq, _) => debug!("Dropping late response {} of peer {}", seq, self.name()),
+            other => messages.push(other),
+        }
+    }
+
+    /// Forward a request and wait for the response, see `PEER_REQUEST_TIMEOUT`
+    /// Other messages received meanwhile are handled as in `receive`
+    pub fn request(&mut self, request: Request, messages: &mut Vec<PeerMessage>) -> Result<Response, String> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if self.session.outgoing.send(UpstreamMessage::Request(seq, request)).is_err() {
+            return Err(format!("Peer {} disconnected", self.name()));
+        }
+
+        let deadline = Instant::now() + PEER_REQUEST_TIMEOUT;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.session.incoming.recv_timeout(timeout) {
+                Ok(PeerMessage::Response(s, response)) if s == seq => return Ok(response),
+                Ok(msg) => self.handle(msg, messages),
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(format!("Peer {} did not respond in time", self.name()));
+                },
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(format!("Peer {} disconnected", self.name()));
+                },
+            }
+        }
+    }
+}
+
+/// Connection of a downstream proxy to its upstream, kept by the supervisor of the downstream
+/// The connection is made and restored in the background
+pub struct UpstreamLink {
+    /// Requests forwarded by the upstream proxy
+    requests: Receiver<(u64, Request)>,
+    /// Messages to the upstream proxy, dropped while disconnected, except the latest playlist
+    outgoing: Sender<PeerMessage>,
+    /// Playlist last sent, to only send changes
+    playlist: Option<Vec<PlaylistEntry>>,
+    /// Connection thread handle
+    pub handle: thread::JoinHandle<()>,
+}
+impl UpstreamLink {
+    /// Start connecting to the upstream proxy
+    pub fn connect(config: UpstreamConfig) -> Self {
+        let (tx_requests, rx_requests) = channel::unbounded();
+        let (tx_outgoing, rx_outgoing) = channel::unbounded();
+        let handle = thread::spawn(move || run_link(&config, &rx_outgoing, &tx_requests));
+        Self {
+            requests: rx_requests,
+            outgoing: tx_outgoing,
+            playlist: None,
+            handle,
+        }
+    }
+
+    /// Receive a forwarded request and its sequence number, if any available
+    pub fn try_recv(&mut self) -> Option<(u64, Request)> {
+        self.requests.try_recv().ok()
+    }
+
+    /// Send the response to a forwarded request
+    pub fn send_response(&mut self, seq: u64, response: Response) {
+        let _ = self.outgoing.send(PeerMessage::Response(seq, response));
+    }
+
+    /// Send an update about a lobby or game created through the upstream
+    pub fn send_update(&mut self, update: Update) {
+        let _ = self.outgoing.send(PeerMessage::Update(update));
+    }
+
+    /// Send the playlist if it has changed since it was last sent
+    pub fn send_playlist(&mut self, playlist: Vec<PlaylistEntry>) {
+        if self.playlist.as_ref() != Some(&playlist) {
+            self.playlist = Some(playlist.clone());
+            let _ = self.outgoing.send(PeerMessage::Playlist(playlist));
+        }
+    }
+}
+
+/// Connect to the upstream proxy and say hello
+/// Returns the connection, or an error if it could not be made or the upstream rejected it
+fn connect_upstream(config: &UpstreamConfig) -> Result<BufStream<TcpStream>, String> {
+    let stream = TcpStream::connect(&config.addr).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(HELLO_TIMEOUT)).map_err(|e| e.to_string())?;
+    let mut stream = BufStream::new(stream);
+    let hello = PeerMessage::Hello {
+        name: config.name.clone(),
+        token: config.token.clone(),
+    };
+    stream.write_all(&to_json_line(&hello)).map_err(|e| e.to_string())?;
+    stream.flush().map_err(|e| e.to_string())?;
+
+    let mut line = Vec::new();
+    stream.read_until(b'\n', &mut line).map_err(|e| e.to_string())?;
+    match serde_json::from_slice::<UpstreamMessage>(&line) {
+        Ok(UpstreamMessage::Welcome) => {
+            stream.get_ref().set_read_timeout(None).map_err(|e| e.to_string())?;
+            Ok(stream)
+        },
+        Ok(UpstreamMessage::Rejected(reason)) => Err(format!("Rejected: {}", reason)),
+        Ok(other) => Err(format!("Unexpected message {:?}", other)),
+        Err(e) => Err(format!("Invalid message: {}", e)),
+    }
+}
+
+/// Keep connected to the upstream proxy, passing messages between it and the supervisor
+/// Returns when the supervisor drops the link
+fn run_link(
+    config: &UpstreamConfig, rx_outgoing: &Receiver<PeerMessage>, tx_requests: &Sender<(u64, Request)>,
+) {
+    // Latest playlist, sent again after reconnecting
+    let mut playlist: Option<PeerMessage> = None;
+    loop {
+        let stream = match connect_upstream(config) {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Could not connect to upstream proxy {}: {}", config.addr, e);
+                let deadline = Instant::now() + RECONNECT_INTERVAL;
+                // Messages are dropped while disconnected, but the latest playlist is kept
+                loop {
+                    match rx_outgoing.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(msg @ PeerMessage::Playlist(_)) => playlist = Some(msg),
+                        Ok(_) => {},
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                continue;
+            },
+        };
+        info!("Connected to upstream proxy {} as {:?}", config.addr, config.name);
+
+        let mut writer = match stream.get_ref().try_clone() {
+            Ok(s) => BufWriter::new(s),
+            Err(e) => {
+                error!("Could not use the upstream connection: {}", e);
+                thread::sleep(RECONNECT_INTERVAL);
+                continue;
+            },
+        };
+        let closed = Arc::new(AtomicBool::new(false));
+        let reader_closed = closed.clone();
+        let (tx_messages, rx_messages) = channel::unbounded::<UpstreamMessage>();
+        thread::spawn(move || {
+            if let Err(e) = read_messages(stream, &tx_messages) {
+                debug!("Upstream connection closed: {}", e);
+            }
+            reader_closed.store(true, Ordering::SeqCst);
+        });
+
+        let mut pending = playlist.clone();
+        while !closed.load(Ordering::SeqCst) {
+            while let Ok(msg) = rx_messages.try_recv() {
+                match msg {
+                    UpstreamMessage::Request(seq, request) => {
+                        if tx_requests.send((seq, request)).is_err() {
+                            return;
+                        }
+                    },
+                    other => warn!("Unexpected message from upstream proxy: {:?}", other),
+                }
+            }
+
+            let msg = match pending.take() {
+                Some(msg) => msg,
+                None => match rx_outgoing.recv_timeout(LINK_POLL_INTERVAL) {
+                    Ok(msg) => msg,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                },
+            };
+            if let PeerMessage::Playlist(_) = msg {
+                playlist = Some(msg.clone());
+            }
+            let written = writer.write_all(&to_json_line(&msg)).and_then(|_| writer.flush());
+            if let Err(e) = written {
+                debug!("Could not write to upstream proxy: {}", e);
+                break;
+            }
+        }
+        warn!("Disconnected from upstream proxy {}, reconnecting", config.addr);
+        let _ = writer.get_ref().shutdown(Shutdown::Both);
+        thread::sleep(RECONNECT_INTERVAL);
+    }
+}
@@ -1,9 +1,12 @@
 //! Messages for the remote control protocol
 
+use sc2_proto::sc2api::ResponseGameInfo;
 use serde::{Deserialize, Serialize};
 
-use crate::config::Config;
-use crate::supervisor::GameId;
+use crate::config::{Config, ProcessOptions, RemoteRole};
+use crate::results::{GameResult, GameStats, Standings};
+use crate::sc2::{PlayerType, Race, ScoreSnapshot, SessionStatus};
+use crate::supervisor::{GameId, KickRecord};
 
 /// Request to the client, always gets a Response
 /// Currently client identifiers are string containg the peer address and port
@@ -16,19 +19,153 @@ pub enum Request {
     /// Read current server configuration
     GetConfig,
     /// Update configuration for the new games
-    SetConfig(Config),
+    /// Changing the matchmaking mode of any queue is rejected while lobbies or pending join requests exist
+    ///
+    /// Each lobby takes a snapshot of the config when it's created, so a change only applies
+    /// to lobbies created afterwards. Existing lobbies, starting games and running games keep
+    /// their old config until they end, including the SC2 processes launched by later AddToLobby
+    /// requests. The response lists their ids, see GetEffectiveConfig.
+    /// The new config is not validated here: if it's not usable, e.g. the map is missing,
+    /// creating new lobbies fails with an error until it's fixed.
+    SetConfig(Box<Config>),
+    /// Read the config snapshot used by a lobby, a starting game or a running game, see SetConfig
+    /// Access tokens are only included for admins
+    GetEffectiveConfig(GameId),
     /// Get identifiers and ready statuses of all clients in the playlist
     GetPlaylist,
+    /// Get identifiers and ready statuses of the clients connected to a named queue, see `Config::queues`
+    GetQueuePlaylist(String),
     /// Remove a client from the playlist by identifier
     DropPlaylistItem(String),
     /// Remove all clients from the playlist
     ClearPlaylist,
-    /// Creates a new lobby with given players
-    CreateLobby,
+    /// Creates a new lobby in the default queue, optionally tagged with an external identifier
+    CreateLobby(Option<String>),
+    /// Creates a new lobby in a named queue, optionally tagged with an external identifier
+    /// The lobby uses the matchmaking and match settings of the queue
+    CreateQueueLobby(String, Option<String>),
+    /// Run the SC2 processes of an empty lobby fullscreen (true) or windowed (false),
+    /// overriding the config
+    SetLobbyFullscreen(GameId, bool),
     /// Moves player from the playlist to a lobby by identifier
-    AddToLobby(GameId, String),
+    /// The client must have connected to the queue of the lobby
+    /// Its SC2 process is launched in the background, use GetLobby to check readiness
+    /// The process is launched with the given options instead of the `process` config, if any
+    AddToLobby(GameId, String, Option<ProcessOptions>),
+    /// Get participants of a lobby and their readiness
+    GetLobby(GameId),
+    /// Remove a lobby, asking its SC2 processes to quit, and return its clients to the playlist
+    CancelLobby(GameId),
     /// Starts a game from lobby
+    /// The game is started in the background, and the outcome is sent as an update
     StartGame(GameId),
+    /// Starts a game from lobby even if it is not full,
+    /// filling empty slots with builtin AI players
+    ForceStart(GameId),
+    /// List all lobbies and running games
+    GetGames,
+    /// Authenticate this connection using an access token from the config
+    Authenticate(String),
+    /// Stop accepting new games, and quit after the running games are over
+    Drain,
+    /// Get cumulative game counters since the proxy was started
+    GetStats,
+    /// Get win/loss records of bots by identifier
+    GetStandings,
+    /// Get the clients most recently removed from the playlist and why, oldest first
+    GetRecentKicks,
+    /// Get the latest score of each participant of a running game, for live commentary
+    GetScore(GameId),
+    /// Get the command lines of the SC2 processes of a lobby or a running game, for auditing
+    GetLaunchCommands(GameId),
+    /// Save the replay of a running game to a path, without ending the game
+    /// The replay is saved after the next request of a participant
+    /// With `record_results.replay_name_pattern`, the path is the directory of the named replay
+    SaveReplay(GameId, String),
+    /// Send the latest replay saved with SaveReplay over this connection, also after the game has ended
+    /// The response has the size of the file, and the contents follow as ReplayChunk updates
+    /// and a ReplayEnd update, see `transfer`
+    FetchReplay(GameId),
+    /// Close the remote controller listener after responding, refusing further connections.
+    /// It can be started again locally, see `RemoteController::enable_flag_path`
+    DisableRemoteControl,
+    /// Move the remote controller listener to a new address, e.g. `127.0.0.1:8643`.
+    /// The new listener is bound before responding, and the current connection and listener
+    /// are closed after the response. If binding fails, the current listener is kept.
+    RebindRemoteControl(String),
+}
+impl Request {
+    /// Checks if the request only reads the proxy state
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            Request::Ping(_)
+            | Request::GetConfig
+            | Request::GetEffectiveConfig(_)
+            | Request::GetPlaylist
+            | Request::GetQueuePlaylist(_)
+            | Request::GetLobby(_)
+            | Request::GetGames
+            | Request::GetStats
+            | Request::GetStandings
+            | Request::GetRecentKicks
+            | Request::GetScore(_)
+            | Request::GetLaunchCommands(_)
+            | Request::FetchReplay(_)
+            | Request::Authenticate(_) => true,
+            Request::Quit
+            | Request::SetConfig(_)
+            | Request::DropPlaylistItem(_)
+            | Request::ClearPlaylist
+            | Request::CreateLobby(_)
+            | Request::CreateQueueLobby(_, _)
+            | Request::SetLobbyFullscreen(_, _)
+            | Request::AddToLobby(_, _, _)
+            | Request::CancelLobby(_)
+            | Request::StartGame(_)
+            | Request::ForceStart(_)
+            | Request::Drain
+            | Request::SaveReplay(_, _)
+            | Request::DisableRemoteControl
+            | Request::RebindRemoteControl(_) => false,
+        }
+    }
+
+    /// Lobby or game the request is about, if any
+    pub fn game_id(&self) -> Option<GameId> {
+        match self {
+            Request::GetEffectiveConfig(id)
+            | Request::SetLobbyFullscreen(id, _)
+            | Request::AddToLobby(id, _, _)
+            | Request::GetLobby(id)
+            | Request::CancelLobby(id)
+            | Request::StartGame(id)
+            | Request::ForceStart(id)
+            | Request::GetScore(id)
+            | Request::GetLaunchCommands(id)
+            | Request::SaveReplay(id, _)
+            | Request::FetchReplay(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// The same request about another lobby or game, see `game_id`
+    pub fn with_game_id(mut self, game_id: GameId) -> Self {
+        match &mut self {
+            Request::GetEffectiveConfig(id)
+            | Request::SetLobbyFullscreen(id, _)
+            | Request::AddToLobby(id, _, _)
+            | Request::GetLobby(id)
+            | Request::CancelLobby(id)
+            | Request::StartGame(id)
+            | Request::ForceStart(id)
+            | Request::GetScore(id)
+            | Request::GetLaunchCommands(id)
+            | Request::SaveReplay(id, _)
+            | Request::FetchReplay(id) => *id = game_id,
+            _ => {},
+        }
+        self
+    }
 }
 
 /// Response to a Request
@@ -39,17 +176,201 @@ pub enum Response {
     Quit,
     Ping(u32),
     GetConfig(Config),
-    SetConfig(Config),
+    /// The new config, and ids of lobbies and games still using their old config
+    SetConfig(Config, Vec<GameId>),
+    GetEffectiveConfig(Config),
     /// Vec of identifier and is_ready
     GetPlaylist(Vec<(String, bool)>),
     DropPlaylist,
     ClearPlaylist,
     CreateLobby(GameId),
-    AddToLobby,
+    SetLobbyFullscreen,
+    AddToLobby(PlayerStatus),
+    GetLobby(LobbyInfo),
+    CancelLobby,
     StartGame,
+    ForceStart,
+    GetGames(Vec<GameInfo>),
+    Authenticate(RemoteRole),
+    Drain,
+    SaveReplay,
+    /// Size of the replay in bytes
+    FetchReplay(u64),
+    GetStats(GameStats),
+    GetStandings(Standings),
+    GetRecentKicks(Vec<KickRecord>),
+    /// Latest score of each participant in join order, None before the first observation with a score
+    GetScore(Vec<Option<ScoreSnapshot>>),
+    /// SC2 command line of each participant in join order, None while the process is being launched
+    GetLaunchCommands(Vec<Option<Vec<String>>>),
+    DisableRemoteControl,
+    /// Address the new listener was bound to
+    RebindRemoteControl(String),
+}
+
+/// Lobby or running game, as listed by GetGames
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GameInfo {
+    /// Id of the lobby or game
+    pub id: GameId,
+    /// Identifier given by an external system, if any
+    pub external_id: Option<String>,
+    /// False for lobbies, true for running games
+    pub running: bool,
+    /// SC2 session status of each participant of a running game, None if not known yet
+    #[serde(default)]
+    pub player_statuses: Vec<Option<SessionStatus>>,
+}
+
+/// Lobby and its participants, as returned by GetLobby
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LobbyInfo {
+    /// Id of the lobby
+    pub id: GameId,
+    /// Identifier given by an external system, if any
+    pub external_id: Option<String>,
+    /// Participants in join order
+    pub players: Vec<LobbyPlayer>,
+}
+
+/// Participant of a lobby
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LobbyPlayer {
+    /// Player name from the join request, if any
+    pub name: Option<String>,
+    /// Readiness of the SC2 process
+    pub status: PlayerStatus,
+    /// Command line of the SC2 process, None while it is being launched
+    #[serde(default)]
+    pub launch_command: Option<Vec<String>>,
+    /// Options the SC2 process is launched with, including an override from AddToLobby
+    #[serde(default)]
+    pub process_options: ProcessOptions,
+}
+
+/// Readiness of the SC2 process of a lobby participant
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerStatus {
+    /// The process is being launched
+    Launching,
+    /// The process is running and connected
+    Ready,
 }
 
 /// Asychronous update to a Request
 /// This can be used for e.g. realtime updates of score values
+/// Updates are sent after the response to the next request, or while no request is being processed
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
-pub enum Update {}
+pub enum Update {
+    /// Game creation and joining started in the background
+    GameStarting(GameId),
+    /// All participants joined the game, and it is now running
+    GameStarted(GameId),
+    /// Game could not be started, the participants were disconnected
+    GameStartFailed(GameId, String),
+    /// SC2 game info of a started game, if `record_results.game_info` is enabled
+    GameInfo(GameId, GameInfoSummary),
+    /// Game ended, with its result including the outcome detail of each participant
+    GameEnded(GameId, GameResult),
+    /// Part of a replay requested with FetchReplay, numbered from zero
+    ReplayChunk {
+        /// Game the replay belongs to
+        game_id: GameId,
+        /// Position of the chunk
+        seq: u64,
+        /// Contents of the chunk
+        data_base64: String,
+    },
+    /// All chunks of a replay have been sent
+    ReplayEnd {
+        /// Game the replay belongs to
+        game_id: GameId,
+        /// Number of chunks sent
+        chunks: u64,
+        /// CRC32 of the whole replay
+        crc32: u32,
+    },
+}
+
+impl Update {
+    /// Lobby or game the update is about
+    pub fn game_id(&self) -> GameId {
+        match self {
+            Update::GameStarting(id)
+            | Update::GameStarted(id)
+            | Update::GameStartFailed(id, _)
+            | Update::GameInfo(id, _)
+            | Update::GameEnded(id, _) => *id,
+            Update::ReplayChunk { game_id, .. } | Update::ReplayEnd { game_id, .. } => *game_id,
+        }
+    }
+
+    /// The same update about another lobby or game, see `game_id`
+    pub fn with_game_id(mut self, new_id: GameId) -> Self {
+        match &mut self {
+            Update::GameStarting(id)
+            | Update::GameStarted(id)
+            | Update::GameStartFailed(id, _)
+            | Update::GameInfo(id, _)
+            | Update::GameEnded(id, _) => *id = new_id,
+            Update::ReplayChunk { game_id, .. } | Update::ReplayEnd { game_id, .. } => *game_id = new_id,
+        }
+        self
+    }
+
+    /// Checks if this is the last update about the game
+    pub fn is_final(&self) -> bool {
+        matches!(self, Update::GameStartFailed(_, _) | Update::GameEnded(_, _))
+    }
+}
+
+/// Map and players of a game, from the SC2 game info
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GameInfoSummary {
+    /// Map name, as reported by SC2
+    pub map_name: String,
+    /// Map width and height in cells
+    pub map_size: (i32, i32),
+    /// Possible start locations of the participants
+    pub start_locations: Vec<(f32, f32)>,
+    /// Players, including computers and observers
+    pub players: Vec<GamePlayerInfo>,
+}
+impl GameInfoSummary {
+    /// Summarize a game info response
+    pub fn from_proto(info: &ResponseGameInfo) -> Self {
+        let start_raw = info.get_start_raw();
+        Self {
+            map_name: info.get_map_name().to_owned(),
+            map_size: (start_raw.get_map_size().get_x(), start_raw.get_map_size().get_y()),
+            start_locations: start_raw
+                .get_start_locations()
+                .iter()
+                .map(|p| (p.get_x(), p.get_y()))
+                .collect(),
+            players: info
+                .get_player_info()
+                .iter()
+                .map(|p| GamePlayerInfo {
+                    player_id: p.get_player_id(),
+                    player_type: PlayerType::from_proto(p.get_field_type()),
+                    race_requested: Race::try_from_proto(p.get_race_requested()),
+                    race_actual: Race::try_from_proto(p.get_race_actual()),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Player in the SC2 game info
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GamePlayerInfo {
+    /// Player id used by SC2
+    pub player_id: u32,
+    /// Participant, computer or observer
+    pub player_type: PlayerType,
+    /// Race the player requested, if any
+    pub race_requested: Option<Race>,
+    /// Race the player plays as, if resolved yet
+    pub race_actual: Option<Race>,
+}
@@ -1,9 +1,25 @@
 //! Remote control endpoint for the proxy server.
-//! Allows only one connection.
+//! Serves one controller connection at a time.
 //! Commands are taken through a TCP socket in JSON format.
 //! This is a custom RPC server.
+//!
+//! # Security model
+//! The protocol is unencrypted, so the listener should only be bound to a trusted interface.
+//! If access tokens are configured, connections must authenticate, and spectators get read-only access.
+//! Without tokens, anyone who can connect has full access.
+//! An admin can close the listener with `DisableRemoteControl`, e.g. after setting up a tournament,
+//! and no connections are accepted after that. As the socket is gone, it can only be started again
+//! locally, by creating the `enable_flag_path` file, e.g. with `sc2-proxy --enable-remote`.
+//! An admin can also move the listener to another address with `RebindRemoteControl`.
+//! Every request can be recorded to an audit log, see `audit`.
+//!
+//! Downstream proxies connect to the same endpoint, see `federation`. They are told apart
+//! from controllers by their first message, and are served concurrently with the controller.
 
+pub mod audit;
+pub mod federation;
 pub mod message;
+pub mod transfer;
 
 use bufstream::BufStream;
 use crossbeam::channel::{self, Receiver, Sender};
@@ -11,34 +27,120 @@ use log::{debug, info, warn};
 use std::io;
 use std::io::{BufRead, Write};
 use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
 use serde::Serialize;
 use serde_json;
 
+use crate::config::RemoteRole;
+
+use self::audit::AuditLog;
+use self::federation::{PeerMessage, PeerSession};
 use self::message::{Request, Response, Update};
 
-#[allow(missing_docs)]
+/// The remote controller connection is closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Disconnected;
+
+/// Channels for a single controller connection
+struct Session {
+    recv: Receiver<Request>,
+    send: Sender<Response>,
+    update: Sender<Update>,
+}
+
+/// Supervisor side of the remote controller.
+/// Each accepted controller connection gets its own set of channels.
 pub struct Remote {
-    pub recv: Receiver<Request>,
-    pub send: Sender<Response>,
-    pub update: Sender<Update>,
+    /// New sessions from the listener thread
+    sessions: Receiver<Session>,
+    /// New downstream proxy connections from the listener thread
+    peers: Receiver<PeerSession>,
+    /// Current session, if any
+    session: Option<Session>,
+    /// Role the current session has authenticated as, if any
+    role: Option<RemoteRole>,
+    /// Address the listener is bound to
+    addr: String,
+    /// Listener thread handle
     pub handle: thread::JoinHandle<()>,
 }
 impl Remote {
+    /// Switch to the newest controller connection, if any
+    fn update_session(&mut self) {
+        while let Ok(session) = self.sessions.try_recv() {
+            debug!("Switching to a new controller connection");
+            self.session = Some(session);
+            self.role = None;
+        }
+    }
+
+    /// Address the listener is bound to
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Checks if the listener thread has stopped, i.e. no connections are accepted
+    pub fn is_closed(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Role the current controller connection has authenticated as, if any
+    pub fn role(&self) -> Option<RemoteRole> {
+        self.role
+    }
+
+    /// Set the role of the current controller connection after authentication
+    pub fn set_role(&mut self, role: RemoteRole) {
+        self.role = Some(role);
+    }
+
     /// Receive a message, if any available
     pub fn try_recv(&mut self) -> Option<Request> {
-        self.recv.try_recv().ok()
+        self.update_session();
+        self.session.as_ref()?.recv.try_recv().ok()
     }
 
-    /// Send a message
-    /// Panics if the channel is disconnected
-    pub fn send(&mut self, msg: Response) {
-        self.send.send(msg).expect("Disconnected");
+    /// Send a response to the current controller connection
+    pub fn send(&mut self, msg: Response) -> Result<(), Disconnected> {
+        let session = self.session.as_ref().ok_or(Disconnected)?;
+        if session.send.send(msg).is_err() {
+            self.session = None;
+            return Err(Disconnected);
+        }
+        Ok(())
+    }
+
+    /// Receive a new downstream proxy connection, if any
+    pub fn try_recv_peer(&mut self) -> Option<PeerSession> {
+        self.peers.try_recv().ok()
+    }
+
+    /// Send an asynchronous update to the current controller connection
+    pub fn send_update(&mut self, update: Update) -> Result<(), Disconnected> {
+        let session = self.session.as_ref().ok_or(Disconnected)?;
+        if session.update.send(update).is_err() {
+            self.session = None;
+            return Err(Disconnected);
+        }
+        Ok(())
     }
 }
 
-fn to_json_line<T>(v: &T) -> Vec<u8>
+/// How often pending updates are sent while waiting for a request
+const UPDATE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How often the listener checks for new connections and whether it should close
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Controller connection waiting to be served, with its peer address and first line
+type PendingController = (BufStream<TcpStream>, String, Vec<u8>);
+
+/// Encode a message as a JSON line, as sent to the remote controller
+pub(crate) fn to_json_line<T>(v: &T) -> Vec<u8>
 where
     T: Serialize,
 {
@@ -47,75 +149,206 @@ where
     vs
 }
 
+/// Process requests from a single controller connection, starting with `first_line`
+/// Returns Ok(()) if quit was requested or the listener disabled or moved,
+/// and an error when the connection closes
 fn process_line(
-    mut stream: BufStream<TcpStream>, tx_recv: &mut Sender<Request>, rx_send: &mut Receiver<Response>,
-    rx_update: &mut Receiver<Update>,
+    mut stream: BufStream<TcpStream>, first_line: Vec<u8>, tx_recv: &mut Sender<Request>,
+    rx_send: &mut Receiver<Response>, rx_update: &mut Receiver<Update>, connection: &str,
+    audit: &mut Option<AuditLog>,
 ) -> io::Result<()> {
+    let mut role = None;
+    // Wake up regularly to send updates, e.g. replay chunks, while waiting for a request
+    stream.get_ref().set_read_timeout(Some(UPDATE_POLL_INTERVAL))?;
+    // Bytes of the next request received so far, kept over read timeouts
+    let mut has_line = !first_line.is_empty();
+    let mut line: Vec<u8> = first_line;
     loop {
-        let mut line = String::new();
         let mut updates: Vec<Update> = Vec::new();
-        stream.read_line(&mut line)?;
+        if !has_line {
+            match stream.read_until(b'\n', &mut line) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(_) => {},
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    while let Ok(update) = rx_update.try_recv() {
+                        stream.write_all(&to_json_line(&update))?;
+                    }
+                    stream.flush()?;
+                    continue;
+                },
+                Err(e) => return Err(e),
+            }
+        }
+        has_line = false;
+        let request = serde_json::from_slice::<Request>(&line);
+        line.clear();
 
-        match serde_json::from_str::<Request>(&line) {
+        match request {
             Ok(req) => {
                 debug!("Request: {:?}", req);
-                tx_recv.send(req).expect("Could not send");
-                let resp = rx_send.recv().expect("Could not recv");
+                // Supervisor side disconnected
+                if tx_recv.send(req.clone()).is_err() {
+                    return Err(io::ErrorKind::BrokenPipe.into());
+                }
+                let resp = match rx_send.recv() {
+                    Ok(resp) => resp,
+                    Err(_) => return Err(io::ErrorKind::BrokenPipe.into()),
+                };
                 while let Ok(u) = rx_update.try_recv() {
                     updates.push(u);
                 }
                 debug!("Response: {:?}", resp);
+                if let Some(log) = audit {
+                    log.record(connection, role, Some(&req), &resp);
+                }
+                if let Response::Authenticate(r) = resp {
+                    role = Some(r);
+                }
 
-                stream.write(&to_json_line(&resp))?;
+                stream.write_all(&to_json_line(&resp))?;
 
-                if resp == message::Response::Quit {
-                    return Ok(());
+                match resp {
+                    Response::Quit | Response::DisableRemoteControl | Response::RebindRemoteControl(_) => {
+                        stream.flush()?;
+                        return Ok(());
+                    },
+                    _ => {},
                 }
             },
             Err(e) => {
-                stream.write(&to_json_line(&Response::Error(format!("Invalid request: {}", e))))?;
+                let resp = Response::Error(format!("Invalid request: {}", e));
+                if let Some(log) = audit {
+                    log.record(connection, role, None, &resp);
+                }
+                stream.write_all(&to_json_line(&resp))?;
             },
         };
         stream.flush()?;
 
         for update in updates {
-            stream.write(&to_json_line(&update))?;
+            stream.write_all(&to_json_line(&update))?;
+        }
+        stream.flush()?;
+    }
+}
+
+/// Tell a new connection apart by its first line, and pass it on
+/// Downstream proxies are served on this thread, and controllers are queued for `serve_controllers`
+fn classify_connection(
+    stream: TcpStream, peer: String, controllers: &Sender<PendingController>, peers: &Sender<PeerSession>,
+) {
+    let mut stream = BufStream::new(stream);
+    let mut first_line = Vec::new();
+    match stream.read_until(b'\n', &mut first_line) {
+        Ok(0) => return,
+        Ok(_) => {},
+        Err(e) => {
+            warn!("Could not read from {}: {:?}", peer, e);
+            return;
+        },
+    }
+    match serde_json::from_slice::<PeerMessage>(&first_line) {
+        Ok(PeerMessage::Hello { name, token }) => {
+            info!("Downstream proxy {:?} connected from {}", name, peer);
+            if let Err(e) = federation::serve_peer(stream, name, token, peers) {
+                warn!("Downstream proxy connection from {} closed: {:?}", peer, e);
+            }
+        },
+        _ => {
+            // Dropped if the listener has been closed meanwhile
+            let _ = controllers.send((stream, peer, first_line));
+        },
+    }
+}
+
+/// Serve controller connections one at a time, in the order they sent their first request
+/// Returns when quit is requested, the listener is disabled or moved, or the supervisor is gone
+fn serve_controllers(
+    controllers: Receiver<PendingController>, sessions: Sender<Session>, mut audit_log: Option<AuditLog>,
+) {
+    for (stream, peer, first_line) in controllers.iter() {
+        // Fresh channels for each connection, so that responses
+        // meant for a closed connection are never delivered to a new one
+        let (mut tx_recv, rx_recv) = channel::unbounded::<Request>();
+        let (tx_send, mut rx_send) = channel::unbounded::<Response>();
+        let (tx_update, mut rx_update) = channel::unbounded::<Update>();
+        let session = Session {
+            recv: rx_recv,
+            send: tx_send,
+            update: tx_update,
+        };
+        if sessions.send(session).is_err() {
+            warn!("Supervisor disconnected, closing the remote controller");
+            return;
+        }
+
+        let result = process_line(
+            stream,
+            first_line,
+            &mut tx_recv,
+            &mut rx_send,
+            &mut rx_update,
+            &peer,
+            &mut audit_log,
+        );
+        match result {
+            Ok(()) => return,
+            Err(e) => warn!("Connection closed: {:?}", e),
         }
     }
 }
 
 /// Run the remote control server
-pub fn run_server(addr: &str) -> Remote {
-    let (mut tx_recv, rx_recv) = channel::unbounded::<Request>();
-    let (tx_send, mut rx_send) = channel::unbounded::<Response>();
-    let (tx_update, mut rx_update) = channel::unbounded::<Update>();
+/// The listener is closed when quit, `DisableRemoteControl` or `RebindRemoteControl` is requested,
+/// after which the server can be started again by calling this function
+/// Requests are recorded to `audit_log`, if any
+/// Returns an error if the listener cannot be bound
+pub fn run_server(addr: &str, audit_log: Option<AuditLog>) -> io::Result<Remote> {
+    let (tx_sessions, rx_sessions) = channel::unbounded::<Session>();
+    let (tx_peers, rx_peers) = channel::unbounded::<PeerSession>();
+    let (tx_controllers, rx_controllers) = channel::unbounded::<PendingController>();
+
+    let listener = TcpListener::bind(addr)?;
+    // Polled, so that the listener can be closed when the controller thread is done
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?.to_string();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let controllers_done = stop.clone();
+    thread::spawn(move || {
+        serve_controllers(rx_controllers, tx_sessions, audit_log);
+        controllers_done.store(true, Ordering::SeqCst);
+    });
 
-    let listener = TcpListener::bind(addr).expect("Could not listen to rc port");
     let handle = thread::spawn(move || {
         debug!("Ready to accept connections");
-        loop {
-            let stream = match listener.accept() {
-                Ok((s, addr)) => {
-                    info!("Connection from {:?} accepted", addr);
-                    BufStream::new(s)
-                },
-                Err(e) => {
-                    warn!("Accept failed: {:?}", e);
-                    continue;
+        while !stop.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    info!("Connection from {:?} accepted", peer);
+                    if let Err(e) = stream.set_nonblocking(false) {
+                        warn!("Could not use connection from {:?}: {:?}", peer, e);
+                        continue;
+                    }
+                    let controllers = tx_controllers.clone();
+                    let peers = tx_peers.clone();
+                    let peer = peer.to_string();
+                    thread::spawn(move || classify_connection(stream, peer, &controllers, &peers));
                 },
-            };
-
-            match process_line(stream, &mut tx_recv, &mut rx_send, &mut rx_update) {
-                Ok(()) => break,
-                Err(e) => warn!("Connection closed: {:?}", e),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL_INTERVAL),
+                Err(e) => warn!("Accept failed: {:?}", e),
             }
         }
     });
 
-    Remote {
-        recv: rx_recv,
-        send: tx_send,
-        update: tx_update,
+    Ok(Remote {
+        sessions: rx_sessions,
+        peers: rx_peers,
+        session: None,
+        role: None,
+        addr,
         handle,
-    }
+    })
 }
@@ -0,0 +1,78 @@
+//! Replay transfers to the remote controller, see `Request::FetchReplay`
+//!
+//! A saved replay is sent as a series of `Update::ReplayChunk` messages with base64 encoded data,
+//! followed by `Update::ReplayEnd` with the number of chunks and the CRC32 of the whole file.
+//! The file is read one chunk at a time, interleaved with processing the other requests.
+
+use flate2::Crc;
+use std::fs::File;
+use std::io::{self, Read};
+
+use crate::supervisor::GameId;
+
+use super::message::Update;
+
+/// Replay file being sent to the remote controller
+#[derive(Debug)]
+pub struct ReplayTransfer {
+    game_id: GameId,
+    file: File,
+    /// Size of each chunk, the last one can be smaller
+    chunk_bytes: usize,
+    /// Chunks sent so far
+    seq: u64,
+    /// Checksum of the chunks sent so far
+    crc: Crc,
+    /// Whether the end message has been sent
+    finished: bool,
+}
+impl ReplayTransfer {
+    /// Open the replay file of a game
+    /// Returns the transfer and the size of the file in bytes
+    pub fn open(game_id: GameId, path: &str, chunk_bytes: usize) -> io::Result<(Self, u64)> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let transfer = Self {
+            game_id,
+            file,
+            chunk_bytes: chunk_bytes.max(1),
+            seq: 0,
+            crc: Crc::new(),
+            finished: false,
+        };
+        Ok((transfer, size))
+    }
+
+    /// Game the replay belongs to
+    pub fn game_id(&self) -> GameId {
+        self.game_id
+    }
+
+    /// Read the next chunk, returning its update, or the end update after the last chunk
+    /// Returns None once the end update has been returned
+    pub fn next_update(&mut self) -> io::Result<Option<Update>> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let mut data = Vec::with_capacity(self.chunk_bytes);
+        (&mut self.file).take(self.chunk_bytes as u64).read_to_end(&mut data)?;
+        if data.is_empty() {
+            self.finished = true;
+            return Ok(Some(Update::ReplayEnd {
+                game_id: self.game_id,
+                chunks: self.seq,
+                crc32: self.crc.sum(),
+            }));
+        }
+
+        self.crc.update(&data);
+        let update = Update::ReplayChunk {
+            game_id: self.game_id,
+            seq: self.seq,
+            data_base64: base64::encode(&data),
+        };
+        self.seq += 1;
+        Ok(Some(update))
+    }
+}
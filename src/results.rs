@@ -0,0 +1,247 @@
+//! Game results, in a stable serializable format for external consumption
+
+pub use crate::game::{
+    ApmStats, GameEndReason, GameResult, LatencyHistogram, LatencyStats, LatencySummary,
+    PlayerOutcomeDetail, PlayerStats, ResultCategory, SlotAssignment, StallDetector, VoidReason,
+};
+pub use crate::config::{ResultFormat, ValidityRules};
+pub use crate::sc2::{PlayerResult, Race};
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::supervisor::GameId;
+
+/// Game loops per game second, at the faster game speed used by the API
+const LOOPS_PER_SEC: f64 = 22.4;
+
+/// Classify a finished game by the validity rules, returning the reason if it is void
+pub fn void_reason(rules: &ValidityRules, result: &GameResult) -> Option<VoidReason> {
+    for (slot, detail) in result.player_details.iter().enumerate() {
+        match *detail {
+            PlayerOutcomeDetail::SC2Crashed if rules.sc2_crash => {
+                return Some(VoidReason::SC2Crashed { slot });
+            },
+            PlayerOutcomeDetail::ClientCrashedBeforeStart if rules.disconnect_before_start => {
+                return Some(VoidReason::EarlyDisconnect { slot, game_loop: None });
+            },
+            PlayerOutcomeDetail::ClientDisconnected { game_loop }
+                if game_loop < rules.disconnect_before_loop =>
+            {
+                return Some(VoidReason::EarlyDisconnect {
+                    slot,
+                    game_loop: Some(game_loop),
+                });
+            },
+            _ => {},
+        }
+    }
+    let game_loop = result.game_loop();
+    if f64::from(game_loop) < f64::from(rules.min_duration_secs) * LOOPS_PER_SEC {
+        return Some(VoidReason::TooShort { game_loop });
+    }
+    None
+}
+
+/// Cumulative game counters since the proxy was started
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct GameStats {
+    /// Finished games, including the ones that crashed
+    pub total_games: u64,
+    /// Games that crashed without a result
+    pub crashed_games: u64,
+    /// Participants with a victory, by race
+    pub wins_by_race: HashMap<Race, u64>,
+    /// Games with a result, by end reason
+    pub games_by_end_reason: HashMap<GameEndReason, u64>,
+    /// Games voided by `record_results.validity`, their wins are not counted
+    #[serde(default)]
+    pub void_games: u64,
+}
+impl GameStats {
+    /// Count a finished game
+    pub fn record(&mut self, result: &GameResult) {
+        self.total_games += 1;
+        *self.games_by_end_reason.entry(result.end_reason).or_insert(0) += 1;
+        if !result.valid {
+            self.void_games += 1;
+            return;
+        }
+        for (race, player_result) in result.player_races.iter().zip(result.participant_results()) {
+            if player_result == Some(PlayerResult::Victory) {
+                *self.wins_by_race.entry(*race).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// Count a game that crashed without a result
+    pub fn record_crash(&mut self) {
+        self.total_games += 1;
+        self.crashed_games += 1;
+    }
+}
+
+/// Win/loss record of a bot
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BotRecord {
+    /// Games won
+    pub wins: u64,
+    /// Games lost
+    pub losses: u64,
+    /// Games tied
+    pub ties: u64,
+}
+
+/// Win/loss records of bots by identifier
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Standings {
+    /// Records by bot identifier
+    pub bots: HashMap<String, BotRecord>,
+}
+impl Standings {
+    /// Load standings from a JSON file, empty if the file does not exist
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save standings to a JSON file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = serde_json::to_string(self).expect("Could not serialize standings");
+        fs::write(path, text)
+    }
+
+    /// Count the results of named participants of a finished game,
+    /// skipping void games and games with a simulated network
+    pub fn record(&mut self, result: &GameResult) {
+        if !result.valid || result.network_sim.is_some() {
+            return;
+        }
+        for (name, player_result) in result.player_names.iter().zip(result.participant_results()) {
+            if let (Some(name), Some(player_result)) = (name, player_result) {
+                let record = self.bots.entry(name.clone()).or_default();
+                match player_result {
+                    PlayerResult::Victory => record.wins += 1,
+                    PlayerResult::Defeat => record.losses += 1,
+                    PlayerResult::Tie => record.ties += 1,
+                }
+            }
+        }
+    }
+}
+
+/// Outcome of a game as reported to the AI Arena ladder, with the participants in join order
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AiArenaOutcome {
+    /// The first participant won
+    Player1Win,
+    /// The second participant, or the computer, won
+    Player2Win,
+    /// The first participant disconnected before the game was over
+    Player1Crash,
+    /// The second participant disconnected before the game was over
+    Player2Crash,
+    /// Neither won, e.g. on the time limit
+    Tie,
+    /// The game could not be played to the end, e.g. SC2 crashed or the game was quit
+    Error,
+}
+impl AiArenaOutcome {
+    /// Outcome of a finished game. Against a computer, it is the second player.
+    pub fn from_result(result: &GameResult) -> Self {
+        match result.end_reason {
+            GameEndReason::QuitRequest | GameEndReason::NoContest => return AiArenaOutcome::Error,
+            GameEndReason::Normal | GameEndReason::TimeLimit | GameEndReason::Stalemate => {},
+        }
+        for (slot, detail) in result.player_details.iter().enumerate().take(2) {
+            match detail {
+                PlayerOutcomeDetail::ClientDisconnected { .. }
+                | PlayerOutcomeDetail::ClientCrashedBeforeStart => {
+                    return if slot == 0 {
+                        AiArenaOutcome::Player1Crash
+                    } else {
+                        AiArenaOutcome::Player2Crash
+                    };
+                },
+                PlayerOutcomeDetail::SC2Crashed => return AiArenaOutcome::Error,
+                _ => {},
+            }
+        }
+        match result.participant_results().first() {
+            Some(Some(PlayerResult::Victory)) => AiArenaOutcome::Player1Win,
+            Some(Some(PlayerResult::Defeat)) => AiArenaOutcome::Player2Win,
+            Some(Some(PlayerResult::Tie)) => AiArenaOutcome::Tie,
+            _ => AiArenaOutcome::Error,
+        }
+    }
+}
+
+/// Result of a game in the JSON schema of the AI Arena ladder, see `ResultFormat::Aiarena`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct AiArenaResult {
+    /// The external id of the game if given, otherwise the game id
+    #[serde(rename = "Match")]
+    pub match_id: String,
+    /// Name of the first participant
+    pub bot1: Option<String>,
+    /// Name of the second participant, None against a computer
+    pub bot2: Option<String>,
+    /// Outcome of the game
+    pub result: AiArenaOutcome,
+    /// Last game loop of the game
+    pub game_time: u32,
+    /// Game time in game seconds
+    pub game_time_seconds: f64,
+    /// Game time as `HH:MM:SS`
+    pub game_time_formatted: String,
+    /// Map name from the config
+    pub map: Option<String>,
+    /// When the result was written, in RFC 3339
+    pub time_stamp: String,
+}
+impl AiArenaResult {
+    /// Convert a game result, `map` being the map name the game was played on
+    pub fn new(id: GameId, result: &GameResult, map: Option<&str>, time: SystemTime) -> Self {
+        let game_time = result.game_loop();
+        let game_time_seconds = f64::from(game_time) / LOOPS_PER_SEC;
+        let secs = game_time_seconds as u64;
+        Self {
+            match_id: result.external_id.clone().unwrap_or_else(|| id.to_string()),
+            bot1: result.player_names.first().cloned().flatten(),
+            bot2: result.player_names.get(1).cloned().flatten(),
+            result: AiArenaOutcome::from_result(result),
+            game_time,
+            game_time_seconds,
+            game_time_formatted: format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60),
+            map: map.map(str::to_owned),
+            time_stamp: humantime::format_rfc3339_seconds(time).to_string(),
+        }
+    }
+}
+
+/// Write the result of a game to `<dir>/<game id>.json` in `format`,
+/// `map` being the map name the game was played on. Returns the path of the file.
+pub fn write_result(
+    dir: &Path, id: GameId, result: &GameResult, format: ResultFormat, map: Option<&str>,
+) -> io::Result<PathBuf> {
+    let text = match format {
+        ResultFormat::Native => serde_json::to_string(result),
+        ResultFormat::Aiarena => {
+            let converted = AiArenaResult::new(id, result, map, SystemTime::now());
+            serde_json::to_string(&converted)
+        },
+    };
+    let path = dir.join(format!("{}.json", id));
+    fs::write(&path, text.expect("Could not serialize result"))?;
+    Ok(path)
+}
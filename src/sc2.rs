@@ -7,7 +7,7 @@ use sc2_proto;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Race {
     Protoss,
     Terran,
@@ -26,6 +26,15 @@ impl Race {
         }
     }
 
+    /// Like `from_proto`, but None for NoRace
+    pub fn try_from_proto(race: sc2_proto::common::Race) -> Option<Self> {
+        if race == sc2_proto::common::Race::NoRace {
+            None
+        } else {
+            Some(Self::from_proto(race))
+        }
+    }
+
     pub fn to_proto(&self) -> sc2_proto::common::Race {
         use sc2_proto::common::Race;
         match self {
@@ -87,6 +96,7 @@ pub struct BuiltinAI {
 
 /// Result of a player
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
 pub enum PlayerResult {
     Victory,
     Defeat,
@@ -112,3 +122,147 @@ impl PlayerResult {
         }
     }
 }
+
+/// Kind of a player in a game
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayerType {
+    Participant,
+    Computer,
+    Observer,
+}
+impl PlayerType {
+    pub fn from_proto(player_type: sc2_proto::sc2api::PlayerType) -> Self {
+        use sc2_proto::sc2api::PlayerType;
+        match player_type {
+            PlayerType::Participant => Self::Participant,
+            PlayerType::Computer => Self::Computer,
+            PlayerType::Observer => Self::Observer,
+        }
+    }
+}
+
+/// State of an SC2 session, as reported in every response
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    Launched,
+    InitGame,
+    InGame,
+    InReplay,
+    Ended,
+    Quit,
+    Unknown,
+}
+impl SessionStatus {
+    pub fn from_proto(status: sc2_proto::sc2api::Status) -> Self {
+        use sc2_proto::sc2api::Status;
+        match status {
+            Status::launched => Self::Launched,
+            Status::init_game => Self::InitGame,
+            Status::in_game => Self::InGame,
+            Status::in_replay => Self::InReplay,
+            Status::ended => Self::Ended,
+            Status::quit => Self::Quit,
+            Status::unknown => Self::Unknown,
+        }
+    }
+
+    /// Checks if SC2 must leave the game before it can host or join another one
+    pub fn is_in_game(self) -> bool {
+        match self {
+            Self::InitGame | Self::InGame | Self::InReplay | Self::Ended => true,
+            Self::Launched | Self::Quit | Self::Unknown => false,
+        }
+    }
+}
+
+/// Economy and army numbers of a player, from the score of an observation
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct ScoreSnapshot {
+    /// Game loop of the observation
+    pub game_loop: u32,
+    /// Total score, as shown by SC2
+    pub score: i32,
+    pub collected_minerals: f32,
+    pub collected_vespene: f32,
+    /// Minerals collected per minute
+    pub collection_rate_minerals: f32,
+    /// Vespene collected per minute
+    pub collection_rate_vespene: f32,
+    pub spent_minerals: f32,
+    pub spent_vespene: f32,
+    /// Resources in the current army units
+    pub army_value: f32,
+    /// Resources in the current workers and economic structures
+    pub economy_value: f32,
+    /// Resources in the current structures
+    #[serde(default)]
+    pub structures_value: f32,
+    /// Resources in the enemy units and structures killed
+    pub killed_value: f32,
+    /// Resources in the own army units lost
+    pub lost_army_value: f32,
+    pub food_used: f32,
+}
+impl ScoreSnapshot {
+    /// Snapshot of the score of an observation, None if it has no score
+    pub fn from_proto(obs: &sc2_proto::sc2api::Observation) -> Option<Self> {
+        if !obs.has_score() {
+            return None;
+        }
+        let score = obs.get_score();
+        let d = score.get_score_details();
+        let food = d.get_food_used();
+        Some(Self {
+            game_loop: obs.get_game_loop(),
+            score: score.get_score(),
+            collected_minerals: d.get_collected_minerals(),
+            collected_vespene: d.get_collected_vespene(),
+            collection_rate_minerals: d.get_collection_rate_minerals(),
+            collection_rate_vespene: d.get_collection_rate_vespene(),
+            spent_minerals: d.get_spent_minerals(),
+            spent_vespene: d.get_spent_vespene(),
+            army_value: d.get_used_minerals().get_army() + d.get_used_vespene().get_army(),
+            economy_value: d.get_used_minerals().get_economy() + d.get_used_vespene().get_economy(),
+            structures_value: d.get_total_value_structures(),
+            killed_value: d.get_killed_value_units() + d.get_killed_value_structures(),
+            lost_army_value: d.get_lost_minerals().get_army() + d.get_lost_vespene().get_army(),
+            food_used: food.get_none()
+                + food.get_army()
+                + food.get_economy()
+                + food.get_technology()
+                + food.get_upgrade(),
+        })
+    }
+}
+
+/// Name of the request type, e.g. `create_game`, for logs and kick reasons
+pub fn request_kind(req: &sc2_proto::sc2api::Request) -> &'static str {
+    use sc2_proto::sc2api::Request_oneof_request::*;
+    match req.request {
+        Some(create_game(_)) => "create_game",
+        Some(join_game(_)) => "join_game",
+        Some(restart_game(_)) => "restart_game",
+        Some(start_replay(_)) => "start_replay",
+        Some(leave_game(_)) => "leave_game",
+        Some(quick_save(_)) => "quick_save",
+        Some(quick_load(_)) => "quick_load",
+        Some(quit(_)) => "quit",
+        Some(game_info(_)) => "game_info",
+        Some(observation(_)) => "observation",
+        Some(action(_)) => "action",
+        Some(obs_action(_)) => "obs_action",
+        Some(step(_)) => "step",
+        Some(data(_)) => "data",
+        Some(query(_)) => "query",
+        Some(save_replay(_)) => "save_replay",
+        Some(map_command(_)) => "map_command",
+        Some(replay_info(_)) => "replay_info",
+        Some(available_maps(_)) => "available_maps",
+        Some(save_map(_)) => "save_map",
+        Some(ping(_)) => "ping",
+        Some(debug(_)) => "debug",
+        None => "empty",
+    }
+}
@@ -1,27 +1,43 @@
 //! SC2 process manager
 
+use std::collections::HashMap;
+use std::fs;
+use std::io;
 use std::io::ErrorKind::ConnectionRefused;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
 use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 use std::thread::sleep;
 use std::time::Duration;
 
 use log::{debug, info, warn};
 
-use portpicker::pick_unused_port;
+use protobuf::{parse_from_bytes, Message};
+use sc2_proto::sc2api::{Request, Response};
 use serde::{Deserialize, Serialize};
 use tempfile::TempDir;
 use websocket::client::sync::Client;
 use websocket::stream::sync::TcpStream;
-use websocket::ClientBuilder;
+use websocket::{ClientBuilder, OwnedMessage};
 
 use crate::paths;
+use crate::portconfig::PortLease;
+
+/// Time SC2 has to confirm quitting before its process is killed
+const QUIT_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Default verbosity level for SC2 process
 fn default_verbosity() -> bool {
     true
 }
 
+/// Temp dirs are removed by default
+fn default_cleanup_temp_dir() -> bool {
+    true
+}
+
 /// Options for SC2 process
 #[allow(missing_docs)]
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -30,16 +46,60 @@ pub struct ProcessOptions {
     pub fullscreen: bool,
     #[serde(default = "default_verbosity")]
     pub verbose: bool,
+    /// Rendering backend, unknown names are rejected when loading the config
+    #[serde(default)]
+    pub renderer: Renderer,
+    /// How SC2 processes are pinned to CPU cores
+    #[serde(default)]
+    pub cpu_affinity: CpuAffinity,
+    /// Cores used with `cpu_affinity`, all available cores if empty
+    #[serde(default)]
+    pub affinity_cores: Vec<usize>,
+    /// Directory under which each SC2 process gets its own temp dir, created if missing.
+    /// The system temp dir if not set.
+    #[serde(default)]
+    pub temp_dir: Option<String>,
+    /// Remove the temp dir of a process when it's killed. Disable to inspect the files afterwards.
+    #[serde(default = "default_cleanup_temp_dir")]
+    pub cleanup_temp_dir: bool,
+    /// Directory maps are searched in instead of the `Maps` directory of the SC2 installation,
+    /// see `maps::find_map_in`
+    #[serde(default)]
+    pub map_dir: Option<String>,
+    /// SC2 executable to launch instead of the latest installed version, e.g. a modded build.
+    /// Data is still read from the SC2 installation.
+    #[serde(default)]
+    pub executable: Option<String>,
+    /// Additional environment variables for the SC2 process
+    /// Tables must come after plain values for TOML serialization
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Options for the SC2 process of a dedicated host, which observes the game, instead of these.
+    /// Ignored inside the preset itself and in per-player overrides.
+    #[serde(default)]
+    pub spectator_defaults: Option<Box<ProcessOptions>>,
 }
 impl ProcessOptions {
-    fn apply(self, mut cmd: &mut Command) -> &mut Command {
-        cmd = cmd
-            .arg("-displayMode")
-            .arg(if self.fullscreen { "1" } else { "0" });
+    /// Options for the SC2 process of a dedicated host, see `spectator_defaults`
+    pub fn spectator(&self) -> ProcessOptions {
+        match &self.spectator_defaults {
+            Some(preset) => (**preset).clone(),
+            None => self.clone(),
+        }
+    }
+
+    /// Command line arguments given by these options
+    fn args(&self) -> Vec<String> {
+        let mut args = vec!["-displayMode".to_owned(), if self.fullscreen { "1" } else { "0" }.to_owned()];
         if self.verbose {
-            cmd = cmd.arg("-verbose");
+            args.push("-verbose".to_owned());
         }
-        cmd
+        match self.renderer {
+            Renderer::Default => {},
+            Renderer::Egl => args.extend(vec!["-eglpath".to_owned(), "libEGL.so".to_owned()]),
+            Renderer::OsMesa => args.extend(vec!["-osmesapath".to_owned(), "libOSMesa.so".to_owned()]),
+        }
+        args
     }
 }
 impl Default for ProcessOptions {
@@ -47,53 +107,182 @@ impl Default for ProcessOptions {
         Self {
             fullscreen: false,
             verbose: true,
+            renderer: Renderer::default(),
+            cpu_affinity: CpuAffinity::default(),
+            affinity_cores: Vec::new(),
+            temp_dir: None,
+            cleanup_temp_dir: default_cleanup_temp_dir(),
+            map_dir: None,
+            executable: None,
+            env: HashMap::new(),
+            spectator_defaults: None,
         }
     }
 }
 
+/// Rendering backend of the SC2 process
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Renderer {
+    /// Let SC2 choose
+    Default,
+    /// Hardware rendering using EGL, for headless Linux hosts with a GPU
+    Egl,
+    /// Software rendering using OSMesa, for headless Linux hosts without a GPU
+    OsMesa,
+}
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer::Default
+    }
+}
+
+/// Pinning of SC2 processes to CPU cores, only supported on Linux
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CpuAffinity {
+    /// Let the OS schedule the processes
+    None,
+    /// Pin each process to a single core, taking the cores in turns
+    RoundRobin,
+}
+impl Default for CpuAffinity {
+    fn default() -> Self {
+        CpuAffinity::None
+    }
+}
+
+/// Next core index for `CpuAffinity::RoundRobin`, shared by all launched processes
+static NEXT_CORE: AtomicUsize = AtomicUsize::new(0);
+
+impl CpuAffinity {
+    /// Core the next launched process should be pinned to, if any
+    fn next_core(self, cores: &[usize]) -> Option<usize> {
+        match self {
+            CpuAffinity::None => None,
+            CpuAffinity::RoundRobin => {
+                let index = NEXT_CORE.fetch_add(1, Ordering::Relaxed);
+                if cores.is_empty() {
+                    let count = thread::available_parallelism().map_or(1, |n| n.get());
+                    Some(index % count)
+                } else {
+                    Some(cores[index % cores.len()])
+                }
+            },
+        }
+    }
+}
+
+/// Pin a process to a single core. Threads it starts later inherit the affinity.
+#[cfg(target_os = "linux")]
+fn set_affinity(pid: u32, core: usize) -> io::Result<()> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "No such core"));
+    }
+    // Safety: the set is a plain bitmask, and its size is passed along with it
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(pid as libc::pid_t, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Pin a process to a single core. Not supported on this platform.
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_pid: u32, _core: usize) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "CPU affinity is only supported on Linux"))
+}
+
 /// SC2 process
 #[derive(Debug)]
 pub struct Process {
     /// The actual SC2 process
     process: Child,
-    /// Temp data dir used by SC2
-    tempdir: TempDir,
+    /// Temp data dir used by SC2, removed when the process is killed or dropped
+    /// None if it has been removed, or if it's kept because `cleanup_temp_dir` is not set
+    tempdir: Option<TempDir>,
+    /// Path of the temp data dir
+    temp_path: PathBuf,
     /// WebSocket port
     ws_port: u16,
+    /// Executable and arguments the process was launched with
+    command: Vec<String>,
+    /// Keeps the WebSocket port reserved from games starting concurrently
+    _ws_lease: PortLease,
 }
 impl Process {
     /// Launch a new process
     pub fn new(options: ProcessOptions) -> Self {
-        let ws_port = pick_unused_port().expect("Could not find a free port");
-        let tempdir = TempDir::new().expect("Could not create temp dir");
-
-        debug!("Starting a new SC2 process");
-
-        let process = options
-            .apply(
-                Command::new(paths::executable())
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .arg("-listen")
-                    .arg("127.0.0.1")
-                    .arg("-port")
-                    .arg(ws_port.to_string())
-                    .arg("-dataDir")
-                    .arg(paths::base_dir().to_str().unwrap())
-                    .arg("-tempDir")
-                    .arg(tempdir.path().to_str().unwrap())
-                    .current_dir(paths::cwd_dir()),
-            )
+        let ws_lease = PortLease::new(1).expect("Could not find a free port");
+        let ws_port = ws_lease.ports()[0];
+        let tempdir = match &options.temp_dir {
+            Some(root) => {
+                let root = shellexpand::tilde(root).into_owned();
+                fs::create_dir_all(&root).expect("Could not create temp dir root");
+                TempDir::new_in(root)
+            },
+            None => TempDir::new(),
+        };
+        let tempdir = tempdir.expect("Could not create temp dir");
+        let (tempdir, temp_path) = if options.cleanup_temp_dir {
+            let path = tempdir.path().to_path_buf();
+            (Some(tempdir), path)
+        } else {
+            (None, tempdir.into_path())
+        };
+
+        let executable = match &options.executable {
+            Some(path) => shellexpand::tilde(path).into_owned(),
+            None => paths::executable().to_str().unwrap().to_owned(),
+        };
+        let mut command = vec![
+            executable,
+            "-listen".to_owned(),
+            "127.0.0.1".to_owned(),
+            "-port".to_owned(),
+            ws_port.to_string(),
+            "-dataDir".to_owned(),
+            paths::base_dir().to_str().unwrap().to_owned(),
+            "-tempDir".to_owned(),
+            temp_path.to_str().unwrap().to_owned(),
+        ];
+        command.extend(options.args());
+        debug!("Starting a new SC2 process: {}", command.join(" "));
+
+        let core = options.cpu_affinity.next_core(&options.affinity_cores);
+        let process = Command::new(&command[0])
+            .args(&command[1..])
+            .envs(options.env)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .current_dir(paths::cwd_dir())
             .spawn()
             .expect("Could not launch SC2 process");
 
+        if let Some(core) = core {
+            match set_affinity(process.id(), core) {
+                Ok(()) => debug!("SC2 process pinned to core {}", core),
+                Err(e) => warn!("Could not pin SC2 process to core {}: {}", core, e),
+            }
+        }
+
         Self {
             process,
             tempdir,
+            temp_path,
             ws_port,
+            command,
+            _ws_lease: ws_lease,
         }
     }
 
+    /// Executable and arguments the process was launched with, for auditing
+    /// Additional environment variables from `ProcessOptions::env` are not included
+    pub fn command(&self) -> &[String] {
+        &self.command
+    }
+
     /// Connect the process websocket
     pub fn connect(&self) -> Option<Client<std::net::TcpStream>> {
         let url = format!("ws://127.0.0.1:{}/sc2api", self.ws_port);
@@ -131,9 +320,58 @@ impl Process {
         self.process.kill().expect("SC2 process was not running");
     }
 
-    /// Kill the process
+    /// Ask SC2 to quit through its websocket connection, then kill the process
+    /// SC2 has `QUIT_TIMEOUT` to confirm quitting
+    pub fn quit(&mut self, sc2_ws: &mut Client<std::net::TcpStream>) {
+        if !request_quit(sc2_ws) {
+            warn!("SC2 did not confirm quitting, killing it");
+        }
+        self.kill();
+    }
+
+    /// Kill the process, and remove its temp dir unless `cleanup_temp_dir` is disabled
     pub fn kill(&mut self) {
         info!("Killing the sc2 process");
         self.process.kill().expect("Could not kill SC2 process");
+        // Reap the process, so it's not left as a zombie
+        let _ = self.process.wait();
+        self.remove_temp_dir();
+    }
+
+    /// Remove the temp dir, after the process has exited
+    fn remove_temp_dir(&mut self) {
+        if let Some(tempdir) = self.tempdir.take() {
+            if let Err(e) = tempdir.close() {
+                warn!("Could not remove SC2 temp dir {}: {}", self.temp_path.display(), e);
+            }
+        }
+    }
+}
+impl Drop for Process {
+    /// Kill the process if it's still running, e.g. when a game thread panics
+    fn drop(&mut self) {
+        if let Ok(None) = self.process.try_wait() {
+            warn!("SC2 process still running on cleanup, killing it");
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+        self.remove_temp_dir();
+    }
+}
+
+/// Send a quit request, waiting at most `QUIT_TIMEOUT` for the response
+/// Returns true if SC2 confirmed quitting
+fn request_quit(sc2_ws: &mut Client<std::net::TcpStream>) -> bool {
+    let mut req = Request::new();
+    req.mut_quit();
+    let bytes = req.write_to_bytes().expect("Invalid protobuf message");
+    if sc2_ws.stream_ref().set_read_timeout(Some(QUIT_TIMEOUT)).is_err()
+        || sc2_ws.send_message(&OwnedMessage::Binary(bytes)).is_err()
+    {
+        return false;
+    }
+    match sc2_ws.recv_message() {
+        Ok(OwnedMessage::Binary(bytes)) => parse_from_bytes::<Response>(&bytes).is_ok_and(|r| r.has_quit()),
+        _ => false,
     }
 }
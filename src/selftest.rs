@@ -0,0 +1,240 @@
+//! Self-test checking that an installation works, run with `sc2-proxy --selftest`
+//!
+//! Launches an SC2 process, creates a game between two builtin AIs on the configured map,
+//! or on any installed map if none is configured, and observes it until it ends.
+//! This exercises the paths, process launching, port and game setup without needing any bots.
+
+use log::info;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use protobuf::{parse_from_bytes, Message, RepeatedField};
+use sc2_proto::sc2api::{
+    InterfaceOptions, LocalMap, PlayerSetup, PlayerType, Request, RequestCreateGame, RequestJoinGame,
+    Response, Status,
+};
+use websocket::OwnedMessage;
+
+use crate::config::Config;
+use crate::game::any_panic_to_string;
+use crate::maps::{find_map_in, first_map};
+use crate::proxy::Client;
+use crate::sc2::PlayerResult;
+use crate::sc2process::Process;
+
+/// Game loops stepped at once
+const STEP_SIZE: u32 = 16;
+
+/// Outcome of a successful self-test
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// Path of the map, relative to the map directory
+    pub map_path: String,
+    /// Game loop of the last observation
+    pub game_loops: u32,
+    /// Result of each builtin AI by player id
+    /// Empty if the game was stopped at `time_limits.game_loops` before it ended
+    pub results: Vec<(u32, PlayerResult)>,
+    /// Time the self-test took, including launching SC2
+    pub elapsed: Duration,
+}
+impl Report {
+    /// Checks if the game ran until it ended
+    pub fn is_finished(&self) -> bool {
+        !self.results.is_empty()
+    }
+}
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.elapsed.as_secs_f64();
+        write!(f, "{} game loops on {} in {:.1}s", self.game_loops, self.map_path, secs)?;
+        if self.is_finished() {
+            write!(f, ", results {:?}", self.results)
+        } else {
+            write!(f, ", stopped at the game loop limit")
+        }
+    }
+}
+
+/// Reason the self-test failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelfTestError {
+    /// No usable map
+    Map(String),
+    /// Launching or playing panicked, e.g. because the SC2 binary was not found
+    Panic(String),
+    /// Could not connect to the SC2 process
+    Connect,
+    /// SC2 closed the connection while answering a request
+    ConnectionClosed(&'static str),
+    /// SC2 answered a request with an error
+    Sc2 {
+        /// Kind of the request
+        request: &'static str,
+        /// Error reported by SC2
+        error: String,
+    },
+}
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Map(e) => write!(f, "{}", e),
+            Self::Panic(e) => write!(f, "Crashed: {}", e),
+            Self::Connect => write!(f, "Could not connect to the SC2 process"),
+            Self::ConnectionClosed(request) => write!(f, "SC2 closed the connection on {}", request),
+            Self::Sc2 { request, error } => write!(f, "SC2 error on {}: {}", request, error),
+        }
+    }
+}
+
+/// Run the self-test using the process, map, builtin AI and time limit settings of `config`
+pub fn run(config: &Config) -> Result<Report, SelfTestError> {
+    let start = Instant::now();
+    let map_dir = config.process.map_dir.as_deref();
+    let map_path = match &config.match_defaults.game.map_name {
+        Some(name) => find_map_in(map_dir, name.clone())
+            .ok_or_else(|| SelfTestError::Map(format!("Map {:?} not found", name)))?,
+        None => first_map(map_dir).ok_or_else(|| SelfTestError::Map("No maps installed".to_owned()))?,
+    };
+    info!("Self-test on {}", map_path);
+
+    let config = config.clone();
+    let thread_map_path = map_path.clone();
+    let (game_loops, results) = thread::spawn(move || {
+        let mut process = Process::new(config.process.clone());
+        match process.connect() {
+            Some(mut sc2_ws) => {
+                let result = play(&mut sc2_ws, &thread_map_path, &config);
+                process.quit(&mut sc2_ws);
+                result
+            },
+            None => {
+                process.kill();
+                Err(SelfTestError::Connect)
+            },
+        }
+    })
+    .join()
+    .map_err(|panic_msg| SelfTestError::Panic(any_panic_to_string(panic_msg)))??;
+
+    Ok(Report {
+        map_path,
+        game_loops,
+        results,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Create the game, join it as an observer, and step until it ends or reaches the time limit
+/// Returns the game loop of the last observation and the results
+fn play(
+    sc2_ws: &mut Client, map_path: &str, config: &Config,
+) -> Result<(u32, Vec<(u32, PlayerResult)>), SelfTestError> {
+    let mut r_local_map = LocalMap::new();
+    r_local_map.set_map_path(map_path.to_owned());
+    let mut r_create_game = RequestCreateGame::new();
+    r_create_game.set_local_map(r_local_map);
+    r_create_game.set_realtime(false);
+    let mut players: Vec<_> = (0..2)
+        .map(|_| {
+            let mut ps = PlayerSetup::new();
+            ps.set_field_type(PlayerType::Computer);
+            ps.set_race(config.matchmaking.cpu_race.to_proto());
+            ps.set_difficulty(config.matchmaking.cpu_difficulty.to_proto());
+            ps
+        })
+        .collect();
+    let mut observer = PlayerSetup::new();
+    observer.set_field_type(PlayerType::Observer);
+    players.push(observer);
+    r_create_game.set_player_setup(RepeatedField::from_vec(players));
+    let mut req = Request::new();
+    req.set_create_game(r_create_game);
+    let resp = query(sc2_ws, req, "create_game")?;
+    if resp.get_create_game().has_error() {
+        return Err(SelfTestError::Sc2 {
+            request: "create_game",
+            error: format!(
+                "{:?} {}",
+                resp.get_create_game().get_error(),
+                resp.get_create_game().get_error_details()
+            ),
+        });
+    }
+
+    let mut ifopts = InterfaceOptions::new();
+    ifopts.set_raw(true);
+    let mut r_join_game = RequestJoinGame::new();
+    r_join_game.set_options(ifopts);
+    r_join_game.set_observed_player_id(0);
+    let mut req = Request::new();
+    req.set_join_game(r_join_game);
+    let resp = query(sc2_ws, req, "join_game")?;
+    if resp.get_join_game().has_error() {
+        return Err(SelfTestError::Sc2 {
+            request: "join_game",
+            error: format!(
+                "{:?} {}",
+                resp.get_join_game().get_error(),
+                resp.get_join_game().get_error_details()
+            ),
+        });
+    }
+
+    let limit = config.match_defaults.time_limits.game_loops;
+    loop {
+        let mut req = Request::new();
+        req.mut_step().set_count(STEP_SIZE);
+        query(sc2_ws, req, "step")?;
+
+        let mut req = Request::new();
+        req.mut_observation();
+        let resp = query(sc2_ws, req, "observation")?;
+        let obs = resp.get_observation();
+        let game_loop = obs.get_observation().get_game_loop();
+
+        let results: Vec<_> = obs
+            .get_player_result()
+            .iter()
+            .filter(|r| r.get_result() != sc2_proto::sc2api::Result::Undecided)
+            .map(|r| (r.get_player_id(), PlayerResult::from_proto(r.get_result())))
+            .collect();
+        if !results.is_empty() {
+            return Ok((game_loop, results));
+        }
+        if resp.get_status() != Status::in_game {
+            return Err(SelfTestError::Sc2 {
+                request: "observation",
+                error: format!("Game ended without results, status {:?}", resp.get_status()),
+            });
+        }
+        if limit.is_some_and(|limit| game_loop as u64 >= limit) {
+            return Ok((game_loop, Vec::new()));
+        }
+    }
+}
+
+/// Send a request to SC2 and return the response, failing on errors reported by SC2
+fn query(sc2_ws: &mut Client, req: Request, request: &'static str) -> Result<Response, SelfTestError> {
+    let bytes = req.write_to_bytes().expect("Invalid protobuf message");
+    sc2_ws
+        .send_message(&OwnedMessage::Binary(bytes))
+        .map_err(|_| SelfTestError::ConnectionClosed(request))?;
+    let resp = match sc2_ws.recv_message() {
+        Ok(OwnedMessage::Binary(bytes)) => {
+            parse_from_bytes::<Response>(&bytes).map_err(|e| SelfTestError::Sc2 {
+                request,
+                error: e.to_string(),
+            })?
+        },
+        _ => return Err(SelfTestError::ConnectionClosed(request)),
+    };
+    if !resp.get_error().is_empty() {
+        return Err(SelfTestError::Sc2 {
+            request,
+            error: resp.get_error().join(", "),
+        });
+    }
+    Ok(resp)
+}
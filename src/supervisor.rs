@@ -2,50 +2,385 @@
 
 #![allow(dead_code)]
 
+use crossbeam::channel::{self, Receiver, Sender};
 use log::{debug, error, info, trace, warn};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
 use std::io::ErrorKind::WouldBlock;
+use std::num::ParseIntError;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
 
 use websocket::message::OwnedMessage;
 use websocket::result::WebSocketError;
 
 use protobuf::parse_from_bytes;
 use protobuf::Message;
-use sc2_proto::{self, sc2api::RequestJoinGame};
+use protobuf::RepeatedField;
+use sc2_proto::{
+    self,
+    sc2api::{Request, RequestJoinGame, ResponseGameInfo, ResponseJoinGame_Error},
+};
 
-use crate::config::{Config, MatchmakingMode};
-use crate::game::{spawn as spawn_game, FromSupervisor, GameLobby, Handle as GameHandle};
-use crate::proxy::Client;
-use crate::remote_control::Remote;
+use crate::config::{Config, MatchmakingMode, ProcessOptions, RemoteRole, ReplayNameFields};
+use crate::game::{
+    spawn as spawn_game, spawn_dedicated, spawn_start, FromSupervisor, GameLobby, GameResult,
+    Handle as GameHandle, LobbyProblem, StartHandle,
+};
+use crate::paths;
+use crate::proxy::{Client, ClientConnection, ConnectionMeta};
+use crate::remote_control::federation::{self, Peer, PeerMessage, Route, UpstreamLink};
+use crate::remote_control::transfer::ReplayTransfer;
+use crate::remote_control::{self, message as remote_message, Remote};
+use crate::results::{write_result, GameStats, Standings};
+use crate::sc2::{request_kind, ScoreSnapshot, SessionStatus};
 
 enum PlaylistAction {
     Respond(OwnedMessage),
-    RespondQuit(OwnedMessage),
+    RespondQuit(OwnedMessage, KickReason),
     JoinGame(sc2_proto::sc2api::RequestJoinGame),
-    Kick,
+    /// Start a session on a dedicated process, e.g. client hosted game or replay
+    Dedicated(sc2_proto::sc2api::Request),
+    Kick(KickReason),
 }
 impl PlaylistAction {
     pub fn respond(r: sc2_proto::sc2api::Response) -> Self {
         let m = OwnedMessage::Binary(r.write_to_bytes().expect("Invalid protobuf message"));
         PlaylistAction::Respond(m)
     }
-    pub fn respond_quit(r: sc2_proto::sc2api::Response) -> Self {
+    pub fn respond_quit(r: sc2_proto::sc2api::Response, reason: KickReason) -> Self {
         let m = OwnedMessage::Binary(r.write_to_bytes().expect("Invalid protobuf message"));
-        PlaylistAction::RespondQuit(m)
+        PlaylistAction::RespondQuit(m, reason)
+    }
+}
+
+/// Human-readable description of the reasons a lobby cannot be started
+fn describe_problems(problems: &[LobbyProblem]) -> String {
+    let problems: Vec<String> = problems.iter().map(ToString::to_string).collect();
+    format!("Cannot start game: {}", problems.join("; "))
+}
+
+/// Number of finished game results kept in memory
+const RECENT_RESULTS_COUNT: usize = 100;
+
+/// Number of clients removed from the playlist kept in memory, see `GetRecentKicks`
+const RECENT_KICKS_COUNT: usize = 100;
+
+/// Number of updates kept while waiting for the remote controller
+const PENDING_UPDATES_COUNT: usize = 1000;
+
+/// Number of replay chunks sent to the remote controller per update, see `FetchReplay`
+const REPLAY_CHUNKS_PER_UPDATE: usize = 16;
+
+/// How often playlist clients with a stored join request are polled for disconnects
+pub const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Set the socket mode of a client connection, logging a warning if it fails
+/// Returns None if the connection is unusable and should be dropped
+#[must_use]
+fn set_nonblocking(conn: &ClientConnection, nonblocking: bool) -> Option<()> {
+    match conn.set_nonblocking(nonblocking) {
+        Ok(()) => Some(()),
+        Err(e) => {
+            warn!("Could not set socket mode of client {}, dropping it: {}", conn.meta.peer_addr, e);
+            None
+        },
+    }
+}
+
+/// Number of start retries allowed for a lobby by the config it was created with, see `lobby_start_retries`
+fn start_retries(lobby: &GameLobby) -> u32 {
+    lobby.config().match_defaults.game.lobby_start_retries
+}
+
+/// Identifier a bot supplies for itself, currently the player name in the join request
+fn bot_identifier(req: &RequestJoinGame) -> Option<String> {
+    if req.has_player_name() && !req.get_player_name().is_empty() {
+        Some(req.get_player_name().to_owned())
+    } else {
+        None
     }
 }
 
 /// Unique identifier for lobby and running games
 /// Game keeps same id from lobby creation until all clients leave the game
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Ids are shown in a short base36 form, e.g. `2s`, in logs, file names and remote messages.
+/// Remote messages with the legacy numeric form are still accepted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct GameId(u64);
 impl GameId {
+    /// Id with the given raw value
+    pub(crate) fn from_raw(raw: u64) -> Self {
+        Self(raw)
+    }
+
     fn next(self) -> Self {
-        Self(self.0 + 1)
+        Self(self.0.wrapping_add(1))
+    }
+}
+impl fmt::Display for GameId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut digits = Vec::new();
+        let mut value = self.0;
+        loop {
+            digits.push(std::char::from_digit((value % 36) as u32, 36).unwrap());
+            value /= 36;
+            if value == 0 {
+                break;
+            }
+        }
+        let text: String = digits.into_iter().rev().collect();
+        f.pad(&text)
+    }
+}
+impl FromStr for GameId {
+    type Err = ParseIntError;
+
+    /// Parse the base36 form, case insensitive
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 36).map(Self)
+    }
+}
+impl Serialize for GameId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+impl<'de> Deserialize<'de> for GameId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct GameIdVisitor;
+        impl<'de> Visitor<'de> for GameIdVisitor {
+            type Value = GameId;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a base36 game id, or a non-negative integer")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<GameId, E> {
+                Ok(GameId(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<GameId, E> {
+                u64::try_from(value).map(GameId).map_err(|_| E::custom("negative game id"))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<GameId, E> {
+                value.parse().map_err(|_| E::custom(format!("invalid game id {:?}", value)))
+            }
+        }
+        deserializer.deserialize_any(GameIdVisitor)
+    }
+}
+
+/// Hands out game ids, never reusing an id of a lobby, a game or the retained history
+/// The history is e.g. the recent results, possibly restored from an earlier run
+#[derive(Debug, Clone, Default)]
+pub struct IdAllocator {
+    /// Next id to try
+    next: GameId,
+    /// Ids in the retained history
+    history: HashSet<GameId>,
+}
+impl IdAllocator {
+    /// Allocator without history, starting from the first id
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocator continuing after the ids of a restored history
+    pub fn with_history<I: IntoIterator<Item = GameId>>(ids: I) -> Self {
+        let history: HashSet<GameId> = ids.into_iter().collect();
+        let next = history.iter().max().map_or(GameId::default(), |id| id.next());
+        Self { next, history }
+    }
+
+    /// Keep an id out of use while it is in the retained history
+    pub fn retain(&mut self, id: GameId) {
+        self.history.insert(id);
+    }
+
+    /// Allow reusing an id that dropped out of the retained history
+    pub fn release(&mut self, id: GameId) {
+        self.history.remove(&id);
+    }
+
+    /// Checks if an id is in the retained history
+    pub fn is_retained(&self, id: GameId) -> bool {
+        self.history.contains(&id)
+    }
+
+    /// Allocate the next id that is neither `in_use` nor in the retained history
+    pub fn allocate<F: Fn(GameId) -> bool>(&mut self, in_use: F) -> GameId {
+        let mut id = self.next;
+        while in_use(id) || self.history.contains(&id) {
+            id = id.next();
+        }
+        self.next = id.next();
+        id
     }
 }
 
+/// Owned summary of the supervisor state, returned by `Supervisor::snapshot`
+/// The remote controller status requests are answered from this, so the data is consistent everywhere
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SupervisorSnapshot {
+    /// Connections waiting for a game, in connection order
+    pub playlist: Vec<PlaylistEntry>,
+    /// Lobbies waiting for players or a start request, ordered by id
+    pub lobbies: Vec<LobbySnapshot>,
+    /// Games being created and joined, ordered by id
+    pub starting: Vec<StartingSnapshot>,
+    /// Running games, ordered by id
+    pub games: Vec<GameSnapshot>,
+    /// Results of the most recently finished games, oldest first
+    pub recent_results: Vec<(GameId, GameResult)>,
+    /// Counters of all finished games
+    pub stats: GameStats,
+    /// New games are rejected, and the proxy quits when the running games are over
+    pub draining: bool,
+}
+impl SupervisorSnapshot {
+    /// Lobbies, starting games and running games as listed by GetGames, ordered by id
+    pub fn game_list(&self) -> Vec<remote_message::GameInfo> {
+        let lobbies = self.lobbies.iter().map(|lobby| remote_message::GameInfo {
+            id: lobby.id,
+            external_id: lobby.external_id.clone(),
+            running: false,
+            player_statuses: Vec::new(),
+        });
+        let starting = self.starting.iter().map(|start| remote_message::GameInfo {
+            id: start.id,
+            external_id: start.external_id.clone(),
+            running: false,
+            player_statuses: Vec::new(),
+        });
+        let games = self.games.iter().map(|game| remote_message::GameInfo {
+            id: game.id,
+            external_id: game.external_id.clone(),
+            running: true,
+            player_statuses: game.player_statuses.clone(),
+        });
+        let mut entries: Vec<_> = lobbies.chain(starting).chain(games).collect();
+        entries.sort_by_key(|e| e.id);
+        entries
+    }
+}
+
+/// Connection waiting for a game
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    /// Client id, the peer address of the connection
+    pub id: String,
+    /// Player name from the pending join request, if any
+    pub name: Option<String>,
+    /// Whether the client has sent a join request, i.e. can be added to a lobby
+    pub ready: bool,
+    /// Matchmaking queue the client connected to, None for the default queue
+    pub queue: Option<String>,
+    /// Details of the connection, captured from the websocket handshake
+    pub connection: ConnectionMeta,
+}
+
+/// Why a client was removed from the playlist
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum KickReason {
+    /// Sent a request or a websocket message of a type not accepted in the playlist
+    UnsupportedMessage(String),
+    /// Sent a message that could not be parsed
+    MalformedMessage,
+    /// Did not send anything in time
+    IdleTimeout,
+    /// Sent another game request after joining, or was replaced by a newer connection of the same bot
+    DuplicateJoin,
+    /// Could not be authenticated or authorized, e.g. connected to a path that is not allowed
+    AuthFailure,
+    /// Removed with a remote controller request
+    RemoteRequest,
+    /// Request for a new game was refused, with the error sent to the client
+    Rejected(String),
+    /// Quit or closed the connection
+    Disconnected,
+}
+impl fmt::Display for KickReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KickReason::UnsupportedMessage(kind) => write!(f, "Unsupported message: {}", kind),
+            KickReason::MalformedMessage => write!(f, "Malformed message"),
+            KickReason::IdleTimeout => write!(f, "Idle timeout"),
+            KickReason::DuplicateJoin => write!(f, "Duplicate join"),
+            KickReason::AuthFailure => write!(f, "Not authorized"),
+            KickReason::RemoteRequest => write!(f, "Removed by the remote controller"),
+            KickReason::Rejected(details) => write!(f, "Rejected: {}", details),
+            KickReason::Disconnected => write!(f, "Disconnected"),
+        }
+    }
+}
+
+/// Client removed from the playlist, as listed by GetRecentKicks
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct KickRecord {
+    /// Client id, the peer address of the connection
+    pub id: String,
+    /// Player name from the pending join request, if any
+    pub name: Option<String>,
+    /// Why the client was removed
+    pub reason: KickReason,
+    /// When the client was removed
+    pub time: SystemTime,
+}
+
+/// Lobby and its participants
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LobbySnapshot {
+    /// Id of the lobby
+    pub id: GameId,
+    /// Identifier given by an external system, if any
+    pub external_id: Option<String>,
+    /// Matchmaking queue of the lobby, None for the default queue
+    pub queue: Option<String>,
+    /// Seconds since the lobby was created
+    pub age_secs: u64,
+    /// Participants in join order
+    pub players: Vec<remote_message::LobbyPlayer>,
+}
+
+/// Game being created and joined
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StartingSnapshot {
+    /// Id of the game
+    pub id: GameId,
+    /// Identifier given by an external system, if any
+    pub external_id: Option<String>,
+    /// Seconds since the start began
+    pub elapsed_secs: u64,
+}
+
+/// Running game
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GameSnapshot {
+    /// Id of the game
+    pub id: GameId,
+    /// Identifier given by an external system, if any
+    pub external_id: Option<String>,
+    /// Names of participants in join order, None if not given
+    pub player_names: Vec<Option<String>>,
+    /// SC2 session status of each participant, None if not known yet
+    pub player_statuses: Vec<Option<SessionStatus>>,
+    /// Latest score of each participant, None before the first observation with a score
+    #[serde(default)]
+    pub player_scores: Vec<Option<ScoreSnapshot>>,
+    /// Command lines of the SC2 processes of participants
+    #[serde(default)]
+    pub launch_commands: Vec<Vec<String>>,
+    /// Seconds since the game was started
+    pub elapsed_secs: u64,
+}
+
 /// Supervisor manages a pool of games and client waiting for games
 pub struct Supervisor {
     /// Configuration
@@ -54,120 +389,456 @@ pub struct Supervisor {
     games: HashMap<GameId, GameHandle>,
     /// Games waiting for more players
     lobbies: HashMap<GameId, GameLobby>,
-    /// Connections (in nonblocking mode) waiting for a game
+    /// Games being created and joined
+    starting: HashMap<GameId, StartHandle>,
+    /// Connections (in nonblocking mode) waiting for a game, and when they were last polled
     /// If a game join is requested is pending (with remote), then also contains that
-    playlist: Vec<(Client, Option<RequestJoinGame>)>,
-    /// Id counter to allocate next id
-    id_counter: GameId,
+    playlist: Vec<(ClientConnection, Option<RequestJoinGame>, Instant)>,
+    /// Allocates ids for new lobbies and sessions
+    ids: IdAllocator,
+    /// Results of the most recently finished games, oldest first
+    recent_results: VecDeque<(GameId, GameResult)>,
+    /// Clients most recently removed from the playlist, oldest first
+    recent_kicks: VecDeque<KickRecord>,
+    /// Updates waiting to be sent to the remote controller, oldest first
+    updates: VecDeque<remote_message::Update>,
+    /// New games are rejected, and the proxy quits when the running games are over
+    draining: bool,
+    /// Counters of all finished games
+    stats: GameStats,
+    /// Win/loss records of bots, persisted to `standings_path` if set
+    standings: Standings,
+    /// Path of the latest replay saved with SaveReplay, by game
+    /// Kept while the game is running or its result is in `recent_results`
+    saved_replays: HashMap<GameId, String>,
+    /// Replays being sent to the remote controller, oldest first
+    replay_transfers: VecDeque<ReplayTransfer>,
+    /// Downstream proxies connected to the remote controller endpoint, by name, see `federation`
+    peers: HashMap<String, Peer>,
+    /// Lobbies moved to a downstream proxy, and the games started from them
+    routes: HashMap<GameId, Route>,
+    /// Lobbies and games created by the upstream proxy, whose updates are also sent there
+    upstream_games: HashSet<GameId>,
+    /// Updates waiting to be sent to the upstream proxy, oldest first
+    upstream_updates: VecDeque<remote_message::Update>,
+    /// Base build of the installed SC2 version, None if it could not be determined
+    sc2_base_build: Option<u32>,
+    /// Clients returned by the players of games that could not be finished, e.g. after a panic
+    recovered: (Sender<ClientConnection>, Receiver<ClientConnection>),
 }
 impl Supervisor {
     /// Create new emty supervisor from config
-    pub fn new(config: Config) -> Self {
+    pub fn new(mut config: Config) -> Self {
+        config.normalize();
+        let standings = match &config.matchmaking.standings_path {
+            Some(path) => Standings::load(Path::new(path)).unwrap_or_else(|e| {
+                error!("Could not load standings from {:?}: {}", path, e);
+                Standings::default()
+            }),
+            None => Standings::default(),
+        };
         Self {
             config,
             games: HashMap::new(),
             lobbies: HashMap::new(),
+            starting: HashMap::new(),
             playlist: Vec::new(),
-            id_counter: GameId(0),
+            ids: IdAllocator::new(),
+            recent_results: VecDeque::new(),
+            recent_kicks: VecDeque::new(),
+            updates: VecDeque::new(),
+            draining: false,
+            stats: GameStats::default(),
+            standings,
+            saved_replays: HashMap::new(),
+            replay_transfers: VecDeque::new(),
+            peers: HashMap::new(),
+            routes: HashMap::new(),
+            upstream_games: HashSet::new(),
+            upstream_updates: VecDeque::new(),
+            sc2_base_build: paths::base_build(),
+            recovered: channel::unbounded(),
+        }
+    }
+
+    /// Allocate a new id, skipping ids still used by lobbies, games, routes or recent results
+    fn allocate_id(&mut self) -> GameId {
+        let (lobbies, starting, games, routes) = (&self.lobbies, &self.starting, &self.games, &self.routes);
+        self.ids.allocate(|id| {
+            lobbies.contains_key(&id)
+                || starting.contains_key(&id)
+                || games.contains_key(&id)
+                || routes.contains_key(&id)
+        })
+    }
+
+    /// Queue an update for the remote controller,
+    /// and for the upstream proxy if the game was created by it
+    fn push_update(&mut self, update: remote_message::Update) {
+        let id = update.game_id();
+        if self.upstream_games.contains(&id) {
+            if update.is_final() {
+                self.upstream_games.remove(&id);
+            }
+            if self.upstream_updates.len() == PENDING_UPDATES_COUNT {
+                self.upstream_updates.pop_front();
+            }
+            self.upstream_updates.push_back(update.clone());
+        }
+        if self.updates.len() == PENDING_UPDATES_COUNT {
+            self.updates.pop_front();
+        }
+        self.updates.push_back(update);
+    }
+
+    /// Start creating and joining the game of a lobby in the background
+    fn start_lobby(&mut self, id: GameId, lobby: GameLobby) {
+        self.push_update(remote_message::Update::GameStarting(id));
+        self.starting.insert(id, spawn_start(lobby));
+    }
+
+    /// Move started games to running games, and abort starts that take too long
+    /// The timeout and retries come from the config the lobby was created with
+    fn update_starting(&mut self) {
+        for (id, start) in self.starting.iter_mut() {
+            let time_limits = &start.config().match_defaults.time_limits;
+            let timeout = time_limits.game_start_timeout_secs.map(Duration::from_secs);
+            if !start.is_aborted() && timeout.is_some_and(|t| start.elapsed() > t) {
+                warn!("Starting game {} timed out, aborting", id);
+                start.abort();
+            }
+        }
+
+        let finished: Vec<GameId> = self
+            .starting
+            .iter()
+            .filter(|(_, start)| start.is_finished())
+            .map(|(&id, _)| id)
+            .collect();
+
+        for id in finished {
+            let start = self.starting.remove(&id).unwrap();
+            let aborted = start.is_aborted();
+            let dedicated_client = start.dedicated_client().map(str::to_owned);
+            match start.collect() {
+                Ok(mut game) => {
+                    let game_info = game.take_game_info();
+                    self.games.insert(id, spawn_game(id, game, self.recovered.0.clone()));
+                    self.push_update(remote_message::Update::GameStarted(id));
+                    if let Some(info) = game_info {
+                        self.publish_game_info(id, &info);
+                    }
+                },
+                Err(Some(lobby))
+                    if !aborted && !self.draining && lobby.start_attempts() <= start_retries(&lobby) =>
+                {
+                    warn!(
+                        "Game {:?} could not be started, relaunching SC2 and retrying ({}/{})",
+                        id,
+                        lobby.start_attempts(),
+                        start_retries(&lobby)
+                    );
+                    self.lobbies.insert(id, lobby.relaunch());
+                },
+                Err(_) => {
+                    let reason = if aborted { "Timed out" } else { "Game creation / joining failed" };
+                    warn!("Game {} could not be started: {}", id, reason);
+                    self.push_update(remote_message::Update::GameStartFailed(id, reason.to_owned()));
+                    // The start thread has closed the connection of a dedicated session
+                    if let Some(client_id) = dedicated_client {
+                        self.record_kick(client_id, None, KickReason::Rejected(reason.to_owned()));
+                    }
+                },
+            }
         }
     }
 
-    /// Create new lobby
-    fn create_lobby(&mut self) -> GameId {
-        if let Err(e) = self.config.check() {
+    /// Send the SC2 game info of a started game to the remote controller,
+    /// and write it to `game_info_dir` if set
+    fn publish_game_info(&mut self, id: GameId, info: &ResponseGameInfo) {
+        if let Some(dir) = &self.config.match_defaults.record_results.game_info_dir {
+            let path = Path::new(dir).join(format!("{}.SC2GameInfo", id));
+            let bytes = info.write_to_bytes().expect("Invalid protobuf message");
+            if let Err(e) = fs::write(&path, bytes) {
+                error!("Could not write game info to {:?}: {}", path, e);
+            }
+        }
+        let summary = remote_message::GameInfoSummary::from_proto(info);
+        self.push_update(remote_message::Update::GameInfo(id, summary));
+    }
+
+    /// Create new lobby in a matchmaking queue, None for the default queue
+    /// The lobby takes a snapshot of the current config, so later config changes do not affect it
+    /// Fails if the config of the queue is not usable, e.g. after an incomplete SetConfig
+    fn create_lobby(&mut self, queue: Option<String>, external_id: Option<String>) -> Result<GameId, String> {
+        let config = self.config.queue_config(queue.as_deref());
+        if let Err(e) = config.check() {
             error!("Invalid configuration: {}", e);
-            panic!("Invalid configuration");
+            return Err(format!("Invalid configuration: {}", e));
         }
 
-        let lobby = GameLobby::new(self.config.clone());
-        let id = self.id_counter;
-        debug_assert!(!self.lobbies.contains_key(&id));
-        debug_assert!(!self.games.contains_key(&id));
-        self.id_counter = self.id_counter.next();
+        let mut lobby = GameLobby::new(config, external_id);
+        lobby.set_queue(queue);
+        let id = self.allocate_id();
         self.lobbies.insert(id, lobby);
-        id
+        Ok(id)
+    }
+
+    /// Identifiers of all lobbies, starting games and running games, in order
+    fn game_ids(&self) -> Vec<GameId> {
+        let mut ids: Vec<GameId> = self
+            .lobbies
+            .keys()
+            .chain(self.starting.keys())
+            .chain(self.games.keys())
+            .copied()
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Config snapshot used by a lobby, a starting game or a running game
+    fn effective_config(&self, game_id: GameId) -> Option<&Config> {
+        if let Some(lobby) = self.lobbies.get(&game_id) {
+            Some(lobby.config())
+        } else if let Some(start) = self.starting.get(&game_id) {
+            Some(start.config())
+        } else {
+            self.games.get(&game_id).map(|game| game.config())
+        }
     }
 
     /// Add a new client socket to playlist
+    /// The connection is dropped if its peer address cannot be read, or the socket is already unusable
     pub fn add_client(&mut self, client: Client) {
-        client.set_nonblocking(true).expect("Could not set nonblocking");
-        self.playlist.push((client, None));
+        match ClientConnection::new(client) {
+            Ok(conn) => self.add_connection(conn),
+            Err(e) => warn!("Could not get peer address of a client, dropping it: {}", e),
+        }
+    }
+
+    /// Add a new client connection, with the details captured when it was accepted, to playlist
+    /// Socket options from `proxy.socket` are set on it
+    /// Connections to paths rejected by `proxy.reject_unknown_paths` are kicked, and
+    /// connections that cannot be made nonblocking, e.g. because the client already left, are dropped
+    pub fn add_connection(&mut self, conn: ClientConnection) {
+        if let Err(e) = self.config.queue_for_path(&conn.meta.path) {
+            warn!("Closing connection from {}: {}", conn.meta.peer_addr, e);
+            self.kick(conn, None, KickReason::AuthFailure);
+            return;
+        }
+        if let Err(e) = self.config.proxy.socket.apply(conn.stream_ref()) {
+            warn!("Could not set socket options of client {}: {}", conn.meta.peer_addr, e);
+        }
+        if set_nonblocking(&conn, true).is_some() {
+            self.playlist.push((conn, None, Instant::now()));
+        } else {
+            self.kick(conn, None, KickReason::Disconnected);
+        }
+    }
+
+    /// Matchmaking queue of a client, None for the default queue
+    fn client_queue(&self, conn: &ClientConnection) -> Option<String> {
+        self.config.queue_for_path(&conn.meta.path).ok().flatten()
     }
 
     /// Remove client from playlist, closing the connection
-    fn drop_client(&mut self, index: usize) {
-        let (client, _) = &mut self.playlist[index];
-        info!("Removing client {:?} from playlist", client.peer_addr().unwrap());
-        client.shutdown().expect("Connection shutdown failed");
-        self.playlist.remove(index);
+    fn drop_client(&mut self, index: usize, reason: KickReason) {
+        let (conn, req, _) = self.playlist.remove(index);
+        self.kick(conn, req.as_ref(), reason);
+    }
+
+    /// Close the connection of a client taken from the playlist, sending the reason in the Close frame,
+    /// and record it in the recent kicks
+    fn kick(&mut self, conn: ClientConnection, req: Option<&RequestJoinGame>, reason: KickReason) {
+        let close_reason = reason.to_string();
+        self.record_kick(conn.meta.peer_addr.clone(), req.and_then(bot_identifier), reason);
+        conn.close(&close_reason);
+    }
+
+    /// Log the removal of a client and record it in the recent kicks
+    fn record_kick(&mut self, id: String, name: Option<String>, reason: KickReason) {
+        info!(
+            "Removing client {} ({}) from playlist: {}",
+            id,
+            name.as_deref().unwrap_or("no name"),
+            reason
+        );
+        if self.recent_kicks.len() == RECENT_KICKS_COUNT {
+            self.recent_kicks.pop_front();
+        }
+        self.recent_kicks.push_back(KickRecord {
+            id,
+            name,
+            reason,
+            time: SystemTime::now(),
+        });
     }
 
     /// Gets a client index by identifier (peer address for now) if any
     #[must_use]
     pub fn client_index_by_id(&mut self, client_id: String) -> Option<usize> {
-        self.playlist
-            .iter()
-            .enumerate()
-            .filter(|(_, (c, _))| c.peer_addr().expect("Could not get peer_addr").to_string() == client_id)
-            .map(|(i, _)| i)
-            .nth(0)
+        self.playlist.iter().position(|(c, _, _)| c.meta.peer_addr == client_id)
+    }
+
+    /// Drops older connections of the same bot from the playlist and waiting lobbies
+    fn drop_duplicates(&mut self, identifier: &str) {
+        for i in (0..self.playlist.len()).rev() {
+            let is_duplicate = match &self.playlist[i].1 {
+                Some(req) => bot_identifier(req).as_deref() == Some(identifier),
+                None => false,
+            };
+            if is_duplicate {
+                info!("Bot {:?} reconnected, dropping the older connection", identifier);
+                self.drop_client(i, KickReason::DuplicateJoin);
+            }
+        }
+
+        let mut emptied = Vec::new();
+        for (id, lobby) in self.lobbies.iter_mut() {
+            let mode = self.config.queue_matchmaking(lobby.queue()).mode;
+            let remote_controlled = mode == MatchmakingMode::RemoteController;
+            if lobby.remove_players_named(identifier) > 0 {
+                info!("Bot {:?} reconnected, removed the older connection from lobby {}", identifier, id);
+                // Lobbies created by the remote controller are kept even when empty
+                if lobby.is_empty() && !remote_controlled {
+                    emptied.push(*id);
+                }
+            }
+        }
+        for id in emptied {
+            self.lobbies.remove(&id).unwrap().close("Lobby emptied");
+        }
     }
 
     /// Join to game from playlist
     /// Iff game join fails, drops connection
     #[must_use]
     fn playlist_join_game(&mut self, index: usize, req: RequestJoinGame) -> Option<()> {
-        let (client, old_req) = self.playlist.remove(index);
+        let (client, old_req, _) = self.playlist.remove(index);
 
         if old_req != None {
             warn!("Client attempted to join a game twice (dropping connection)");
+            self.kick(client, old_req.as_ref(), KickReason::DuplicateJoin);
             return None;
         }
 
-        client.set_nonblocking(false).expect("Could not set nonblocking");
+        let queue = self.client_queue(&client);
+        let matchmaking = self.config.queue_matchmaking(queue.as_deref()).clone();
+
+        if matchmaking.deduplicate_clients {
+            if let Some(identifier) = bot_identifier(&req) {
+                self.drop_duplicates(&identifier);
+            }
+        }
+
+        if set_nonblocking(&client, false).is_none() {
+            self.kick(client, Some(&req), KickReason::Disconnected);
+            return None;
+        }
 
         // TODO: Verify that InterfaceOptions are allowed
 
-        match self.config.matchmaking.mode {
+        match matchmaking.mode {
             MatchmakingMode::AgainstBuiltinAI => {
-                let id = self.create_lobby();
-                let mut lobby = self.lobbies.remove(&id).unwrap();
+                let id = match self.create_lobby(queue, None) {
+                    Ok(id) => id,
+                    Err(e) => {
+                        self.kick(client, Some(&req), KickReason::Rejected(e));
+                        return None;
+                    },
+                };
+                let lobby = self.lobbies.get_mut(&id).unwrap();
                 lobby.join(client, req);
-                lobby.add_computer(
-                    self.config.matchmaking.cpu_race,
-                    self.config.matchmaking.cpu_difficulty,
-                );
-                let game = lobby.start()?;
-                self.games.insert(id, spawn_game(game));
+                lobby.add_computer(matchmaking.cpu_race, matchmaking.cpu_difficulty);
+                // The bot and the computer
+                lobby.start_when_full(2);
             },
             MatchmakingMode::Pairs => {
-                if let Some(&id) = self.lobbies.keys().nth(0) {
-                    let mut lobby = self.lobbies.remove(&id).unwrap();
-                    lobby.join(client, req);
-                    let game = lobby.start()?;
-                    self.games.insert(id, spawn_game(game));
-                } else {
-                    let id = self.create_lobby();
-                    let lobby = self.lobbies.get_mut(&id).unwrap();
-                    lobby.join(client, req);
-                }
+                let identifier = bot_identifier(&req);
+                let forbid_self_match = matchmaking.forbid_self_match;
+                let players_per_game = matchmaking.players_per_game;
+                let open_lobby = self
+                    .lobbies
+                    .iter()
+                    .filter(|(_, lobby)| lobby.queue() == queue.as_deref())
+                    .filter(|(_, lobby)| !lobby.is_full(players_per_game))
+                    .filter(|(_, lobby)| match &identifier {
+                        Some(name) if forbid_self_match => !lobby.has_player_named(name),
+                        _ => true,
+                    })
+                    .map(|(&id, _)| id)
+                    .nth(0);
+
+                let created = match open_lobby {
+                    Some(id) => Ok(id),
+                    None => self.create_lobby(queue, None),
+                };
+                let id = match created {
+                    Ok(id) => id,
+                    Err(e) => {
+                        self.kick(client, Some(&req), KickReason::Rejected(e));
+                        return None;
+                    },
+                };
+                let lobby = self.lobbies.get_mut(&id).unwrap();
+                lobby.join(client, req);
+                lobby.start_when_full(players_per_game);
             },
             MatchmakingMode::RemoteController => {
                 // Return client to playlist, the remote can handle this
-                client.set_nonblocking(true).expect("Could not set nonblocking");
-                self.playlist.push((client, Some(req)));
+                if set_nonblocking(&client, true).is_none() {
+                    self.kick(client, Some(&req), KickReason::Disconnected);
+                    return None;
+                }
+                self.playlist.push((client, Some(req), Instant::now()));
+            },
+            MatchmakingMode::Singleplayer => {
+                let reason = "Singleplayer matchmaking is not supported".to_owned();
+                self.kick(client, Some(&req), KickReason::Rejected(reason));
+                return None;
             },
-            other => panic!("Unimplemented matchmaking mode {:?}", other),
         }
 
         Some(())
     }
 
-    /// Process message from a client in the playlist
-    fn process_playlist_message(&mut self, msg: OwnedMessage) -> PlaylistAction {
+    /// Start a dedicated session, i.e. a client hosted game or a replay, from playlist
+    /// The SC2 process is launched in a start thread, see `update_starting`
+    /// Iff the session cannot be started, drops connection
+    #[must_use]
+    fn playlist_dedicated_session(&mut self, index: usize, req: Request) -> Option<()> {
+        let (client, old_req, _) = self.playlist.remove(index);
+
+        if old_req.is_some() {
+            warn!("Client attempted to start a session after joining a game (dropping connection)");
+            self.kick(client, old_req.as_ref(), KickReason::DuplicateJoin);
+            return None;
+        }
+
+        if set_nonblocking(&client, false).is_none() {
+            self.kick(client, None, KickReason::Disconnected);
+            return None;
+        }
+
+        let queue = self.client_queue(&client);
+        let mut lobby = GameLobby::new(self.config.queue_config(queue.as_deref()), None);
+        lobby.set_queue(queue);
+        let id = self.allocate_id();
+        self.push_update(remote_message::Update::GameStarting(id));
+        self.starting.insert(id, spawn_dedicated(lobby, client, req));
+        Some(())
+    }
+
+    /// Process message from a client in the playlist, using the settings of its queue
+    /// `expected_base_build` is the SC2 version the client was built against, if it gave one
+    fn process_playlist_message(
+        &self, msg: OwnedMessage, queue: Option<&str>, expected_base_build: Option<u32>,
+    ) -> PlaylistAction {
+        let matchmaking = self.config.queue_matchmaking(queue);
         match msg {
             OwnedMessage::Binary(bytes) => {
-                let req = parse_from_bytes::<sc2_proto::sc2api::Request>(&bytes);
+                let req = parse_from_bytes::<Request>(&bytes);
                 debug!("Incoming playlist request: {:?}", req);
 
                 match req {
@@ -176,49 +847,145 @@ impl Supervisor {
                         let mut resp = sc2_proto::sc2api::Response::new();
                         let quit = sc2_proto::sc2api::ResponseQuit::new();
                         resp.set_quit(quit);
-                        PlaylistAction::respond_quit(resp)
+                        PlaylistAction::respond_quit(resp, KickReason::Disconnected)
                     },
                     Ok(ref m) if m.has_ping() => {
                         trace!("Ping => Pong");
                         let mut resp = sc2_proto::sc2api::Response::new();
-                        let pong = sc2_proto::sc2api::ResponsePing::new();
-                        // TODO: Set pong fields, like game version?
+                        let mut pong = sc2_proto::sc2api::ResponsePing::new();
+                        if let Some(base_build) = self.sc2_base_build {
+                            pong.set_base_build(base_build);
+                        }
                         resp.set_ping(pong);
                         PlaylistAction::respond(resp)
                     },
+                    Ok(ref m)
+                        if self.draining
+                            && (m.has_join_game()
+                                || m.has_create_game()
+                                || m.has_replay_info()
+                                || m.has_start_replay()) =>
+                    {
+                        info!("Rejecting a new game while draining");
+                        let details = "Busy, not accepting new games";
+                        let mut resp = sc2_proto::sc2api::Response::new();
+                        resp.set_error(RepeatedField::from_vec(vec![format!("Proxy: {}", details)]));
+                        PlaylistAction::respond_quit(resp, KickReason::Rejected(details.to_owned()))
+                    },
+                    Ok(ref m) if m.has_join_game() && self.is_version_mismatch(expected_base_build) => {
+                        let details = format!(
+                            "Version mismatch, bot built for SC2 base build {}, but the proxy runs {}",
+                            expected_base_build.unwrap(),
+                            self.sc2_base_build.unwrap()
+                        );
+                        warn!("Rejecting a join request: {}", details);
+                        let mut resp = sc2_proto::sc2api::Response::new();
+                        let join_resp = resp.mut_join_game();
+                        join_resp.set_error(ResponseJoinGame_Error::OtherError);
+                        join_resp.set_error_details(format!("Proxy: {}", details));
+                        PlaylistAction::respond_quit(resp, KickReason::Rejected(details))
+                    },
                     Ok(ref m) if m.has_join_game() => {
                         debug!("Game join");
-                        PlaylistAction::JoinGame(m.get_join_game().clone())
+                        let mut join = m.get_join_game().clone();
+                        if join.get_race() == sc2_proto::common::Race::NoRace {
+                            match matchmaking.default_race {
+                                Some(race) => {
+                                    info!("Join request without a race, using the default {:?}", race);
+                                    join.set_race(race.to_proto());
+                                },
+                                None => {
+                                    warn!("Rejecting a join request without a race");
+                                    let mut resp = sc2_proto::sc2api::Response::new();
+                                    let join_resp = resp.mut_join_game();
+                                    join_resp.set_error(ResponseJoinGame_Error::OtherError);
+                                    join_resp.set_error_details("Proxy: No race set in join_game".to_owned());
+                                    let reason = KickReason::Rejected("No race set in join_game".to_owned());
+                                    return PlaylistAction::respond_quit(resp, reason);
+                                },
+                            }
+                        }
+                        PlaylistAction::JoinGame(join)
+                    },
+                    Ok(ref m) if m.has_create_game() && matchmaking.allow_client_hosting => {
+                        debug!("Client hosted game creation");
+                        PlaylistAction::Dedicated(m.clone())
+                    },
+                    Ok(ref m)
+                        if (m.has_replay_info() || m.has_start_replay())
+                            && matchmaking.allow_replay_clients =>
+                    {
+                        debug!("Replay session");
+                        PlaylistAction::Dedicated(m.clone())
                     },
                     Ok(other) => {
                         warn!("Unsupported message in playlist {:?}", other);
-                        PlaylistAction::Kick
+                        PlaylistAction::Kick(KickReason::UnsupportedMessage(request_kind(&other).to_owned()))
                     },
                     Err(err) => {
                         warn!("Invalid message {:?}", err);
-                        PlaylistAction::Kick
+                        PlaylistAction::Kick(KickReason::MalformedMessage)
                     },
                 }
             },
+            OwnedMessage::Close(_) => {
+                debug!("Client closed the connection");
+                PlaylistAction::Kick(KickReason::Disconnected)
+            },
             other => {
                 warn!("Unsupported message type {:?}", other);
-                PlaylistAction::Kick
+                let kind = match other {
+                    OwnedMessage::Text(_) => "text",
+                    OwnedMessage::Ping(_) => "ping",
+                    _ => "pong",
+                };
+                PlaylistAction::Kick(KickReason::UnsupportedMessage(kind.to_owned()))
             },
         }
     }
 
+    /// Checks if a client built against `expected_base_build` cannot join games of the installed SC2
+    /// Unknown versions on either side are not a mismatch
+    fn is_version_mismatch(&self, expected_base_build: Option<u32>) -> bool {
+        match (expected_base_build, self.sc2_base_build) {
+            (Some(expected), Some(installed)) => expected != installed,
+            _ => false,
+        }
+    }
+
     /// Update clients in playlist to see if they join a game or disconnect
+    /// Clients whose join request is already stored are polled at most every `IDLE_POLL_INTERVAL`
     pub fn update_playlist(&mut self) {
         for i in (0..self.playlist.len()).rev() {
+            // Joining a game may remove other clients from the playlist as well
+            if i >= self.playlist.len() {
+                continue;
+            }
+
+            // Clients waiting for a lobby have nothing more to send,
+            // so they are only polled now and then to notice disconnects
+            let (_, req, last_polled) = &mut self.playlist[i];
+            if req.is_some() && last_polled.elapsed() < IDLE_POLL_INTERVAL {
+                continue;
+            }
+            *last_polled = Instant::now();
+
+            let queue = self.client_queue(&self.playlist[i].0);
+            let expected_base_build = self.playlist[i].0.meta.expected_base_build();
             match self.playlist[i].0.recv_message() {
-                Ok(msg) => match self.process_playlist_message(msg) {
-                    PlaylistAction::Kick => self.drop_client(i),
+                Ok(msg) => match self.process_playlist_message(msg, queue.as_deref(), expected_base_build) {
+                    PlaylistAction::Kick(reason) => self.drop_client(i, reason),
                     PlaylistAction::Respond(resp) => {
-                        self.playlist[i].0.send_message(&resp).expect("Could not respond");
+                        if let Err(e) = self.playlist[i].0.send_message(&resp) {
+                            warn!("Could not respond to client {}: {}", self.playlist[i].0.meta.peer_addr, e);
+                            self.drop_client(i, KickReason::Disconnected);
+                        }
                     },
-                    PlaylistAction::RespondQuit(resp) => {
-                        self.playlist[i].0.send_message(&resp).expect("Could not respond");
-                        self.drop_client(i);
+                    PlaylistAction::RespondQuit(resp, reason) => {
+                        if let Err(e) = self.playlist[i].0.send_message(&resp) {
+                            warn!("Could not respond to client {}: {}", self.playlist[i].0.meta.peer_addr, e);
+                        }
+                        self.drop_client(i, reason);
                     },
                     PlaylistAction::JoinGame(req) => {
                         let joinres = self.playlist_join_game(i, req);
@@ -226,17 +993,252 @@ impl Supervisor {
                             warn!("Game creation / joining failed");
                         }
                     },
+                    PlaylistAction::Dedicated(req) => {
+                        let res = self.playlist_dedicated_session(i, req);
+                        if res.is_none() {
+                            warn!("Dedicated session could not be started");
+                        }
+                    },
                 },
                 Err(WebSocketError::IoError(ref e)) if e.kind() == WouldBlock => {},
                 Err(err) => {
                     warn!("Invalid message {:?}", err);
-                    self.drop_client(i);
+                    let reason = match err {
+                        WebSocketError::IoError(_) | WebSocketError::NoDataAvailable => {
+                            KickReason::Disconnected
+                        },
+                        _ => KickReason::MalformedMessage,
+                    };
+                    self.drop_client(i, reason);
                 },
             };
         }
+
+        self.start_filler_games();
+    }
+
+    /// Start Pairs lobbies that have waited for a partner too long against the filler AI
+    fn start_filler_games(&mut self) {
+        let config = &self.config;
+        let waiting: Vec<GameId> = self
+            .lobbies
+            .iter()
+            .filter(|(_, lobby)| {
+                let matchmaking = config.queue_matchmaking(lobby.queue());
+                let filler = &matchmaking.filler_ai;
+                filler.enabled
+                    && matchmaking.mode == MatchmakingMode::Pairs
+                    && !lobby.is_empty()
+                    && !lobby.is_full(matchmaking.players_per_game)
+                    && lobby.age() >= Duration::from_secs(filler.wait_secs)
+            })
+            .map(|(&id, _)| id)
+            .collect();
+
+        for id in waiting {
+            let lobby = self.lobbies.get_mut(&id).unwrap();
+            let matchmaking = self.config.queue_matchmaking(lobby.queue());
+            info!("No partner found for lobby {}, starting against the filler AI", id);
+            lobby.force();
+            let filler = &matchmaking.filler_ai;
+            lobby.fill_with_computers(matchmaking.players_per_game, filler.race, filler.difficulty);
+        }
+    }
+
+    /// Collect launched SC2 processes, remove disconnected players from lobbies,
+    /// start lobbies that are full and ready, collect started games,
+    /// abort starts that take too long, and close lobbies older than
+    /// the configured maximum age. With remote controller matchmaking, the clients
+    /// of expired lobbies are returned to the playlist still waiting for a game,
+    /// otherwise they are disconnected.
+    pub fn update_lobbies(&mut self) {
+        let mut emptied = Vec::new();
+        for (&id, lobby) in self.lobbies.iter_mut() {
+            let mode = self.config.queue_matchmaking(lobby.queue()).mode;
+            let remote_controlled = mode == MatchmakingMode::RemoteController;
+            lobby.update_pending();
+            let removed = lobby.remove_disconnected();
+            if removed > 0 {
+                info!("Removed {} disconnected players from lobby {}", removed, id);
+                // Lobbies created by the remote controller are kept even when empty
+                if lobby.is_empty() && !remote_controlled {
+                    emptied.push(id);
+                }
+            }
+        }
+        for id in emptied {
+            self.lobbies.remove(&id).unwrap().close("Lobby emptied");
+        }
+
+        let startable: Vec<GameId> = self
+            .lobbies
+            .iter()
+            .filter(|(_, lobby)| lobby.should_start())
+            .map(|(&id, _)| id)
+            .collect();
+        for id in startable {
+            let lobby = self.lobbies.remove(&id).unwrap();
+            self.start_lobby(id, lobby);
+        }
+        self.update_starting();
+
+        let config = &self.config;
+        let expired: Vec<GameId> = self
+            .lobbies
+            .iter()
+            .filter(|(_, lobby)| {
+                let max_age = config.queue_matchmaking(lobby.queue()).max_lobby_age_secs;
+                max_age.is_some_and(|secs| lobby.age() > Duration::from_secs(secs))
+            })
+            .map(|(&id, _)| id)
+            .collect();
+
+        for id in expired {
+            let lobby = self.lobbies.remove(&id).unwrap();
+            let mode = self.config.queue_matchmaking(lobby.queue()).mode;
+            if mode == MatchmakingMode::RemoteController {
+                info!("Lobby {} expired, returning its clients to the playlist", id);
+                for (client, req) in lobby.cancel() {
+                    self.return_to_playlist(client, req);
+                }
+            } else {
+                info!("Lobby {} expired, closing it", id);
+                lobby.close("Lobby expired");
+            }
+        }
+    }
+
+    /// Checks if there are lobbies or join requests waiting for the remote controller,
+    /// which were created under the current matchmaking mode and could be orphaned by changing it
+    fn has_matchmaking_state(&self) -> bool {
+        !self.lobbies.is_empty() || self.playlist.iter().any(|(_, req, _)| req.is_some())
+    }
+
+    /// Put a client whose join request is still pending back to the playlist
+    /// The client is kicked if its connection cannot be used anymore
+    fn return_to_playlist(&mut self, client: ClientConnection, req: RequestJoinGame) {
+        if set_nonblocking(&client, true).is_some() {
+            self.playlist.push((client, Some(req), Instant::now()));
+        } else {
+            self.kick(client, Some(&req), KickReason::Disconnected);
+        }
+    }
+
+    /// Results of the most recently finished games, oldest first
+    pub fn recent_results(&self) -> impl Iterator<Item = &(GameId, GameResult)> {
+        self.recent_results.iter()
+    }
+
+    /// Clients most recently removed from the playlist, oldest first
+    pub fn recent_kicks(&self) -> impl Iterator<Item = &KickRecord> {
+        self.recent_kicks.iter()
+    }
+
+    /// Restore results saved from an earlier run, e.g. from a snapshot
+    /// Their ids are not reused by later lobbies
+    pub fn restore_results<I: IntoIterator<Item = (GameId, GameResult)>>(&mut self, results: I) {
+        let mut results: VecDeque<_> = results.into_iter().collect();
+        while results.len() > RECENT_RESULTS_COUNT {
+            results.pop_front();
+        }
+        self.ids = IdAllocator::with_history(results.iter().map(|(id, _)| *id));
+        self.recent_results = results;
+    }
+
+    /// Number of games being created and joined
+    pub fn starting_count(&self) -> usize {
+        self.starting.len()
+    }
+
+    /// Number of lobbies waiting for players or a start request
+    pub fn lobby_count(&self) -> usize {
+        self.lobbies.len()
+    }
+
+    /// Number of running games
+    pub fn game_count(&self) -> usize {
+        self.games.len()
+    }
+
+    /// Owned summary of the playlist, lobbies, games, recent results and counters
+    pub fn snapshot(&self) -> SupervisorSnapshot {
+        let playlist = self
+            .playlist
+            .iter()
+            .map(|(c, r, _)| PlaylistEntry {
+                id: c.meta.peer_addr.clone(),
+                name: r.as_ref().and_then(bot_identifier),
+                ready: r.is_some(),
+                queue: self.client_queue(c),
+                connection: c.meta.clone(),
+            })
+            .collect();
+
+        let mut lobbies: Vec<_> = self
+            .lobbies
+            .iter()
+            .map(|(&id, lobby)| LobbySnapshot {
+                id,
+                external_id: lobby.external_id().map(str::to_owned),
+                queue: lobby.queue().map(str::to_owned),
+                age_secs: lobby.age().as_secs(),
+                players: lobby
+                    .participants()
+                    .into_iter()
+                    .map(|(name, command, process_options)| remote_message::LobbyPlayer {
+                        name: name.map(str::to_owned),
+                        status: if command.is_some() {
+                            remote_message::PlayerStatus::Ready
+                        } else {
+                            remote_message::PlayerStatus::Launching
+                        },
+                        launch_command: command.map(<[String]>::to_vec),
+                        process_options: process_options.clone(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        lobbies.sort_by_key(|l| l.id);
+
+        let mut starting: Vec<_> = self
+            .starting
+            .iter()
+            .map(|(&id, start)| StartingSnapshot {
+                id,
+                external_id: start.external_id().map(str::to_owned),
+                elapsed_secs: start.elapsed().as_secs(),
+            })
+            .collect();
+        starting.sort_by_key(|s| s.id);
+
+        let mut games: Vec<_> = self
+            .games
+            .iter()
+            .map(|(&id, game)| GameSnapshot {
+                id,
+                external_id: game.external_id().map(str::to_owned),
+                player_names: game.player_names().to_vec(),
+                player_statuses: game.player_statuses().to_vec(),
+                player_scores: game.player_scores().to_vec(),
+                launch_commands: game.launch_commands().to_vec(),
+                elapsed_secs: game.elapsed().as_secs(),
+            })
+            .collect();
+        games.sort_by_key(|g| g.id);
+
+        SupervisorSnapshot {
+            playlist,
+            lobbies,
+            starting,
+            games,
+            recent_results: self.recent_results.iter().cloned().collect(),
+            stats: self.stats.clone(),
+            draining: self.draining,
+        }
     }
 
     /// Update game handles to see if they are still running
+    /// Clients recovered from games that could not be finished are returned to the playlist
     pub fn update_games(&mut self) {
         let mut games_over = Vec::new();
         for (id, game) in self.games.iter_mut() {
@@ -246,102 +1248,732 @@ impl Supervisor {
         }
 
         for id in games_over {
-            match self.games.remove(&id).unwrap().collect_result() {
+            let game = self.games.remove(&id).unwrap();
+            let record = game.config().match_defaults.record_results.clone();
+            let map = game.config().match_defaults.game.map_name.clone();
+            match game.collect_result() {
                 Ok((result, players)) => {
                     // Return players to playlist
                     for p in players.into_iter() {
                         // TODO: process reuse
-                        self.add_client(p.extract_client());
+                        if let Some(client) = p.extract_client() {
+                            self.add_connection(client);
+                        }
                     }
 
-                    info!("Game result: {:?}", result);
+                    info!("Game {} result: {:?}", id, result);
+                    self.stats.record(&result);
+                    self.record_standings(&result);
+                    if let Some(dir) = &record.results_dir {
+                        let format = record.result_format;
+                        if let Err(e) = write_result(Path::new(dir), id, &result, format, map.as_deref()) {
+                            error!("Could not write result of game {} to {:?}: {}", id, dir, e);
+                        }
+                    }
+                    if self.recent_results.len() == RECENT_RESULTS_COUNT {
+                        if let Some((old_id, _)) = self.recent_results.pop_front() {
+                            self.ids.release(old_id);
+                            self.saved_replays.remove(&old_id);
+                        }
+                    }
+                    self.ids.retain(id);
+                    self.push_update(remote_message::Update::GameEnded(id, result.clone()));
+                    self.recent_results.push_back((id, result));
                 },
                 Err(msg) => {
                     error!("Game thread panicked with: {:?}", msg);
+                    self.stats.record_crash();
+                    self.saved_replays.remove(&id);
                 },
             }
         }
+
+        while let Ok(client) = self.recovered.1.try_recv() {
+            info!("Client {} recovered from a failed game", client.meta.peer_addr);
+            self.add_connection(client);
+        }
     }
 
-    /// Update game handles to see if they are still running
-    /// Returns true if a request was processed
+    /// Count a game result into the standings, and persist them if configured
+    fn record_standings(&mut self, result: &GameResult) {
+        self.standings.record(result);
+        if let Some(path) = &self.config.matchmaking.standings_path {
+            if let Err(e) = self.standings.save(Path::new(path)) {
+                error!("Could not save standings to {:?}: {}", path, e);
+            }
+        }
+    }
+
+    /// Update remote controller, processing a request if one is available
+    /// If the listener is moved by `RebindRemoteControl`, `remote` is replaced with the new one
     #[must_use]
     pub fn update_remote(&mut self, remote: &mut Remote) -> RemoteUpdateStatus {
-        use crate::remote_control::message::*;
+        self.update_peers(remote);
+        while let Some(update) = self.updates.pop_front() {
+            if remote.send_update(update).is_err() {
+                // Updates are dropped while there is no controller connected
+                self.updates.clear();
+            }
+        }
+        self.send_replay_chunks(remote);
 
         if let Some(msg) = remote.try_recv() {
-            match msg {
-                Request::Quit => {
-                    remote.send(Response::Quit);
-                    return RemoteUpdateStatus::Quit;
+            let mut response = self.authorize_remote_request(remote, msg);
+            let mut rebound = None;
+            if let remote_message::Response::RebindRemoteControl(addr) = &response {
+                let audit_log = self.config.remote_controller.audit_log();
+                match remote_control::run_server(addr, audit_log) {
+                    Ok(new_remote) => {
+                        info!("Remote controller listener moved to {}", new_remote.addr());
+                        let bound = new_remote.addr().to_owned();
+                        response = remote_message::Response::RebindRemoteControl(bound);
+                        rebound = Some(new_remote);
+                    },
+                    Err(e) => {
+                        error!("Could not bind remote controller listener to {}: {}", addr, e);
+                        response = remote_message::Response::Error(format!("Could not bind {}: {}", addr, e));
+                    },
+                }
+            }
+            let status = match response {
+                remote_message::Response::Quit => RemoteUpdateStatus::Quit,
+                remote_message::Response::DisableRemoteControl => RemoteUpdateStatus::Disabled,
+                _ => RemoteUpdateStatus::Processed,
+            };
+
+            let sent = remote.send(response).is_ok();
+            if !sent {
+                warn!("Remote controller disconnected before the response was sent");
+            }
+            if let Some(new_remote) = rebound {
+                let old = std::mem::replace(remote, new_remote);
+                // The old listener thread exits after sending the response. If the connection was
+                // already gone, it exits when the next connection finds the supervisor side gone.
+                if sent && old.handle.join().is_err() {
+                    warn!("Remote controller listener thread panicked");
+                }
+            }
+            status
+        } else {
+            RemoteUpdateStatus::NoAction
+        }
+    }
+
+    /// Check that the current controller connection may make a request, and process it if so
+    /// When access tokens are configured, connections must authenticate first
+    fn authorize_remote_request(
+        &mut self, remote: &mut Remote, msg: remote_message::Request,
+    ) -> remote_message::Response {
+        use crate::remote_control::message::*;
+
+        let tokens = &self.config.remote_controller.tokens;
+        let role = if tokens.is_empty() {
+            Some(RemoteRole::Admin)
+        } else {
+            remote.role()
+        };
+
+        if let Request::Authenticate(token) = msg {
+            if tokens.is_empty() {
+                return Response::Authenticate(RemoteRole::Admin);
+            }
+            return match tokens.get(&token) {
+                Some(&role) => {
+                    info!("Remote controller authenticated as {:?}", role);
+                    remote.set_role(role);
+                    Response::Authenticate(role)
                 },
-                Request::Ping(v) => remote.send(Response::Ping(v)),
-                Request::GetConfig => {
-                    remote.send(Response::GetConfig(self.config.clone()));
+                None => {
+                    warn!("Remote controller authentication failed");
+                    Response::Error("Invalid token".to_owned())
                 },
-                Request::SetConfig(config) => {
-                    self.config = config.clone();
-                    remote.send(Response::SetConfig(config));
+            };
+        }
+
+        match role {
+            None if !matches!(msg, Request::Ping(_)) => {
+                Response::Error("Authentication required".to_owned())
+            },
+            Some(RemoteRole::Spectator) if !msg.is_read_only() => {
+                Response::Error("Permission denied: spectators have read-only access".to_owned())
+            },
+            _ => match self.process_remote_request(msg) {
+                // Only admins may see the tokens
+                Response::GetConfig(config) if role != Some(RemoteRole::Admin) => {
+                    Response::GetConfig(config.redacted())
                 },
-                Request::GetPlaylist => {
-                    remote.send(Response::GetPlaylist(
-                        self.playlist
-                            .iter()
-                            .map(|(c, r)| {
-                                (
-                                    c.peer_addr().expect("Could not get peer_addr").to_string(),
-                                    r.is_some(),
-                                )
-                            })
-                            .collect(),
-                    ));
+                Response::GetEffectiveConfig(config) if role != Some(RemoteRole::Admin) => {
+                    Response::GetEffectiveConfig(config.redacted())
                 },
-                Request::CreateLobby => {
-                    let game_id = self.create_lobby();
-                    remote.send(Response::CreateLobby(game_id));
+                response => response,
+            },
+        }
+    }
+
+    /// Process a request from the remote controller
+    fn process_remote_request(&mut self, msg: remote_message::Request) -> remote_message::Response {
+        use crate::remote_control::message::*;
+
+        if let Some(response) = self.route_remote_request(&msg) {
+            return response;
+        }
+
+        match msg {
+            Request::Quit => Response::Quit,
+            Request::Ping(v) => Response::Ping(v),
+            Request::GetConfig => Response::GetConfig(self.config.clone()),
+            Request::SetConfig(ref config)
+                if !config.same_matchmaking_modes(&self.config) && self.has_matchmaking_state() =>
+            {
+                Response::Error(
+                    "Cannot change matchmaking mode while lobbies or pending join requests exist".to_owned(),
+                )
+            },
+            Request::SetConfig(config) => {
+                self.config = *config;
+                self.config.normalize();
+                Response::SetConfig(self.config.clone(), self.game_ids())
+            },
+            Request::GetEffectiveConfig(game_id) => match self.effective_config(game_id) {
+                Some(config) => Response::GetEffectiveConfig(config.clone()),
+                None => Response::Error("No such game".to_owned()),
+            },
+            Request::GetPlaylist => Response::GetPlaylist(
+                self.snapshot()
+                    .playlist
+                    .into_iter()
+                    .chain(self.peer_playlist())
+                    .map(|entry| (entry.id, entry.ready))
+                    .collect(),
+            ),
+            Request::GetQueuePlaylist(queue) => {
+                if self.config.queues.contains_key(&queue) {
+                    let clients = self
+                        .snapshot()
+                        .playlist
+                        .into_iter()
+                        .chain(self.peer_playlist())
+                        .filter(|entry| entry.queue.as_ref() == Some(&queue))
+                        .map(|entry| (entry.id, entry.ready))
+                        .collect();
+                    Response::GetPlaylist(clients)
+                } else {
+                    Response::Error("No such queue".to_owned())
+                }
+            },
+            Request::CreateLobby(_) | Request::CreateQueueLobby(_, _) if self.draining => {
+                Response::Error("Draining, not accepting new games".to_owned())
+            },
+            Request::CreateLobby(external_id) => match self.create_lobby(None, external_id) {
+                Ok(game_id) => Response::CreateLobby(game_id),
+                Err(e) => Response::Error(e),
+            },
+            Request::CreateQueueLobby(queue, external_id) => {
+                if self.config.queues.contains_key(&queue) {
+                    match self.create_lobby(Some(queue), external_id) {
+                        Ok(game_id) => Response::CreateLobby(game_id),
+                        Err(e) => Response::Error(e),
+                    }
+                } else {
+                    Response::Error("No such queue".to_owned())
+                }
+            },
+            Request::SetLobbyFullscreen(game_id, fullscreen) => match self.lobbies.get_mut(&game_id) {
+                Some(lobby) if lobby.is_empty() => {
+                    lobby.set_fullscreen(fullscreen);
+                    Response::SetLobbyFullscreen
                 },
-                Request::AddToLobby(game_id, client_id) => {
-                    if let Some(index) = self.client_index_by_id(client_id) {
-                        let (client, req_opt) = self.playlist.remove(index);
-                        if let Some(req) = req_opt {
-                            if let Some(lobby) = self.lobbies.get_mut(&game_id) {
-                                client.set_nonblocking(false).expect("Could not set nonblocking");
-                                lobby.join(client, req);
-                                remote.send(Response::AddToLobby);
+                Some(_) => Response::Error("Lobby already has participants".to_owned()),
+                None => Response::Error("No such game".to_owned()),
+            },
+            Request::Drain => {
+                self.drain();
+                Response::Drain
+            },
+            Request::DisableRemoteControl => {
+                warn!("Remote controller disabled by request");
+                Response::DisableRemoteControl
+            },
+            // The new listener is bound by `update_remote`, which owns the current one
+            Request::RebindRemoteControl(addr) => Response::RebindRemoteControl(addr),
+            Request::AddToLobby(game_id, client_id, process_options) => {
+                let index = self.client_index_by_id(client_id);
+                let client_queue = index.and_then(|i| self.client_queue(&self.playlist[i].0));
+                let lobby_queue = self.lobbies.get(&game_id).map(|lobby| lobby.queue().map(str::to_owned));
+                if lobby_queue.is_some_and(|queue| queue != client_queue) {
+                    Response::Error("Client is not in the queue of the lobby".to_owned())
+                } else if let Some(index) = index {
+                    let (client, req_opt, _) = self.playlist.remove(index);
+                    if let Some(req) = req_opt {
+                        if let Some(lobby) = self.lobbies.get_mut(&game_id) {
+                            if set_nonblocking(&client, false).is_some() {
+                                lobby.join_with_options(client, req, process_options);
+                                Response::AddToLobby(PlayerStatus::Launching)
                             } else {
-                                remote.send(Response::Error("No such game".to_owned()));
-                                // Client connection dropped here
+                                self.kick(client, Some(&req), KickReason::Disconnected);
+                                Response::Error("Client connection failed".to_owned())
                             }
                         } else {
-                            remote.send(Response::Error("Client not ready".to_owned()));
-                            // Client connection dropped here
+                            self.kick(client, Some(&req), KickReason::RemoteRequest);
+                            Response::Error("No such game".to_owned())
                         }
                     } else {
-                        remote.send(Response::Error("No such client".to_owned()));
+                        self.kick(client, None, KickReason::RemoteRequest);
+                        Response::Error("Client not ready".to_owned())
+                    }
+                } else {
+                    Response::Error("No such client".to_owned())
+                }
+            },
+            Request::ForceStart(game_id) => {
+                if let Some(mut lobby) = self.lobbies.remove(&game_id) {
+                    lobby.update_pending();
+                    lobby.force();
+                    let matchmaking = self.config.queue_matchmaking(lobby.queue());
+                    let added = lobby.fill_with_computers(
+                        matchmaking.players_per_game,
+                        matchmaking.cpu_race,
+                        matchmaking.cpu_difficulty,
+                    );
+                    info!("Force starting game {} with {} computer players added", game_id, added);
+                    if let Err(problems) = lobby.is_valid() {
+                        // The lobby is kept, including the added computers
+                        self.lobbies.insert(game_id, lobby);
+                        Response::Error(describe_problems(&problems))
+                    } else {
+                        self.start_lobby(game_id, lobby);
+                        Response::ForceStart
+                    }
+                } else {
+                    Response::Error("No such game".to_owned())
+                }
+            },
+            Request::StartGame(game_id) => {
+                if let Some(mut lobby) = self.lobbies.remove(&game_id) {
+                    lobby.update_pending();
+                    if let Err(problems) = lobby.is_valid() {
+                        self.lobbies.insert(game_id, lobby);
+                        Response::Error(describe_problems(&problems))
+                    } else {
+                        // TODO: Connections are dropped if the start fails,
+                        // maybe they should be returned to the playlist instead
+                        self.start_lobby(game_id, lobby);
+                        Response::StartGame
                     }
+                } else {
+                    Response::Error("No such game".to_owned())
+                }
+            },
+            Request::CancelLobby(game_id) => match self.lobbies.remove(&game_id) {
+                Some(lobby) => {
+                    info!("Lobby {} cancelled, returning its clients to the playlist", game_id);
+                    for (client, req) in lobby.cancel() {
+                        self.return_to_playlist(client, req);
+                    }
+                    Response::CancelLobby
                 },
-                Request::StartGame(game_id) => {
-                    if let Some(lobby) = self.lobbies.remove(&game_id) {
-                        if !lobby.is_valid() {
-                            remote.send(Response::Error("The lobby is empty".to_owned()));
-                        } else if let Some(game) = lobby.start() {
-                            self.games.insert(game_id, spawn_game(game));
-                            remote.send(Response::StartGame);
-                        } else {
-                            remote.send(Response::Error("Game start failed".to_owned()));
-                            // TODO: Connections are dropped here
-                            // maybe they should be returned to the playlist instead
-                        }
+                None => Response::Error("No such game".to_owned()),
+            },
+            Request::GetGames => {
+                let mut games = self.snapshot().game_list();
+                games.extend(self.routed_games());
+                games.sort_by_key(|game| game.id);
+                Response::GetGames(games)
+            },
+            Request::GetLobby(game_id) => {
+                if let Some(lobby) = self.lobbies.get_mut(&game_id) {
+                    lobby.update_pending();
+                }
+                match self.snapshot().lobbies.into_iter().find(|l| l.id == game_id) {
+                    Some(lobby) => Response::GetLobby(LobbyInfo {
+                        id: lobby.id,
+                        external_id: lobby.external_id,
+                        players: lobby.players,
+                    }),
+                    None => Response::Error("No such game".to_owned()),
+                }
+            },
+            Request::GetStats => Response::GetStats(self.snapshot().stats),
+            Request::GetStandings => Response::GetStandings(self.standings.clone()),
+            Request::GetRecentKicks => Response::GetRecentKicks(self.recent_kicks.iter().cloned().collect()),
+            Request::DropPlaylistItem(client_id) => match self.client_index_by_id(client_id) {
+                Some(index) => {
+                    self.drop_client(index, KickReason::RemoteRequest);
+                    Response::DropPlaylist
+                },
+                None => Response::Error("No such client".to_owned()),
+            },
+            Request::ClearPlaylist => {
+                for i in (0..self.playlist.len()).rev() {
+                    self.drop_client(i, KickReason::RemoteRequest);
+                }
+                Response::ClearPlaylist
+            },
+            Request::GetLaunchCommands(game_id) => {
+                let snapshot = self.snapshot();
+                let lobby = snapshot.lobbies.into_iter().find(|l| l.id == game_id);
+                let game = snapshot.games.into_iter().find(|g| g.id == game_id);
+                match (lobby, game) {
+                    (Some(lobby), _) => Response::GetLaunchCommands(
+                        lobby.players.into_iter().map(|p| p.launch_command).collect(),
+                    ),
+                    (None, Some(game)) => {
+                        Response::GetLaunchCommands(game.launch_commands.into_iter().map(Some).collect())
+                    },
+                    (None, None) => Response::Error("No such game".to_owned()),
+                }
+            },
+            Request::GetScore(game_id) => match self.snapshot().games.into_iter().find(|g| g.id == game_id) {
+                Some(game) => Response::GetScore(game.player_scores),
+                None => Response::Error("No such game".to_owned()),
+            },
+            Request::SaveReplay(game_id, path) => {
+                if let Some(game) = self.games.get_mut(&game_id) {
+                    let record = &game.config().match_defaults.record_results;
+                    let path = match &record.replay_name_pattern {
+                        Some(pattern) => {
+                            let name = pattern.expand(&ReplayNameFields {
+                                game_id,
+                                timestamp: SystemTime::now(),
+                                map: game.config().match_defaults.game.map_name.as_deref(),
+                                players: game.player_names(),
+                                winner: None,
+                                external_id: game.external_id(),
+                            });
+                            Path::new(&path).join(name).to_string_lossy().into_owned()
+                        },
+                        None => path,
+                    };
+                    let output_path = record.output_path(&path);
+                    if game.try_send(FromSupervisor::SaveReplay(path)).is_some() {
+                        self.saved_replays.insert(game_id, output_path);
+                        Response::SaveReplay
                     } else {
-                        remote.send(Response::Error("No such game".to_owned()));
+                        Response::Error("Game is already over".to_owned())
+                    }
+                } else {
+                    Response::Error("No such game".to_owned())
+                }
+            },
+            Request::FetchReplay(game_id) => match self.saved_replays.get(&game_id) {
+                Some(path) => {
+                    let chunk_bytes = self.config.remote_controller.replay_chunk_bytes;
+                    match ReplayTransfer::open(game_id, path, chunk_bytes) {
+                        Ok((transfer, size)) => {
+                            self.replay_transfers.push_back(transfer);
+                            Response::FetchReplay(size)
+                        },
+                        Err(e) => Response::Error(format!("Could not read replay: {}", e)),
                     }
                 },
-                _ => remote.send(Response::Error("Unsupported".to_owned())),
+                None => Response::Error("No saved replay".to_owned()),
+            },
+            _ => Response::Error("Unsupported".to_owned()),
+        }
+    }
+
+    /// Send the next chunks of the replays being transferred to the remote controller
+    /// At most `REPLAY_CHUNKS_PER_UPDATE` chunks are read per call, so that requests are not delayed
+    /// Transfers are dropped if the controller disconnects or the file cannot be read
+    fn send_replay_chunks(&mut self, remote: &mut Remote) {
+        for _ in 0..REPLAY_CHUNKS_PER_UPDATE {
+            let transfer = match self.replay_transfers.front_mut() {
+                Some(transfer) => transfer,
+                None => return,
             };
-            RemoteUpdateStatus::Processed
-        } else {
-            RemoteUpdateStatus::NoAction
+            match transfer.next_update() {
+                Ok(Some(update)) => {
+                    if remote.send_update(update).is_err() {
+                        self.replay_transfers.clear();
+                        return;
+                    }
+                },
+                Ok(None) => {
+                    self.replay_transfers.pop_front();
+                },
+                Err(e) => {
+                    error!("Could not read the replay of game {}: {}", transfer.game_id(), e);
+                    self.replay_transfers.pop_front();
+                },
+            }
+        }
+    }
+
+    /// Accept new downstream proxies, and handle the messages of the connected ones
+    /// Downstream proxies must authenticate with an admin token if tokens are configured
+    fn update_peers(&mut self, remote: &mut Remote) {
+        let names: Vec<String> = self.peers.keys().cloned().collect();
+        for name in names {
+            let mut messages = Vec::new();
+            let connected = self.peers.get_mut(&name).unwrap().receive(&mut messages);
+            for msg in messages {
+                self.peer_message(&name, msg);
+            }
+            if !connected {
+                warn!("Downstream proxy {:?} disconnected", name);
+                self.peers.remove(&name);
+            }
+        }
+
+        while let Some(session) = remote.try_recv_peer() {
+            let tokens = &self.config.remote_controller.tokens;
+            let role = session.token.as_ref().and_then(|token| tokens.get(token));
+            let rejection = if !tokens.is_empty() && role != Some(&RemoteRole::Admin) {
+                Some("Invalid token, downstream proxies must authenticate as admins".to_owned())
+            } else if let Err(e) = federation::check_peer_name(&session.name) {
+                Some(e)
+            } else if self.peers.contains_key(&session.name) {
+                Some(format!("Peer {:?} is already connected", session.name))
+            } else {
+                None
+            };
+            match rejection {
+                Some(reason) => {
+                    warn!("Rejected downstream proxy {:?}: {}", session.name, reason);
+                    session.reject(&reason);
+                },
+                None => {
+                    info!("Accepted downstream proxy {:?}", session.name);
+                    let peer = session.accept();
+                    self.peers.insert(peer.name().to_owned(), peer);
+                },
+            }
+        }
+    }
+
+    /// Handle a message of a downstream proxy
+    /// Updates about routed games are passed to the remote controller with the ids of this proxy
+    fn peer_message(&mut self, peer: &str, msg: PeerMessage) {
+        match msg {
+            PeerMessage::Update(update) => {
+                let routed = self
+                    .routes
+                    .iter()
+                    .find(|(_, route)| route.peer == peer && route.id == update.game_id())
+                    .map(|(&id, _)| id);
+                match routed {
+                    Some(id) => {
+                        if update.is_final() {
+                            self.routes.remove(&id);
+                        }
+                        self.push_update(update.with_game_id(id));
+                    },
+                    None => debug!("Dropping update of peer {} about an unknown game: {:?}", peer, update),
+                }
+            },
+            other => warn!("Unexpected message from peer {}: {:?}", peer, other),
+        }
+    }
+
+    /// Forward a request to a downstream proxy and wait for the response
+    fn forward(&mut self, peer: &str, request: remote_message::Request) -> remote_message::Response {
+        let mut messages = Vec::new();
+        let result = match self.peers.get_mut(peer) {
+            Some(p) => p.request(request, &mut messages),
+            None => Err(format!("Peer {} is not connected", peer)),
+        };
+        for msg in messages {
+            self.peer_message(peer, msg);
+        }
+        result.unwrap_or_else(remote_message::Response::Error)
+    }
+
+    /// Clients waiting in the playlists of the downstream proxies, with ids of this proxy
+    fn peer_playlist(&self) -> Vec<PlaylistEntry> {
+        let mut names: Vec<&String> = self.peers.keys().collect();
+        names.sort();
+        names.into_iter().flat_map(|name| self.peers[name].playlist()).collect()
+    }
+
+    /// Lobbies and games moved to the downstream proxies, as listed by GetGames, with ids of this proxy
+    fn routed_games(&mut self) -> Vec<remote_message::GameInfo> {
+        let mut peers: Vec<String> = self.routes.values().map(|route| route.peer.clone()).collect();
+        peers.sort();
+        peers.dedup();
+
+        let mut games = Vec::new();
+        for peer in peers {
+            let list = match self.forward(&peer, remote_message::Request::GetGames) {
+                remote_message::Response::GetGames(list) => list,
+                _ => continue,
+            };
+            for mut game in list {
+                let routed = self.routes.iter().find(|(_, r)| r.peer == peer && r.id == game.id);
+                if let Some((&id, _)) = routed {
+                    game.id = id;
+                    games.push(game);
+                }
+            }
+        }
+        games
+    }
+
+    /// Forward a request about a lobby or game moved to a downstream proxy, or about its clients
+    /// Returns None if the request is processed locally
+    fn route_remote_request(&mut self, msg: &remote_message::Request) -> Option<remote_message::Response> {
+        use crate::remote_control::message::*;
+
+        if let Request::AddToLobby(game_id, client_id, process_options) = msg {
+            return self.route_add_to_lobby(*game_id, client_id, process_options.clone());
+        }
+
+        let game_id = msg.game_id()?;
+        let route = self.routes.get(&game_id)?.clone();
+        if let Request::FetchReplay(_) = msg {
+            return Some(Response::Error(format!("Replays of peer {} cannot be fetched", route.peer)));
+        }
+        let response = match self.forward(&route.peer, msg.clone().with_game_id(route.id)) {
+            Response::GetLobby(mut info) => {
+                info.id = game_id;
+                Response::GetLobby(info)
+            },
+            Response::CancelLobby => {
+                self.routes.remove(&game_id);
+                Response::CancelLobby
+            },
+            other => other,
+        };
+        Some(response)
+    }
+
+    /// Add a client of a downstream proxy to a lobby, moving the lobby there if it's empty
+    /// Lobbies with clients of different proxies are rejected
+    /// Returns None if both the lobby and the client are local
+    fn route_add_to_lobby(
+        &mut self, game_id: GameId, client_id: &str, process_options: Option<ProcessOptions>,
+    ) -> Option<remote_message::Response> {
+        use crate::remote_control::message::*;
+
+        let peer_client = federation::split_peer_client(client_id)
+            .filter(|(peer, _)| self.peers.contains_key(*peer))
+            .map(|(peer, id)| (peer.to_owned(), id.to_owned()));
+        let route = self.routes.get(&game_id).cloned();
+        let cross_proxy = |lobby_on: &str, client_on: &str| {
+            Response::Error(format!(
+                "Cannot pair clients of different proxies: lobby {} is on {}, but the client is on {}",
+                game_id, lobby_on, client_on
+            ))
+        };
+
+        let (peer, id, route) = match (peer_client, route) {
+            (None, None) => return None,
+            (None, Some(route)) => {
+                if self.client_index_by_id(client_id.to_owned()).is_none() {
+                    return Some(Response::Error("No such client".to_owned()));
+                }
+                return Some(cross_proxy(&format!("peer {}", route.peer), "this proxy"));
+            },
+            (Some((peer, _)), Some(route)) if peer != route.peer => {
+                return Some(cross_proxy(&format!("peer {}", route.peer), &format!("peer {}", peer)));
+            },
+            (Some((peer, id)), Some(route)) => (peer, id, route),
+            (Some((peer, id)), None) => match self.lobbies.get(&game_id) {
+                Some(lobby) if !lobby.is_empty() => {
+                    return Some(cross_proxy("this proxy", &format!("peer {}", peer)));
+                },
+                Some(_) => match self.move_lobby_to_peer(game_id, &peer) {
+                    Ok(route) => (peer, id, route),
+                    Err(response) => return Some(response),
+                },
+                None => return Some(Response::Error("No such game".to_owned())),
+            },
+        };
+        Some(self.forward(&peer, Request::AddToLobby(route.id, id, process_options)))
+    }
+
+    /// Replace an empty lobby with one created on a downstream proxy, keeping its id
+    /// The external id, queue and fullscreen override of the lobby are carried over,
+    /// but otherwise the new lobby uses the config of the downstream proxy
+    fn move_lobby_to_peer(&mut self, game_id: GameId, peer: &str) -> Result<Route, remote_message::Response> {
+        use crate::remote_control::message::*;
+
+        let lobby = &self.lobbies[&game_id];
+        let external_id = lobby.external_id().map(str::to_owned);
+        let queue = lobby.queue().map(str::to_owned);
+        let fullscreen = lobby.config().match_defaults.game.fullscreen;
+        let default_fullscreen = self.config.queue_match_defaults(queue.as_deref()).game.fullscreen;
+
+        let create = match queue {
+            Some(queue) => Request::CreateQueueLobby(queue, external_id),
+            None => Request::CreateLobby(external_id),
+        };
+        let id = match self.forward(peer, create) {
+            Response::CreateLobby(id) => id,
+            Response::Error(e) => return Err(Response::Error(format!("Peer {}: {}", peer, e))),
+            other => {
+                let e = format!("Unexpected response from peer {}: {:?}", peer, other);
+                return Err(Response::Error(e));
+            },
+        };
+        if let Some(fullscreen) = fullscreen.filter(|_| fullscreen != default_fullscreen) {
+            if let Response::Error(e) = self.forward(peer, Request::SetLobbyFullscreen(id, fullscreen)) {
+                warn!("Could not set fullscreen of lobby {} on peer {}: {}", game_id, peer, e);
+            }
+        }
+
+        info!("Lobby {} moved to peer {} as {}", game_id, peer, id);
+        self.lobbies.remove(&game_id).unwrap().close("Lobby moved to another proxy");
+        let route = Route {
+            peer: peer.to_owned(),
+            id,
+        };
+        self.routes.insert(game_id, route.clone());
+        Ok(route)
+    }
+
+    /// Exchange messages with the upstream proxy this one is federated with, see `federation`
+    /// The playlist is sent when it changes, and forwarded requests are processed
+    pub fn update_upstream(&mut self, link: &mut UpstreamLink) {
+        link.send_playlist(self.snapshot().playlist);
+        while let Some(update) = self.upstream_updates.pop_front() {
+            link.send_update(update);
+        }
+        while let Some((seq, request)) = link.try_recv() {
+            let response = self.process_upstream_request(request);
+            link.send_response(seq, response);
+        }
+    }
+
+    /// Process a request forwarded by the upstream proxy
+    /// The lobbies it creates are tracked, so that their updates are sent to it
+    fn process_upstream_request(&mut self, msg: remote_message::Request) -> remote_message::Response {
+        use crate::remote_control::message::*;
+
+        if !federation::is_forwardable(&msg) {
+            return Response::Error("Not allowed from the upstream proxy".to_owned());
         }
+        let cancelled = match msg {
+            Request::CancelLobby(id) => Some(id),
+            _ => None,
+        };
+        let response = self.process_remote_request(msg);
+        match &response {
+            Response::CreateLobby(id) => {
+                self.upstream_games.insert(*id);
+            },
+            Response::CancelLobby => {
+                if let Some(id) = cancelled {
+                    self.upstream_games.remove(&id);
+                }
+            },
+            _ => {},
+        }
+        response
+    }
+
+    /// Stop accepting new games, closing the lobbies, and let the started games finish
+    /// After this, the proxy should quit when `is_drained` returns true
+    pub fn drain(&mut self) {
+        info!("Draining, {} games still running", self.starting.len() + self.games.len());
+        self.draining = true;
+        for (_id, lobby) in self.lobbies.drain() {
+            lobby.close("Proxy is draining, not accepting new games");
+        }
+    }
+
+    /// Checks if draining has been requested, and all games are over
+    pub fn is_drained(&self) -> bool {
+        self.draining && self.starting.is_empty() && self.games.is_empty()
     }
 
     /// Destroys the supervisor, ending all games,
@@ -354,9 +1986,15 @@ impl Supervisor {
             game.send(FromSupervisor::Quit);
         }
 
+        // Abort and wait for games being started
+        for (_id, mut start) in self.starting.into_iter() {
+            start.abort();
+            let _ = start.collect();
+        }
+
         // Destroy all lobbies
         for (_id, lobby) in self.lobbies.into_iter() {
-            lobby.close();
+            lobby.close("Proxy is shutting down");
         }
 
         // Close all gamelist connections by drop
@@ -368,6 +2006,8 @@ impl Supervisor {
 pub enum RemoteUpdateStatus {
     /// Server quit requested
     Quit,
+    /// The remote controller listener was closed by request
+    Disabled,
     /// A request was processed
     Processed,
     /// No action was taken
@@ -0,0 +1,116 @@
+mod common;
+
+use sc2_proto::sc2api::{Request, Response, Status};
+
+use sc2_proxy::config::{MatchConfig, MatchmakingMode};
+use sc2_proxy::refine::{AnnounceRemaining, Pipeline, RefineContext, ResponseRefiner};
+use sc2_proxy::supervisor::Supervisor;
+
+fn observation(game_loop: u32, game_over: bool) -> Response {
+    let mut resp = Response::new();
+    resp.set_status(Status::in_game);
+    let obs = resp.mut_observation();
+    obs.mut_observation().set_game_loop(game_loop);
+    if game_over {
+        obs.mut_player_result().push(Default::default());
+    }
+    resp
+}
+
+/// Chat messages added to the observation of `game_loop`
+fn announced(refiner: &mut AnnounceRemaining, game_loop: u32, game_over: bool) -> Vec<String> {
+    let mut resp = observation(game_loop, game_over);
+    refiner.refine(&mut resp, &RefineContext::default());
+    let chat = resp.get_observation().get_chat();
+    assert!(chat.iter().all(|c| c.get_player_id() == 0));
+    chat.iter().map(|c| c.get_message().to_owned()).collect()
+}
+
+#[test]
+fn test_announce_refiner() {
+    let mut config = MatchConfig::default();
+    config.time_limits.announce_remaining = true;
+    // Nothing to announce without a limit
+    assert!(!Pipeline::new(&config).refines_responses());
+    config.time_limits.game_loops = Some(1000);
+    assert!(Pipeline::new(&config).refines_responses());
+}
+
+#[test]
+fn test_checkpoints_announced_once() {
+    let mut refiner = AnnounceRemaining::new(1000, &[75, 90, 95]);
+    assert!(announced(&mut refiner, 700, false).is_empty());
+    assert_eq!(announced(&mut refiner, 750, false), vec!["Proxy: 250 loops remaining"]);
+    // Repeated observations of the same game loop
+    assert!(announced(&mut refiner, 750, false).is_empty());
+    assert!(announced(&mut refiner, 800, false).is_empty());
+    assert_eq!(announced(&mut refiner, 904, false), vec!["Proxy: 96 loops remaining"]);
+    assert_eq!(announced(&mut refiner, 950, false), vec!["Proxy: 50 loops remaining"]);
+    assert!(announced(&mut refiner, 990, false).is_empty());
+}
+
+#[test]
+fn test_checkpoints_passed_in_one_step() {
+    let mut refiner = AnnounceRemaining::new(1000, &[95, 75, 90]);
+    assert_eq!(announced(&mut refiner, 920, false), vec!["Proxy: 80 loops remaining"]);
+    assert_eq!(announced(&mut refiner, 960, false), vec!["Proxy: 40 loops remaining"]);
+    assert!(announced(&mut refiner, 980, false).is_empty());
+
+    // Not after the game is over
+    let mut refiner = AnnounceRemaining::new(1000, &[75]);
+    assert!(announced(&mut refiner, 800, true).is_empty());
+}
+
+#[test]
+fn test_announce_checkpoints_config() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    assert_eq!(config.match_defaults.time_limits.announce_checkpoints, vec![75, 90, 95]);
+    config.match_defaults.time_limits.announce_checkpoints = vec![50, 100];
+    assert!(config.check().is_err());
+    config.match_defaults.time_limits.announce_checkpoints = vec![50, 99];
+    assert!(config.check().is_ok());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_remaining_loops_in_chat() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.time_limits.game_loops = Some(80);
+    config.match_defaults.time_limits.announce_remaining = true;
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("clockbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    let mut step = Request::new();
+    step.mut_step().set_count(2);
+    let mut obs = Request::new();
+    obs.mut_observation();
+    let mut messages = Vec::new();
+    'game: loop {
+        common::send(&mut bot, &step);
+        assert!(common::recv(&mut bot).has_step());
+        // Observed twice per game loop, announced only once
+        for _ in 0..2 {
+            common::send(&mut bot, &obs);
+            let resp = common::recv(&mut bot);
+            let game_loop = resp.get_observation().get_observation().get_game_loop();
+            for chat in resp.get_observation().get_chat() {
+                messages.push((game_loop, chat.get_message().to_owned()));
+            }
+            // The connection is closed after the time limit
+            if game_loop >= 80 {
+                break 'game;
+            }
+        }
+    }
+    assert_eq!(messages, vec![
+        (60, "Proxy: 20 loops remaining".to_owned()),
+        (72, "Proxy: 8 loops remaining".to_owned()),
+        (76, "Proxy: 4 loops remaining".to_owned()),
+    ]);
+    common::wait_games(&mut sv);
+}
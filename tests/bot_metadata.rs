@@ -0,0 +1,34 @@
+mod common;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::proxy::{ClientConnection, ConnectionMeta};
+use sc2_proxy::supervisor::Supervisor;
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_metadata_in_result() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::Pairs));
+    let mut bots = Vec::new();
+    for (name, uri) in &[("taggedbot", "/sc2api?meta=build-517"), ("plainbot", "/sc2api")] {
+        let (proxy_side, mut bot) = common::connect_bot();
+        let peer_addr = proxy_side.peer_addr().unwrap().to_string();
+        sv.add_connection(ClientConnection {
+            client: proxy_side,
+            meta: ConnectionMeta::from_handshake(peer_addr, uri, None, None),
+        });
+        common::send(&mut bot, &common::join_request(name));
+        sv.update_playlist();
+        bots.push(bot);
+    }
+    common::wait_lobbies(&mut sv);
+    for bot in bots.iter_mut() {
+        assert!(common::recv(bot).has_join_game());
+    }
+    for bot in bots.iter_mut() {
+        common::play_until_end(bot);
+    }
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.player_metadata, vec![Some("build-517".to_owned()), None]);
+}
@@ -0,0 +1,394 @@
+//! Test helpers for running the proxy against the fake SC2 process in `examples/fake_sc2.rs`
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io::prelude::*;
+use std::io::ErrorKind::{TimedOut, WouldBlock};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+
+use protobuf::{parse_from_bytes, Message};
+use sc2_proto::common::Race;
+use sc2_proto::sc2api::{Request, RequestJoinGame, Response};
+use tempfile::TempDir;
+use websocket::sync::{Client as WsClient, Server};
+use websocket::{ClientBuilder, OwnedMessage};
+
+use bufstream::BufStream;
+use portpicker::pick_unused_port;
+
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::remote_control::audit::AuditLog;
+use sc2_proxy::remote_control::{self, message, Remote};
+use sc2_proxy::supervisor::{GameId, RemoteUpdateStatus, Supervisor};
+
+/// Websocket connection, same type the proxy uses for its clients
+pub type Client = WsClient<TcpStream>;
+
+/// Map name available in the fake installation
+pub const MAP_NAME: &str = "Test";
+
+static INSTALL: OnceLock<TempDir> = OnceLock::new();
+
+/// Path to the fake SC2 binary, built as an example by `cargo test`
+fn fake_sc2_binary() -> PathBuf {
+    let exe = env::current_exe().expect("Could not get test executable path");
+    let target_dir = exe.parent().unwrap().parent().unwrap();
+    target_dir.join("examples").join("fake_sc2")
+}
+
+/// Creates a fake SC2 installation and points the proxy to it
+/// Returns the base directory of the installation
+pub fn fake_install() -> &'static Path {
+    INSTALL
+        .get_or_init(|| {
+            let dir = TempDir::new().expect("Could not create temp dir");
+
+            let version_dir = dir.path().join("Versions").join("Base99999");
+            fs::create_dir_all(&version_dir).unwrap();
+            fs::copy(fake_sc2_binary(), version_dir.join("fake_sc2")).expect("Fake SC2 binary not built");
+
+            let map_dir = dir.path().join("Maps").join("Testing");
+            fs::create_dir_all(&map_dir).unwrap();
+            fs::write(map_dir.join(format!("{}.SC2Map", MAP_NAME)), b"").unwrap();
+
+            env::set_var("SC2_PROXY_BASE", dir.path());
+            env::set_var("SC2_PROXY_BIN", "fake_sc2");
+            dir
+        })
+        .path()
+}
+
+/// Config using the fake installation
+pub fn config(mode: MatchmakingMode) -> Config {
+    fake_install();
+    let mut config = Config::new();
+    config.matchmaking.mode = mode;
+    config.match_defaults.game.map_name = Some(MAP_NAME.to_owned());
+    config
+}
+
+/// Process ids of all fake SC2 processes launched so far
+pub fn fake_pids() -> Vec<u32> {
+    match fs::read_dir(fake_install().join("pids")) {
+        Ok(entries) => entries
+            .map(|e| e.unwrap().file_name().to_str().unwrap().parse().unwrap())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Contents of the pid file of a fake SC2 process
+pub fn fake_pid_file(pid: u32) -> String {
+    fs::read_to_string(fake_install().join("pids").join(pid.to_string())).expect("Could not read pid file")
+}
+
+/// Mark the fake SC2 processes launched with this config, see `marked_pids`
+/// Tests run in parallel, so this is needed to tell their processes apart
+pub fn mark(config: &mut Config, marker: &str) {
+    config
+        .process
+        .env
+        .insert("FAKE_SC2_MARKER".to_owned(), marker.to_owned());
+}
+
+/// Process ids of the fake SC2 processes with the given mark,
+/// waiting until there are at least `count` of them
+pub fn marked_pids(marker: &str, count: usize) -> Vec<u32> {
+    let start = std::time::Instant::now();
+    loop {
+        let pids: Vec<u32> = fake_pids()
+            .into_iter()
+            .filter(|&pid| fake_pid_file(pid) == marker)
+            .collect();
+        if pids.len() >= count {
+            return pids;
+        }
+        assert!(start.elapsed() < std::time::Duration::from_secs(10), "Processes not launched");
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
+
+/// Wait for a process to exit, failing if it doesn't
+pub fn wait_exit(pid: u32) {
+    let start = std::time::Instant::now();
+    while is_running(pid) {
+        assert!(start.elapsed() < std::time::Duration::from_secs(10), "SC2 process {} leaked", pid);
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
+
+/// Checks if a process is running (and not a zombie)
+pub fn is_running(pid: u32) -> bool {
+    match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => {
+            let state = stat.rsplit(')').next().unwrap().trim_start().chars().next();
+            state != Some('Z')
+        },
+        Err(_) => false,
+    }
+}
+
+/// Creates a connected websocket pair: (proxy side, bot side)
+pub fn connect_bot() -> (Client, Client) {
+    let mut server = Server::bind("127.0.0.1:0").expect("Could not bind");
+    let addr = server.local_addr().unwrap();
+
+    let bot = thread::spawn(move || {
+        ClientBuilder::new(&format!("ws://{}/sc2api", addr))
+            .unwrap()
+            .connect_insecure()
+            .expect("Could not connect")
+    });
+
+    let proxy_side = server.accept().ok().unwrap().accept().expect("Could not accept");
+    (proxy_side, bot.join().unwrap())
+}
+
+/// Send a request from the bot
+pub fn send(bot: &mut Client, req: &Request) {
+    let bytes = req.write_to_bytes().expect("Invalid request");
+    bot.send_message(&OwnedMessage::Binary(bytes)).expect("Could not send");
+}
+
+/// Receive a response to the bot
+pub fn recv(bot: &mut Client) -> Response {
+    match bot.recv_message().expect("Could not receive") {
+        OwnedMessage::Binary(bytes) => parse_from_bytes::<Response>(&bytes).expect("Invalid response"),
+        other => panic!("Expected a binary message, got {:?}", other),
+    }
+}
+
+/// Join game request with the given player name
+pub fn join_request(name: &str) -> Request {
+    let mut join = RequestJoinGame::new();
+    join.set_race(Race::Terran);
+    join.set_player_name(name.to_owned());
+    join.mut_options().set_raw(true);
+
+    let mut req = Request::new();
+    req.set_join_game(join);
+    req
+}
+
+/// Play the game until results are available, and then leave it
+/// Returns the final observation response
+pub fn play_until_end(bot: &mut Client) -> Response {
+    loop {
+        let mut step = Request::new();
+        step.mut_step().set_count(10);
+        send(bot, &step);
+        assert!(recv(bot).has_step());
+
+        let mut obs = Request::new();
+        obs.mut_observation();
+        send(bot, &obs);
+        let resp = recv(bot);
+        assert!(resp.has_observation());
+
+        if !resp.get_observation().get_player_result().is_empty() {
+            let mut leave = Request::new();
+            leave.mut_leave_game();
+            send(bot, &leave);
+            assert!(recv(bot).has_leave_game());
+            return resp;
+        }
+    }
+}
+
+/// Update games until none are running
+pub fn wait_games(sv: &mut Supervisor) {
+    let start = std::time::Instant::now();
+    while sv.starting_count() > 0 || sv.game_count() > 0 {
+        assert!(start.elapsed() < std::time::Duration::from_secs(10), "Game did not end");
+        sv.update_lobbies();
+        sv.update_games();
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
+
+/// Update lobbies until all of them have started or closed
+pub fn wait_lobbies(sv: &mut Supervisor) {
+    let start = std::time::Instant::now();
+    while sv.lobby_count() > 0 || sv.starting_count() > 0 {
+        assert!(start.elapsed() < std::time::Duration::from_secs(10), "Lobbies did not start");
+        sv.update_lobbies();
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
+
+/// Start a remote control server and connect to it
+pub fn connect_remote() -> (Remote, RemoteConn) {
+    connect_remote_audited(None)
+}
+
+/// Start a remote control server recording requests to an audit log, and connect to it
+pub fn connect_remote_audited(audit_log: Option<AuditLog>) -> (Remote, RemoteConn) {
+    let port = pick_unused_port().expect("Could not find a free port");
+    let addr = format!("127.0.0.1:{}", port);
+    let remote = remote_control::run_server(&addr, audit_log).expect("Could not bind");
+    let stream = BufStream::new(TcpStream::connect(&addr).expect("Could not connect"));
+    (remote, RemoteConn {
+        stream,
+        updates: Vec::new(),
+    })
+}
+
+/// Remote controller connection, collecting the updates it receives
+pub struct RemoteConn {
+    stream: BufStream<TcpStream>,
+    /// Updates received so far
+    pub updates: Vec<message::Update>,
+}
+impl RemoteConn {
+    /// Read a line, returning None on timeout
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match self.stream.read_line(&mut line) {
+            Ok(0) => panic!("Remote controller connection closed"),
+            Ok(_) => Some(line),
+            Err(ref e) if e.kind() == WouldBlock || e.kind() == TimedOut => None,
+            Err(e) => panic!("Could not read: {:?}", e),
+        }
+    }
+
+    /// Send a raw line, e.g. an invalid request, and read the response
+    pub fn send_line(&mut self, line: &[u8]) -> message::Response {
+        self.stream.write_all(line).unwrap();
+        self.stream.write_all(b"\n").unwrap();
+        self.stream.flush().unwrap();
+        self.read_response()
+    }
+
+    /// Read the next response, collecting updates before it
+    fn read_response(&mut self) -> message::Response {
+        loop {
+            let line = self.read_line().expect("No response");
+            match serde_json::from_str(&line) {
+                Ok(resp) => return resp,
+                Err(_) => self
+                    .updates
+                    .push(serde_json::from_str(&line).expect("Invalid JSON returned")),
+            }
+        }
+    }
+}
+
+/// Collect the updates sent by the supervisor so far
+pub fn remote_updates(
+    sv: &mut Supervisor, remote: &mut Remote, conn: &mut RemoteConn,
+) -> Vec<message::Update> {
+    // Updates are sent after the response to the next request
+    remote_request(sv, remote, conn, &message::Request::Ping(0));
+
+    let timeout = Some(std::time::Duration::from_millis(100));
+    conn.stream.get_ref().set_read_timeout(timeout).unwrap();
+    while let Some(line) = conn.read_line() {
+        conn.updates
+            .push(serde_json::from_str(&line).expect("Invalid JSON returned"));
+    }
+    conn.stream.get_ref().set_read_timeout(None).unwrap();
+
+    conn.updates.drain(..).collect()
+}
+
+/// Keep updating the remote controller until an update matching `done` is received
+/// Returns the updates received so far, including the matching one
+pub fn wait_update(
+    sv: &mut Supervisor, remote: &mut Remote, conn: &mut RemoteConn, done: impl Fn(&message::Update) -> bool,
+) -> Vec<message::Update> {
+    let start = std::time::Instant::now();
+    let timeout = Some(std::time::Duration::from_millis(100));
+    conn.stream.get_ref().set_read_timeout(timeout).unwrap();
+    while !conn.updates.iter().any(&done) {
+        assert!(start.elapsed() < std::time::Duration::from_secs(30), "Update not received");
+        sv.update_remote(remote);
+        while let Some(line) = conn.read_line() {
+            conn.updates
+                .push(serde_json::from_str(&line).expect("Invalid JSON returned"));
+        }
+    }
+    conn.stream.get_ref().set_read_timeout(None).unwrap();
+
+    conn.updates.drain(..).collect()
+}
+
+/// Send a remote control request, process it and return the response
+pub fn remote_request(
+    sv: &mut Supervisor, remote: &mut Remote, stream: &mut RemoteConn, req: &message::Request,
+) -> message::Response {
+    let mut line = serde_json::to_vec(req).expect("JSON writing failed");
+    line.push(b'\n');
+    stream.stream.write_all(&line).unwrap();
+    stream.stream.flush().unwrap();
+
+    while sv.update_remote(remote) == RemoteUpdateStatus::NoAction {
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    stream.read_response()
+}
+
+/// Create a lobby through the remote controller, and add a joining bot for each name to it
+/// Returns the lobby id and the bot side connections
+pub fn remote_lobby(
+    sv: &mut Supervisor, remote: &mut Remote, stream: &mut RemoteConn, names: &[&str],
+) -> (GameId, Vec<Client>) {
+    let mut bots = Vec::new();
+    for name in names {
+        let (proxy_side, mut bot) = connect_bot();
+        sv.add_client(proxy_side);
+        send(&mut bot, &join_request(name));
+        bots.push(bot);
+    }
+
+    let client_ids = loop {
+        sv.update_playlist();
+        match remote_request(sv, remote, stream, &message::Request::GetPlaylist) {
+            message::Response::GetPlaylist(clients) if clients.iter().all(|(_, ready)| *ready) => {
+                break clients.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+            },
+            message::Response::GetPlaylist(_) => thread::sleep(std::time::Duration::from_millis(10)),
+            other => panic!("Unexpected response {:?}", other),
+        }
+    };
+
+    let id = match remote_request(sv, remote, stream, &message::Request::CreateLobby(None)) {
+        message::Response::CreateLobby(id) => id,
+        other => panic!("Unexpected response {:?}", other),
+    };
+    for client_id in client_ids {
+        let resp = remote_request(sv, remote, stream, &message::Request::AddToLobby(id, client_id, None));
+        assert_eq!(resp, message::Response::AddToLobby(message::PlayerStatus::Launching));
+    }
+
+    wait_lobby_ready(sv, remote, stream, id);
+    (id, bots)
+}
+
+/// Wait until the SC2 processes of all participants of a lobby are ready
+pub fn wait_lobby_ready(
+    sv: &mut Supervisor, remote: &mut Remote, stream: &mut RemoteConn, id: GameId,
+) {
+    let start = std::time::Instant::now();
+    loop {
+        match remote_request(sv, remote, stream, &message::Request::GetLobby(id)) {
+            message::Response::GetLobby(info) => {
+                if info
+                    .players
+                    .iter()
+                    .all(|p| p.status == message::PlayerStatus::Ready)
+                {
+                    return;
+                }
+            },
+            other => panic!("Unexpected response {:?}", other),
+        }
+        assert!(start.elapsed() < std::time::Duration::from_secs(10), "Lobby not ready");
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
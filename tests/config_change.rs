@@ -0,0 +1,129 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::remote_control::message::{PlayerStatus, Request, Response};
+use sc2_proxy::remote_control::Remote;
+use sc2_proxy::supervisor::{GameId, Supervisor};
+
+/// Config snapshot of a lobby or game
+fn effective_config(
+    sv: &mut Supervisor, remote: &mut Remote, stream: &mut common::RemoteConn, id: GameId,
+) -> Config {
+    match common::remote_request(sv, remote, stream, &Request::GetEffectiveConfig(id)) {
+        Response::GetEffectiveConfig(config) => config,
+        other => panic!("Unexpected response {:?}", other),
+    }
+}
+
+/// Create a lobby in the default queue
+fn create_lobby(sv: &mut Supervisor, remote: &mut Remote, stream: &mut common::RemoteConn) -> GameId {
+    match common::remote_request(sv, remote, stream, &Request::CreateLobby(None)) {
+        Response::CreateLobby(id) => id,
+        other => panic!("Unexpected response {:?}", other),
+    }
+}
+
+#[test]
+fn test_config_change_applies_to_new_lobbies() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let (mut remote, mut stream) = common::connect_remote();
+    let old_lobby = create_lobby(&mut sv, &mut remote, &mut stream);
+
+    let mut new_config = common::config(MatchmakingMode::RemoteController);
+    new_config.match_defaults.game.disable_fog = true;
+    let req = Request::SetConfig(Box::new(new_config.clone()));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::SetConfig(new_config, vec![old_lobby]));
+
+    let config = effective_config(&mut sv, &mut remote, &mut stream, old_lobby);
+    assert!(!config.match_defaults.game.disable_fog);
+
+    let new_lobby = create_lobby(&mut sv, &mut remote, &mut stream);
+    let config = effective_config(&mut sv, &mut remote, &mut stream, new_lobby);
+    assert!(config.match_defaults.game.disable_fog);
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::CancelLobby(old_lobby));
+    assert_eq!(resp, Response::CancelLobby);
+    let req = Request::GetEffectiveConfig(old_lobby);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Error("No such game".to_owned()));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_incomplete_config_keeps_old_lobbies() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let (mut remote, mut stream) = common::connect_remote();
+    let old_lobby = create_lobby(&mut sv, &mut remote, &mut stream);
+
+    // No map, so new lobbies cannot be created
+    let mut new_config = common::config(MatchmakingMode::RemoteController);
+    new_config.match_defaults.game.map_name = None;
+    let req = Request::SetConfig(Box::new(new_config.clone()));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::SetConfig(new_config, vec![old_lobby]));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::CreateLobby(None));
+    assert_eq!(resp, Response::Error("Invalid configuration: Missing map name".to_owned()));
+
+    // A client joining after the change can still be added to the old lobby
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("latebot"));
+    let client_id = loop {
+        sv.update_playlist();
+        match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetPlaylist) {
+            Response::GetPlaylist(clients) if clients.iter().any(|(_, ready)| *ready) => {
+                break clients[0].0.clone();
+            },
+            Response::GetPlaylist(_) => thread::sleep(Duration::from_millis(10)),
+            other => panic!("Unexpected response {:?}", other),
+        }
+    };
+    let req = Request::AddToLobby(old_lobby, client_id, None);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::AddToLobby(PlayerStatus::Launching));
+    common::wait_lobby_ready(&mut sv, &mut remote, &mut stream, old_lobby);
+
+    let config = effective_config(&mut sv, &mut remote, &mut stream, old_lobby);
+    assert_eq!(config.match_defaults.game.map_name, Some(common::MAP_NAME.to_owned()));
+    sv.close();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_config_change_keeps_start_retries() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let once = dir.path().join("failed");
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    // InvalidMapPath, not retried by the first SC2 process
+    for (key, value) in &[
+        ("FAKE_SC2_CREATE_GAME_FAILURES", "1"),
+        ("FAKE_SC2_CREATE_GAME_ERROR", "2"),
+        ("FAKE_SC2_CREATE_GAME_FAILURES_ONCE", once.to_str().unwrap()),
+    ] {
+        config.process.env.insert(key.to_string(), value.to_string());
+    }
+    config.match_defaults.game.lobby_start_retries = 1;
+    let mut sv = Supervisor::new(config.clone());
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, mut bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["retrybot"]);
+
+    // The lobby keeps the retry of the config it was created with
+    config.match_defaults.game.lobby_start_retries = 0;
+    let req = Request::SetConfig(Box::new(config));
+    assert!(matches!(
+        common::remote_request(&mut sv, &mut remote, &mut stream, &req),
+        Response::SetConfig(..)
+    ));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+    common::wait_lobbies(&mut sv);
+    assert!(once.exists());
+    assert_eq!(sv.game_count(), 1);
+    assert!(common::recv(&mut bots[0]).has_join_game());
+    common::play_until_end(&mut bots[0]);
+    common::wait_games(&mut sv);
+}
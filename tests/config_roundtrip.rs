@@ -0,0 +1,100 @@
+use sc2_proxy::config::*;
+use sc2_proxy::refine::RefinerKind;
+
+/// Config with a non-default value in every section
+fn non_default_config() -> Config {
+    let mut config = Config::new();
+    config.proxy.port = 1234;
+    config.proxy.reject_unknown_paths = true;
+    config.process.fullscreen = true;
+    config.process.verbose = false;
+    config.process.renderer = Renderer::OsMesa;
+    config.process.cpu_affinity = CpuAffinity::RoundRobin;
+    config.process.affinity_cores = vec![2, 3];
+    config
+        .process
+        .env
+        .insert("SC2_TEST".to_owned(), "1".to_owned());
+    config.matchmaking.mode = MatchmakingMode::AgainstBuiltinAI;
+    config.matchmaking.cpu_race = Race::Zerg;
+    config.matchmaking.cpu_difficulty = Difficulty::VeryEasy;
+    config.matchmaking.players_per_game = 4;
+    config.matchmaking.max_lobby_age_secs = Some(600);
+    config.matchmaking.standings_path = Some("standings.json".to_owned());
+    config.matchmaking.default_race = Some(Race::Protoss);
+    config.matchmaking.filler_ai.enabled = true;
+    config.matchmaking.randomize_slots = false;
+    config.match_defaults.game.map_name = Some("Test".to_owned());
+    config.match_defaults.game.random_seed = Some(42);
+    config.match_defaults.game.random_race = RandomRace::Seeded;
+    config.match_defaults.game.overwrite_races = Some(vec![Some(Race::Zerg), Some(Race::Zerg)]);
+    config.match_defaults.game.min_participants = 2;
+    config.match_defaults.game.lobby_start_retries = 3;
+    config.match_defaults.game.measure_latency = true;
+    config.match_defaults.game.fullscreen = Some(false);
+    config.match_defaults.game.request_refiners = vec![RefinerKind::TagChat, RefinerKind::StripCheats];
+    config.match_defaults.game.allowed_interfaces.score = false;
+    config.match_defaults.integrity = true;
+    config.match_defaults.request_limits.disable_cheats = true;
+    config.match_defaults.request_limits.disable_save_replay = true;
+    config.match_defaults.time_limits.game_loops = Some(1234);
+    config.match_defaults.record_results.compress = true;
+    config.match_defaults.record_results.game_info = true;
+    config.match_defaults.record_results.game_info_dir = Some("game_info".to_owned());
+    config.match_defaults.record_results.validity.sc2_crash = false;
+    config.match_defaults.record_results.validity.min_duration_secs = 30;
+    config.remote_controller.enabled = false;
+    config.remote_controller.enable_flag_path = Some("enable_remote".to_owned());
+    config.remote_controller.audit_log = Some("audit.log".to_owned());
+    config.remote_controller.audit_log_max_bytes = 4096;
+    config
+        .remote_controller
+        .tokens
+        .insert("token".to_owned(), RemoteRole::Spectator);
+    config.logging.file = Some("proxy.log".to_owned());
+    config.logging.keep_files = 2;
+    let mut queue = QueueConfig::default();
+    queue.matchmaking.mode = MatchmakingMode::RemoteController;
+    queue.match_defaults.integrity = true;
+    config.queues.insert("ladder".to_owned(), queue);
+    config
+}
+
+#[test]
+fn test_config_toml_roundtrip() {
+    let config = non_default_config();
+    let text = toml::to_string(&config).expect("Serialization failed");
+    let back: Config = toml::from_str(&text).expect("Deserialization failed");
+    assert_eq!(back, config);
+}
+
+#[test]
+fn test_config_json_roundtrip() {
+    let config = non_default_config();
+    let text = serde_json::to_string(&config).expect("Serialization failed");
+    let back: Config = serde_json::from_str(&text).expect("Deserialization failed");
+    assert_eq!(back, config);
+}
+
+#[test]
+fn test_config_empty() {
+    let config: Config = toml::from_str("").expect("Deserialization failed");
+    assert_eq!(config, Config::new());
+
+    let config: Config = serde_json::from_str("{}").expect("Deserialization failed");
+    assert_eq!(config, Config::new());
+}
+
+#[test]
+fn test_config_without_match_defaults() {
+    let config: Config = toml::from_str(
+        r#"
+        [proxy]
+        host = "0.0.0.0"
+        port = 1234
+        "#,
+    )
+    .expect("Deserialization failed");
+    assert_eq!(config.proxy.port, 1234);
+    assert_eq!(config.match_defaults, MatchConfig::default());
+}
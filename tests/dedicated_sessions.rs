@@ -0,0 +1,92 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use sc2_proto::sc2api::Request;
+use websocket::OwnedMessage;
+
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::supervisor::{KickReason, Supervisor};
+
+/// Config allowing client hosted games, with the fake SC2 environment variables set
+fn config(env: &[(&str, &str)]) -> Config {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.matchmaking.allow_client_hosting = true;
+    for (key, value) in env {
+        config.process.env.insert(key.to_string(), value.to_string());
+    }
+    config
+}
+
+/// Connect a bot that creates its own game
+fn create_game(sv: &mut Supervisor) -> common::Client {
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    let mut req = Request::new();
+    req.mut_create_game().mut_local_map().set_map_path(common::MAP_NAME.to_owned());
+    common::send(&mut bot, &req);
+    bot
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_dedicated_session_started_in_background() {
+    let mut sv = Supervisor::new(config(&[("FAKE_SC2_CREATE_GAME_DELAY_MS", "500")]));
+    let mut bot = create_game(&mut sv);
+    let start = Instant::now();
+    while sv.starting_count() == 0 {
+        assert!(start.elapsed() < Duration::from_secs(10), "Session not started");
+        sv.update_playlist();
+    }
+    // Launching SC2 and creating the game don't block the supervisor
+    assert!(start.elapsed() < Duration::from_millis(500), "Took {:?}", start.elapsed());
+
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 1);
+    let resp = common::recv(&mut bot);
+    assert!(resp.has_create_game() && !resp.get_create_game().has_error(), "{:?}", resp);
+    drop(bot);
+    common::wait_games(&mut sv);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_failed_dedicated_session_kicked() {
+    let mut sv = Supervisor::new(config(&[("FAKE_SC2_CREATE_GAME_FAILURES", "1")]));
+    let mut bot = create_game(&mut sv);
+    let start = Instant::now();
+    while !sv.snapshot().playlist.is_empty() {
+        assert!(start.elapsed() < Duration::from_secs(10), "Create request not processed");
+        sv.update_playlist();
+    }
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 0);
+
+    assert!(common::recv(&mut bot).get_create_game().has_error());
+    match bot.recv_message() {
+        Ok(OwnedMessage::Close(Some(data))) => assert_eq!(data.reason, "Could not start the session"),
+        other => panic!("Unexpected message {:?}", other),
+    }
+    let kick = sv.recent_kicks().last().expect("No kick recorded");
+    assert_eq!(kick.reason, KickReason::Rejected("Game creation / joining failed".to_owned()));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_stalled_dedicated_session_aborted() {
+    let mut config = config(&[("FAKE_SC2_CREATE_GAME_DELAY_MS", "60000")]);
+    config.match_defaults.time_limits.game_start_timeout_secs = Some(1);
+    let mut sv = Supervisor::new(config);
+    let _bot = create_game(&mut sv);
+    let start = Instant::now();
+    while sv.starting_count() == 0 {
+        assert!(start.elapsed() < Duration::from_secs(10), "Session not started");
+        sv.update_playlist();
+    }
+
+    // The stalled create_game is interrupted when the start times out
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 0);
+    let kick = sv.recent_kicks().last().expect("No kick recorded");
+    assert_eq!(kick.reason, KickReason::Rejected("Timed out".to_owned()));
+}
@@ -0,0 +1,176 @@
+mod common;
+
+use protobuf::{parse_from_bytes, Message};
+use sc2_proto::sc2api::{Request, Response};
+use websocket::OwnedMessage;
+
+use sc2_proxy::bench_support::observation_response;
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::delta::{self, DeltaError, Decoder, Encoder, FRAME_DELTA, FRAME_KEY};
+use sc2_proxy::proxy::{ClientConnection, ConnectionMeta};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Observation of `units` units on `game_loop`, where every tenth unit has lost health
+fn damaged_observation(units: usize, game_loop: u32) -> Vec<u8> {
+    let mut response = observation_response(units, game_loop);
+    let raw = response.mut_observation().mut_observation().mut_raw_data();
+    for unit in raw.mut_units().iter_mut().step_by(10) {
+        unit.set_health(40.0 - game_loop as f32);
+    }
+    response.write_to_bytes().unwrap()
+}
+
+#[test]
+fn test_diff_roundtrip() {
+    let base = damaged_observation(200, 0);
+    let target = damaged_observation(200, 1);
+    let ops = delta::diff(&base, &target);
+    assert!(ops.len() * 10 < target.len(), "Delta of {} bytes is too large", ops.len());
+    assert_eq!(delta::apply(&base, &ops), Ok(target));
+
+    // Shifted contents are still copied
+    let fewer = damaged_observation(150, 1);
+    let ops = delta::diff(&base, &fewer);
+    assert!(ops.len() * 10 < fewer.len());
+    assert_eq!(delta::apply(&base, &ops), Ok(fewer));
+
+    let cases: [(&[u8], &[u8]); 3] = [(b"", b"unrelated"), (b"unrelated", b""), (b"", b"")];
+    for (a, b) in &cases {
+        assert_eq!(delta::apply(a, &delta::diff(a, b)), Ok(b.to_vec()));
+    }
+}
+
+#[test]
+fn test_keyframe_interval() {
+    let mut encoder = Encoder::new(3);
+    let mut decoder = Decoder::new();
+    let mut kinds = Vec::new();
+    for game_loop in 0..7 {
+        let data = damaged_observation(100, game_loop);
+        let frame = encoder.encode(data.clone());
+        kinds.push(frame[0]);
+        assert_eq!(decoder.decode(&frame), Ok(data));
+    }
+    let (k, d) = (FRAME_KEY, FRAME_DELTA);
+    assert_eq!(kinds, vec![k, d, d, k, d, d, k]);
+    assert!(encoder.bytes_saved() > 0);
+
+    // Unrelated data is sent as a keyframe, as the delta would not be smaller
+    let mut encoder = Encoder::new(10);
+    encoder.encode(damaged_observation(100, 0));
+    assert_eq!(encoder.encode(vec![7; 64])[0], FRAME_KEY);
+}
+
+#[test]
+fn test_decode_errors() {
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.decode(&[]), Err(DeltaError::Empty));
+    assert_eq!(decoder.decode(&[9]), Err(DeltaError::UnknownFrame(9)));
+    assert_eq!(decoder.decode(&[FRAME_DELTA]), Err(DeltaError::NoBase));
+
+    assert_eq!(decoder.decode(&[FRAME_KEY, 1, 2, 3]), Ok(vec![1, 2, 3]));
+    assert_eq!(
+        decoder.decode(&[FRAME_DELTA, 0, 2, 2]),
+        Err(DeltaError::CopyOutOfRange { offset: 2, len: 2 })
+    );
+    assert_eq!(decoder.decode(&[FRAME_DELTA, 1, 5, 0]), Err(DeltaError::Malformed));
+    assert_eq!(decoder.decode(&[FRAME_DELTA, 7]), Err(DeltaError::Malformed));
+    // Failed frames leave the base intact
+    assert_eq!(decoder.decode(&[FRAME_DELTA, 0, 1, 2, 1, 1, 9]), Ok(vec![2, 3, 9]));
+}
+
+#[test]
+fn test_query_param() {
+    let meta = |uri| ConnectionMeta::from_handshake("127.0.0.1:1".to_owned(), uri, None, None);
+    assert_eq!(meta("/sc2api?delta=8").observation_delta(), Some(8));
+    assert_eq!(
+        meta("/sc2api?meta=v1&delta").observation_delta(),
+        Some(delta::DEFAULT_KEYFRAME_INTERVAL)
+    );
+    assert_eq!(meta("/sc2api?delta=0").observation_delta(), None);
+    assert_eq!(meta("/sc2api?delta=x").observation_delta(), None);
+    assert_eq!(meta("/sc2api").observation_delta(), None);
+}
+
+/// Receive a raw binary message to the bot
+fn recv_bytes(bot: &mut common::Client) -> Vec<u8> {
+    match bot.recv_message().expect("Could not receive") {
+        OwnedMessage::Binary(bytes) => bytes,
+        other => panic!("Expected binary message, got {:?}", other),
+    }
+}
+
+/// Request an observation, decoding the response with `decoder` if given
+/// Returns the kind byte of the frame, and the response
+fn observe(bot: &mut common::Client, decoder: Option<&mut Decoder>) -> (Option<u8>, Response) {
+    let mut req = Request::new();
+    req.mut_observation();
+    common::send(bot, &req);
+    let bytes = recv_bytes(bot);
+    match decoder {
+        Some(decoder) => (Some(bytes[0]), decoder.decode_response(&bytes).expect("Invalid frame")),
+        None => (None, parse_from_bytes::<Response>(&bytes).expect("Invalid response")),
+    }
+}
+
+/// Play a game connected with `uri`, observing after every step, and checking the game loops
+/// Returns the kind bytes of the observation frames, if `delta` is set
+fn play(config: Config, uri: &str, delta: bool) -> Vec<u8> {
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    let peer_addr = proxy_side.peer_addr().unwrap().to_string();
+    sv.add_connection(ClientConnection {
+        client: proxy_side,
+        meta: ConnectionMeta::from_handshake(peer_addr, uri, None, None),
+    });
+    common::send(&mut bot, &common::join_request("deltabot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    let mut decoder = Decoder::new();
+    let mut kinds = Vec::new();
+    for game_loop in (0..).step_by(10) {
+        let (kind, response) = observe(&mut bot, Some(&mut decoder).filter(|_| delta));
+        kinds.extend(kind);
+        let obs = response.get_observation();
+        assert_eq!(obs.get_observation().get_game_loop(), game_loop);
+        if !obs.get_player_result().is_empty() {
+            break;
+        }
+
+        // Other responses are not framed
+        let mut step = Request::new();
+        step.mut_step().set_count(10);
+        common::send(&mut bot, &step);
+        assert!(common::recv(&mut bot).has_step());
+    }
+
+    let mut leave = Request::new();
+    leave.mut_leave_game();
+    common::send(&mut bot, &leave);
+    assert!(common::recv(&mut bot).has_leave_game());
+    common::wait_games(&mut sv);
+    kinds
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_observation_delta() {
+    let config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    let kinds = play(config, "/sc2api?delta=3", true);
+    assert!(kinds.len() > 3);
+    assert_eq!(kinds[0], FRAME_KEY);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_observation_delta_disabled() {
+    // Not requested
+    play(common::config(MatchmakingMode::AgainstBuiltinAI), "/sc2api", false);
+
+    // Not allowed
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.allow_observation_delta = false;
+    play(config, "/sc2api?delta=3", false);
+}
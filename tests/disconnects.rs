@@ -0,0 +1,63 @@
+mod common;
+
+use websocket::OwnedMessage;
+
+use sc2_proxy::config::{Config, DisconnectScoring, MatchmakingMode};
+use sc2_proxy::results::{
+    GameEndReason, GameResult, PlayerOutcomeDetail, PlayerResult, ResultCategory, VoidReason,
+};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Start a game between two bots, which both disconnect right after joining
+fn double_disconnect(config: Config) -> GameResult {
+    let mut sv = Supervisor::new(config);
+    let mut bots = Vec::new();
+    for name in &["flakybot1", "flakybot2"] {
+        let (proxy_side, mut bot) = common::connect_bot();
+        sv.add_client(proxy_side);
+        common::send(&mut bot, &common::join_request(name));
+        sv.update_playlist();
+        bots.push(bot);
+    }
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 1);
+
+    for bot in bots.iter_mut() {
+        assert!(common::recv(bot).has_join_game());
+    }
+    for bot in bots.iter_mut() {
+        bot.send_message(&OwnedMessage::Close(None)).unwrap();
+    }
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    result.clone()
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_double_disconnect_defeat() {
+    let result = double_disconnect(common::config(MatchmakingMode::Pairs));
+    assert_eq!(result.end_reason, GameEndReason::Normal);
+    assert_eq!(result.player_results, vec![PlayerResult::Defeat, PlayerResult::Defeat]);
+    // Neither bot requested an observation before disconnecting
+    let crashed = PlayerOutcomeDetail::ClientCrashedBeforeStart;
+    assert_eq!(result.player_details, vec![crashed, crashed]);
+    assert_eq!(result.player_categories, vec![ResultCategory::Crash, ResultCategory::Crash]);
+    assert!(!result.valid);
+    let reason = VoidReason::EarlyDisconnect {
+        slot: 0,
+        game_loop: None,
+    };
+    assert_eq!(result.void_reason, Some(reason));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_double_disconnect_no_contest() {
+    let mut config = common::config(MatchmakingMode::Pairs);
+    config.match_defaults.game.simultaneous_disconnect = DisconnectScoring::NoContest;
+    let result = double_disconnect(config);
+    assert_eq!(result.end_reason, GameEndReason::NoContest);
+    assert_eq!(result.player_results, vec![PlayerResult::Tie, PlayerResult::Tie]);
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use sc2_proxy::config::{HostSelection, MatchmakingMode, ProcessOptions};
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Value of the `-displayMode` argument of a process
+#[cfg(target_os = "linux")]
+fn display_mode(pid: u32) -> String {
+    let cmdline = std::fs::read_to_string(format!("/proc/{}/cmdline", pid)).unwrap();
+    let args: Vec<&str> = cmdline.split('\0').collect();
+    let index = args.iter().position(|&a| a == "-displayMode").expect("No -displayMode");
+    args[index + 1].to_owned()
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_match_fullscreen_override() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.fullscreen = Some(true);
+    common::mark(&mut config, "fullscreen");
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("fullscreenbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    let pids = common::marked_pids("fullscreen", 1);
+    assert_eq!(display_mode(pids[0]), "1");
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_set_lobby_fullscreen() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.process.fullscreen = true;
+    common::mark(&mut config, "windowed");
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    let client_id = proxy_side.peer_addr().unwrap().to_string();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("windowedbot"));
+
+    let id = match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::CreateLobby(None)) {
+        Response::CreateLobby(id) => id,
+        other => panic!("Unexpected response {:?}", other),
+    };
+    let req = Request::SetLobbyFullscreen(id, false);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::SetLobbyFullscreen);
+
+    while sv.snapshot().playlist.iter().any(|e| !e.ready) {
+        sv.update_playlist();
+    }
+    let req_add = Request::AddToLobby(id, client_id, None);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req_add);
+    assert!(matches!(resp, Response::AddToLobby(_)));
+    let pids = common::marked_pids("windowed", 1);
+    assert_eq!(display_mode(pids[0]), "0");
+
+    // Processes are launched on joining, so the mode cannot be changed afterwards
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Error("Lobby already has participants".to_owned()));
+
+    sv.close();
+
+    // Unknown lobbies are rejected
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let req = Request::SetLobbyFullscreen(id, true);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Error("No such game".to_owned()));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_add_to_lobby_process_override() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    common::mark(&mut config, "headless");
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let mut client_ids = Vec::new();
+    let mut bots = Vec::new();
+    for name in &["viewedbot", "headlessbot"] {
+        let (proxy_side, mut bot) = common::connect_bot();
+        client_ids.push(proxy_side.peer_addr().unwrap().to_string());
+        sv.add_client(proxy_side);
+        common::send(&mut bot, &common::join_request(name));
+        bots.push(bot);
+    }
+    while sv.snapshot().playlist.iter().any(|e| !e.ready) {
+        sv.update_playlist();
+    }
+
+    let id = match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::CreateLobby(None)) {
+        Response::CreateLobby(id) => id,
+        other => panic!("Unexpected response {:?}", other),
+    };
+    let mut viewed = ProcessOptions {
+        fullscreen: true,
+        ..ProcessOptions::default()
+    };
+    viewed.env.insert("FAKE_SC2_MARKER".to_owned(), "viewed".to_owned());
+    let overrides = vec![Some(viewed), None];
+    for (client_id, process_options) in client_ids.into_iter().zip(overrides) {
+        let req = Request::AddToLobby(id, client_id, process_options);
+        let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+        assert!(matches!(resp, Response::AddToLobby(_)));
+    }
+
+    assert_eq!(display_mode(common::marked_pids("viewed", 1)[0]), "1");
+    assert_eq!(display_mode(common::marked_pids("headless", 1)[0]), "0");
+
+    match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetLobby(id)) {
+        Response::GetLobby(info) => {
+            let fullscreen: Vec<bool> = info.players.iter().map(|p| p.process_options.fullscreen).collect();
+            assert_eq!(fullscreen, vec![true, false]);
+            assert_eq!(info.players[0].process_options.env["FAKE_SC2_MARKER"], "viewed");
+        },
+        other => panic!("Unexpected response {:?}", other),
+    }
+
+    sv.close();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_spectator_defaults() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.host_selection = HostSelection::Dedicated;
+    common::mark(&mut config, "spectated");
+    let mut spectator = config.process.clone();
+    spectator.fullscreen = true;
+    spectator.env.insert("FAKE_SC2_MARKER".to_owned(), "spectator".to_owned());
+    config.process.spectator_defaults = Some(Box::new(spectator));
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("spectatedbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    assert_eq!(display_mode(common::marked_pids("spectator", 1)[0]), "1");
+    assert_eq!(display_mode(common::marked_pids("spectated", 1)[0]), "0");
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+}
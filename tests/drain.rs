@@ -0,0 +1,42 @@
+mod common;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::supervisor::Supervisor;
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_drain() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::AgainstBuiltinAI));
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("earlybot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+    assert!(!sv.is_drained());
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::Drain);
+    assert_eq!(resp, Response::Drain);
+    assert!(!sv.is_drained(), "A game is still running");
+
+    // New games are rejected
+    let (proxy_side, mut late) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut late, &common::join_request("latebot"));
+    sv.update_playlist();
+    let resp = common::recv(&mut late);
+    assert_eq!(resp.get_error(), ["Proxy: Busy, not accepting new games"]);
+    assert_eq!(sv.lobby_count(), 0);
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::CreateLobby(None));
+    assert_eq!(resp, Response::Error("Draining, not accepting new games".to_owned()));
+
+    // The running game finishes normally
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+    assert_eq!(sv.recent_results().count(), 1);
+    assert!(sv.is_drained());
+}
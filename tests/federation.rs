@@ -0,0 +1,220 @@
+mod common;
+
+use bufstream::BufStream;
+use crossbeam::channel;
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sc2_proxy::config::{MatchmakingMode, RemoteRole, UpstreamConfig};
+use sc2_proxy::proxy::Client;
+use sc2_proxy::remote_control::federation::{
+    check_peer_name, is_forwardable, split_peer_client, PeerMessage, UpstreamLink, UpstreamMessage,
+};
+use sc2_proxy::remote_control::message::{PlayerStatus, Request, Response, Update};
+use sc2_proxy::remote_control::Remote;
+use sc2_proxy::supervisor::Supervisor;
+
+/// Downstream proxy running on its own thread, so that the upstream can wait for its responses
+struct Downstream {
+    clients: channel::Sender<Client>,
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+impl Downstream {
+    fn start(upstream_addr: &str, name: &str) -> Self {
+        let mut config = common::config(MatchmakingMode::RemoteController);
+        config.upstream = Some(UpstreamConfig {
+            addr: upstream_addr.to_owned(),
+            name: name.to_owned(),
+            token: None,
+        });
+        let (clients, rx_clients) = channel::unbounded::<Client>();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let handle = thread::spawn(move || {
+            let mut link = UpstreamLink::connect(config.upstream.clone().unwrap());
+            let mut sv = Supervisor::new(config);
+            while !thread_stop.load(Ordering::SeqCst) {
+                while let Ok(client) = rx_clients.try_recv() {
+                    sv.add_client(client);
+                }
+                sv.update_playlist();
+                sv.update_lobbies();
+                sv.update_games();
+                sv.update_upstream(&mut link);
+                thread::sleep(Duration::from_millis(10));
+            }
+            sv.close();
+        });
+        Self { clients, stop, handle }
+    }
+
+    /// Connect a bot to the downstream proxy and send its join request
+    fn connect_bot(&self, name: &str) -> Client {
+        let (proxy_side, mut bot) = common::connect_bot();
+        self.clients.send(proxy_side).unwrap();
+        common::send(&mut bot, &common::join_request(name));
+        bot
+    }
+
+    fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.handle.join().unwrap();
+    }
+}
+
+/// Wait until the playlist of the upstream has `count` ready clients with ids starting with `prefix`
+fn wait_playlist(
+    sv: &mut Supervisor, remote: &mut Remote, stream: &mut common::RemoteConn, prefix: &str, count: usize,
+) -> Vec<String> {
+    let start = Instant::now();
+    loop {
+        sv.update_playlist();
+        match common::remote_request(sv, remote, stream, &Request::GetPlaylist) {
+            Response::GetPlaylist(clients) => {
+                let ids: Vec<String> = clients
+                    .into_iter()
+                    .filter(|(id, ready)| *ready && id.starts_with(prefix))
+                    .map(|(id, _)| id)
+                    .collect();
+                if ids.len() == count {
+                    return ids;
+                }
+            },
+            other => panic!("Unexpected response {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(30), "Clients not in the playlist");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn test_peer_names() {
+    assert_eq!(split_peer_client("b/127.0.0.1:5000"), Some(("b", "127.0.0.1:5000")));
+    assert_eq!(split_peer_client("127.0.0.1:5000"), None);
+    assert!(check_peer_name("machine-b").is_ok());
+    assert!(check_peer_name("").is_err());
+    assert!(check_peer_name("a/b").is_err());
+
+    assert!(is_forwardable(&Request::CreateLobby(None)));
+    assert!(is_forwardable(&Request::GetGames));
+    assert!(!is_forwardable(&Request::Quit));
+    let config = common::config(MatchmakingMode::RemoteController);
+    assert!(!is_forwardable(&Request::SetConfig(Box::new(config))));
+    assert!(!is_forwardable(&Request::DisableRemoteControl));
+}
+
+#[test]
+fn test_peer_rejected_without_admin_token() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.remote_controller.tokens.insert("watch".to_owned(), RemoteRole::Spectator);
+    let mut sv = Supervisor::new(config);
+    let (mut remote, _stream) = common::connect_remote();
+
+    let mut peer = BufStream::new(TcpStream::connect(remote.addr()).unwrap());
+    let hello = PeerMessage::Hello {
+        name: "b".to_owned(),
+        token: Some("watch".to_owned()),
+    };
+    let mut line = serde_json::to_vec(&hello).unwrap();
+    line.push(b'\n');
+    peer.write_all(&line).unwrap();
+    peer.flush().unwrap();
+
+    peer.get_ref().set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    let start = Instant::now();
+    let mut line = String::new();
+    while peer.read_line(&mut line).is_err() {
+        assert!(start.elapsed() < Duration::from_secs(10), "No answer to hello");
+        let _ = sv.update_remote(&mut remote);
+    }
+    match serde_json::from_str::<UpstreamMessage>(&line).unwrap() {
+        UpstreamMessage::Rejected(reason) => assert!(reason.starts_with("Invalid token"), "{}", reason),
+        other => panic!("Unexpected message {:?}", other),
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_federated_game() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let (mut remote, mut stream) = common::connect_remote();
+    let downstream = Downstream::start(remote.addr(), "b");
+
+    let mut bot = downstream.connect_bot("peerbot");
+    let peer_clients = wait_playlist(&mut sv, &mut remote, &mut stream, "b/", 1);
+
+    // A local client of the upstream
+    let (proxy_side, mut local_bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut local_bot, &common::join_request("localbot"));
+    let local_clients = wait_playlist(&mut sv, &mut remote, &mut stream, "127.0.0.1", 1);
+
+    let id = match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::CreateLobby(None)) {
+        Response::CreateLobby(id) => id,
+        other => panic!("Unexpected response {:?}", other),
+    };
+    let req = Request::AddToLobby(id, "c/127.0.0.1:1".to_owned(), None);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Error("No such client".to_owned()));
+
+    // The lobby moves to the downstream proxy, keeping its id
+    let req = Request::AddToLobby(id, peer_clients[0].clone(), None);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::AddToLobby(PlayerStatus::Launching));
+    match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetLobby(id)) {
+        Response::GetLobby(info) => {
+            assert_eq!(info.id, id);
+            assert_eq!(info.players.len(), 1);
+            assert_eq!(info.players[0].name.as_deref(), Some("peerbot"));
+        },
+        other => panic!("Unexpected response {:?}", other),
+    }
+    match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetGames) {
+        Response::GetGames(games) => assert_eq!(games.iter().map(|g| g.id).collect::<Vec<_>>(), vec![id]),
+        other => panic!("Unexpected response {:?}", other),
+    }
+
+    // Clients of different proxies cannot be paired
+    let req = Request::AddToLobby(id, local_clients[0].clone(), None);
+    match common::remote_request(&mut sv, &mut remote, &mut stream, &req) {
+        Response::Error(e) => assert!(e.starts_with("Cannot pair clients of different proxies"), "{}", e),
+        other => panic!("Unexpected response {:?}", other),
+    }
+    let req = Request::CreateLobby(None);
+    let local_id = match common::remote_request(&mut sv, &mut remote, &mut stream, &req) {
+        Response::CreateLobby(local_id) => local_id,
+        other => panic!("Unexpected response {:?}", other),
+    };
+    assert_ne!(local_id, id);
+    let req = Request::AddToLobby(local_id, local_clients[0].clone(), None);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::AddToLobby(PlayerStatus::Launching));
+    let _other_bot = downstream.connect_bot("peerbot2");
+    let other_clients = wait_playlist(&mut sv, &mut remote, &mut stream, "b/", 1);
+    assert_ne!(other_clients, peer_clients);
+    let req = Request::AddToLobby(local_id, other_clients[0].clone(), None);
+    match common::remote_request(&mut sv, &mut remote, &mut stream, &req) {
+        Response::Error(e) => assert!(e.starts_with("Cannot pair clients of different proxies"), "{}", e),
+        other => panic!("Unexpected response {:?}", other),
+    }
+
+    // The game runs on the downstream proxy, and its updates use the id of the upstream
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+    common::wait_update(&mut sv, &mut remote, &mut stream, |u| *u == Update::GameStarted(id));
+    assert!(common::recv(&mut bot).has_join_game());
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::FetchReplay(id));
+    assert_eq!(resp, Response::Error("Replays of peer b cannot be fetched".to_owned()));
+    common::play_until_end(&mut bot);
+    let updates = common::wait_update(&mut sv, &mut remote, &mut stream, |u| {
+        matches!(u, Update::GameEnded(..))
+    });
+    assert!(updates.iter().all(|u| u.game_id() == id));
+
+    downstream.stop();
+}
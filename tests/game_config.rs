@@ -19,3 +19,115 @@ fn test_load_game_config() {
     assert_eq!(config.matchmaking.mode, MatchmakingMode::Pairs);
     assert_eq!(config.match_defaults.time_limits.game_loops, Some(1234));
 }
+
+#[test]
+fn test_random_race_resolution() {
+    assert_eq!(RandomRace::Keep.resolve(Race::Random, 1, 0), Race::Random);
+    assert_eq!(RandomRace::Zerg.resolve(Race::Random, 1, 0), Race::Zerg);
+    assert_eq!(RandomRace::Zerg.resolve(Race::Terran, 1, 0), Race::Terran);
+
+    for seed in 0..10 {
+        for slot in 0..4 {
+            let race = RandomRace::Seeded.resolve(Race::Random, seed, slot);
+            assert_ne!(race, Race::Random);
+            assert_eq!(race, RandomRace::Seeded.resolve(Race::Random, seed, slot));
+        }
+    }
+}
+
+#[test]
+fn test_overwrite_races_config() {
+    let config: Config = toml::from_str(
+        r#"
+        [match_defaults.game]
+        overwrite_races = ["Zerg", "Protoss"]
+        "#,
+    )
+    .expect("Deserialization failed");
+
+    assert_eq!(
+        config.match_defaults.game.overwrite_races,
+        Some(vec![Some(Race::Zerg), Some(Race::Protoss)])
+    );
+}
+
+#[test]
+fn test_renderer_config() {
+    let config: Config = toml::from_str(
+        r#"
+        [process]
+        renderer = "OsMesa"
+
+        [match_defaults]
+        "#,
+    )
+    .expect("Deserialization failed");
+    assert_eq!(config.process.renderer, Renderer::OsMesa);
+
+    let config: Config = toml::from_str("[match_defaults]").expect("Deserialization failed");
+    assert_eq!(config.process.renderer, Renderer::Default);
+
+    let invalid = toml::from_str::<Config>(
+        r#"
+        [process]
+        renderer = "Vulkan"
+
+        [match_defaults]
+        "#,
+    );
+    assert!(invalid.is_err());
+}
+
+#[test]
+fn test_host_selection() {
+    let config: Config = toml::from_str(
+        r#"
+        [match_defaults.game]
+        host_selection = "dedicated"
+        "#,
+    )
+    .expect("Deserialization failed");
+    assert_eq!(config.match_defaults.game.host_selection, HostSelection::Dedicated);
+    assert_eq!(Config::new().match_defaults.game.host_selection, HostSelection::First);
+
+    assert_eq!(HostSelection::First.host_slot(3, None), Some(0));
+    assert_eq!(HostSelection::Dedicated.host_slot(3, None), None);
+    for seed in 0..20 {
+        let slot = HostSelection::Random.host_slot(3, Some(seed));
+        assert!(slot.is_some_and(|s| s < 3));
+        assert_eq!(slot, HostSelection::Random.host_slot(3, Some(seed)));
+    }
+}
+
+#[test]
+fn test_check_allowed_interfaces() {
+    let mut config = Config::new();
+    config.match_defaults.game.allowed_interfaces.raw = false;
+    config.match_defaults.game.allowed_interfaces.feature_layer = false;
+    assert_eq!(
+        config.check(),
+        Err("Allowed interfaces must include raw or feature_layer".to_owned())
+    );
+
+    // Only the map is missing when either interface is allowed
+    config.match_defaults.game.allowed_interfaces.feature_layer = true;
+    assert_eq!(config.check(), Err("Missing map name".to_owned()));
+}
+
+#[test]
+fn test_integrity_preset() {
+    let mut config = Config::new();
+    config.match_defaults.game.disable_fog = true;
+    config.normalize();
+    // Nothing is enforced unless the preset is enabled
+    assert!(config.match_defaults.game.disable_fog);
+    assert!(!config.match_defaults.request_limits.disable_cheats);
+
+    config.match_defaults.integrity = true;
+    config.normalize();
+    assert!(!config.match_defaults.game.disable_fog);
+    assert!(!config.match_defaults.game.allowed_interfaces.score);
+    assert!(config.match_defaults.request_limits.disable_cheats);
+    assert!(config.match_defaults.request_limits.disable_save_replay);
+    assert!(config.match_defaults.game.allowed_interfaces.raw);
+}
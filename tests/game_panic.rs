@@ -0,0 +1,115 @@
+mod common;
+
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use sc2_proto::sc2api::Request;
+use tempfile::TempDir;
+use websocket::OwnedMessage;
+
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::results::ResultCategory;
+use sc2_proxy::supervisor::Supervisor;
+
+/// Config where the SC2 process of the first player to observe sends an invalid response,
+/// which panics its player thread, and then the game thread as the player never sends a result
+fn panicking_config(dir: &TempDir, mode: MatchmakingMode) -> Config {
+    let mut config = common::config(mode);
+    let once = dir.path().join("invalid");
+    config
+        .process
+        .env
+        .insert("FAKE_SC2_INVALID_RESPONSE_ONCE".to_owned(), once.to_str().unwrap().to_owned());
+    config
+}
+
+/// Connect bots and wait until their game has started
+fn start_game(sv: &mut Supervisor, names: &[&str]) -> Vec<common::Client> {
+    let mut bots = Vec::new();
+    for name in names {
+        let (proxy_side, mut bot) = common::connect_bot();
+        sv.add_client(proxy_side);
+        common::send(&mut bot, &common::join_request(name));
+        sv.update_playlist();
+        bots.push(bot);
+    }
+    common::wait_lobbies(sv);
+    for bot in bots.iter_mut() {
+        assert!(common::recv(bot).has_join_game());
+    }
+    bots
+}
+
+/// Send an observation request, which gets the invalid response
+/// The connection is dropped with the panicking player thread
+fn observe_invalid(bot: &mut common::Client) {
+    let mut obs = Request::new();
+    obs.mut_observation();
+    common::send(bot, &obs);
+    assert!(!bot.recv_message().is_ok_and(|msg| msg.is_data()));
+}
+
+/// Update games until none are running
+fn wait_crash(sv: &mut Supervisor) {
+    let start = Instant::now();
+    while sv.game_count() > 0 {
+        assert!(start.elapsed() < Duration::from_secs(10), "Game did not end");
+        sv.update_games();
+        sleep(Duration::from_millis(10));
+    }
+    assert_eq!(sv.recent_results().count(), 0);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_game_panic_cleanup() {
+    let dir = TempDir::new().unwrap();
+    let mut config = panicking_config(&dir, MatchmakingMode::AgainstBuiltinAI);
+    common::mark(&mut config, "panicsingle");
+    let mut sv = Supervisor::new(config);
+    let mut bots = start_game(&mut sv, &["panicbot"]);
+    let pids = common::marked_pids("panicsingle", 1);
+
+    observe_invalid(&mut bots[0]);
+    wait_crash(&mut sv);
+    for pid in pids {
+        common::wait_exit(pid);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_game_panic_recovers_clients() {
+    let dir = TempDir::new().unwrap();
+    let mut config = panicking_config(&dir, MatchmakingMode::Pairs);
+    common::mark(&mut config, "panicpair");
+    let mut sv = Supervisor::new(config);
+    let mut bots = start_game(&mut sv, &["panicbot", "survivorbot"]);
+    let pids = common::marked_pids("panicpair", 2);
+
+    observe_invalid(&mut bots[0]);
+    common::play_until_end(&mut bots[1]);
+    wait_crash(&mut sv);
+
+    // The surviving client is back in the playlist
+    let playlist = sv.snapshot().playlist;
+    assert_eq!(playlist.len(), 1);
+    assert_eq!(playlist[0].id, bots[1].local_addr().unwrap().to_string());
+    for pid in pids {
+        common::wait_exit(pid);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_text_frame_is_disconnect() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::AgainstBuiltinAI));
+    let mut bots = start_game(&mut sv, &["textbot"]);
+
+    // Only binary frames are requests, anything else ends the session like a disconnect
+    bots[0].send_message(&OwnedMessage::Text("hello".to_owned())).unwrap();
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.player_categories, vec![ResultCategory::Crash]);
+}
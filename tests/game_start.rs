@@ -0,0 +1,190 @@
+mod common;
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+use protobuf::parse_from_bytes;
+use sc2_proto::sc2api::{Request as SC2Request, ResponseGameInfo};
+
+use sc2_proxy::config::{MatchmakingMode, Race};
+use sc2_proxy::remote_control::message::{GameInfoSummary, GamePlayerInfo, Request, Response, Update};
+use sc2_proxy::results::{GameEndReason, PlayerOutcomeDetail};
+use sc2_proxy::sc2::{PlayerType, SessionStatus};
+use sc2_proxy::supervisor::Supervisor;
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_game_start_updates() {
+    let config = common::config(MatchmakingMode::RemoteController);
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, mut bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["startbot"]);
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+    assert_eq!(sv.starting_count(), 1);
+
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 1);
+    assert!(common::recv(&mut bots[0]).has_join_game());
+
+    let updates = common::remote_updates(&mut sv, &mut remote, &mut stream);
+    assert_eq!(updates, vec![Update::GameStarting(id), Update::GameStarted(id)]);
+
+    common::play_until_end(&mut bots[0]);
+    common::wait_games(&mut sv);
+    let updates = common::remote_updates(&mut sv, &mut remote, &mut stream);
+    match &updates[..] {
+        [Update::GameEnded(ended, result)] => {
+            assert_eq!(*ended, id);
+            assert_eq!(result.player_details, vec![PlayerOutcomeDetail::NormalResult]);
+        },
+        other => panic!("Unexpected updates {:?}", other),
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_game_start_timeout() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.match_defaults.time_limits.game_start_timeout_secs = Some(1);
+    config
+        .process
+        .env
+        .insert("FAKE_SC2_CREATE_GAME_DELAY_MS".to_owned(), "3000".to_owned());
+    common::mark(&mut config, "slowstart");
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, mut bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["slowbot"]);
+    let pids = common::marked_pids("slowstart", 1);
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 0);
+
+    let updates = common::remote_updates(&mut sv, &mut remote, &mut stream);
+    assert_eq!(updates, vec![
+        Update::GameStarting(id),
+        Update::GameStartFailed(id, "Timed out".to_owned()),
+    ]);
+
+    // The bot is disconnected instead of receiving a response
+    if let Ok(msg) = bots[0].recv_message() {
+        assert!(!msg.is_data(), "Unexpected message {:?}", msg);
+    }
+
+    for pid in pids {
+        common::wait_exit(pid);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_post_join_delay() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.post_join_delay_ms = Some(1500);
+    config.match_defaults.game.measure_latency = true;
+    // Longer than the start, but shorter than the start and the delay together
+    config.match_defaults.time_limits.game_start_timeout_secs = Some(1);
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("analysisbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 1);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    // The first request is answered only after the delay
+    let joined = Instant::now();
+    let mut step = SC2Request::new();
+    step.mut_step().set_count(1);
+    common::send(&mut bot, &step);
+    assert!(common::recv(&mut bot).has_step());
+    assert!(joined.elapsed() >= Duration::from_millis(1500));
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.end_reason, GameEndReason::Normal);
+    // The wait is not part of the measured latency
+    let latency = result.player_stats[0].latency.expect("Latency not measured");
+    assert!(latency.to_sc2.max_us < 1_000_000, "{:?}", latency);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_game_player_statuses() {
+    let config = common::config(MatchmakingMode::RemoteController);
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, mut bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["statusbot"]);
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetGames);
+    match resp {
+        Response::GetGames(games) => assert!(games[0].player_statuses.is_empty()),
+        other => panic!("Unexpected response {:?}", other),
+    }
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bots[0]).has_join_game());
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetGames);
+    match resp {
+        Response::GetGames(games) => {
+            assert!(games[0].running);
+            assert_eq!(games[0].player_statuses, vec![Some(SessionStatus::InGame)]);
+        },
+        other => panic!("Unexpected response {:?}", other),
+    }
+
+    common::play_until_end(&mut bots[0]);
+    common::wait_games(&mut sv);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_game_info_update() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.match_defaults.record_results.game_info = true;
+    config.match_defaults.record_results.game_info_dir = Some(dir.path().to_str().unwrap().to_owned());
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, mut bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["infobot"]);
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bots[0]).has_join_game());
+
+    let updates = common::remote_updates(&mut sv, &mut remote, &mut stream);
+    assert_eq!(updates, vec![
+        Update::GameStarting(id),
+        Update::GameStarted(id),
+        Update::GameInfo(id, GameInfoSummary {
+            map_name: "Fake map".to_owned(),
+            map_size: (64, 48),
+            start_locations: Vec::new(),
+            players: vec![GamePlayerInfo {
+                player_id: 1,
+                player_type: PlayerType::Participant,
+                race_requested: Some(Race::Terran),
+                race_actual: None,
+            }],
+        }),
+    ]);
+
+    let files: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().path()).collect();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].extension().unwrap(), "SC2GameInfo");
+    let info: ResponseGameInfo = parse_from_bytes(&fs::read(&files[0]).unwrap()).unwrap();
+    assert_eq!(info.get_map_name(), "Fake map");
+
+    common::play_until_end(&mut bots[0]);
+    common::wait_games(&mut sv);
+}
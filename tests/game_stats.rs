@@ -0,0 +1,71 @@
+mod common;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::results::{BotRecord, GameEndReason, GameStats, Race, Standings};
+use sc2_proxy::supervisor::Supervisor;
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_get_stats() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetStats);
+    assert_eq!(resp, Response::GetStats(GameStats::default()));
+
+    let (id, mut bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["statsbot"]);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bots[0]).has_join_game());
+    common::play_until_end(&mut bots[0]);
+    common::wait_games(&mut sv);
+
+    let stats = match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetStats) {
+        Response::GetStats(stats) => stats,
+        other => panic!("Unexpected response {:?}", other),
+    };
+    assert_eq!(stats.total_games, 1);
+    assert_eq!(stats.crashed_games, 0);
+    assert_eq!(stats.wins_by_race.get(&Race::Terran), Some(&1));
+    assert_eq!(stats.games_by_end_reason.get(&GameEndReason::Normal), Some(&1));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_get_standings() {
+    let dir = tempfile::TempDir::new().expect("Could not create temp dir");
+    let path = dir.path().join("standings.json");
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.matchmaking.standings_path = Some(path.to_str().unwrap().to_owned());
+
+    let mut sv = Supervisor::new(config.clone());
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, mut bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["standingsbot"]);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bots[0]).has_join_game());
+    common::play_until_end(&mut bots[0]);
+    common::wait_games(&mut sv);
+
+    let expected = BotRecord {
+        wins: 1,
+        losses: 0,
+        ties: 0,
+    };
+    let standings = match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetStandings) {
+        Response::GetStandings(standings) => standings,
+        other => panic!("Unexpected response {:?}", other),
+    };
+    assert_eq!(standings.bots.get("standingsbot"), Some(&expected));
+
+    assert_eq!(Standings::load(&path).expect("Could not load standings"), standings);
+
+    // Loaded again after a restart
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetStandings);
+    assert_eq!(resp, Response::GetStandings(standings));
+}
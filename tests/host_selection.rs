@@ -0,0 +1,103 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+use sc2_proxy::config::{HostSelection, MatchmakingMode};
+use sc2_proxy::results::SlotAssignment;
+use sc2_proxy::supervisor::Supervisor;
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_dedicated_host() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.host_selection = HostSelection::Dedicated;
+    common::mark(&mut config, "dedicated");
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("hostedbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 1);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    // One process for the bot, and one for the host
+    let pids = common::marked_pids("dedicated", 2);
+    assert_eq!(pids.len(), 2);
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.host_slot, None);
+    assert_eq!(result.player_races.len(), 1);
+
+    for pid in pids {
+        common::wait_exit(pid);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_first_host() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::AgainstBuiltinAI));
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("hostingbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.host_slot, Some(0));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_cpu_process() {
+    // A "modded" SC2 build for the builtin AI
+    let dir = TempDir::new().unwrap();
+    let modded = dir.path().join("modded_sc2");
+    let installed = common::fake_install().join("Versions").join("Base99999").join("fake_sc2");
+    fs::copy(installed, &modded).unwrap();
+
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    common::mark(&mut config, "cpu-bot");
+    let mut cpu_process = config.process.clone();
+    cpu_process.executable = Some(modded.to_str().unwrap().to_owned());
+    cpu_process.env.insert("FAKE_SC2_MARKER".to_owned(), "cpu-ai".to_owned());
+    config.matchmaking.cpu_process = Some(cpu_process);
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("moddedaibot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    // The computer runs on its own process, launched from the modded executable
+    let bot_pids = common::marked_pids("cpu-bot", 1);
+    assert_eq!(bot_pids.len(), 1);
+    let cpu_pids = common::marked_pids("cpu-ai", 1);
+    assert_eq!(cpu_pids.len(), 1);
+    let cmdline = fs::read_to_string(format!("/proc/{}/cmdline", cpu_pids[0])).unwrap();
+    assert_eq!(cmdline.split('\0').next(), modded.to_str());
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.host_slot, None);
+    assert!(result.slot_assignment.contains(&SlotAssignment::Observer));
+    for pid in bot_pids.into_iter().chain(cpu_pids) {
+        common::wait_exit(pid);
+    }
+}
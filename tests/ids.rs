@@ -0,0 +1,89 @@
+mod common;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::results::{GameEndReason, GameResult};
+use sc2_proxy::supervisor::{GameId, IdAllocator, Supervisor};
+
+fn id(s: &str) -> GameId {
+    s.parse().unwrap()
+}
+
+/// Result of a game without players
+fn empty_result() -> GameResult {
+    GameResult {
+        external_id: None,
+        player_races: vec![],
+        requested_races: vec![],
+        player_names: vec![],
+        player_metadata: vec![],
+        host_slot: None,
+        slot_assignment: Vec::new(),
+        end_reason: GameEndReason::Normal,
+        player_ids: vec![],
+        player_results: vec![],
+        player_details: vec![],
+        player_categories: vec![],
+        player_stats: vec![],
+        valid: true,
+        void_reason: None,
+        network_sim: None,
+    }
+}
+
+#[test]
+fn test_id_format() {
+    assert_eq!(id("0").to_string(), "0");
+    assert_eq!(id("z").to_string(), "z");
+    assert_eq!(id("10").to_string(), "10");
+    assert_eq!(id("ZZ").to_string(), "zz");
+    assert_eq!(format!("{:>4}", id("2s")), "  2s");
+    assert!("-1".parse::<GameId>().is_err());
+    assert!("".parse::<GameId>().is_err());
+
+    assert_eq!(serde_json::to_string(&id("2s")).unwrap(), r#""2s""#);
+    assert_eq!(serde_json::from_str::<GameId>(r#""2s""#).unwrap(), id("2s"));
+    // Legacy numeric form
+    assert_eq!(serde_json::from_str::<GameId>("100").unwrap(), id("2s"));
+    assert!(serde_json::from_str::<GameId>("-1").is_err());
+    assert!(serde_json::from_str::<GameId>(r#""2s!""#).is_err());
+}
+
+#[test]
+fn test_allocator_skips_in_use() {
+    let mut ids = IdAllocator::new();
+    assert_eq!(ids.allocate(|_| false), id("0"));
+    assert_eq!(ids.allocate(|i| i == id("1") || i == id("2")), id("3"));
+    assert_eq!(ids.allocate(|_| false), id("4"));
+}
+
+#[test]
+fn test_allocator_with_history() {
+    let mut ids = IdAllocator::with_history(vec![id("3"), id("a"), id("5")]);
+    assert!(ids.is_retained(id("a")));
+    assert_eq!(ids.allocate(|_| false), id("b"));
+
+    // Releasing an id does not move the counter back
+    let mut ids = IdAllocator::with_history(vec![id("0"), id("1"), id("3")]);
+    ids.release(id("3"));
+    assert!(!ids.is_retained(id("3")));
+    assert_eq!(ids.allocate(|_| false), id("4"));
+
+    let mut ids = IdAllocator::new();
+    ids.retain(id("0"));
+    ids.retain(id("1"));
+    assert_eq!(ids.allocate(|i| i == id("2")), id("3"));
+}
+
+#[test]
+fn test_restored_ids_not_reused() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    sv.restore_results(vec![(id("7"), empty_result()), (id("2"), empty_result())]);
+    assert_eq!(sv.recent_results().map(|(i, _)| *i).collect::<Vec<_>>(), vec![id("7"), id("2")]);
+
+    let (mut remote, mut stream) = common::connect_remote();
+    for expected in &["8", "9"] {
+        let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::CreateLobby(None));
+        assert_eq!(resp, Response::CreateLobby(id(expected)));
+    }
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::results::PlayerOutcomeDetail;
+use sc2_proxy::supervisor::Supervisor;
+
+/// Pairs config where creating the game takes long enough for a bot to disconnect during the start
+fn slow_start_config() -> Config {
+    let mut config = common::config(MatchmakingMode::Pairs);
+    config
+        .process
+        .env
+        .insert("FAKE_SC2_CREATE_GAME_DELAY_MS".to_owned(), "500".to_owned());
+    config
+}
+
+/// Connect two bots, and disconnect the first one right after the start has begun
+/// Returns the second bot
+fn start_with_disconnect(sv: &mut Supervisor) -> common::Client {
+    let mut bots = Vec::new();
+    for name in &["leavingbot", "stayingbot"] {
+        let (proxy_side, mut bot) = common::connect_bot();
+        sv.add_client(proxy_side);
+        common::send(&mut bot, &common::join_request(name));
+        sv.update_playlist();
+        bots.push(bot);
+    }
+
+    let start = Instant::now();
+    while sv.starting_count() == 0 {
+        assert!(start.elapsed() < Duration::from_secs(10), "Game did not start");
+        sv.update_lobbies();
+        thread::sleep(Duration::from_millis(10));
+    }
+    let staying = bots.pop().unwrap();
+    drop(bots);
+    staying
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_join_disconnect_continue() {
+    let mut sv = Supervisor::new(slow_start_config());
+    let mut bot = start_with_disconnect(&mut sv);
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 1);
+
+    assert!(common::recv(&mut bot).has_join_game());
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.player_details, vec![
+        PlayerOutcomeDetail::ClientCrashedBeforeStart,
+        PlayerOutcomeDetail::NormalResult,
+    ]);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_join_disconnect_abort() {
+    let mut config = slow_start_config();
+    config.match_defaults.game.abort_start_on_join_disconnect = true;
+    let mut sv = Supervisor::new(config);
+    let mut bot = start_with_disconnect(&mut sv);
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 0);
+    assert_eq!(sv.recent_results().count(), 0);
+
+    // The remaining bot never gets a join response, and is disconnected
+    assert!(!matches!(bot.recv_message(), Ok(websocket::OwnedMessage::Binary(_))));
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use sc2_proto::sc2api::ResponseJoinGame_Error;
+
+use sc2_proxy::config::{MatchmakingMode, Race};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Connect a bot sending a join request without a race
+fn join_without_race(sv: &mut Supervisor) -> common::Client {
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    let mut req = common::join_request("racelessbot");
+    req.mut_join_game().clear_race();
+    common::send(&mut bot, &req);
+    sv.update_playlist();
+    bot
+}
+
+#[test]
+fn test_missing_race_rejected() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::AgainstBuiltinAI));
+    let mut bot = join_without_race(&mut sv);
+
+    let resp = common::recv(&mut bot);
+    assert_eq!(resp.get_join_game().get_error(), ResponseJoinGame_Error::OtherError);
+    assert!(resp.get_join_game().get_error_details().contains("No race"));
+    assert_eq!(sv.lobby_count(), 0);
+
+    // The connection is closed
+    if let Ok(msg) = bot.recv_message() {
+        assert!(!msg.is_data(), "Unexpected message {:?}", msg);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_missing_race_defaulted() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.matchmaking.default_race = Some(Race::Zerg);
+    let mut sv = Supervisor::new(config);
+    let mut bot = join_without_race(&mut sv);
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.requested_races, vec![Race::Zerg]);
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use sc2_proto::sc2api::Request;
+use websocket::OwnedMessage;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::remote_control::message;
+use sc2_proxy::supervisor::{KickReason, Supervisor};
+
+/// Connect a bot to the proxy, returns its client id and connection
+fn connect(sv: &mut Supervisor) -> (String, common::Client) {
+    let (proxy_side, bot) = common::connect_bot();
+    let id = proxy_side.peer_addr().unwrap().to_string();
+    sv.add_client(proxy_side);
+    (id, bot)
+}
+
+/// Keep updating the playlist until the bot receives a Close frame, returns the reason in it
+fn close_reason(sv: &mut Supervisor, bot: &mut common::Client) -> String {
+    bot.stream_ref().set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    let start = Instant::now();
+    loop {
+        sv.update_playlist();
+        match bot.recv_message() {
+            Ok(OwnedMessage::Close(Some(data))) => return data.reason,
+            Ok(other) => panic!("Unexpected message {:?}", other),
+            Err(_) => assert!(start.elapsed() < Duration::from_secs(10), "Client not kicked"),
+        }
+    }
+}
+
+#[test]
+fn test_unsupported_message_kicked() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let (id, mut bot) = connect(&mut sv);
+    let mut req = Request::new();
+    req.mut_game_info();
+    common::send(&mut bot, &req);
+
+    assert_eq!(close_reason(&mut sv, &mut bot), "Unsupported message: game_info");
+    assert!(sv.snapshot().playlist.is_empty());
+    let kick = sv.recent_kicks().last().expect("No kick recorded");
+    assert_eq!(kick.id, id);
+    assert_eq!(kick.name, None);
+    assert_eq!(kick.reason, KickReason::UnsupportedMessage("game_info".to_owned()));
+}
+
+#[test]
+fn test_malformed_message_kicked() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let (_, mut bot) = connect(&mut sv);
+    bot.send_message(&OwnedMessage::Binary(vec![0xff; 3])).unwrap();
+
+    assert_eq!(close_reason(&mut sv, &mut bot), "Malformed message");
+    let kick = sv.recent_kicks().last().expect("No kick recorded");
+    assert_eq!(kick.reason, KickReason::MalformedMessage);
+}
+
+#[test]
+fn test_singleplayer_join_rejected() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::Singleplayer));
+    let (_, mut bot) = connect(&mut sv);
+    common::send(&mut bot, &common::join_request("solobot"));
+
+    assert_eq!(close_reason(&mut sv, &mut bot), "Rejected: Singleplayer matchmaking is not supported");
+    assert!(sv.snapshot().playlist.is_empty());
+    assert_eq!(sv.lobby_count(), 0);
+    let kick = sv.recent_kicks().last().expect("No kick recorded");
+    assert_eq!(kick.name.as_deref(), Some("solobot"));
+}
+
+#[test]
+fn test_older_duplicate_kicked() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.matchmaking.deduplicate_clients = true;
+    let mut sv = Supervisor::new(config);
+    let (old_id, mut old_bot) = connect(&mut sv);
+    common::send(&mut old_bot, &common::join_request("twinbot"));
+    let start = Instant::now();
+    while !sv.snapshot().playlist.iter().any(|e| e.ready) {
+        assert!(start.elapsed() < Duration::from_secs(10), "Join request not processed");
+        sv.update_playlist();
+    }
+
+    let (new_id, mut new_bot) = connect(&mut sv);
+    common::send(&mut new_bot, &common::join_request("twinbot"));
+    assert_eq!(close_reason(&mut sv, &mut old_bot), "Duplicate join");
+    let playlist = sv.snapshot().playlist;
+    assert_eq!(playlist.len(), 1);
+    assert_eq!(playlist[0].id, new_id);
+
+    let kick = sv.recent_kicks().last().expect("No kick recorded");
+    assert_eq!(kick.id, old_id);
+    assert_eq!(kick.name.as_deref(), Some("twinbot"));
+    assert_eq!(kick.reason, KickReason::DuplicateJoin);
+}
+
+#[test]
+fn test_recent_kicks_query() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let (mut remote, mut stream) = common::connect_remote();
+    let query = message::Request::GetRecentKicks;
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &query);
+    assert_eq!(resp, message::Response::GetRecentKicks(Vec::new()));
+
+    let (id, mut bot) = connect(&mut sv);
+    let req = message::Request::DropPlaylistItem(id.clone());
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, message::Response::DropPlaylist);
+    assert_eq!(close_reason(&mut sv, &mut bot), "Removed by the remote controller");
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, message::Response::Error("No such client".to_owned()));
+
+    let kicks = match common::remote_request(&mut sv, &mut remote, &mut stream, &query) {
+        message::Response::GetRecentKicks(kicks) => kicks,
+        other => panic!("Unexpected response {:?}", other),
+    };
+    assert_eq!(kicks.len(), 1);
+    assert_eq!(kicks[0].id, id);
+    assert_eq!(kicks[0].reason, KickReason::RemoteRequest);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_duplicate_keeps_remote_lobby() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.matchmaking.deduplicate_clients = true;
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, _bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["twinbot"]);
+
+    let (_, mut bot) = connect(&mut sv);
+    common::send(&mut bot, &common::join_request("twinbot"));
+    let start = Instant::now();
+    while !sv.snapshot().playlist.iter().any(|e| e.ready) {
+        assert!(start.elapsed() < Duration::from_secs(10), "Join request not processed");
+        sv.update_playlist();
+    }
+
+    // The older connection is removed, but the lobby of the remote controller is kept
+    match common::remote_request(&mut sv, &mut remote, &mut stream, &message::Request::GetLobby(id)) {
+        message::Response::GetLobby(info) => assert!(info.players.is_empty()),
+        other => panic!("Unexpected response {:?}", other),
+    }
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use std::time::Duration;
+
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::results::{LatencyHistogram, PlayerStats};
+use sc2_proxy::supervisor::Supervisor;
+
+#[test]
+fn test_histogram_percentiles() {
+    let mut hist = LatencyHistogram::new();
+    assert_eq!(hist.count(), 0);
+    assert_eq!(hist.percentile(50.0), 0);
+
+    for us in 1..=100 {
+        hist.record(Duration::from_micros(us));
+    }
+    assert_eq!(hist.count(), 100);
+
+    // Buckets are at most 25% wide
+    let p50 = hist.percentile(50.0);
+    assert!(p50 >= 50 && p50 <= 63, "p50 = {}", p50);
+    let p99 = hist.percentile(99.0);
+    assert!(p99 >= 99 && p99 <= 100, "p99 = {}", p99);
+
+    let summary = hist.summary();
+    assert_eq!(summary.max_us, 100);
+    assert_eq!(summary.p50_us, p50);
+    assert!(summary.p95_us >= summary.p50_us && summary.p99_us >= summary.p95_us);
+}
+
+#[test]
+fn test_histogram_outliers() {
+    let mut hist = LatencyHistogram::new();
+    for _ in 0..99 {
+        hist.record(Duration::from_micros(10));
+    }
+    hist.record(Duration::from_secs(2));
+    assert!(hist.percentile(50.0) <= 11);
+    assert!(hist.percentile(99.0) <= 11);
+    assert_eq!(hist.percentile(100.0), 2_000_000);
+
+    // Durations too long for the buckets are still counted
+    hist.record(Duration::from_secs(1_000_000));
+    assert_eq!(hist.count(), 101);
+    assert_eq!(hist.summary().max_us, 1_000_000_000_000);
+}
+
+/// Play a game against the builtin AI, and return the stats of the bot
+fn play(config: Config) -> PlayerStats {
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("latencybot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.player_stats.len(), 1);
+    result.player_stats[0]
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_latency_measured() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.measure_latency = true;
+    let stats = play(config);
+    let latency = stats.latency.expect("Latency not measured");
+    assert!(latency.requests > 0);
+    assert!(latency.requests <= stats.sc2_requests);
+    assert!(latency.to_sc2.p50_us <= latency.to_sc2.max_us);
+    assert!(latency.to_client.p99_us <= latency.to_client.max_us);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_latency_not_measured_by_default() {
+    let stats = play(common::config(MatchmakingMode::AgainstBuiltinAI));
+    assert!(stats.latency.is_none());
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Command line of a running process
+#[cfg(target_os = "linux")]
+fn cmdline(pid: u32) -> Vec<String> {
+    let cmdline = std::fs::read_to_string(format!("/proc/{}/cmdline", pid)).unwrap();
+    cmdline.trim_end_matches('\0').split('\0').map(str::to_owned).collect()
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_get_launch_commands() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    common::mark(&mut config, "launchcmd");
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, mut bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["launchbot"]);
+
+    let pids = common::marked_pids("launchcmd", 1);
+    let expected = cmdline(pids[0]);
+    assert!(expected.iter().any(|a| a == "-listen"));
+    assert!(expected.iter().any(|a| a == "-displayMode"));
+
+    let req = Request::GetLaunchCommands(id);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::GetLaunchCommands(vec![Some(expected.clone())]));
+    match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetLobby(id)) {
+        Response::GetLobby(info) => assert_eq!(info.players[0].launch_command, Some(expected.clone())),
+        other => panic!("Unexpected response {:?}", other),
+    }
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bots[0]).has_join_game());
+
+    // Still available while the game is running
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::GetLaunchCommands(vec![Some(expected.clone())]));
+    assert_eq!(sv.snapshot().games[0].launch_commands, vec![expected]);
+
+    common::play_until_end(&mut bots[0]);
+    common::wait_games(&mut sv);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Error("No such game".to_owned()));
+}
@@ -0,0 +1,47 @@
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use crossbeam::channel;
+use websocket::ClientBuilder;
+
+use sc2_proxy::config::Config;
+use sc2_proxy::proxy;
+
+/// Connections opened at once, more than the default backlog of Linux
+const BURST: usize = 300;
+
+#[test]
+fn test_listen_backlog_config() {
+    assert_eq!(Config::new().proxy.listen_backlog, None);
+    let config: Config = toml::from_str("[proxy]\nhost = \"127.0.0.1\"\nport = 8642\nlisten_backlog = 1024\n")
+        .expect("Invalid config");
+    assert_eq!(config.proxy.listen_backlog, Some(1024));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_listen_backlog_burst() {
+    let server = proxy::bind("127.0.0.1:0", Some(1024)).expect("Could not bind");
+    let addr = server.local_addr().unwrap();
+
+    // Nothing is accepted yet, so all connections wait in the backlog
+    let burst: Vec<_> = (0..BURST)
+        .map(|i| {
+            TcpStream::connect_timeout(&addr, Duration::from_secs(5))
+                .unwrap_or_else(|e| panic!("Connection {} not queued: {}", i, e))
+        })
+        .collect();
+
+    let (sender, receiver) = channel::unbounded();
+    thread::spawn(move || proxy::run(server, sender));
+    // The queued connections never send a handshake, so close them to let the server get past them
+    drop(burst);
+
+    let _bot = ClientBuilder::new(&format!("ws://{}/sc2api", addr))
+        .unwrap()
+        .connect_insecure()
+        .expect("Could not connect");
+    let conn = receiver.recv_timeout(Duration::from_secs(10)).expect("No connection");
+    assert_eq!(conn.meta.path, "/sc2api");
+}
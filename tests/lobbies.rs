@@ -0,0 +1,254 @@
+mod common;
+
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use websocket::OwnedMessage;
+
+use sc2_proxy::config::{MatchmakingMode, Race};
+use sc2_proxy::remote_control::message::{PlayerStatus, Request, Response};
+use sc2_proxy::supervisor::Supervisor;
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_lobby_expiry() {
+    let mut config = common::config(MatchmakingMode::Pairs);
+    config.matchmaking.max_lobby_age_secs = Some(0);
+    common::mark(&mut config, "expiry");
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+
+    common::send(&mut bot, &common::join_request("lonelybot"));
+    sv.update_playlist();
+    assert_eq!(sv.lobby_count(), 1);
+    let pids = common::marked_pids("expiry", 1);
+
+    sleep(Duration::from_millis(10));
+    sv.update_lobbies();
+    assert_eq!(sv.lobby_count(), 0);
+
+    // Connection closed without a join response, giving the reason
+    match bot.recv_message() {
+        Ok(OwnedMessage::Close(Some(data))) => assert_eq!(data.reason, "Lobby expired"),
+        other => panic!("Expected a close frame, got {:?}", other),
+    }
+    for pid in pids {
+        common::wait_exit(pid);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_overwrite_races() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.overwrite_races = Some(vec![Some(Race::Zerg)]);
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+
+    // The join request asks for Terran
+    common::send(&mut bot, &common::join_request("terranbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.player_races, vec![Race::Zerg]);
+    assert_eq!(result.requested_races, vec![Race::Terran]);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_overwrite_races_count_mismatch() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.overwrite_races = Some(vec![Some(Race::Zerg), Some(Race::Zerg)]);
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+
+    common::send(&mut bot, &common::join_request("zergbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 0);
+
+    // Connection closed without a join response
+    assert!(bot.recv_message().is_err());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_force_start() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::Pairs));
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+
+    // The opponent never shows up
+    common::send(&mut bot, &common::join_request("waitingbot"));
+    sv.update_playlist();
+    assert_eq!(sv.lobby_count(), 1);
+
+    let id = match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetGames) {
+        Response::GetGames(games) => games[0].id,
+        other => panic!("Unexpected response {:?}", other),
+    };
+    common::wait_lobby_ready(&mut sv, &mut remote, &mut stream, id);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+    assert_eq!(sv.lobby_count(), 0);
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 1);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    // Unknown lobbies cannot be started
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::Error("No such game".to_owned()));
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_filler_ai() {
+    let mut config = common::config(MatchmakingMode::Pairs);
+    config.matchmaking.filler_ai.enabled = true;
+    config.matchmaking.filler_ai.wait_secs = 0;
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+
+    common::send(&mut bot, &common::join_request("lonelybot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 1);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+    assert_eq!(sv.recent_results().count(), 1);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_players_per_game() {
+    let mut config = common::config(MatchmakingMode::Pairs);
+    config.matchmaking.players_per_game = 3;
+    let mut sv = Supervisor::new(config);
+
+    let mut bots = Vec::new();
+    for i in 0..3 {
+        assert_eq!(sv.game_count(), 0);
+        let (proxy_side, mut bot) = common::connect_bot();
+        sv.add_client(proxy_side);
+        common::send(&mut bot, &common::join_request(&format!("ffabot{}", i)));
+        sv.update_playlist();
+        bots.push(bot);
+    }
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.game_count(), 1);
+
+    for bot in bots.iter_mut() {
+        assert!(common::recv(bot).has_join_game());
+    }
+    for bot in bots.iter_mut() {
+        common::play_until_end(bot);
+    }
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.player_races.len(), 3);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_lobby_player_disconnect() {
+    let mut config = common::config(MatchmakingMode::Pairs);
+    common::mark(&mut config, "disconnect");
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("impatientbot"));
+    sv.update_playlist();
+    assert_eq!(sv.lobby_count(), 1);
+    let pids = common::marked_pids("disconnect", 1);
+
+    drop(bot);
+    sleep(Duration::from_millis(50));
+    sv.update_lobbies();
+    assert_eq!(sv.lobby_count(), 0);
+    for pid in pids {
+        common::wait_exit(pid);
+    }
+
+    // The next bot waits for a new partner instead of joining a dead game
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("patientbot"));
+    sv.update_playlist();
+    assert_eq!(sv.lobby_count(), 1);
+    assert_eq!(sv.game_count(), 0);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_lobby_join_does_not_block() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("slowbot"));
+    let client_id = loop {
+        sv.update_playlist();
+        match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetPlaylist) {
+            Response::GetPlaylist(clients) if clients[0].1 => break clients[0].0.clone(),
+            _ => sleep(Duration::from_millis(10)),
+        }
+    };
+    let id = match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::CreateLobby(None)) {
+        Response::CreateLobby(id) => id,
+        other => panic!("Unexpected response {:?}", other),
+    };
+
+    // Connecting to a new SC2 process takes at least a second
+    let start = Instant::now();
+    let req = Request::AddToLobby(id, client_id, None);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::AddToLobby(PlayerStatus::Launching));
+    assert!(start.elapsed() < Duration::from_millis(500));
+
+    match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetLobby(id)) {
+        Response::GetLobby(info) => {
+            assert_eq!(info.players.len(), 1);
+            assert_eq!(info.players[0].name.as_deref(), Some("slowbot"));
+            assert_eq!(info.players[0].status, PlayerStatus::Launching);
+        },
+        other => panic!("Unexpected response {:?}", other),
+    }
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::StartGame(id));
+    assert_eq!(
+        resp,
+        Response::Error("Cannot start game: SC2 processes of 1 participants are still launching".to_owned())
+    );
+
+    common::wait_lobby_ready(&mut sv, &mut remote, &mut stream, id);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::StartGame(id));
+    assert_eq!(resp, Response::StartGame);
+
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+}
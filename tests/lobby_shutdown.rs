@@ -0,0 +1,100 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+use websocket::OwnedMessage;
+
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Remote controlled config logging the requests received by the fake SC2 processes to a file
+fn logged_config(dir: &TempDir, marker: &str) -> Config {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    let log = dir.path().join("requests.log");
+    config
+        .process
+        .env
+        .insert("FAKE_SC2_REQUEST_LOG".to_owned(), log.to_str().unwrap().to_owned());
+    common::mark(&mut config, marker);
+    config
+}
+
+/// Number of quit requests received by the fake SC2 processes
+fn quit_count(dir: &TempDir) -> usize {
+    let text = fs::read_to_string(dir.path().join("requests.log")).unwrap_or_default();
+    text.lines().filter(|&line| line == "quit").count()
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_close_lobby_on_shutdown() {
+    let dir = TempDir::new().unwrap();
+    let mut sv = Supervisor::new(logged_config(&dir, "lobbyclose"));
+    let (mut remote, mut stream) = common::connect_remote();
+    let (_, mut bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["closebot1", "closebot2"]);
+    let pids = common::marked_pids("lobbyclose", 2);
+
+    sv.close();
+
+    for bot in bots.iter_mut() {
+        match bot.recv_message() {
+            Ok(OwnedMessage::Close(Some(data))) => assert_eq!(data.reason, "Proxy is shutting down"),
+            other => panic!("Expected a close frame, got {:?}", other),
+        }
+    }
+    assert_eq!(quit_count(&dir), 2);
+    for pid in pids {
+        common::wait_exit(pid);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_cancel_lobby() {
+    let dir = TempDir::new().unwrap();
+    let mut sv = Supervisor::new(logged_config(&dir, "lobbycancel"));
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, _bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["cancelbot"]);
+    let pids = common::marked_pids("lobbycancel", 1);
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::CancelLobby(id));
+    assert_eq!(resp, Response::CancelLobby);
+    assert_eq!(sv.lobby_count(), 0);
+    assert_eq!(quit_count(&dir), 1);
+    for pid in pids {
+        common::wait_exit(pid);
+    }
+
+    // The client is back in the playlist, still waiting for a game
+    match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetPlaylist) {
+        Response::GetPlaylist(clients) => assert_eq!(clients.len(), 1),
+        other => panic!("Unexpected response {:?}", other),
+    }
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::CancelLobby(id));
+    assert_eq!(resp, Response::Error("No such game".to_owned()));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_disconnect_while_launching() {
+    let mut config = common::config(MatchmakingMode::Pairs);
+    common::mark(&mut config, "lobbypending");
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("pendingbot"));
+    sv.update_playlist();
+    assert_eq!(sv.lobby_count(), 1);
+
+    // Leave before the lobby has seen the launch finish
+    drop(bot);
+    let pids = common::marked_pids("lobbypending", 1);
+    common::wait_lobbies(&mut sv);
+    assert_eq!(sv.lobby_count(), 0);
+    for pid in pids {
+        common::wait_exit(pid);
+    }
+}
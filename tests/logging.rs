@@ -0,0 +1,43 @@
+use std::fs;
+
+use log::info;
+use tempfile::TempDir;
+
+use sc2_proxy::config::LoggingConfig;
+use sc2_proxy::{init_logging, Error};
+
+// Only one test here, as the logger is global
+#[test]
+fn test_log_file_rotation() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("proxy.log");
+    let config = LoggingConfig {
+        file: Some(path.to_str().unwrap().to_owned()),
+        max_size_mb: 1,
+        keep_files: 2,
+    };
+    std::env::set_var("RUST_LOG", "logging=info");
+    init_logging(&config).expect("Could not set up logging");
+
+    let message = "x".repeat(1000);
+    for i in 0..3500 {
+        info!("{} {}", i, message);
+    }
+
+    let rotated = |n: usize| dir.path().join(format!("proxy.log.{}", n));
+    for file in &[path.clone(), rotated(1), rotated(2)] {
+        let size = fs::metadata(file).expect("Log file missing").len();
+        assert!(size <= 1024 * 1024, "{:?} too large: {}", file, size);
+    }
+    assert!(!rotated(3).exists());
+
+    let text = fs::read_to_string(&path).unwrap();
+    let last = text.lines().last().unwrap();
+    assert!(last.contains("INFO"), "{}", last);
+    assert!(last.contains(&format!("3499 {}", message)));
+
+    match init_logging(&LoggingConfig::default()) {
+        Err(Error::Logging(_)) => {},
+        other => panic!("Expected a logging error, got {:?}", other),
+    }
+}
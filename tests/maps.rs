@@ -0,0 +1,53 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::maps::{find_map, find_map_in, first_map};
+
+/// Custom map directory with a map at the top level and another in a subdirectory
+fn custom_map_dir() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("Custom.SC2Map"), b"").unwrap();
+    fs::create_dir(dir.path().join("Season1")).unwrap();
+    fs::write(dir.path().join("Season1").join("OtherMap.SC2Map"), b"").unwrap();
+    fs::write(dir.path().join("notes.txt"), b"").unwrap();
+    dir
+}
+
+#[test]
+fn test_find_map_default_dir() {
+    common::fake_install();
+    assert_eq!(find_map(common::MAP_NAME.to_owned()), Some("Testing/Test.SC2Map".to_owned()));
+    assert_eq!(find_map("Missing".to_owned()), None);
+}
+
+#[test]
+fn test_find_map_custom_dir() {
+    let dir = custom_map_dir();
+    let root = fs::canonicalize(dir.path()).unwrap();
+    let custom = dir.path().to_str();
+
+    let found = find_map_in(custom, "Custom".to_owned()).expect("Map not found");
+    assert_eq!(found, root.join("Custom.SC2Map").to_str().unwrap());
+    let found = find_map_in(custom, "Other Map".to_owned()).expect("Map not found");
+    assert_eq!(found, root.join("Season1").join("OtherMap.SC2Map").to_str().unwrap());
+    assert_eq!(find_map_in(custom, common::MAP_NAME.to_owned()), None);
+
+    let first = first_map(custom).expect("No maps found");
+    assert_eq!(first, root.join("Custom.SC2Map").to_str().unwrap());
+    assert_eq!(find_map_in(Some("/nonexistent/maps"), "Custom".to_owned()), None);
+}
+
+#[test]
+fn test_config_check_map_dir() {
+    let dir = custom_map_dir();
+    let mut config = common::config(MatchmakingMode::Pairs);
+    config.match_defaults.game.map_name = Some("Custom".to_owned());
+    assert_eq!(config.check(), Err("Map not found".to_owned()));
+
+    config.process.map_dir = Some(dir.path().to_str().unwrap().to_owned());
+    assert_eq!(config.check(), Ok(()));
+}
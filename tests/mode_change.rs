@@ -0,0 +1,70 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::supervisor::Supervisor;
+
+const REJECTED: &str = "Cannot change matchmaking mode while lobbies or pending join requests exist";
+
+#[test]
+fn test_mode_change_when_idle() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::Pairs));
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let new_config = common::config(MatchmakingMode::RemoteController);
+    let req = Request::SetConfig(Box::new(new_config.clone()));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::SetConfig(new_config, vec![]));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_mode_change_with_lobby() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::Pairs));
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("waitingbot"));
+    sv.update_playlist();
+    assert_eq!(sv.lobby_count(), 1);
+
+    let req = Request::SetConfig(Box::new(common::config(MatchmakingMode::RemoteController)));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Error(REJECTED.to_owned()));
+
+    // Other settings can still be changed
+    let mut new_config = common::config(MatchmakingMode::Pairs);
+    new_config.matchmaking.players_per_game = 3;
+    let req = Request::SetConfig(Box::new(new_config.clone()));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    match resp {
+        Response::SetConfig(config, old_ids) => {
+            assert_eq!(config, new_config);
+            assert_eq!(old_ids.len(), 1);
+        },
+        other => panic!("Unexpected response {:?}", other),
+    }
+    sv.close();
+}
+
+#[test]
+fn test_mode_change_with_pending_join() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("waitingbot"));
+    let start = Instant::now();
+    while !sv.snapshot().playlist[0].ready {
+        assert!(start.elapsed() < Duration::from_secs(10), "Join request not received");
+        sv.update_playlist();
+    }
+
+    let req = Request::SetConfig(Box::new(common::config(MatchmakingMode::Pairs)));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Error(REJECTED.to_owned()));
+}
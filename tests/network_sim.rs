@@ -0,0 +1,112 @@
+mod common;
+
+use sc2_proto::sc2api::{Response, Status};
+
+use sc2_proxy::config::{Config, MatchConfig, MatchmakingMode, NetworkSim};
+use sc2_proxy::refine::{is_droppable, NetworkSimResponses, Pipeline, RefineContext, ResponseRefiner};
+use sc2_proxy::results::Standings;
+use sc2_proxy::supervisor::Supervisor;
+
+fn sim(added_latency_ms: u64, jitter_ms: u64, drop_probability: f64) -> NetworkSim {
+    NetworkSim {
+        added_latency_ms,
+        jitter_ms,
+        drop_probability,
+    }
+}
+
+fn observation(game_over: bool) -> Response {
+    let mut resp = Response::new();
+    resp.set_status(Status::in_game);
+    let obs = resp.mut_observation();
+    obs.mut_observation().set_game_loop(10);
+    if game_over {
+        obs.mut_player_result().push(Default::default());
+    }
+    resp
+}
+
+#[test]
+fn test_network_sim_refiners() {
+    let mut config = MatchConfig::default();
+    assert!(!Pipeline::new(&config).refines_responses());
+    config.game.network_sim = Some(sim(0, 0, 0.5));
+    let pipeline = Pipeline::new(&config);
+    assert_eq!(pipeline.len(), 2);
+    assert!(pipeline.refines_responses());
+}
+
+#[test]
+fn test_drops_only_non_essential_responses() {
+    assert!(is_droppable(&observation(false)));
+    assert!(!is_droppable(&observation(true)));
+    let mut join = Response::new();
+    join.mut_join_game().set_player_id(1);
+    assert!(!is_droppable(&join));
+    let mut leave = Response::new();
+    leave.mut_leave_game();
+    assert!(!is_droppable(&leave));
+
+    let ctx = RefineContext::default();
+    let mut always = NetworkSimResponses::new(&sim(0, 0, 1.0), Some(1));
+    let mut resp = observation(false);
+    always.refine(&mut resp, &ctx);
+    assert!(!resp.has_observation());
+    assert_eq!(resp.get_error(), &["Proxy: Simulated drop".to_owned()]);
+    assert_eq!(resp.get_status(), Status::in_game);
+
+    let mut resp = observation(true);
+    always.refine(&mut resp, &ctx);
+    assert_eq!(resp, observation(true));
+    let mut resp = join.clone();
+    always.refine(&mut resp, &ctx);
+    assert_eq!(resp, join);
+
+    let mut never = NetworkSimResponses::new(&sim(0, 0, 0.0), Some(1));
+    let mut resp = observation(false);
+    never.refine(&mut resp, &ctx);
+    assert_eq!(resp, observation(false));
+}
+
+#[test]
+fn test_network_sim_config() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.network_sim = Some(sim(10, 5, 1.5));
+    assert!(config.check().is_err());
+    config.match_defaults.game.network_sim = Some(sim(10, 5, 0.1));
+    assert!(config.check().is_ok());
+
+    // Never with the fair play preset
+    config.match_defaults.integrity = true;
+    config.normalize();
+    assert_eq!(config.match_defaults.game.network_sim, None);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_added_latency_measured() {
+    let mut config: Config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.measure_latency = true;
+    config.match_defaults.game.network_sim = Some(sim(40, 0, 0.0));
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("laggybot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.network_sim, Some(sim(40, 0, 0.0)));
+    let latency = result.player_stats[0].latency.expect("Latency not measured");
+    // Half of the added latency in each direction
+    assert!(latency.to_sc2.p50_us >= 20_000, "{:?}", latency);
+    assert!(latency.to_client.p50_us >= 20_000, "{:?}", latency);
+
+    // Not counted in the standings
+    let mut standings = Standings::default();
+    standings.record(result);
+    assert!(standings.bots.is_empty());
+}
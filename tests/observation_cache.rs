@@ -0,0 +1,71 @@
+mod common;
+
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::results::PlayerStats;
+use sc2_proxy::supervisor::Supervisor;
+use sc2_proto::sc2api::Request;
+
+/// Request the observation of the current loop
+fn observe(bot: &mut common::Client, disable_fog: bool) -> u32 {
+    let mut req = Request::new();
+    req.mut_observation().set_disable_fog(disable_fog);
+    common::send(bot, &req);
+    let resp = common::recv(bot);
+    assert!(resp.has_observation());
+    resp.get_observation().get_observation().get_game_loop()
+}
+
+/// Play a game observing the first loops repeatedly, and return the stats of the bot
+fn play(config: Config) -> PlayerStats {
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("cachebot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    assert_eq!(observe(&mut bot, false), 0);
+    assert_eq!(observe(&mut bot, false), 0);
+    // Different options are not served from the cache
+    assert_eq!(observe(&mut bot, true), 0);
+
+    let mut step = Request::new();
+    step.mut_step().set_count(5);
+    common::send(&mut bot, &step);
+    assert!(common::recv(&mut bot).has_step());
+    assert_eq!(observe(&mut bot, false), 5);
+    assert_eq!(observe(&mut bot, false), 5);
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.player_stats.len(), 1);
+    result.player_stats[0]
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_observation_cache() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.cache_observations = true;
+    let stats = play(config);
+    assert_eq!(stats.cached_observations, 2);
+    assert!(stats.sc2_requests > 0);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_observation_cache_disabled() {
+    let stats = play(common::config(MatchmakingMode::AgainstBuiltinAI));
+    assert_eq!(stats.cached_observations, 0);
+
+    // Disabled in realtime games
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.cache_observations = true;
+    config.match_defaults.game.realtime = true;
+    let realtime_stats = play(config);
+    assert_eq!(realtime_stats.cached_observations, 0);
+    assert_eq!(realtime_stats.sc2_requests, stats.sc2_requests);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use sc2_proto::sc2api::Request;
+
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::results::{GameResult, PlayerResult};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Start a game between two bots, whose SC2 player ids are the reverse of the join order
+fn reversed_ids(mut config: Config, play: impl Fn(&mut [common::Client])) -> GameResult {
+    config
+        .process
+        .env
+        .insert("FAKE_SC2_PLAYER_IDS".to_owned(), "firstbot:2,secondbot:1".to_owned());
+    let mut sv = Supervisor::new(config);
+    let mut bots = Vec::new();
+    for name in &["firstbot", "secondbot"] {
+        let (proxy_side, mut bot) = common::connect_bot();
+        sv.add_client(proxy_side);
+        common::send(&mut bot, &common::join_request(name));
+        sv.update_playlist();
+        bots.push(bot);
+    }
+    common::wait_lobbies(&mut sv);
+    for bot in bots.iter_mut() {
+        assert!(common::recv(bot).has_join_game());
+    }
+    play(&mut bots);
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.player_ids, vec![Some(2), Some(1)]);
+    result.clone()
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_results_by_player_id() {
+    let mut config = common::config(MatchmakingMode::Pairs);
+    config.match_defaults.time_limits.game_loops = Some(20);
+    let result = reversed_ids(config, |bots| {
+        // The first bot leaves, and the second one plays until the time limit
+        let mut leave = Request::new();
+        leave.mut_leave_game();
+        common::send(&mut bots[0], &leave);
+        assert!(common::recv(&mut bots[0]).has_leave_game());
+
+        let mut step = Request::new();
+        step.mut_step().set_count(20);
+        common::send(&mut bots[1], &step);
+        assert!(common::recv(&mut bots[1]).has_step());
+        let mut obs = Request::new();
+        obs.mut_observation();
+        common::send(&mut bots[1], &obs);
+        assert!(common::recv(&mut bots[1]).has_observation());
+    });
+    assert_eq!(result.player_results, vec![PlayerResult::Tie, PlayerResult::Defeat]);
+    assert_eq!(result.participant_results(), vec![Some(PlayerResult::Defeat), Some(PlayerResult::Tie)]);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_reported_results_by_player_id() {
+    let result = reversed_ids(common::config(MatchmakingMode::Pairs), |bots| {
+        for bot in bots.iter_mut() {
+            common::play_until_end(bot);
+        }
+    });
+    // The fake SC2 reports victory for player 1
+    assert_eq!(result.player_results, vec![PlayerResult::Victory, PlayerResult::Defeat]);
+    assert_eq!(result.participant_results(), vec![Some(PlayerResult::Defeat), Some(PlayerResult::Victory)]);
+}
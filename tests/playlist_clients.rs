@@ -0,0 +1,132 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use sc2_proto::sc2api::Request;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::proxy::{ClientConnection, ConnectionMeta};
+use sc2_proxy::supervisor::{Supervisor, IDLE_POLL_INTERVAL};
+
+/// Wrap a proxy side connection, as if it was accepted from `peer_addr`
+fn connection(client: common::Client, peer_addr: &str) -> ClientConnection {
+    ClientConnection {
+        client,
+        meta: ConnectionMeta::new(peer_addr.to_owned()),
+    }
+}
+
+#[test]
+fn test_cached_peer_addr() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+
+    let (proxy_side, _bot) = common::connect_bot();
+    let addr = proxy_side.peer_addr().unwrap().to_string();
+    sv.add_client(proxy_side);
+    let (proxy_side, _other) = common::connect_bot();
+    sv.add_connection(connection(proxy_side, "accepted-bot"));
+
+    let ids: Vec<_> = sv.snapshot().playlist.into_iter().map(|e| e.id).collect();
+    assert_eq!(ids, vec![addr.clone(), "accepted-bot".to_owned()]);
+    assert_eq!(sv.client_index_by_id(addr), Some(0));
+    assert_eq!(sv.client_index_by_id("accepted-bot".to_owned()), Some(1));
+    assert_eq!(sv.client_index_by_id("unknown".to_owned()), None);
+}
+
+#[test]
+fn test_closed_client_dropped() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+
+    let (proxy_side, bot) = common::connect_bot();
+    sv.add_connection(connection(proxy_side, "closed-bot"));
+    bot.shutdown().unwrap();
+    drop(bot);
+
+    sv.update_playlist();
+    assert!(sv.snapshot().playlist.is_empty());
+}
+
+#[test]
+fn test_quit_then_disconnect() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    let (proxy_side, _other) = common::connect_bot();
+    sv.add_client(proxy_side);
+
+    let mut quit = Request::new();
+    quit.mut_quit();
+    common::send(&mut bot, &quit);
+    // The bot closes the connection before the supervisor shuts it down
+    bot.shutdown().unwrap();
+    drop(bot);
+
+    let start = Instant::now();
+    while sv.snapshot().playlist.len() > 1 {
+        assert!(start.elapsed() < Duration::from_secs(10), "Quit not processed");
+        sv.update_playlist();
+    }
+    assert_eq!(sv.snapshot().playlist.len(), 1);
+}
+
+#[test]
+fn test_send_failure_dropped() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    // Responses to this client cannot be sent anymore
+    proxy_side.shutdown_sender().unwrap();
+    sv.add_connection(connection(proxy_side, "unwritable-bot"));
+    let (proxy_side, _other) = common::connect_bot();
+    sv.add_client(proxy_side);
+
+    let mut ping = Request::new();
+    ping.mut_ping();
+    common::send(&mut bot, &ping);
+
+    let start = Instant::now();
+    while sv.snapshot().playlist.len() > 1 {
+        assert!(start.elapsed() < Duration::from_secs(10), "Ping not processed");
+        sv.update_playlist();
+    }
+    let ids: Vec<_> = sv.snapshot().playlist.into_iter().map(|e| e.id).collect();
+    assert!(!ids.contains(&"unwritable-bot".to_owned()));
+}
+
+#[test]
+fn test_waiting_clients_polled_rarely() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let mut bots = Vec::new();
+    for i in 0..50 {
+        let (proxy_side, mut bot) = common::connect_bot();
+        sv.add_client(proxy_side);
+        common::send(&mut bot, &common::join_request(&format!("idlebot{}", i)));
+        bots.push(bot);
+    }
+    let start = Instant::now();
+    while sv.snapshot().playlist.iter().any(|e| !e.ready) {
+        assert!(start.elapsed() < Duration::from_secs(10), "Join requests not processed");
+        sv.update_playlist();
+    }
+
+    // Waiting clients are not polled again right away
+    let mut ping = Request::new();
+    ping.mut_ping();
+    common::send(&mut bots[0], &ping);
+    let start = Instant::now();
+    for _ in 0..100 {
+        sv.update_playlist();
+    }
+    assert!(start.elapsed() < IDLE_POLL_INTERVAL, "Updates took {:?}", start.elapsed());
+    bots[0].stream_ref().set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    assert!(bots[0].recv_message().is_err(), "Waiting client polled early");
+
+    // But eventually, so that pings are answered and disconnects noticed
+    drop(bots.pop());
+    std::thread::sleep(IDLE_POLL_INTERVAL);
+    sv.update_playlist();
+    bots[0].stream_ref().set_read_timeout(None).unwrap();
+    assert!(common::recv(&mut bots[0]).has_ping());
+    assert_eq!(sv.snapshot().playlist.len(), 49);
+}
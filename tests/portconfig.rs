@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+use std::thread;
+
+use sc2_proto::sc2api::RequestJoinGame;
+
+use sc2_proxy::portconfig::{PortConfig, PortConfigError, PortPair};
+
+#[test]
+fn test_concurrent_port_configs_do_not_overlap() {
+    let handles: Vec<_> = (0..32)
+        .map(|_| thread::spawn(|| PortConfig::new(4).expect("Unable to find free ports")))
+        .collect();
+    let configs: Vec<PortConfig> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    let mut seen = HashSet::new();
+    for config in &configs {
+        assert_eq!(config.ports().len(), 9);
+        for port in config.ports() {
+            assert!(seen.insert(port), "Port {} leased twice", port);
+        }
+    }
+}
+
+#[test]
+fn test_port_config_clones_share_lease() {
+    let config = PortConfig::new(2).expect("Unable to find free ports");
+    let copy = config.clone();
+    drop(config);
+
+    // The clone still holds the ports, so new configs get other ones
+    let other = PortConfig::new(2).expect("Unable to find free ports");
+    assert!(other.ports().iter().all(|p| !copy.ports().contains(p)));
+}
+
+/// Ports 1000, 1001, ... in order: shared, server and then clients
+fn fixed(processes: usize) -> PortConfig {
+    let pair = |i: u16| PortPair {
+        game: 1001 + 2 * i,
+        base: 1002 + 2 * i,
+    };
+    let clients = (1..processes as u16).map(pair).collect();
+    PortConfig::from_ports(processes, 1000, Some(pair(0)), clients).expect("Invalid ports")
+}
+
+/// Port fields of the join request of every process, as (shared, server, clients)
+fn join_ports(config: &PortConfig) -> Vec<(i32, (i32, i32), Vec<(i32, i32)>)> {
+    (0..config.processes())
+        .map(|process| {
+            let mut req = RequestJoinGame::new();
+            config.apply_proto(&mut req, process).expect("Could not apply");
+            let server = req.get_server_ports();
+            let clients = req
+                .get_client_ports()
+                .iter()
+                .map(|ps| (ps.get_game_port(), ps.get_base_port()))
+                .collect();
+            (
+                req.get_shared_port(),
+                (server.get_game_port(), server.get_base_port()),
+                clients,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_join_ports_two_players() {
+    let expected = (1000, (1001, 1002), vec![(1003, 1004)]);
+    assert_eq!(join_ports(&fixed(2)), vec![expected.clone(), expected]);
+}
+
+#[test]
+fn test_join_ports_three_players() {
+    let expected = (1000, (1001, 1002), vec![(1003, 1004), (1005, 1006)]);
+    assert_eq!(join_ports(&fixed(3)), vec![expected; 3]);
+}
+
+#[test]
+fn test_join_ports_four_players() {
+    let expected = (1000, (1001, 1002), vec![(1003, 1004), (1005, 1006), (1007, 1008)]);
+    assert_eq!(join_ports(&fixed(4)), vec![expected; 4]);
+}
+
+#[test]
+fn test_join_ports_single_process() {
+    let config = PortConfig::new(1).expect("Unable to find free ports");
+    assert_eq!(config.ports().len(), 1);
+    assert_eq!(config.server_ports(), None);
+
+    let mut req = RequestJoinGame::new();
+    config.apply_proto(&mut req, 0).expect("Could not apply");
+    assert_eq!(req.get_shared_port(), config.shared_port() as i32);
+    assert!(!req.has_server_ports());
+    assert!(req.get_client_ports().is_empty());
+}
+
+#[test]
+fn test_leased_port_config_shape() {
+    for processes in 2..=4 {
+        let config = PortConfig::new(processes).expect("Unable to find free ports");
+        assert_eq!(config.client_ports().len(), processes - 1);
+        assert!(config.server_ports().is_some());
+        assert_eq!(config.validate(), Ok(()));
+    }
+}
+
+#[test]
+fn test_port_config_validation() {
+    let pair = |game, base| PortPair { game, base };
+    assert_eq!(
+        PortConfig::from_ports(0, 1000, None, Vec::new()).unwrap_err(),
+        PortConfigError::NoProcesses
+    );
+    assert_eq!(
+        PortConfig::from_ports(2, 1000, None, vec![pair(1003, 1004)]).unwrap_err(),
+        PortConfigError::MissingServerPorts
+    );
+    assert_eq!(
+        PortConfig::from_ports(3, 1000, Some(pair(1001, 1002)), vec![pair(1003, 1004)]).unwrap_err(),
+        PortConfigError::NotEnoughClientPorts {
+            processes: 3,
+            client_ports: 1,
+        }
+    );
+    assert_eq!(
+        PortConfig::from_ports(2, 1000, Some(pair(1001, 1002)), vec![pair(1002, 1004)]).unwrap_err(),
+        PortConfigError::DuplicatePort(1002)
+    );
+
+    let mut req = RequestJoinGame::new();
+    assert_eq!(
+        fixed(2).apply_proto(&mut req, 2),
+        Err(PortConfigError::ProcessOutOfRange {
+            process: 2,
+            processes: 2,
+        })
+    );
+    assert_eq!(
+        PortConfigError::NotEnoughClientPorts {
+            processes: 3,
+            client_ports: 1,
+        }
+        .to_string(),
+        "3 SC2 processes need 2 client port pairs, got 1"
+    );
+}
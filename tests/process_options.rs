@@ -0,0 +1,102 @@
+mod common;
+
+use std::fs;
+
+use tempfile::TempDir;
+
+use sc2_proxy::config::{CpuAffinity, MatchmakingMode};
+use sc2_proxy::supervisor::Supervisor;
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_process_env() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    common::mark(&mut config, "vulkan");
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("envbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    let pids = common::marked_pids("vulkan", 1);
+    assert_eq!(pids.len(), 1);
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+}
+
+/// CPU cores a process is allowed to run on, as listed by the kernel
+#[cfg(target_os = "linux")]
+fn allowed_cpus(pid: &str) -> String {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).unwrap();
+    let line = status
+        .lines()
+        .find(|l| l.starts_with("Cpus_allowed_list:"))
+        .expect("No Cpus_allowed_list");
+    line.split(':').nth(1).unwrap().trim().to_owned()
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_cpu_affinity() {
+    // Use a core this process is allowed to run on
+    let allowed = allowed_cpus("self");
+    let core: usize = allowed.split(|c| c == ',' || c == '-').next().unwrap().parse().unwrap();
+
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.process.cpu_affinity = CpuAffinity::RoundRobin;
+    config.process.affinity_cores = vec![core];
+    common::mark(&mut config, "pinned");
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("pinnedbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    let pids = common::marked_pids("pinned", 1);
+    assert_eq!(allowed_cpus(&pids[0].to_string()), core.to_string());
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+}
+
+/// Play a game with the SC2 temp dirs under `root`
+/// Returns the number of temp dirs during the game, and after it
+#[cfg(target_os = "linux")]
+fn play_with_temp_root(root: &TempDir, cleanup: bool) -> (usize, usize) {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.process.temp_dir = Some(root.path().join("sc2").to_str().unwrap().to_owned());
+    config.process.cleanup_temp_dir = cleanup;
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("tempbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+    let root = root.path().join("sc2");
+    let count = || fs::read_dir(&root).unwrap().count();
+    let during = count();
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+    (during, count())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_temp_dir_cleanup() {
+    let root = TempDir::new().unwrap();
+    assert_eq!(play_with_temp_root(&root, true), (1, 0));
+
+    // Kept for inspection
+    let root = TempDir::new().unwrap();
+    assert_eq!(play_with_temp_root(&root, false), (1, 1));
+}
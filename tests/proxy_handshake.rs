@@ -0,0 +1,75 @@
+use std::thread;
+use std::time::Duration;
+
+use crossbeam::channel;
+use websocket::header::{Headers, UserAgent};
+use websocket::ClientBuilder;
+
+use sc2_proxy::proxy::{self, ConnectionMeta, BOT_METADATA_MAX_LEN};
+
+#[test]
+fn test_parse_request_uri() {
+    let meta = ConnectionMeta::from_handshake("1.2.3.4:5".to_owned(), "/sc2api", None, None);
+    assert_eq!(meta.peer_addr, "1.2.3.4:5");
+    assert_eq!(meta.path, "/sc2api");
+    assert_eq!(meta.query, None);
+
+    let meta = ConnectionMeta::from_handshake("a".to_owned(), "/ladder?token=abc&x", Some("o"), Some("ua"));
+    assert_eq!(meta.path, "/ladder");
+    assert_eq!(meta.query.as_deref(), Some("token=abc&x"));
+    assert_eq!(meta.origin.as_deref(), Some("o"));
+    assert_eq!(meta.user_agent.as_deref(), Some("ua"));
+    assert_eq!(meta.query_param("token"), Some("abc"));
+    assert_eq!(meta.query_param("x"), Some(""));
+    assert_eq!(meta.query_param("missing"), None);
+
+    let meta = ConnectionMeta::from_handshake("a".to_owned(), "ws://example.org:80/test?q=1", None, None);
+    assert_eq!(meta.path, "/test");
+    assert_eq!(meta.query.as_deref(), Some("q=1"));
+
+    let meta = ConnectionMeta::from_handshake("a".to_owned(), "ws://example.org", None, None);
+    assert_eq!(meta.path, "/");
+
+    let meta = ConnectionMeta::from_handshake("a".to_owned(), "?token=abc", None, None);
+    assert_eq!(meta.path, "/");
+    assert_eq!(meta.query_param("token"), Some("abc"));
+}
+
+#[test]
+fn test_bot_metadata() {
+    let meta = ConnectionMeta::from_handshake("a".to_owned(), "/sc2api?meta=v1.2-rush&token=x", None, None);
+    assert_eq!(meta.bot_metadata(), Some("v1.2-rush"));
+
+    let meta = ConnectionMeta::from_handshake("a".to_owned(), "/sc2api", None, None);
+    assert_eq!(meta.bot_metadata(), None);
+
+    let long = "x".repeat(BOT_METADATA_MAX_LEN + 1);
+    let meta = ConnectionMeta::from_handshake("a".to_owned(), &format!("/?meta={}", long), None, None);
+    assert_eq!(meta.bot_metadata(), None);
+}
+
+#[test]
+fn test_accept_captures_handshake() {
+    let server = proxy::bind("127.0.0.1:0", None).expect("Could not bind");
+    let addr = server.local_addr().unwrap();
+    let (sender, receiver) = channel::unbounded();
+    thread::spawn(move || proxy::run(server, sender));
+
+    let mut headers = Headers::new();
+    headers.set(UserAgent("testbot/1.0".to_owned()));
+    let bot = ClientBuilder::new(&format!("ws://{}/ladder?token=secret", addr))
+        .unwrap()
+        .origin("http://localhost".to_owned())
+        .custom_headers(&headers)
+        .connect_insecure()
+        .expect("Could not connect");
+
+    let conn = receiver.recv_timeout(Duration::from_secs(10)).expect("No connection");
+    assert_eq!(conn.meta.peer_addr, bot.local_addr().unwrap().to_string());
+    assert_eq!(conn.meta.path, "/ladder");
+    assert_eq!(conn.meta.query_param("token"), Some("secret"));
+    assert_eq!(conn.meta.origin.as_deref(), Some("http://localhost"));
+    assert_eq!(conn.meta.user_agent.as_deref(), Some("testbot/1.0"));
+    // The connection can be used through the wrapper
+    assert_eq!(conn.peer_addr().unwrap(), bot.local_addr().unwrap());
+}
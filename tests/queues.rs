@@ -0,0 +1,158 @@
+mod common;
+
+use sc2_proxy::config::{Config, MatchmakingMode, QueueConfig};
+use sc2_proxy::proxy::{ClientConnection, ConnectionMeta};
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::supervisor::{KickReason, Supervisor};
+
+/// Default queue with the given mode, and a "ladder" queue using the remote controller
+fn config(default_mode: MatchmakingMode) -> Config {
+    let mut config = common::config(default_mode);
+    let ladder = common::config(MatchmakingMode::RemoteController);
+    let queue = QueueConfig {
+        matchmaking: ladder.matchmaking,
+        match_defaults: ladder.match_defaults,
+    };
+    config.queues.insert("ladder".to_owned(), queue);
+    config
+}
+
+/// Connect a bot to `path`, returning the proxy side wrapped with its handshake details
+fn connect(path: &str) -> (ClientConnection, common::Client) {
+    let (proxy_side, bot) = common::connect_bot();
+    let peer_addr = proxy_side.peer_addr().unwrap().to_string();
+    let meta = ConnectionMeta::from_handshake(peer_addr, path, None, None);
+    (ClientConnection { client: proxy_side, meta }, bot)
+}
+
+#[test]
+fn test_queue_config() {
+    let config: Config = toml::from_str(
+        r#"
+        [matchmaking]
+        mode = "Pairs"
+
+        [queues.ladder.matchmaking]
+        mode = "RemoteController"
+
+        [queues.ladder.match_defaults]
+        integrity = true
+        "#,
+    )
+    .expect("Deserialization failed");
+
+    assert_eq!(config.queue_for_path("/ladder"), Ok(Some("ladder".to_owned())));
+    assert_eq!(config.queue_for_path("/sc2api"), Ok(None));
+    assert_eq!(config.queue_for_path("/unknown"), Ok(None));
+    assert!(config.requires_remote_controller());
+
+    let ladder = config.queue_config(Some("ladder"));
+    assert_eq!(ladder.matchmaking.mode, MatchmakingMode::RemoteController);
+    assert!(ladder.match_defaults.integrity);
+    let default = config.queue_config(None);
+    assert_eq!(default.matchmaking.mode, MatchmakingMode::Pairs);
+    assert!(!default.match_defaults.integrity);
+
+    let mut strict = config.clone();
+    strict.proxy.reject_unknown_paths = true;
+    assert!(strict.queue_for_path("/unknown").is_err());
+    assert_eq!(strict.queue_for_path("/"), Ok(None));
+    assert_eq!(strict.queue_for_path("/sc2api"), Ok(None));
+
+    let mut changed = config.clone();
+    assert!(changed.same_matchmaking_modes(&config));
+    changed.queues.get_mut("ladder").unwrap().matchmaking.mode = MatchmakingMode::Pairs;
+    assert!(!changed.same_matchmaking_modes(&config));
+}
+
+#[test]
+fn test_reject_unknown_paths() {
+    let mut config = config(MatchmakingMode::RemoteController);
+    config.proxy.reject_unknown_paths = true;
+    let mut sv = Supervisor::new(config);
+
+    let (conn, _bot) = connect("/unknown");
+    let id = conn.meta.peer_addr.clone();
+    sv.add_connection(conn);
+    assert!(sv.snapshot().playlist.is_empty());
+    let kick = sv.recent_kicks().last().expect("No kick recorded");
+    assert_eq!(kick.id, id);
+    assert_eq!(kick.reason, KickReason::AuthFailure);
+
+    let (conn, _bot) = connect("/sc2api");
+    sv.add_connection(conn);
+    let (conn, _bot) = connect("/ladder");
+    sv.add_connection(conn);
+    let queues: Vec<_> = sv.snapshot().playlist.into_iter().map(|e| e.queue).collect();
+    assert_eq!(queues, vec![None, Some("ladder".to_owned())]);
+}
+
+#[test]
+fn test_queue_remote_requests() {
+    let mut sv = Supervisor::new(config(MatchmakingMode::RemoteController));
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let (conn, _default_bot) = connect("/sc2api");
+    let default_id = conn.meta.peer_addr.clone();
+    sv.add_connection(conn);
+    let (conn, _ladder_bot) = connect("/ladder");
+    let ladder_id = conn.meta.peer_addr.clone();
+    sv.add_connection(conn);
+
+    let req = Request::GetQueuePlaylist("ladder".to_owned());
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::GetPlaylist(vec![(ladder_id, false)]));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetPlaylist);
+    match resp {
+        Response::GetPlaylist(clients) => assert_eq!(clients.len(), 2),
+        other => panic!("Unexpected response {:?}", other),
+    }
+
+    let req = Request::GetQueuePlaylist("missing".to_owned());
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Error("No such queue".to_owned()));
+    let req = Request::CreateQueueLobby("missing".to_owned(), None);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Error("No such queue".to_owned()));
+
+    let req = Request::CreateQueueLobby("ladder".to_owned(), Some("match-1".to_owned()));
+    let id = match common::remote_request(&mut sv, &mut remote, &mut stream, &req) {
+        Response::CreateLobby(id) => id,
+        other => panic!("Unexpected response {:?}", other),
+    };
+    let snapshot = sv.snapshot();
+    assert_eq!(snapshot.lobbies[0].id, id);
+    assert_eq!(snapshot.lobbies[0].queue.as_deref(), Some("ladder"));
+
+    // Clients of other queues cannot join the lobby, and stay in the playlist
+    let req = Request::AddToLobby(id, default_id, None);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Error("Client is not in the queue of the lobby".to_owned()));
+    assert_eq!(sv.snapshot().playlist.len(), 2);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_queues_matched_separately() {
+    let mut config = common::config(MatchmakingMode::Pairs);
+    let queue = QueueConfig {
+        matchmaking: config.matchmaking.clone(),
+        match_defaults: config.match_defaults.clone(),
+    };
+    config.queues.insert("test".to_owned(), queue);
+    let mut sv = Supervisor::new(config);
+
+    let (conn, mut default_bot) = connect("/sc2api");
+    sv.add_connection(conn);
+    common::send(&mut default_bot, &common::join_request("defaultbot"));
+    sv.update_playlist();
+    let (conn, mut test_bot) = connect("/test");
+    sv.add_connection(conn);
+    common::send(&mut test_bot, &common::join_request("testbot"));
+    sv.update_playlist();
+
+    let mut queues: Vec<_> = sv.snapshot().lobbies.into_iter().map(|l| l.queue).collect();
+    queues.sort();
+    assert_eq!(queues, vec![None, Some("test".to_owned())]);
+    sv.close();
+}
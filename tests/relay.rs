@@ -0,0 +1,434 @@
+use std::time::{Duration, Instant};
+
+use protobuf::{parse_from_bytes, Message};
+use sc2_proto::sc2api::Result::{Defeat, Victory};
+use sc2_proto::sc2api::{Action, Request, Response, ResponseObservation, Status};
+
+use sc2_proxy::bench_support::*;
+use sc2_proxy::config::MatchConfig;
+use sc2_proxy::refine::RefinerKind;
+use sc2_proxy::relay::{
+    action_count, ApmRecorder, Engine, ObservationRate, ResponseActions, SessionStep, ToGameContent,
+};
+use sc2_proxy::results::{ApmStats, PlayerOutcomeDetail, PlayerResult, ResultCategory};
+use sc2_proxy::sc2::{ScoreSnapshot, SessionStatus};
+
+#[test]
+fn test_fixtures() {
+    let bytes = std::fs::read("tests/data/observation_response.pb").unwrap();
+    let response = parse_from_bytes::<Response>(&bytes).unwrap();
+    assert_eq!(response, observation_response(200, 1344));
+    assert_eq!(response.write_to_bytes().unwrap(), bytes);
+
+    let bytes = std::fs::read("tests/data/action_request.pb").unwrap();
+    let req = parse_from_bytes::<Request>(&bytes).unwrap();
+    assert_eq!(req, action_request(20));
+}
+
+#[test]
+fn test_request_decisions() {
+    let mut filter = RequestFilter::new(&match_config(&[]), refine_context());
+    assert_eq!(filter.decide(step_request(8)), RequestDecision::Forward(step_request(8)));
+    match filter.decide(step_request(32)) {
+        RequestDecision::Respond(response) => assert_eq!(response.get_error(), ["Proxy: Request denied"]),
+        other => panic!("Step over the limit forwarded: {:?}", other),
+    }
+
+    let bytes = action_request(3).write_to_bytes().unwrap();
+    assert_eq!(filter.decide_bytes(&bytes).unwrap(), RequestDecision::Forward(action_request(3)));
+    assert!(filter.decide_bytes(&[0xff, 0xff]).is_err());
+    assert_eq!(filter.stripped_debug_draws(), 0);
+}
+
+#[test]
+fn test_request_decisions_refined() {
+    let refiners = [RefinerKind::ClampStep, RefinerKind::LimitDebugDraw];
+    let mut filter = RequestFilter::new(&match_config(&refiners), refine_context());
+    assert_eq!(filter.decide(step_request(32)), RequestDecision::Forward(step_request(16)));
+
+    match filter.decide(debug_draw_request(150)) {
+        RequestDecision::Forward(req) => {
+            assert_eq!(req.get_debug().get_debug()[0].get_draw().get_text().len(), 100)
+        },
+        other => panic!("Debug draw request denied: {:?}", other),
+    }
+    assert_eq!(filter.stripped_debug_draws(), 50);
+}
+
+/// Observation response of the last game loop, with the given results by player id
+fn game_over(game_loop: u32, results: &[(u32, sc2_proto::sc2api::Result)]) -> Response {
+    let mut response = Response::new();
+    let obs: &mut ResponseObservation = response.mut_observation();
+    obs.mut_observation().set_game_loop(game_loop);
+    for (id, result) in results {
+        let mut player_result = sc2_proto::sc2api::PlayerResult::new();
+        player_result.set_player_id(*id);
+        player_result.set_result(*result);
+        obs.mut_player_result().push(player_result);
+    }
+    response
+}
+
+#[test]
+fn test_response_events() {
+    assert_eq!(
+        ResponseEvent::from_response(&observation_response(3, 42)),
+        ResponseEvent::Observation {
+            game_loop: 42,
+            results: None,
+        }
+    );
+
+    let response = game_over(0, &[(2, Defeat), (1, Victory)]);
+    assert_eq!(
+        ResponseEvent::from_response(&response),
+        ResponseEvent::Observation {
+            game_loop: 0,
+            results: Some(vec![PlayerResult::Victory, PlayerResult::Defeat]),
+        }
+    );
+
+    let mut response = Response::new();
+    response.mut_leave_game();
+    assert_eq!(ResponseEvent::from_response(&response), ResponseEvent::LeftGame);
+    let mut response = Response::new();
+    response.mut_quit();
+    assert_eq!(ResponseEvent::from_response(&response), ResponseEvent::Quit);
+    let mut response = Response::new();
+    response.mut_step();
+    assert_eq!(ResponseEvent::from_response(&response), ResponseEvent::Other);
+}
+
+#[test]
+fn test_remote_json() {
+    for response in remote_responses(4) {
+        let line = encode_remote_response(&response);
+        assert_eq!(line.last(), Some(&b'\n'));
+        assert_eq!(line.iter().filter(|&&b| b == b'\n').count(), 1);
+        assert!(serde_json::from_slice::<serde_json::Value>(&line).is_ok());
+    }
+}
+
+/// Actions that only continue the session, sending the given messages
+fn continue_with(messages: Vec<ToGameContent>) -> ResponseActions {
+    ResponseActions {
+        messages,
+        step: SessionStep::Continue,
+    }
+}
+
+#[test]
+fn test_engine_requests() {
+    let mut engine = Engine::new(&match_config(&[RefinerKind::LimitDebugDraw]), refine_context());
+    assert_eq!(engine.last_request(), None);
+    assert!(matches!(engine.on_request(step_request(32)), RequestDecision::Respond(_)));
+    assert_eq!(engine.last_request(), None);
+    assert!(matches!(engine.on_request(debug_draw_request(120)), RequestDecision::Forward(_)));
+    assert_eq!(engine.last_request(), Some("debug"));
+    assert_eq!(engine.stats().stripped_debug_draws, 20);
+    assert_eq!(engine.context().player_name.as_deref(), Some("benchbot"));
+}
+
+#[test]
+fn test_engine_status_tracking() {
+    let mut engine = Engine::new(&MatchConfig::default(), refine_context());
+    let mut response = Response::new();
+    response.mut_step();
+    response.set_status(Status::in_game);
+    let actions = engine.on_response(&response, false);
+    assert_eq!(actions, continue_with(vec![ToGameContent::StatusChanged(SessionStatus::InGame)]));
+
+    // Only changes are reported
+    assert_eq!(engine.on_response(&response, false), continue_with(vec![]));
+    response.set_status(Status::ended);
+    let actions = engine.on_response(&response, false);
+    assert_eq!(actions, continue_with(vec![ToGameContent::StatusChanged(SessionStatus::Ended)]));
+}
+
+#[test]
+fn test_engine_game_over() {
+    let mut engine = Engine::new(&MatchConfig::default(), refine_context());
+    assert_eq!(engine.on_response(&observation_response(0, 120), false), continue_with(vec![]));
+    assert_eq!(engine.game_loop(), 120);
+
+    let actions = engine.on_response(&game_over(130, &[(2, Victory), (1, Defeat)]), false);
+    let results = vec![PlayerResult::Defeat, PlayerResult::Victory];
+    assert_eq!(actions, continue_with(vec![ToGameContent::GameOver(results)]));
+    assert_eq!(engine.game_loop(), 130);
+}
+
+#[test]
+fn test_engine_session_end() {
+    let mut engine = Engine::new(&MatchConfig::default(), refine_context());
+    let mut response = Response::new();
+    response.mut_leave_game();
+    let actions = engine.on_response(&response, false);
+    assert_eq!(actions.messages, vec![ToGameContent::LeftGame]);
+    assert_eq!(actions.step, SessionStep::Leave);
+
+    let mut response = Response::new();
+    response.mut_quit();
+    let actions = engine.on_response(&response, false);
+    assert_eq!(actions.messages, vec![ToGameContent::QuitBeforeLeave]);
+    assert_eq!(actions.step, SessionStep::WaitForExit);
+
+    let actions = engine.on_sc2_closed();
+    assert_eq!(actions.messages, vec![ToGameContent::SC2UnexpectedConnectionClose]);
+    assert_eq!(actions.step, SessionStep::Kill);
+}
+
+#[test]
+fn test_engine_time_limit() {
+    let mut config = MatchConfig::default();
+    config.time_limits.game_loops = Some(100);
+    let mut engine = Engine::new(&config, refine_context());
+    assert_eq!(engine.on_response(&observation_response(0, 99), false).step, SessionStep::Continue);
+
+    let actions = engine.on_response(&game_over(100, &[(1, Victory)]), false);
+    let results = vec![PlayerResult::Victory];
+    assert_eq!(actions.messages, vec![ToGameContent::GameOver(results), ToGameContent::TimeLimitReached]);
+    assert_eq!(actions.step, SessionStep::Shutdown);
+}
+
+#[test]
+fn test_engine_sc2_error_limit() {
+    let mut config = MatchConfig::default();
+    config.game.max_consecutive_sc2_errors = Some(3);
+    let mut engine = Engine::new(&config, refine_context());
+    let mut error = Response::new();
+    error.mut_error().push("Invalid".to_owned());
+
+    // A successful response resets the count
+    for _ in 0..2 {
+        assert_eq!(engine.on_response(&error, false), continue_with(vec![]));
+    }
+    engine.on_response(&observation_response(0, 1), false);
+    for _ in 0..2 {
+        assert_eq!(engine.on_response(&error, false), continue_with(vec![]));
+    }
+
+    let actions = engine.on_response(&error, false);
+    assert_eq!(actions.messages, vec![ToGameContent::SC2ErrorLimitReached]);
+    assert_eq!(actions.step, SessionStep::Shutdown);
+    assert_eq!(engine.stats().sc2_errors, 5);
+}
+
+#[test]
+fn test_engine_total_sc2_error_limit() {
+    let mut config = MatchConfig::default();
+    config.request_limits.max_sc2_errors = Some(3);
+    let mut engine = Engine::new(&config, refine_context());
+    let mut error = Response::new();
+    error.mut_error().push("Invalid".to_owned());
+
+    // Successful responses in between do not reset the count
+    for _ in 0..2 {
+        assert_eq!(engine.on_response(&error, false), continue_with(vec![]));
+        engine.on_response(&observation_response(0, 1), false);
+    }
+    let actions = engine.on_response(&error, false);
+    assert_eq!(actions.messages, vec![ToGameContent::SC2ErrorLimitReached]);
+    assert_eq!(actions.step, SessionStep::Shutdown);
+    assert_eq!(engine.stats().sc2_errors, 3);
+}
+
+#[test]
+fn test_engine_stats() {
+    let mut engine = Engine::new(&MatchConfig::default(), refine_context());
+    let mut error = Response::new();
+    error.mut_error().push("Invalid".to_owned());
+    engine.on_response(&error, false);
+    engine.on_response(&observation_response(0, 1), false);
+    engine.on_response(&observation_response(0, 1), true);
+    engine.on_sc2_closed();
+
+    let stats = engine.stats();
+    assert_eq!(stats.sc2_requests, 3);
+    assert_eq!(stats.cached_observations, 1);
+    assert_eq!(stats.sc2_errors, 1);
+    assert_eq!(stats.latency, None);
+}
+
+/// Observation request, waiting for `game_loop` if given
+fn observation_request(game_loop: Option<u32>) -> Request {
+    let mut req = Request::new();
+    let obs = req.mut_observation();
+    if let Some(game_loop) = game_loop {
+        obs.set_game_loop(game_loop);
+    }
+    req
+}
+
+#[test]
+fn test_observation_rate_realtime() {
+    let start = Instant::now();
+    let at = |ms| start + Duration::from_millis(ms);
+    let mut rate = ObservationRate::new(10, true);
+    assert_eq!(rate.coalesce(&observation_request(None), at(0)), None);
+    rate.on_response(&observation_response(0, 16), at(0));
+
+    // Coalesced within 100 ms, even after actions
+    assert_eq!(rate.coalesce(&observation_request(None), at(50)), Some(observation_response(0, 16)));
+    assert_eq!(rate.coalesce(&action_request(1), at(60)), None);
+    assert_eq!(rate.coalesce(&observation_request(None), at(70)), Some(observation_response(0, 16)));
+    assert_eq!(rate.coalesce(&observation_request(Some(20)), at(80)), None);
+    assert_eq!(rate.coalesce(&observation_request(None), at(100)), None);
+}
+
+#[test]
+fn test_observation_rate_step_mode() {
+    let start = Instant::now();
+    let mut rate = ObservationRate::new(10, false);
+    rate.on_response(&observation_response(0, 8), start);
+    assert_eq!(rate.coalesce(&observation_request(None), start), Some(observation_response(0, 8)));
+
+    // Stepped loops are never skipped
+    assert_eq!(rate.coalesce(&step_request(8), start), None);
+    assert_eq!(rate.coalesce(&observation_request(None), start), None);
+    rate.on_response(&observation_response(0, 16), start);
+    assert_eq!(rate.coalesce(&observation_request(None), start), Some(observation_response(0, 16)));
+}
+
+#[test]
+fn test_engine_score() {
+    let mut engine = Engine::new(&MatchConfig::default(), refine_context());
+    assert_eq!(engine.on_response(&observation_response(0, 10), false), continue_with(vec![]));
+
+    let mut response = observation_response(0, 20);
+    let obs = response.mut_observation().mut_observation();
+    obs.mut_score().set_score(1234);
+    obs.mut_score().mut_score_details().set_collected_minerals(500.0);
+    obs.mut_score().mut_score_details().mut_used_minerals().set_army(300.0);
+    obs.mut_score().mut_score_details().mut_used_vespene().set_army(100.0);
+    let actions = engine.on_response(&response, false);
+    let score = match &actions.messages[..] {
+        [ToGameContent::Score(score)] => *score,
+        other => panic!("Unexpected messages {:?}", other),
+    };
+    assert_eq!(score.game_loop, 20);
+    assert_eq!(score.score, 1234);
+    assert_eq!(score.collected_minerals, 500.0);
+    assert_eq!(score.army_value, 400.0);
+
+    // Reported once per game loop
+    assert_eq!(engine.on_response(&response, true), continue_with(vec![]));
+}
+
+#[test]
+fn test_engine_client_closed() {
+    let mut engine = Engine::new(&MatchConfig::default(), refine_context());
+    let actions = engine.on_client_closed();
+    assert_eq!(actions.messages, vec![ToGameContent::UnexpectedConnectionClose(None)]);
+    assert_eq!(actions.step, SessionStep::Shutdown);
+
+    engine.on_response(&observation_response(0, 0), false);
+    let actions = engine.on_client_closed();
+    assert_eq!(actions.messages, vec![ToGameContent::UnexpectedConnectionClose(Some(0))]);
+    engine.on_response(&observation_response(0, 230), false);
+    let actions = engine.on_client_closed();
+    assert_eq!(actions.messages, vec![ToGameContent::UnexpectedConnectionClose(Some(230))]);
+}
+
+#[test]
+fn test_outcome_details() {
+    use PlayerOutcomeDetail::*;
+    let cases = vec![
+        (ToGameContent::GameOver(vec![PlayerResult::Victory]), Some(NormalResult)),
+        (ToGameContent::TimeLimitReached, Some(NormalResult)),
+        (ToGameContent::LeftGame, Some(LeftEarly)),
+        (ToGameContent::QuitBeforeLeave, Some(LeftEarly)),
+        (ToGameContent::SC2UnexpectedConnectionClose, Some(SC2Crashed)),
+        (ToGameContent::SC2ErrorLimitReached, Some(SC2ErrorLimit)),
+        (ToGameContent::UnexpectedConnectionClose(Some(42)), Some(ClientDisconnected { game_loop: 42 })),
+        (ToGameContent::UnexpectedConnectionClose(None), Some(ClientCrashedBeforeStart)),
+        (ToGameContent::StatusChanged(SessionStatus::InGame), None),
+        (ToGameContent::Score(ScoreSnapshot::default()), None),
+    ];
+    for (content, expected) in cases {
+        assert_eq!(PlayerOutcomeDetail::from_message(&content), expected, "{:?}", content);
+    }
+}
+
+#[test]
+fn test_result_categories() {
+    use ResultCategory::*;
+    let cases = vec![
+        (ToGameContent::GameOver(vec![PlayerResult::Victory]), Some(Normal)),
+        (ToGameContent::TimeLimitReached, Some(Timeout)),
+        (ToGameContent::LeftGame, Some(SurrenderDisconnect)),
+        (ToGameContent::QuitBeforeLeave, Some(SurrenderDisconnect)),
+        (ToGameContent::SC2UnexpectedConnectionClose, Some(Crash)),
+        (ToGameContent::SC2ErrorLimitReached, Some(Crash)),
+        (ToGameContent::UnexpectedConnectionClose(Some(42)), Some(Crash)),
+        (ToGameContent::UnexpectedConnectionClose(None), Some(Crash)),
+        (ToGameContent::StatusChanged(SessionStatus::InGame), None),
+        (ToGameContent::Score(ScoreSnapshot::default()), None),
+    ];
+    for (content, expected) in cases {
+        assert_eq!(ResultCategory::from_message(&content), expected, "{:?}", content);
+    }
+}
+
+/// Action request with `commands` unit commands, followed by a camera move and a chat message
+fn mixed_action_request(commands: usize) -> Request {
+    let mut req = action_request(commands);
+    let actions = req.mut_action().mut_actions();
+    let mut camera = Action::new();
+    camera.mut_action_raw().mut_camera_move();
+    actions.push(camera);
+    let mut chat = Action::new();
+    chat.mut_action_chat().set_message("gl hf".to_owned());
+    actions.push(chat);
+    let mut spatial = Action::new();
+    spatial.mut_action_feature_layer().mut_unit_command().set_ability_id(23);
+    actions.push(spatial);
+    req
+}
+
+#[test]
+fn test_action_count() {
+    assert_eq!(action_count(action_request(0).get_action()), 0);
+    assert_eq!(action_count(action_request(7).get_action()), 7);
+    // Camera and chat actions are not counted, spatial unit commands are
+    assert_eq!(action_count(mixed_action_request(0).get_action()), 1);
+    assert_eq!(action_count(mixed_action_request(5).get_action()), 6);
+}
+
+#[test]
+fn test_apm_recorder() {
+    let mut apm = ApmRecorder::new();
+    assert_eq!(apm.stats(0), ApmStats::default());
+
+    apm.record(0, 30);
+    apm.record(1000, 30);
+    apm.record(1344, 10);
+    apm.record(3000, 50);
+    // Three started minutes, up to the last observation
+    assert_eq!(apm.stats(3100), ApmStats {
+        actions: 120,
+        average_apm: 40,
+        peak_apm: 60,
+    });
+    // Minutes without actions lower the average
+    assert_eq!(apm.stats(5400).average_apm, 24);
+}
+
+#[test]
+fn test_engine_apm() {
+    let mut engine = Engine::new(&MatchConfig::default(), refine_context());
+    engine.on_request(action_request(5));
+    assert_eq!(engine.stats().apm, None);
+
+    let mut config = MatchConfig::default();
+    config.game.record_apm = true;
+    let mut engine = Engine::new(&config, refine_context());
+    engine.on_request(mixed_action_request(4));
+    engine.on_response(&observation_response(0, 1400), false);
+    engine.on_request(action_request(20));
+    engine.on_request(step_request(1));
+    assert_eq!(engine.stats().apm, Some(ApmStats {
+        actions: 25,
+        average_apm: 12,
+        peak_apm: 20,
+    }));
+}
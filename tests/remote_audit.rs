@@ -0,0 +1,97 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+use sc2_proxy::config::{Config, RemoteRole};
+use sc2_proxy::remote_control::audit::{AuditEntry, AuditLog};
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::supervisor::Supervisor;
+
+fn entries(path: &Path) -> Vec<AuditEntry> {
+    let text = fs::read_to_string(path).expect("Audit log not written");
+    text.lines()
+        .map(|line| serde_json::from_str(line).expect("Invalid audit entry"))
+        .collect()
+}
+
+#[test]
+fn test_audit_session() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("audit.log");
+    let mut config = Config::new();
+    config
+        .remote_controller
+        .tokens
+        .insert("secrettoken".to_owned(), RemoteRole::Admin);
+    let mut sv = Supervisor::new(config.clone());
+    let (mut remote, mut stream) = common::connect_remote_audited(Some(AuditLog::new(&path, 1 << 20)));
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetGames);
+    assert_eq!(resp, Response::Error("Authentication required".to_owned()));
+    let req = Request::Authenticate("secrettoken".to_owned());
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Authenticate(RemoteRole::Admin));
+    let req = Request::SetConfig(Box::new(config));
+    common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::Ping(7));
+    assert_eq!(resp, Response::Ping(7));
+
+    // Invalid requests are answered without the supervisor
+    stream.send_line(b"garbage");
+
+    let entries = entries(&path);
+    let summary: Vec<_> = entries
+        .iter()
+        .map(|e| (e.role, e.request.as_deref(), e.response.as_str()))
+        .collect();
+    assert_eq!(summary, vec![
+        (None, Some("\"GetGames\""), "Error: Authentication required"),
+        (None, Some("Authenticate"), "Authenticate"),
+        (Some(RemoteRole::Admin), Some("SetConfig"), "SetConfig"),
+        (Some(RemoteRole::Admin), Some("{\"Ping\":7}"), "Ping"),
+        (
+            Some(RemoteRole::Admin),
+            None,
+            "Error: Invalid request: expected value at line 1 column 1"
+        ),
+    ]);
+    assert!(entries.windows(2).all(|w| w[0].time_ms <= w[1].time_ms));
+    assert!(entries.iter().all(|e| e.connection.starts_with("127.0.0.1:")));
+
+    // Secrets are never written
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(!text.contains("secrettoken"));
+}
+
+#[test]
+fn test_audit_rotation() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("audit.log");
+    let rotated = dir.path().join("audit.log.1");
+    let mut log = AuditLog::new(&path, 200);
+
+    for i in 0..3 {
+        log.record("127.0.0.1:1", None, Some(&Request::Ping(i)), &Response::Ping(i));
+    }
+    assert!(fs::metadata(&path).unwrap().len() <= 200);
+    assert!(rotated.exists());
+    let kept = entries(&path).len() + entries(&rotated).len();
+    assert!(kept >= 2 && kept <= 3, "{} entries kept", kept);
+}
+
+#[test]
+fn test_audit_write_failure() {
+    let dir = TempDir::new().unwrap();
+    // A directory cannot be opened for writing
+    let mut sv = Supervisor::new(Config::new());
+    let audit = AuditLog::new(dir.path(), 1 << 20);
+    let (mut remote, mut stream) = common::connect_remote_audited(Some(audit));
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::Ping(1));
+    assert_eq!(resp, Response::Ping(1));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::Ping(2));
+    assert_eq!(resp, Response::Ping(2));
+}
@@ -27,7 +27,7 @@ fn test_remote_control() {
     let port = pick_unused_port().expect("Could not find a free port");
     let addr = format!("127.0.0.1:{}", port);
 
-    let mut r = remote_control::run_server(&addr);
+    let mut r = remote_control::run_server(&addr, None).expect("Could not bind");
     let mut sv = Supervisor::new(Config::new());
 
     assert_eq!(sv.update_remote(&mut r), RemoteUpdateStatus::NoAction);
@@ -78,3 +78,46 @@ fn test_remote_control() {
 
     r.handle.join().unwrap();
 }
+
+#[test]
+fn test_remote_controller_reconnect() {
+    let port = pick_unused_port().expect("Could not find a free port");
+    let addr = format!("127.0.0.1:{}", port);
+
+    let mut r = remote_control::run_server(&addr, None).expect("Could not bind");
+    let mut sv = Supervisor::new(Config::new());
+
+    // Close the connection right after sending a request
+    {
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        stream.write(&to_json_line(&message::Request::Ping(1))).unwrap();
+        stream.flush().unwrap();
+    }
+
+    while sv.update_remote(&mut r) == RemoteUpdateStatus::NoAction {
+        sleep(Duration::from_millis(10));
+    }
+
+    // A new connection must still be served
+    let mut stream = BufStream::new(TcpStream::connect(&addr).unwrap());
+    stream.write(&to_json_line(&message::Request::Ping(2))).unwrap();
+    stream.flush().unwrap();
+
+    while sv.update_remote(&mut r) == RemoteUpdateStatus::NoAction {
+        sleep(Duration::from_millis(10));
+    }
+
+    let mut line = String::new();
+    stream.read_line(&mut line).unwrap();
+    let data = serde_json::from_str::<message::Response>(&line).expect("Invalid JSON returned");
+    assert_eq!(data, message::Response::Ping(2));
+
+    stream.write(&to_json_line(&message::Request::Quit)).unwrap();
+    stream.flush().unwrap();
+
+    while sv.update_remote(&mut r) != RemoteUpdateStatus::Quit {
+        sleep(Duration::from_millis(10));
+    }
+
+    r.handle.join().unwrap();
+}
@@ -0,0 +1,164 @@
+use bufstream::BufStream;
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sc2_proxy::config::Config;
+use sc2_proxy::remote_control::{self, message, Remote};
+use sc2_proxy::supervisor::{RemoteUpdateStatus, Supervisor};
+use sc2_proxy::{request_remote_enable, run_server_config};
+
+use portpicker::pick_unused_port;
+use tempfile::TempDir;
+
+/// Send a request and read the response
+fn request(stream: &mut BufStream<TcpStream>, req: &message::Request) -> message::Response {
+    let mut bytes = serde_json::to_vec(req).unwrap();
+    bytes.push(b'\n');
+    stream.write_all(&bytes).unwrap();
+    stream.flush().unwrap();
+
+    let mut line = String::new();
+    stream.read_line(&mut line).unwrap();
+    serde_json::from_str(&line).expect("Invalid JSON returned")
+}
+
+/// Send a request, process it with the supervisor and read the response
+fn process(
+    sv: &mut Supervisor, remote: &mut Remote, stream: &mut BufStream<TcpStream>, req: &message::Request,
+) -> message::Response {
+    let mut bytes = serde_json::to_vec(req).unwrap();
+    bytes.push(b'\n');
+    stream.write_all(&bytes).unwrap();
+    stream.flush().unwrap();
+    while sv.update_remote(remote) == RemoteUpdateStatus::NoAction {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut line = String::new();
+    stream.read_line(&mut line).unwrap();
+    serde_json::from_str(&line).expect("Invalid JSON returned")
+}
+
+/// Connect to the remote controller, retrying until it's up
+fn connect(addr: &str) -> BufStream<TcpStream> {
+    let start = Instant::now();
+    loop {
+        match TcpStream::connect(addr) {
+            Ok(stream) => return BufStream::new(stream),
+            Err(e) => assert!(start.elapsed() < Duration::from_secs(10), "Could not connect: {}", e),
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn test_disable_remote_control() {
+    let addr = format!("127.0.0.1:{}", pick_unused_port().expect("Could not find a free port"));
+    let mut r = remote_control::run_server(&addr, None).expect("Could not bind");
+    let mut sv = Supervisor::new(Config::new());
+
+    let mut stream = BufStream::new(TcpStream::connect(&addr).unwrap());
+    let mut bytes = serde_json::to_vec(&message::Request::DisableRemoteControl).unwrap();
+    bytes.push(b'\n');
+    stream.write_all(&bytes).unwrap();
+    stream.flush().unwrap();
+
+    while sv.update_remote(&mut r) != RemoteUpdateStatus::Disabled {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let mut line = String::new();
+    stream.read_line(&mut line).unwrap();
+    let data = serde_json::from_str::<message::Response>(&line).expect("Invalid JSON returned");
+    assert_eq!(data, message::Response::DisableRemoteControl);
+
+    // The connection and the listener are closed
+    r.handle.join().unwrap();
+    let mut line = String::new();
+    assert_eq!(stream.read_line(&mut line).unwrap(), 0);
+    assert!(TcpStream::connect(&addr).is_err());
+
+    // The listener can be started again on the same address
+    remote_control::run_server(&addr, None).expect("Could not bind again");
+}
+
+#[test]
+fn test_enable_flag() {
+    let mut config = Config::new();
+    assert!(request_remote_enable(&config).is_err());
+
+    let dir = TempDir::new().unwrap();
+    let flag = dir.path().join("enable_remote");
+    config.proxy.port = pick_unused_port().expect("Could not find a free port");
+    config.remote_controller.port = pick_unused_port().expect("Could not find a free port");
+    config.remote_controller.enable_flag_path = Some(flag.to_str().unwrap().to_owned());
+    let addr = config.remote_controller.addr();
+
+    let enable_config = config.clone();
+    let server = thread::spawn(move || run_server_config(config));
+
+    let mut stream = connect(&addr);
+    let resp = request(&mut stream, &message::Request::DisableRemoteControl);
+    assert_eq!(resp, message::Response::DisableRemoteControl);
+    drop(stream);
+
+    let start = Instant::now();
+    while TcpStream::connect(&addr).is_ok() {
+        assert!(start.elapsed() < Duration::from_secs(10), "Listener not closed");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    request_remote_enable(&enable_config).expect("Could not create the flag file");
+    let mut stream = connect(&addr);
+    assert!(!flag.exists());
+    let resp = request(&mut stream, &message::Request::Ping(5));
+    assert_eq!(resp, message::Response::Ping(5));
+
+    let resp = request(&mut stream, &message::Request::Quit);
+    assert_eq!(resp, message::Response::Quit);
+    server.join().unwrap().expect("Server failed");
+}
+
+#[test]
+fn test_rebind_remote_control() {
+    let addr = format!("127.0.0.1:{}", pick_unused_port().expect("Could not find a free port"));
+    let mut r = remote_control::run_server(&addr, None).expect("Could not bind");
+    let mut sv = Supervisor::new(Config::new());
+    let mut stream = BufStream::new(TcpStream::connect(&addr).unwrap());
+
+    // The current listener is kept if the new address cannot be bound
+    let req = message::Request::RebindRemoteControl(addr.clone());
+    match process(&mut sv, &mut r, &mut stream, &req) {
+        message::Response::Error(e) => assert!(e.starts_with(&format!("Could not bind {}", addr))),
+        other => panic!("Unexpected response {:?}", other),
+    }
+    let resp = process(&mut sv, &mut r, &mut stream, &message::Request::Ping(1));
+    assert_eq!(resp, message::Response::Ping(1));
+
+    let new_addr = format!("127.0.0.1:{}", pick_unused_port().expect("Could not find a free port"));
+    let req = message::Request::RebindRemoteControl(new_addr.clone());
+    let resp = process(&mut sv, &mut r, &mut stream, &req);
+    assert_eq!(resp, message::Response::RebindRemoteControl(new_addr.clone()));
+    assert_eq!(r.addr(), new_addr);
+
+    // The old connection and listener are closed
+    let mut line = String::new();
+    assert_eq!(stream.read_line(&mut line).unwrap(), 0);
+    assert!(TcpStream::connect(&addr).is_err());
+
+    let mut stream = BufStream::new(TcpStream::connect(&new_addr).unwrap());
+    let resp = process(&mut sv, &mut r, &mut stream, &message::Request::Ping(2));
+    assert_eq!(resp, message::Response::Ping(2));
+
+    // Port 0 binds to any free port, and the response tells which one
+    let req = message::Request::RebindRemoteControl("127.0.0.1:0".to_owned());
+    match process(&mut sv, &mut r, &mut stream, &req) {
+        message::Response::RebindRemoteControl(bound) => {
+            assert_ne!(bound, "127.0.0.1:0");
+            assert_eq!(bound, r.addr());
+        },
+        other => panic!("Unexpected response {:?}", other),
+    }
+    assert!(!r.is_closed());
+}
@@ -0,0 +1,57 @@
+use sc2_proxy::config::ProcessOptions;
+use sc2_proxy::remote_control::message::{GameInfo, Request, Response};
+use sc2_proxy::sc2::SessionStatus;
+use sc2_proxy::supervisor::GameId;
+
+#[test]
+fn test_create_lobby_external_id() {
+    let req: Request = serde_json::from_str(r#"{"CreateLobby":"match-42"}"#).expect("Deserialization failed");
+    assert_eq!(req, Request::CreateLobby(Some("match-42".to_owned())));
+
+    let req: Request = serde_json::from_str(r#"{"CreateLobby":null}"#).expect("Deserialization failed");
+    assert_eq!(req, Request::CreateLobby(None));
+}
+
+#[test]
+fn test_add_to_lobby_process_options() {
+    let id: GameId = "3".parse().unwrap();
+    let req: Request = serde_json::from_str(r#"{"AddToLobby":["3","127.0.0.1:4000",null]}"#).unwrap();
+    assert_eq!(req, Request::AddToLobby(id, "127.0.0.1:4000".to_owned(), None));
+
+    // Omitted options have their defaults
+    let json = r#"{"AddToLobby":["3","127.0.0.1:4000",{"fullscreen":true}]}"#;
+    let options = ProcessOptions {
+        fullscreen: true,
+        ..ProcessOptions::default()
+    };
+    let req: Request = serde_json::from_str(json).unwrap();
+    assert_eq!(req, Request::AddToLobby(id, "127.0.0.1:4000".to_owned(), Some(options)));
+}
+
+#[test]
+fn test_get_games_roundtrip() {
+    let resp: Response =
+        serde_json::from_str(r#"{"GetGames":[{"id":3,"external_id":"match-42","running":true}]}"#)
+            .expect("Deserialization failed");
+
+    match &resp {
+        Response::GetGames(games) => {
+            assert_eq!(games.len(), 1);
+            assert_eq!(games[0].external_id, Some("match-42".to_owned()));
+            assert!(games[0].running);
+        },
+        other => panic!("Unexpected response {:?}", other),
+    }
+
+    let json = serde_json::to_string(&resp).expect("Serialization failed");
+    let back: Response = serde_json::from_str(&json).expect("Deserialization failed");
+    assert_eq!(resp, back);
+
+    let info: GameInfo = serde_json::from_str(r#"{"id":0,"external_id":null,"running":false}"#).unwrap();
+    assert_eq!(info.external_id, None);
+    assert!(info.player_statuses.is_empty());
+
+    let json = r#"{"id":1,"external_id":null,"running":true,"player_statuses":["in_game",null]}"#;
+    let info: GameInfo = serde_json::from_str(json).unwrap();
+    assert_eq!(info.player_statuses, vec![Some(SessionStatus::InGame), None]);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use sc2_proxy::config::{Config, RemoteRole, UpstreamConfig};
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::supervisor::Supervisor;
+
+fn config() -> Config {
+    let mut config = Config::new();
+    let tokens = &mut config.remote_controller.tokens;
+    tokens.insert("admintoken".to_owned(), RemoteRole::Admin);
+    tokens.insert("dashtoken".to_owned(), RemoteRole::Spectator);
+    config.upstream = Some(UpstreamConfig {
+        addr: "127.0.0.1:2468".to_owned(),
+        name: "downstream".to_owned(),
+        token: Some("upstreamtoken".to_owned()),
+    });
+    config
+}
+
+#[test]
+fn test_authentication_required() {
+    let mut sv = Supervisor::new(config());
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::Ping(1));
+    assert_eq!(resp, Response::Ping(1));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetGames);
+    assert_eq!(resp, Response::Error("Authentication required".to_owned()));
+
+    let req = Request::Authenticate("wrong".to_owned());
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Error("Invalid token".to_owned()));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::Quit);
+    assert_eq!(resp, Response::Error("Authentication required".to_owned()));
+}
+
+#[test]
+fn test_spectator_is_read_only() {
+    let mut sv = Supervisor::new(config());
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let req = Request::Authenticate("dashtoken".to_owned());
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Authenticate(RemoteRole::Spectator));
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetGames);
+    assert_eq!(resp, Response::GetGames(Vec::new()));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetPlaylist);
+    assert_eq!(resp, Response::GetPlaylist(Vec::new()));
+    match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetConfig) {
+        Response::GetConfig(config) => {
+            assert!(config.remote_controller.tokens.is_empty());
+            assert_eq!(config.upstream.expect("Upstream missing").token, None);
+        },
+        other => panic!("Unexpected response {:?}", other),
+    }
+
+    let denied = Response::Error("Permission denied: spectators have read-only access".to_owned());
+    for req in &[
+        Request::Quit,
+        Request::SetConfig(Box::new(Config::new())),
+        Request::CreateLobby(None),
+        Request::ClearPlaylist,
+        Request::DisableRemoteControl,
+        Request::RebindRemoteControl("127.0.0.1:0".to_owned()),
+    ] {
+        let resp = common::remote_request(&mut sv, &mut remote, &mut stream, req);
+        assert_eq!(resp, denied);
+    }
+    assert_eq!(sv.lobby_count(), 0);
+}
+
+#[test]
+fn test_admin_has_full_access() {
+    let mut sv = Supervisor::new(config());
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let req = Request::Authenticate("admintoken".to_owned());
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::Authenticate(RemoteRole::Admin));
+
+    match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetConfig) {
+        Response::GetConfig(config) => {
+            assert_eq!(config.remote_controller.tokens.len(), 2);
+            assert_eq!(config.upstream.expect("Upstream missing").token.as_deref(), Some("upstreamtoken"));
+        },
+        other => panic!("Unexpected response {:?}", other),
+    }
+    let req = Request::SetConfig(Box::new(config()));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &req);
+    assert_eq!(resp, Response::SetConfig(config(), vec![]));
+}
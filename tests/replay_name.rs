@@ -0,0 +1,107 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use sc2_proxy::config::{
+    sanitize_name_value, Config, ReplayNameError, ReplayNameFields, ReplayNamePattern,
+};
+use sc2_proxy::supervisor::GameId;
+
+/// Fields of a finished game between two named bots
+fn fields<'a>(players: &'a [Option<String>]) -> ReplayNameFields<'a> {
+    ReplayNameFields {
+        game_id: "z1".parse::<GameId>().unwrap(),
+        timestamp: UNIX_EPOCH + Duration::from_secs(1_551_973_512),
+        map: Some("Automaton LE"),
+        players,
+        winner: Some("alpha"),
+        external_id: Some("match-42"),
+    }
+}
+
+fn expand(pattern: &str, fields: &ReplayNameFields) -> String {
+    ReplayNamePattern::new(pattern).expect("Invalid pattern").expand(fields)
+}
+
+#[test]
+fn test_replay_name_placeholders() {
+    let players = [Some("alpha".to_owned()), Some("beta".to_owned())];
+    let f = fields(&players);
+    assert_eq!(expand("{game_id}", &f), "z1");
+    assert_eq!(expand("{timestamp}", &f), "20190307T154512Z");
+    assert_eq!(expand("{map}", &f), "Automaton_LE");
+    assert_eq!(expand("{p1}_vs_{p2}", &f), "alpha_vs_beta");
+    assert_eq!(expand("{winner}", &f), "alpha");
+    assert_eq!(expand("{external_id}", &f), "match-42");
+    assert_eq!(
+        expand("replays/{external_id}/{timestamp}_{p1}_vs_{p2}.SC2Replay", &f),
+        "replays/match-42/20190307T154512Z_alpha_vs_beta.SC2Replay"
+    );
+    assert_eq!(expand("plain.SC2Replay", &f), "plain.SC2Replay");
+    assert_eq!(expand("", &f), "");
+    assert_eq!(expand("{p1}{p1}", &f), "alphaalpha");
+}
+
+#[test]
+fn test_replay_name_missing_values() {
+    let players = [None];
+    let f = ReplayNameFields {
+        map: None,
+        winner: None,
+        external_id: None,
+        ..fields(&players)
+    };
+    assert_eq!(expand("{map}_{winner}_{external_id}", &f), "none_none_none");
+    // Unnamed participants differ from missing ones
+    assert_eq!(expand("{p1}_vs_{p2}", &f), "unnamed_vs_none");
+}
+
+#[test]
+fn test_replay_name_sanitize() {
+    assert_eq!(sanitize_name_value("Bot-1_v2"), "Bot-1_v2");
+    assert_eq!(sanitize_name_value("../../etc/passwd"), "______etc_passwd");
+    assert_eq!(sanitize_name_value("a b\\c:d*e"), "a_b_c_d_e");
+    assert_eq!(sanitize_name_value(""), "none");
+    assert_eq!(sanitize_name_value("Zergling"), "Zergling");
+
+    let players = [Some("../evil".to_owned()), Some("{p2}".to_owned())];
+    let f = fields(&players);
+    assert_eq!(expand("{p1}_vs_{p2}.SC2Replay", &f), "___evil_vs__p2_.SC2Replay");
+}
+
+#[test]
+fn test_replay_name_invalid() {
+    let unknown = ReplayNameError::UnknownPlaceholder("player1".to_owned());
+    assert_eq!(ReplayNamePattern::new("{player1}"), Err(unknown));
+    let empty = ReplayNameError::UnknownPlaceholder(String::new());
+    assert_eq!(ReplayNamePattern::new("a{}b"), Err(empty));
+    assert_eq!(ReplayNamePattern::new("{p1"), Err(ReplayNameError::Unclosed));
+    assert_eq!(ReplayNamePattern::new("{p1{p2}"), Err(ReplayNameError::Unclosed));
+    assert_eq!(ReplayNamePattern::new("p1}"), Err(ReplayNameError::Unopened));
+    assert_eq!(ReplayNamePattern::new("}{p1}"), Err(ReplayNameError::Unopened));
+    assert_eq!(ReplayNamePattern::new("{p1}}"), Err(ReplayNameError::Unopened));
+}
+
+#[test]
+fn test_replay_name_config() {
+    let config: Config = toml::from_str(
+        r#"
+        [match_defaults.record_results]
+        replay_name_pattern = "{timestamp}_{p1}_vs_{p2}.SC2Replay"
+        "#,
+    )
+    .expect("Valid pattern rejected");
+    let pattern = config.match_defaults.record_results.replay_name_pattern.clone();
+    assert_eq!(pattern.as_ref().unwrap().as_str(), "{timestamp}_{p1}_vs_{p2}.SC2Replay");
+
+    let json = serde_json::to_string(&config).unwrap();
+    let roundtrip: Config = serde_json::from_str(&json).unwrap();
+    assert_eq!(roundtrip.match_defaults.record_results.replay_name_pattern, pattern);
+
+    let error = toml::from_str::<Config>(
+        r#"
+        [match_defaults.record_results]
+        replay_name_pattern = "{date}.SC2Replay"
+        "#,
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("Unknown placeholder {date}"), "{}", error);
+}
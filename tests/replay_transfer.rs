@@ -0,0 +1,134 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use flate2::Crc;
+use sc2_proto::sc2api::Request as SC2Request;
+use tempfile::TempDir;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::remote_control::message::{Request, Response, Update};
+use sc2_proxy::remote_control::transfer::ReplayTransfer;
+use sc2_proxy::supervisor::{GameId, Supervisor};
+
+/// Pseudorandom bytes, which do not compress or repeat
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+/// Reassemble the replay of a game from its updates, checking the chunk order and checksum
+fn reassemble(game_id: GameId, updates: &[Update]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut next_seq = 0;
+    for update in updates {
+        match update {
+            Update::ReplayChunk {
+                game_id: id,
+                seq,
+                data_base64,
+            } if *id == game_id => {
+                assert_eq!(*seq, next_seq);
+                next_seq += 1;
+                data.extend(base64::decode(data_base64).expect("Invalid base64"));
+            },
+            Update::ReplayEnd {
+                game_id: id,
+                chunks,
+                crc32,
+            } if *id == game_id => {
+                assert_eq!(*chunks, next_seq);
+                let mut crc = Crc::new();
+                crc.update(&data);
+                assert_eq!(*crc32, crc.sum());
+                return data;
+            },
+            _ => {},
+        }
+    }
+    panic!("No end of replay");
+}
+
+#[test]
+fn test_replay_transfer_chunks() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("replay.SC2Replay");
+    let data = random_bytes(3 * 1024 * 1024 + 123);
+    fs::write(&path, &data).unwrap();
+
+    let id: GameId = "3".parse().unwrap();
+    let (mut transfer, size) = ReplayTransfer::open(id, path.to_str().unwrap(), 64 * 1024).unwrap();
+    assert_eq!(size, data.len() as u64);
+    let mut updates = Vec::new();
+    while let Some(update) = transfer.next_update().unwrap() {
+        updates.push(update);
+    }
+    // 48 full chunks, one partial chunk, and the end
+    assert_eq!(updates.len(), 50);
+    assert_eq!(reassemble(id, &updates), data);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_fetch_replay() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("replay.SC2Replay");
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.remote_controller.replay_chunk_bytes = 100 * 1000;
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, mut bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["fetchbot"]);
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::FetchReplay(id));
+    assert_eq!(resp, Response::Error("No saved replay".to_owned()));
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bots[0]).has_join_game());
+
+    let save = Request::SaveReplay(id, path.to_str().unwrap().to_owned());
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &save);
+    assert_eq!(resp, Response::SaveReplay);
+    let mut step = SC2Request::new();
+    step.mut_step().set_count(1);
+    while !Path::new(&path).exists() {
+        common::send(&mut bots[0], &step);
+        assert!(common::recv(&mut bots[0]).has_step());
+        thread::sleep(Duration::from_millis(10));
+    }
+    common::play_until_end(&mut bots[0]);
+    common::wait_games(&mut sv);
+
+    // The fake replay is tiny, so replace it with a large one
+    let data = random_bytes(5 * 1024 * 1024);
+    fs::write(&path, &data).unwrap();
+
+    // The replay is still available after the game has ended
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::FetchReplay(id));
+    assert_eq!(resp, Response::FetchReplay(data.len() as u64));
+    let updates = common::wait_update(&mut sv, &mut remote, &mut stream, |u| {
+        matches!(u, Update::ReplayEnd { .. })
+    });
+    assert_eq!(reassemble(id, &updates), data);
+
+    // Requests are answered between the chunks of a transfer
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::FetchReplay(id));
+    assert_eq!(resp, Response::FetchReplay(data.len() as u64));
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::Ping(5));
+    assert_eq!(resp, Response::Ping(5));
+    let updates = common::wait_update(&mut sv, &mut remote, &mut stream, |u| {
+        matches!(u, Update::ReplayEnd { .. })
+    });
+    assert_eq!(reassemble(id, &updates), data);
+}
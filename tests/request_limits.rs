@@ -0,0 +1,80 @@
+use sc2_proxy::config::*;
+use sc2_proxy::refine::{Pipeline, RefineContext};
+
+use sc2_proto::sc2api::Request;
+
+fn step(count: u32) -> Request {
+    let mut req = Request::new();
+    req.mut_step().set_count(count);
+    req
+}
+
+#[test]
+fn test_step_count_clamp() {
+    let mut config = MatchConfig::default();
+    config.request_limits.max_step_count = Some(8);
+    assert_eq!(config.request_limits.step_limit_action, LimitAction::Clamp);
+    let mut refiners = Pipeline::new(&config);
+
+    let mut req = step(100);
+    assert!(config.request_limits.is_request_allowed(&req));
+    refiners.refine(&mut req, &RefineContext::default());
+    assert_eq!(req.get_step().get_count(), 8);
+
+    let mut req = step(5);
+    refiners.refine(&mut req, &RefineContext::default());
+    assert_eq!(req.get_step().get_count(), 5);
+}
+
+#[test]
+fn test_step_count_reject() {
+    let limits = RequestLimits {
+        max_step_count: Some(8),
+        step_limit_action: LimitAction::Reject,
+        ..Default::default()
+    };
+    assert!(!limits.is_request_allowed(&step(9)));
+    assert!(limits.is_request_allowed(&step(8)));
+
+    let mut req = Request::new();
+    req.mut_observation();
+    assert!(limits.is_request_allowed(&req));
+}
+
+#[test]
+fn test_step_count_unlimited() {
+    let config = MatchConfig::default();
+    let mut req = step(1000);
+    assert!(config.request_limits.is_request_allowed(&req));
+    Pipeline::new(&config).refine(&mut req, &RefineContext::default());
+    assert_eq!(req.get_step().get_count(), 1000);
+}
+
+#[test]
+fn test_force_step_mode() {
+    let mut config = MatchConfig::default();
+    config.game.realtime = true;
+    assert!(config.game.is_realtime());
+
+    config.game.force_step_mode = true;
+    assert!(!config.game.is_realtime());
+
+    let mut req = Request::new();
+    req.mut_create_game().set_realtime(true);
+    Pipeline::new(&config).refine(&mut req, &RefineContext::default());
+    assert!(!req.get_create_game().get_realtime());
+}
+
+#[test]
+fn test_disable_save_replay() {
+    let mut req = Request::new();
+    req.mut_save_replay();
+    assert!(RequestLimits::default().is_request_allowed(&req));
+
+    let limits = RequestLimits {
+        disable_save_replay: true,
+        ..Default::default()
+    };
+    assert!(!limits.is_request_allowed(&req));
+    assert!(limits.is_request_allowed(&step(1)));
+}
@@ -0,0 +1,184 @@
+mod common;
+
+use sc2_proto::debug::DebugCommand;
+use sc2_proto::raw::ActionRaw;
+use sc2_proto::sc2api::{Action, Request};
+
+use sc2_proxy::config::{MatchConfig, MatchmakingMode, Race};
+use sc2_proxy::refine::{debug_draw_count, Pipeline, RefineContext, RefinerKind};
+use sc2_proxy::supervisor::Supervisor;
+
+fn ctx(slot: usize, name: &str) -> RefineContext {
+    RefineContext {
+        slot,
+        player_name: Some(name.to_owned()),
+    }
+}
+
+fn debug_request() -> Request {
+    let mut req = Request::new();
+    let mut draw = DebugCommand::new();
+    draw.mut_draw();
+    let mut kill = DebugCommand::new();
+    kill.mut_kill_unit().mut_tag().push(1);
+    req.mut_debug().mut_debug().push(draw);
+    req.mut_debug().mut_debug().push(kill);
+    req
+}
+
+#[test]
+fn test_default_pipeline() {
+    let config = MatchConfig::default();
+    assert!(Pipeline::new(&config).is_empty());
+
+    let mut config = MatchConfig::default();
+    config.request_limits.max_step_count = Some(8);
+    config.game.force_step_mode = true;
+    config.game.request_refiners = vec![RefinerKind::ClampStep, RefinerKind::TagChat];
+    assert_eq!(Pipeline::new(&config).len(), 3);
+}
+
+#[test]
+fn test_override_race() {
+    let mut config = MatchConfig::default();
+    config.game.overwrite_races = Some(vec![None, Some(Race::Zerg)]);
+    config.game.request_refiners = vec![RefinerKind::OverrideRace];
+    let mut refiners = Pipeline::new(&config);
+
+    let mut req = common::join_request("racebot");
+    let requested = req.get_join_game().get_race();
+    refiners.refine(&mut req, &ctx(0, "racebot"));
+    assert_eq!(req.get_join_game().get_race(), requested);
+    refiners.refine(&mut req, &ctx(1, "racebot"));
+    assert_eq!(req.get_join_game().get_race(), Race::Zerg.to_proto());
+}
+
+#[test]
+fn test_tag_chat() {
+    let mut config = MatchConfig::default();
+    config.game.request_refiners = vec![RefinerKind::TagChat];
+    let mut refiners = Pipeline::new(&config);
+
+    let mut req = Request::new();
+    let mut chat = Action::new();
+    chat.mut_action_chat().set_message("gl hf".to_owned());
+    let mut raw = Action::new();
+    raw.set_action_raw(ActionRaw::new());
+    req.mut_action().mut_actions().push(chat);
+    req.mut_action().mut_actions().push(raw);
+
+    refiners.refine(&mut req, &ctx(0, "chatbot"));
+    let actions = req.get_action().get_actions();
+    assert_eq!(actions[0].get_action_chat().get_message(), "[chatbot] gl hf");
+    assert!(!actions[1].has_action_chat());
+
+    let mut unnamed = req.clone();
+    refiners.refine(&mut unnamed, &RefineContext::default());
+    assert_eq!(unnamed, req);
+}
+
+#[test]
+fn test_strip_cheats() {
+    let mut config = MatchConfig::default();
+    config.request_limits.disable_cheats = true;
+    let mut req = debug_request();
+    assert!(!config.request_limits.is_request_allowed(&req));
+
+    config.game.request_refiners = vec![RefinerKind::StripCheats];
+    Pipeline::new(&config).refine(&mut req, &RefineContext::default());
+    assert_eq!(req.get_debug().get_debug().len(), 1);
+    assert!(req.get_debug().get_debug()[0].has_draw());
+    assert!(config.request_limits.is_request_allowed(&req));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_strip_cheats_in_game() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.request_limits.disable_cheats = true;
+    config.match_defaults.game.request_refiners = vec![RefinerKind::StripCheats];
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("cheatbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    // Forwarded to SC2 without the cheat instead of being denied by the proxy
+    common::send(&mut bot, &debug_request());
+    let resp = common::recv(&mut bot);
+    assert_eq!(resp.get_error(), &["Unsupported by fake SC2".to_owned()][..]);
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+}
+
+fn draw_request(spheres: usize, texts: usize) -> Request {
+    let mut draw = DebugCommand::new();
+    for _ in 0..spheres {
+        draw.mut_draw().mut_spheres().push(Default::default());
+    }
+    for _ in 0..texts {
+        draw.mut_draw().mut_text().push(Default::default());
+    }
+    let mut req = Request::new();
+    req.mut_debug().mut_debug().push(draw);
+    req
+}
+
+#[test]
+fn test_limit_debug_draw() {
+    let mut config = MatchConfig::default();
+    config.request_limits.max_debug_draw_per_step = Some(10);
+    let mut refiners = Pipeline::new(&config);
+    assert_eq!(refiners.len(), 1);
+
+    let mut req = draw_request(4, 2);
+    refiners.refine(&mut req, &RefineContext::default());
+    assert_eq!(debug_draw_count(&req), 6);
+
+    // Texts are kept before shapes
+    let mut req = draw_request(4, 2);
+    refiners.refine(&mut req, &RefineContext::default());
+    assert_eq!(debug_draw_count(&req), 4);
+    assert_eq!(req.get_debug().get_debug()[0].get_draw().get_text().len(), 2);
+    assert_eq!(req.get_debug().get_debug()[0].get_draw().get_spheres().len(), 2);
+
+    let mut req = draw_request(1, 0);
+    refiners.refine(&mut req, &RefineContext::default());
+    assert_eq!(debug_draw_count(&req), 0);
+
+    // Stepping resets the count
+    let mut step = Request::new();
+    step.mut_step().set_count(1);
+    refiners.refine(&mut step, &RefineContext::default());
+    let mut req = draw_request(20, 0);
+    refiners.refine(&mut req, &RefineContext::default());
+    assert_eq!(debug_draw_count(&req), 10);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_limit_debug_draw_stats() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.request_limits.max_debug_draw_per_step = Some(5);
+    let mut sv = Supervisor::new(config);
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("drawbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    common::send(&mut bot, &draw_request(8, 0));
+    common::recv(&mut bot);
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.player_stats[0].stripped_debug_draws, 3);
+}
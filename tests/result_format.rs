@@ -0,0 +1,117 @@
+mod common;
+
+use std::fs;
+use std::time::{Duration, UNIX_EPOCH};
+
+use tempfile::TempDir;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::results::*;
+use sc2_proxy::supervisor::{GameId, Supervisor};
+
+fn result(player_results: Vec<PlayerResult>, player_details: Vec<PlayerOutcomeDetail>) -> GameResult {
+    GameResult {
+        external_id: None,
+        player_races: vec![Race::Terran, Race::Zerg],
+        requested_races: vec![Race::Terran, Race::Zerg],
+        player_names: vec![Some("alpha".to_owned()), Some("beta".to_owned())],
+        player_metadata: vec![None, None],
+        host_slot: Some(0),
+        slot_assignment: Vec::new(),
+        end_reason: GameEndReason::Normal,
+        player_ids: vec![Some(1), Some(2)],
+        player_results,
+        player_details,
+        player_categories: Vec::new(),
+        player_stats: vec![
+            PlayerStats {
+                game_loop: 13440,
+                ..Default::default()
+            },
+            PlayerStats {
+                game_loop: 13439,
+                ..Default::default()
+            },
+        ],
+        valid: true,
+        void_reason: None,
+        network_sim: None,
+    }
+}
+
+fn normal() -> Vec<PlayerOutcomeDetail> {
+    vec![PlayerOutcomeDetail::NormalResult; 2]
+}
+
+#[test]
+fn test_aiarena_outcomes() {
+    use PlayerResult::*;
+    let outcome = |r: &GameResult| AiArenaOutcome::from_result(r);
+    assert_eq!(outcome(&result(vec![Victory, Defeat], normal())), AiArenaOutcome::Player1Win);
+    assert_eq!(outcome(&result(vec![Defeat, Victory], normal())), AiArenaOutcome::Player2Win);
+    assert_eq!(outcome(&result(vec![Tie, Tie], normal())), AiArenaOutcome::Tie);
+
+    let disconnected = vec![
+        PlayerOutcomeDetail::NormalResult,
+        PlayerOutcomeDetail::ClientDisconnected { game_loop: 100 },
+    ];
+    assert_eq!(outcome(&result(vec![Victory, Defeat], disconnected)), AiArenaOutcome::Player2Crash);
+    let crashed = vec![PlayerOutcomeDetail::ClientCrashedBeforeStart, PlayerOutcomeDetail::NormalResult];
+    assert_eq!(outcome(&result(vec![Defeat, Victory], crashed)), AiArenaOutcome::Player1Crash);
+    let sc2_crashed = vec![PlayerOutcomeDetail::SC2Crashed, PlayerOutcomeDetail::NormalResult];
+    assert_eq!(outcome(&result(vec![Defeat, Victory], sc2_crashed)), AiArenaOutcome::Error);
+
+    let mut quit = result(Vec::new(), vec![PlayerOutcomeDetail::Kicked; 2]);
+    quit.end_reason = GameEndReason::QuitRequest;
+    assert_eq!(outcome(&quit), AiArenaOutcome::Error);
+
+    // Against a computer, the results of both players are reported
+    let mut computer = result(vec![Defeat, Victory], vec![PlayerOutcomeDetail::NormalResult]);
+    computer.player_names.truncate(1);
+    computer.player_ids.truncate(1);
+    computer.player_races.truncate(1);
+    assert_eq!(outcome(&computer), AiArenaOutcome::Player2Win);
+}
+
+#[test]
+fn test_aiarena_json_shape() {
+    let mut r = result(vec![PlayerResult::Victory, PlayerResult::Defeat], normal());
+    r.external_id = Some("4521".to_owned());
+    let id: GameId = "2s".parse().unwrap();
+    let time = UNIX_EPOCH + Duration::from_secs(1_552_000_000);
+    let converted = AiArenaResult::new(id, &r, Some("AcolyteLE"), time);
+    assert_eq!(
+        serde_json::to_string(&converted).unwrap(),
+        r#"{"Match":"4521","Bot1":"alpha","Bot2":"beta","Result":"Player1Win","GameTime":13440,"GameTimeSeconds":600.0,"GameTimeFormatted":"00:10:00","Map":"AcolyteLE","TimeStamp":"2019-03-07T23:06:40Z"}"#
+    );
+
+    // The game id without an external id
+    r.external_id = None;
+    assert_eq!(AiArenaResult::new(id, &r, None, time).match_id, "2s");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_result_files() {
+    let dir = TempDir::new().unwrap();
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.record_results.results_dir = Some(dir.path().to_str().unwrap().to_owned());
+    config.match_defaults.record_results.result_format = ResultFormat::Aiarena;
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("ladderbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+
+    let (id, _) = sv.recent_results().last().expect("No result recorded");
+    let text = fs::read_to_string(dir.path().join(format!("{}.json", id))).expect("No result file");
+    let written: AiArenaResult = serde_json::from_str(&text).unwrap();
+    assert_eq!(written.match_id, id.to_string());
+    assert_eq!(written.bot1.as_deref(), Some("ladderbot"));
+    assert_eq!(written.bot2, None);
+    assert_eq!(written.map.as_deref(), Some(common::MAP_NAME));
+}
@@ -0,0 +1,325 @@
+use sc2_proxy::results::*;
+
+#[test]
+fn test_game_result_json_shape() {
+    let result = GameResult {
+        external_id: Some("match-42".to_owned()),
+        player_races: vec![Race::Terran, Race::Zerg],
+        requested_races: vec![Race::Random, Race::Zerg],
+        player_names: vec![Some("terranbot".to_owned()), None],
+        player_metadata: vec![Some("v1.2".to_owned()), None],
+        host_slot: Some(0),
+        slot_assignment: vec![
+            SlotAssignment::Participant { slot: 0 },
+            SlotAssignment::Participant { slot: 1 },
+        ],
+        end_reason: GameEndReason::Normal,
+        player_ids: vec![Some(1), Some(2)],
+        player_results: vec![PlayerResult::Victory, PlayerResult::Defeat],
+        player_details: vec![
+            PlayerOutcomeDetail::NormalResult,
+            PlayerOutcomeDetail::ClientDisconnected { game_loop: 7 },
+        ],
+        player_categories: vec![ResultCategory::Normal, ResultCategory::Crash],
+        player_stats: vec![PlayerStats {
+            sc2_requests: 10,
+            cached_observations: 2,
+            sc2_errors: 1,
+            stripped_debug_draws: 0,
+            latency: None,
+            game_loop: 0,
+            apm: None,
+        }],
+        valid: true,
+        void_reason: None,
+        network_sim: None,
+    };
+
+    let json = serde_json::to_string(&result).expect("Serialization failed");
+    assert_eq!(
+        json,
+        r#"{"external_id":"match-42","player_races":["Terran","Zerg"],"requested_races":["Random","Zerg"],"player_names":["terranbot",null],"player_metadata":["v1.2",null],"host_slot":0,"slot_assignment":[{"participant":{"slot":0}},{"participant":{"slot":1}}],"end_reason":"normal","player_ids":[1,2],"player_results":["victory","defeat"],"player_details":["normal_result",{"client_disconnected":{"game_loop":7}}],"player_categories":["normal","crash"],"player_stats":[{"sc2_requests":10,"cached_observations":2,"sc2_errors":1,"stripped_debug_draws":0,"latency":null,"game_loop":0,"apm":null}],"valid":true,"void_reason":null}"#
+    );
+
+    let back: GameResult = serde_json::from_str(&json).expect("Deserialization failed");
+    assert_eq!(back, result);
+}
+
+#[test]
+fn test_result_enum_names() {
+    assert_eq!(serde_json::to_string(&GameEndReason::QuitRequest).unwrap(), r#""quit_request""#);
+    assert_eq!(serde_json::to_string(&PlayerResult::Tie).unwrap(), r#""tie""#);
+}
+
+#[test]
+fn test_result_categories_json() {
+    use PlayerOutcomeDetail::*;
+    assert_eq!(
+        serde_json::to_string(&ResultCategory::SurrenderDisconnect).unwrap(),
+        r#""surrender_disconnect""#
+    );
+
+    // Missing from results recorded before the categories
+    let mut result = synthetic(vec![NormalResult, LeftEarly], 100);
+    result.player_categories = vec![ResultCategory::Normal, ResultCategory::SurrenderDisconnect];
+    let json = serde_json::to_string(&result).unwrap();
+    let json = json.replace(r#""player_categories":["normal","surrender_disconnect"],"#, "");
+    let back: GameResult = serde_json::from_str(&json).unwrap();
+    assert!(back.player_categories.is_empty());
+}
+
+#[test]
+fn test_game_stats() {
+    let mut stats = GameStats::default();
+    stats.record(&GameResult {
+        external_id: None,
+        player_races: vec![Race::Terran, Race::Zerg],
+        requested_races: vec![Race::Terran, Race::Zerg],
+        player_names: Vec::new(),
+        player_metadata: Vec::new(),
+        host_slot: Some(0),
+        slot_assignment: Vec::new(),
+        end_reason: GameEndReason::Normal,
+        player_ids: Vec::new(),
+        player_results: vec![PlayerResult::Defeat, PlayerResult::Victory],
+        player_details: Vec::new(),
+        player_categories: Vec::new(),
+        player_stats: Vec::new(),
+        valid: true,
+        void_reason: None,
+        network_sim: None,
+    });
+    stats.record(&GameResult {
+        external_id: None,
+        player_races: vec![Race::Zerg, Race::Zerg],
+        requested_races: vec![Race::Zerg, Race::Zerg],
+        player_names: Vec::new(),
+        player_metadata: Vec::new(),
+        host_slot: Some(0),
+        slot_assignment: Vec::new(),
+        end_reason: GameEndReason::NoContest,
+        player_ids: Vec::new(),
+        player_results: vec![PlayerResult::Tie, PlayerResult::Tie],
+        player_details: Vec::new(),
+        player_categories: Vec::new(),
+        player_stats: Vec::new(),
+        valid: true,
+        void_reason: None,
+        network_sim: None,
+    });
+    stats.record_crash();
+
+    assert_eq!(stats.total_games, 3);
+    assert_eq!(stats.crashed_games, 1);
+    assert_eq!(stats.wins_by_race.get(&Race::Zerg), Some(&1));
+    assert_eq!(stats.wins_by_race.get(&Race::Terran), None);
+    assert_eq!(stats.games_by_end_reason.get(&GameEndReason::Normal), Some(&1));
+    assert_eq!(stats.games_by_end_reason.get(&GameEndReason::NoContest), Some(&1));
+
+    let json = serde_json::to_string(&stats).expect("Serialization failed");
+    let back: GameStats = serde_json::from_str(&json).expect("Deserialization failed");
+    assert_eq!(back, stats);
+}
+
+#[test]
+fn test_standings() {
+    let mut standings = Standings::default();
+    let mut result = GameResult {
+        external_id: None,
+        player_races: vec![Race::Terran, Race::Zerg],
+        requested_races: vec![Race::Terran, Race::Zerg],
+        player_names: vec![Some("alpha".to_owned()), Some("beta".to_owned())],
+        player_metadata: vec![None, None],
+        host_slot: Some(0),
+        slot_assignment: Vec::new(),
+        end_reason: GameEndReason::Normal,
+        player_ids: Vec::new(),
+        player_results: vec![PlayerResult::Victory, PlayerResult::Defeat],
+        player_details: Vec::new(),
+        player_categories: Vec::new(),
+        player_stats: Vec::new(),
+        valid: true,
+        void_reason: None,
+        network_sim: None,
+    };
+    standings.record(&result);
+    result.player_results = vec![PlayerResult::Tie, PlayerResult::Tie];
+    standings.record(&result);
+    result.player_names[1] = None;
+    result.player_results = vec![PlayerResult::Defeat, PlayerResult::Victory];
+    standings.record(&result);
+
+    let alpha = BotRecord {
+        wins: 1,
+        losses: 1,
+        ties: 1,
+    };
+    let beta = BotRecord {
+        wins: 0,
+        losses: 1,
+        ties: 1,
+    };
+    assert_eq!(standings.bots.len(), 2);
+    assert_eq!(standings.bots["alpha"], alpha);
+    assert_eq!(standings.bots["beta"], beta);
+}
+
+#[test]
+fn test_standings_persistence() {
+    let dir = tempfile::TempDir::new().expect("Could not create temp dir");
+    let path = dir.path().join("standings.json");
+    assert_eq!(Standings::load(&path).expect("Could not load"), Standings::default());
+
+    let mut standings = Standings::default();
+    standings.bots.insert("alpha".to_owned(), BotRecord {
+        wins: 3,
+        losses: 2,
+        ties: 1,
+    });
+    standings.save(&path).expect("Could not save");
+    assert_eq!(Standings::load(&path).expect("Could not load"), standings);
+
+    std::fs::write(&path, "not json").unwrap();
+    assert!(Standings::load(&path).is_err());
+}
+
+#[test]
+fn test_participant_results() {
+    use PlayerResult::*;
+    let mut result = GameResult {
+        external_id: None,
+        player_races: vec![Race::Terran, Race::Zerg],
+        requested_races: vec![Race::Terran, Race::Zerg],
+        player_names: vec![None, None],
+        player_metadata: vec![None, None],
+        host_slot: None,
+        slot_assignment: Vec::new(),
+        end_reason: GameEndReason::Normal,
+        player_ids: vec![Some(2), Some(1)],
+        player_results: vec![Victory, Defeat],
+        player_details: Vec::new(),
+        player_categories: Vec::new(),
+        player_stats: Vec::new(),
+        valid: true,
+        void_reason: None,
+        network_sim: None,
+    };
+    assert_eq!(result.participant_results(), vec![Some(Defeat), Some(Victory)]);
+
+    // Equal or unknown ids keep the join order
+    result.player_ids = vec![Some(1), Some(1)];
+    assert_eq!(result.participant_results(), vec![Some(Victory), Some(Defeat)]);
+    result.player_ids = vec![Some(2), None];
+    assert_eq!(result.participant_results(), vec![Some(Victory), Some(Defeat)]);
+
+    // Results reported by SC2 include computer players
+    result.player_races = vec![Race::Zerg];
+    result.player_ids = vec![Some(2)];
+    assert_eq!(result.participant_results(), vec![Some(Defeat)]);
+    result.player_results = Vec::new();
+    assert_eq!(result.participant_results(), vec![None]);
+}
+
+/// Finished game between two participants with the given outcome details, lasting `game_loop` loops
+fn synthetic(details: Vec<PlayerOutcomeDetail>, game_loop: u32) -> GameResult {
+    let stats = PlayerStats {
+        game_loop,
+        ..PlayerStats::default()
+    };
+    GameResult {
+        external_id: None,
+        player_races: vec![Race::Terran, Race::Zerg],
+        requested_races: vec![Race::Terran, Race::Zerg],
+        player_names: vec![Some("alpha".to_owned()), Some("beta".to_owned())],
+        player_metadata: vec![None, None],
+        host_slot: None,
+        slot_assignment: Vec::new(),
+        end_reason: GameEndReason::Normal,
+        player_ids: vec![Some(1), Some(2)],
+        player_results: vec![PlayerResult::Victory, PlayerResult::Defeat],
+        player_details: details,
+        player_categories: vec![ResultCategory::Normal, ResultCategory::Normal],
+        player_stats: vec![stats, stats],
+        valid: true,
+        void_reason: None,
+        network_sim: None,
+    }
+}
+
+#[test]
+fn test_void_reason_defaults() {
+    use PlayerOutcomeDetail::*;
+    let rules = ValidityRules::default();
+    let normal = synthetic(vec![NormalResult, NormalResult], 5000);
+    assert_eq!(void_reason(&rules, &normal), None);
+
+    let crashed = synthetic(vec![NormalResult, SC2Crashed], 5000);
+    assert_eq!(void_reason(&rules, &crashed), Some(VoidReason::SC2Crashed { slot: 1 }));
+
+    let early = synthetic(vec![ClientCrashedBeforeStart, NormalResult], 0);
+    assert_eq!(
+        void_reason(&rules, &early),
+        Some(VoidReason::EarlyDisconnect {
+            slot: 0,
+            game_loop: None
+        })
+    );
+
+    // Disconnects and leaving during the game are losses
+    let disconnect = synthetic(vec![ClientDisconnected { game_loop: 10 }, NormalResult], 10);
+    assert_eq!(void_reason(&rules, &disconnect), None);
+    let left = synthetic(vec![LeftEarly, NormalResult], 10);
+    assert_eq!(void_reason(&rules, &left), None);
+}
+
+#[test]
+fn test_void_reason_rules() {
+    use PlayerOutcomeDetail::*;
+    let rules = ValidityRules {
+        sc2_crash: false,
+        disconnect_before_start: false,
+        disconnect_before_loop: 224,
+        min_duration_secs: 60,
+    };
+    let crashed = synthetic(vec![SC2Crashed, NormalResult], 5000);
+    assert_eq!(void_reason(&rules, &crashed), None);
+    let early = synthetic(vec![ClientCrashedBeforeStart, NormalResult], 5000);
+    assert_eq!(void_reason(&rules, &early), None);
+
+    let disconnect = synthetic(vec![NormalResult, ClientDisconnected { game_loop: 223 }], 5000);
+    assert_eq!(
+        void_reason(&rules, &disconnect),
+        Some(VoidReason::EarlyDisconnect {
+            slot: 1,
+            game_loop: Some(223)
+        })
+    );
+    let disconnect = synthetic(vec![NormalResult, ClientDisconnected { game_loop: 224 }], 5000);
+    assert_eq!(void_reason(&rules, &disconnect), None);
+
+    // 60 game seconds are 1344 game loops
+    let short = synthetic(vec![NormalResult, NormalResult], 1343);
+    assert_eq!(void_reason(&rules, &short), Some(VoidReason::TooShort { game_loop: 1343 }));
+    let long = synthetic(vec![NormalResult, NormalResult], 1344);
+    assert_eq!(void_reason(&rules, &long), None);
+}
+
+#[test]
+fn test_void_games_skipped() {
+    let mut result = synthetic(vec![PlayerOutcomeDetail::NormalResult; 2], 5000);
+    result.valid = false;
+    result.void_reason = Some(VoidReason::TooShort { game_loop: 5000 });
+
+    let mut standings = Standings::default();
+    standings.record(&result);
+    assert!(standings.bots.is_empty());
+
+    let mut stats = GameStats::default();
+    stats.record(&result);
+    assert_eq!(stats.total_games, 1);
+    assert_eq!(stats.void_games, 1);
+    assert!(stats.wins_by_race.is_empty());
+
+    let json = serde_json::to_string(&result).unwrap();
+    assert!(json.contains(r#""valid":false,"void_reason":{"too_short":{"game_loop":5000}}"#));
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use flate2::read::GzDecoder;
+
+use sc2_proto::sc2api::Request as SC2Request;
+
+use sc2_proxy::config::{Config, MatchmakingMode, ReplayNamePattern};
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Save a replay of a running game to `path`, and return the contents of the written `expected` file
+fn saved_replay(config: Config, path: &PathBuf, expected: &PathBuf) -> Vec<u8> {
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, mut bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["replaybot"]);
+
+    let _ = fs::remove_file(expected);
+
+    let save = Request::SaveReplay(id, path.to_str().unwrap().to_owned());
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &save);
+    assert_eq!(resp, Response::Error("No such game".to_owned()));
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bots[0]).has_join_game());
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &save);
+    assert_eq!(resp, Response::SaveReplay);
+
+    // Saved between requests of the bot, while the game keeps running
+    let mut step = SC2Request::new();
+    step.mut_step().set_count(1);
+    for _ in 0..50 {
+        if expected.exists() {
+            break;
+        }
+        common::send(&mut bots[0], &step);
+        assert!(common::recv(&mut bots[0]).has_step());
+        thread::sleep(Duration::from_millis(10));
+    }
+    // The file is complete once the next response is received
+    common::send(&mut bots[0], &step);
+    assert!(common::recv(&mut bots[0]).has_step());
+
+    let data = fs::read(expected).expect("Replay not saved");
+    fs::remove_file(expected).expect("Could not remove replay");
+
+    common::play_until_end(&mut bots[0]);
+    common::wait_games(&mut sv);
+    data
+}
+
+fn replay_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("sc2_proxy_test_{}_{}.SC2Replay", name, process::id()))
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_save_replay() {
+    let path = replay_path("plain");
+    let data = saved_replay(common::config(MatchmakingMode::RemoteController), &path, &path);
+    assert_eq!(data, b"fake replay");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_save_replay_compressed() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.match_defaults.record_results.compress = true;
+    let path = replay_path("gzip");
+    let expected = PathBuf::from(format!("{}.gz", path.to_str().unwrap()));
+    assert_eq!(
+        config.match_defaults.record_results.output_path(path.to_str().unwrap()),
+        expected.to_str().unwrap()
+    );
+
+    let data = saved_replay(config, &path, &expected);
+    assert!(!path.exists());
+    let mut replay = Vec::new();
+    GzDecoder::new(&data[..]).read_to_end(&mut replay).expect("Invalid gzip data");
+    assert_eq!(replay, b"fake replay");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_save_replay_name_pattern() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.match_defaults.record_results.replay_name_pattern =
+        Some(ReplayNamePattern::new("{map}/{p1}_vs_{p2}.SC2Replay").unwrap());
+    let dir = std::env::temp_dir().join(format!("sc2_proxy_test_named_{}", process::id()));
+    let expected = dir.join("Test").join("replaybot_vs_none.SC2Replay");
+
+    // The path is a directory, and the subdirectory of the pattern is created
+    let data = saved_replay(config, &dir, &expected);
+    assert_eq!(data, b"fake replay");
+    fs::remove_dir_all(&dir).expect("Could not remove replay directory");
+}
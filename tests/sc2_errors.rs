@@ -0,0 +1,112 @@
+mod common;
+
+use sc2_proto::sc2api::Request;
+
+use sc2_proxy::config::{Config, GameConfig, MatchmakingMode};
+use sc2_proxy::results::{PlayerOutcomeDetail, PlayerResult, PlayerStats};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Play a game sending `count` requests the fake SC2 does not support
+fn play_with_errors(config: Config, count: usize) -> PlayerStats {
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("errorbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    let mut req = Request::new();
+    req.mut_query();
+    for _ in 0..count {
+        common::send(&mut bot, &req);
+        assert!(!common::recv(&mut bot).get_error().is_empty());
+    }
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    result.player_stats[0]
+}
+
+#[test]
+fn test_log_sc2_errors_default() {
+    assert!(GameConfig::default().log_sc2_errors);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_sc2_errors_counted() {
+    let stats = play_with_errors(common::config(MatchmakingMode::AgainstBuiltinAI), 2);
+    assert_eq!(stats.sc2_errors, 2);
+
+    // Counted even if not logged
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.log_sc2_errors = false;
+    let stats = play_with_errors(config, 3);
+    assert_eq!(stats.sc2_errors, 3);
+
+    let stats = play_with_errors(common::config(MatchmakingMode::AgainstBuiltinAI), 0);
+    assert_eq!(stats.sc2_errors, 0);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_sc2_error_limit() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.game.max_consecutive_sc2_errors = Some(3);
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("floodbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    // The errors are still forwarded, and the game ends after the last one
+    let mut req = Request::new();
+    req.mut_query();
+    for _ in 0..3 {
+        common::send(&mut bot, &req);
+        assert!(!common::recv(&mut bot).get_error().is_empty());
+    }
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.player_results[0], PlayerResult::Defeat);
+    assert_eq!(result.player_details, vec![PlayerOutcomeDetail::SC2ErrorLimit]);
+    assert_eq!(result.player_stats[0].sc2_errors, 3);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_sc2_error_forfeit() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.request_limits.max_sc2_errors = Some(2);
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("loopbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    // Errors separated by successful requests still count
+    let mut query = Request::new();
+    query.mut_query();
+    let mut observation = Request::new();
+    observation.mut_observation();
+    common::send(&mut bot, &query);
+    assert!(!common::recv(&mut bot).get_error().is_empty());
+    common::send(&mut bot, &observation);
+    assert!(common::recv(&mut bot).has_observation());
+    common::send(&mut bot, &query);
+    assert!(!common::recv(&mut bot).get_error().is_empty());
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.player_results[0], PlayerResult::Defeat);
+    assert_eq!(result.player_details, vec![PlayerOutcomeDetail::SC2ErrorLimit]);
+    assert_eq!(result.player_stats[0].sc2_errors, 2);
+}
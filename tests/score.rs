@@ -0,0 +1,59 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use sc2_proto::sc2api::Request as SC2Request;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::supervisor::Supervisor;
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_get_score() {
+    let config = common::config(MatchmakingMode::RemoteController);
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, mut bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, &["scorebot"]);
+
+    // Lobbies have no score
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetScore(id));
+    assert_eq!(resp, Response::Error("No such game".to_owned()));
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::ForceStart(id));
+    assert_eq!(resp, Response::ForceStart);
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bots[0]).has_join_game());
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetScore(id));
+    assert_eq!(resp, Response::GetScore(vec![None]));
+
+    let mut step = SC2Request::new();
+    step.mut_step().set_count(20);
+    common::send(&mut bots[0], &step);
+    assert!(common::recv(&mut bots[0]).has_step());
+    let mut obs = SC2Request::new();
+    obs.mut_observation();
+    common::send(&mut bots[0], &obs);
+    assert!(common::recv(&mut bots[0]).has_observation());
+
+    let start = Instant::now();
+    let score = loop {
+        sv.update_games();
+        match common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetScore(id)) {
+            Response::GetScore(scores) if scores[0].is_some() => break scores[0].unwrap(),
+            Response::GetScore(_) => {},
+            other => panic!("Unexpected response {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(10), "Score not reported");
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(score.game_loop, 20);
+    assert_eq!(score.score, 20);
+    assert_eq!(score.collected_minerals, 70.0);
+
+    common::play_until_end(&mut bots[0]);
+    common::wait_games(&mut sv);
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetScore(id));
+    assert_eq!(resp, Response::Error("No such game".to_owned()));
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::selftest::{self, SelfTestError};
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_selftest() {
+    let config = common::config(MatchmakingMode::Pairs);
+    let report = selftest::run(&config).expect("Self-test failed");
+    assert!(report.is_finished());
+    assert_eq!(report.results.len(), 2);
+    assert!(report.game_loops >= 100);
+    assert!(report.map_path.ends_with("Test.SC2Map"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_selftest_any_map() {
+    let mut config = common::config(MatchmakingMode::Pairs);
+    config.match_defaults.game.map_name = None;
+    let report = selftest::run(&config).expect("Self-test failed");
+    assert!(report.map_path.ends_with("Test.SC2Map"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_selftest_game_loop_limit() {
+    let mut config = common::config(MatchmakingMode::Pairs);
+    config.match_defaults.time_limits.game_loops = Some(32);
+    let report = selftest::run(&config).expect("Self-test failed");
+    assert!(!report.is_finished());
+    assert_eq!(report.game_loops, 32);
+}
+
+#[test]
+fn test_selftest_missing_map() {
+    let mut config = common::config(MatchmakingMode::Pairs);
+    config.match_defaults.game.map_name = Some("Missing".to_owned());
+    assert_eq!(
+        selftest::run(&config),
+        Err(SelfTestError::Map("Map \"Missing\" not found".to_owned()))
+    );
+}
@@ -0,0 +1,43 @@
+use std::net::TcpListener;
+
+use sc2_proxy::config::Config;
+use sc2_proxy::{run_server_config, Error};
+
+use portpicker::pick_unused_port;
+
+fn config_with_ports(proxy_port: u16, remote_port: u16) -> Config {
+    let mut config = Config::new();
+    config.proxy.port = proxy_port;
+    config.remote_controller.port = remote_port;
+    config
+}
+
+#[test]
+fn test_proxy_port_taken() {
+    let occupied = TcpListener::bind("127.0.0.1:0").expect("Could not bind");
+    let port = occupied.local_addr().unwrap().port();
+    let remote_port = pick_unused_port().expect("Could not find a free port");
+
+    match run_server_config(config_with_ports(port, remote_port)) {
+        Err(Error::Bind { listener, .. }) => assert_eq!(listener, "proxy"),
+        other => panic!("Expected a bind error, got {:?}", other),
+    }
+
+    // The remote controller must not have been left running
+    TcpListener::bind(("127.0.0.1", remote_port)).expect("Remote controller port left bound");
+}
+
+#[test]
+fn test_remote_port_taken() {
+    let occupied = TcpListener::bind("127.0.0.1:0").expect("Could not bind");
+    let port = occupied.local_addr().unwrap().port();
+    let proxy_port = pick_unused_port().expect("Could not find a free port");
+
+    match run_server_config(config_with_ports(proxy_port, port)) {
+        Err(Error::Bind { listener, .. }) => assert_eq!(listener, "remote controller"),
+        other => panic!("Expected a bind error, got {:?}", other),
+    }
+
+    // The proxy listener must have been closed
+    TcpListener::bind(("127.0.0.1", proxy_port)).expect("Proxy port left bound");
+}
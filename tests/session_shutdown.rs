@@ -0,0 +1,187 @@
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use protobuf::Message;
+use sc2_proto::sc2api::Request;
+use tempfile::TempDir;
+use websocket::OwnedMessage;
+
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::results::{GameEndReason, PlayerOutcomeDetail, PlayerResult, ResultCategory};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Config logging the requests received by the fake SC2 processes to a file
+fn logged_config(dir: &TempDir, marker: &str) -> Config {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    let log = dir.path().join("requests.log");
+    config
+        .process
+        .env
+        .insert("FAKE_SC2_REQUEST_LOG".to_owned(), log.to_str().unwrap().to_owned());
+    common::mark(&mut config, marker);
+    config
+}
+
+/// Requests received by the fake SC2 processes
+fn request_log(dir: &TempDir) -> Vec<String> {
+    let text = fs::read_to_string(dir.path().join("requests.log")).unwrap_or_default();
+    text.lines().map(str::to_owned).collect()
+}
+
+/// Join a game against builtin AI
+fn join(sv: &mut Supervisor, name: &str) -> common::Client {
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request(name));
+    sv.update_playlist();
+    common::wait_lobbies(sv);
+    assert!(common::recv(&mut bot).has_join_game());
+    bot
+}
+
+/// Wait until the SC2 process of the bot has been shut down
+fn wait_shutdown(marker: &str) {
+    for pid in common::marked_pids(marker, 1) {
+        common::wait_exit(pid);
+    }
+}
+
+/// Checks that the last request SC2 received before it was shut down was leave_game
+fn assert_left_last(dir: &TempDir) {
+    let log = request_log(dir);
+    assert_eq!(log.last().map(String::as_str), Some("leave_game"), "Requests: {:?}", log);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_leave_on_time_limit() {
+    let dir = TempDir::new().unwrap();
+    let mut config = logged_config(&dir, "timelimit");
+    config.match_defaults.time_limits.game_loops = Some(20);
+    let mut sv = Supervisor::new(config);
+    let mut bot = join(&mut sv, "timelimitbot");
+
+    let mut step = Request::new();
+    step.mut_step().set_count(10);
+    let mut obs = Request::new();
+    obs.mut_observation();
+    let last_loop = loop {
+        common::send(&mut bot, &step);
+        assert!(common::recv(&mut bot).has_step());
+        common::send(&mut bot, &obs);
+        let game_loop = common::recv(&mut bot).get_observation().get_observation().get_game_loop();
+        if game_loop >= 20 {
+            break game_loop;
+        }
+    };
+    assert_eq!(last_loop, 20);
+
+    // The connection is closed after the time limit
+    if let Ok(msg) = bot.recv_message() {
+        assert!(!msg.is_data(), "Unexpected message {:?}", msg);
+    }
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.end_reason, GameEndReason::TimeLimit);
+    assert_eq!(result.player_results, vec![PlayerResult::Tie]);
+    assert_eq!(result.player_details, vec![PlayerOutcomeDetail::NormalResult]);
+    assert_eq!(result.player_categories, vec![ResultCategory::Timeout]);
+
+    wait_shutdown("timelimit");
+    assert_left_last(&dir);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_time_limit_ends_other_players() {
+    let dir = TempDir::new().unwrap();
+    let mut config = logged_config(&dir, "timelimitpair");
+    config.matchmaking.mode = MatchmakingMode::Pairs;
+    config.match_defaults.time_limits.game_loops = Some(20);
+    let mut sv = Supervisor::new(config);
+    let mut bots = Vec::new();
+    for name in &["fastbot", "slowbot"] {
+        let (proxy_side, mut bot) = common::connect_bot();
+        sv.add_client(proxy_side);
+        common::send(&mut bot, &common::join_request(name));
+        sv.update_playlist();
+        bots.push(bot);
+    }
+    common::wait_lobbies(&mut sv);
+    for bot in bots.iter_mut() {
+        assert!(common::recv(bot).has_join_game());
+    }
+
+    let mut step = Request::new();
+    step.mut_step().set_count(20);
+    common::send(&mut bots[0], &step);
+    assert!(common::recv(&mut bots[0]).has_step());
+    let mut obs = Request::new();
+    obs.mut_observation();
+    common::send(&mut bots[0], &obs);
+    assert!(common::recv(&mut bots[0]).has_observation());
+
+    // The other player is removed from the game on one of its next requests
+    let mut ping = Request::new();
+    ping.mut_ping();
+    let ping = OwnedMessage::Binary(ping.write_to_bytes().unwrap());
+    let start = Instant::now();
+    while bots[1].send_message(&ping).is_ok() && bots[1].recv_message().is_ok_and(|msg| msg.is_data()) {
+        assert!(start.elapsed() < Duration::from_secs(10), "Player not removed");
+        thread::sleep(Duration::from_millis(10));
+    }
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.end_reason, GameEndReason::TimeLimit);
+    assert_eq!(result.player_results, vec![PlayerResult::Tie, PlayerResult::Tie]);
+    assert_eq!(result.player_categories, vec![ResultCategory::Timeout; 2]);
+    for pid in common::marked_pids("timelimitpair", 2) {
+        common::wait_exit(pid);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_leave_on_quit() {
+    let dir = TempDir::new().unwrap();
+    let mut sv = Supervisor::new(logged_config(&dir, "quitleave"));
+    let mut bot = join(&mut sv, "quitbot");
+    assert_eq!(sv.game_count(), 1);
+    sv.close();
+
+    // The quit is handled after the next request
+    let mut step = Request::new();
+    step.mut_step().set_count(1);
+    common::send(&mut bot, &step);
+    assert!(common::recv(&mut bot).has_step());
+
+    wait_shutdown("quitleave");
+    assert_left_last(&dir);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_leave_on_client_disconnect() {
+    let dir = TempDir::new().unwrap();
+    let mut sv = Supervisor::new(logged_config(&dir, "dropleave"));
+    let mut bot = join(&mut sv, "dropbot");
+    let mut obs = Request::new();
+    obs.mut_observation();
+    common::send(&mut bot, &obs);
+    let game_loop = common::recv(&mut bot).get_observation().get_observation().get_game_loop();
+    bot.send_message(&OwnedMessage::Close(None)).unwrap();
+    drop(bot);
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.player_results, vec![PlayerResult::Defeat]);
+    assert_eq!(result.player_details, vec![PlayerOutcomeDetail::ClientDisconnected { game_loop }]);
+
+    wait_shutdown("dropleave");
+    assert_left_last(&dir);
+}
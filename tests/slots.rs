@@ -0,0 +1,44 @@
+mod common;
+
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::results::{GameResult, SlotAssignment};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Play a game against the builtin AI, returning its result
+fn play(config: Config) -> GameResult {
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("slotbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    result.clone()
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_randomize_slots() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    assert!(config.matchmaking.randomize_slots);
+    let computer = SlotAssignment::Computer {
+        race: config.matchmaking.cpu_race,
+        difficulty: config.matchmaking.cpu_difficulty,
+    };
+    let participant = SlotAssignment::Participant { slot: 0 };
+
+    // Seeds picked so that both orders occur
+    config.match_defaults.game.random_seed = Some(0);
+    assert_eq!(play(config.clone()).slot_assignment, vec![participant, computer]);
+    config.match_defaults.game.random_seed = Some(1);
+    let result = play(config.clone());
+    assert_eq!(result.slot_assignment, vec![computer, participant]);
+    assert_eq!(result.player_names, vec![Some("slotbot".to_owned())]);
+
+    config.matchmaking.randomize_slots = false;
+    assert_eq!(play(config).slot_assignment, vec![participant, computer]);
+}
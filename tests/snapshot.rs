@@ -0,0 +1,110 @@
+mod common;
+
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::remote_control::message::{PlayerStatus, Request, Response};
+use sc2_proxy::supervisor::Supervisor;
+
+#[test]
+fn test_snapshot_empty() {
+    let sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let snapshot = sv.snapshot();
+    assert!(snapshot.playlist.is_empty());
+    assert!(snapshot.lobbies.is_empty());
+    assert!(snapshot.starting.is_empty());
+    assert!(snapshot.games.is_empty());
+    assert!(snapshot.recent_results.is_empty());
+    assert_eq!(snapshot.stats.total_games, 0);
+    assert!(!snapshot.draining);
+
+    let text = serde_json::to_string(&snapshot).expect("Serialization failed");
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&text).unwrap()["draining"], false);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_snapshot_playlist() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let (proxy_side, _idle_bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    assert_eq!(sv.snapshot().playlist.len(), 2);
+
+    common::send(&mut bot, &common::join_request("waitingbot"));
+    let start = Instant::now();
+    let entry = loop {
+        assert!(start.elapsed() < Duration::from_secs(10), "Join request not received");
+        sv.update_playlist();
+        let snapshot = sv.snapshot();
+        if snapshot.playlist[1].ready {
+            break snapshot.playlist[1].clone();
+        }
+        sleep(Duration::from_millis(10));
+    };
+    assert_eq!(entry.name.as_deref(), Some("waitingbot"));
+    assert!(!sv.snapshot().playlist[0].ready);
+    assert_eq!(sv.snapshot().playlist[0].name, None);
+
+    // The remote controller sees the same data
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetPlaylist);
+    let expected: Vec<_> = sv.snapshot().playlist.into_iter().map(|e| (e.id, e.ready)).collect();
+    assert_eq!(resp, Response::GetPlaylist(expected));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_snapshot_lobby_and_game() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::Pairs));
+    let (mut remote, mut stream) = common::connect_remote();
+
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("firstbot"));
+    sv.update_playlist();
+
+    let snapshot = sv.snapshot();
+    assert!(snapshot.playlist.is_empty());
+    assert_eq!(snapshot.lobbies.len(), 1);
+    assert_eq!(snapshot.lobbies[0].players.len(), 1);
+    assert_eq!(snapshot.lobbies[0].players[0].name.as_deref(), Some("firstbot"));
+    assert_eq!(snapshot.lobbies[0].players[0].status, PlayerStatus::Launching);
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetGames);
+    assert_eq!(resp, Response::GetGames(sv.snapshot().game_list()));
+
+    let (proxy_side, mut other) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut other, &common::join_request("secondbot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+    assert!(common::recv(&mut other).has_join_game());
+
+    let snapshot = sv.snapshot();
+    assert!(snapshot.lobbies.is_empty());
+    assert_eq!(snapshot.games.len(), 1);
+    let names: Vec<_> = snapshot.games[0].player_names.iter().map(|n| n.as_deref()).collect();
+    assert_eq!(names, vec![Some("firstbot"), Some("secondbot")]);
+    assert_eq!(snapshot.games[0].player_statuses.len(), 2);
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetGames);
+    assert_eq!(resp, Response::GetGames(sv.snapshot().game_list()));
+
+    common::play_until_end(&mut bot);
+    common::play_until_end(&mut other);
+    common::wait_games(&mut sv);
+
+    let snapshot = sv.snapshot();
+    assert!(snapshot.games.is_empty());
+    assert_eq!(snapshot.recent_results.len(), 1);
+    assert_eq!(snapshot.stats.total_games, 1);
+    assert_eq!(snapshot.playlist.len(), 2);
+
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::GetStats);
+    assert_eq!(resp, Response::GetStats(snapshot.stats));
+}
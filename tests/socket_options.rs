@@ -0,0 +1,55 @@
+mod common;
+
+use std::net::{TcpListener, TcpStream};
+
+use sc2_proxy::config::{MatchmakingMode, SocketOptions};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Connected pair of local TCP streams
+fn tcp_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    (server, client)
+}
+
+#[test]
+fn test_socket_options_default() {
+    let options = SocketOptions::default();
+    assert!(options.nodelay);
+    assert_eq!(options.send_buffer_bytes, None);
+    assert_eq!(options.recv_buffer_bytes, None);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_apply_socket_options() {
+    let (stream, _peer) = tcp_pair();
+    let options = SocketOptions {
+        nodelay: true,
+        send_buffer_bytes: Some(64 * 1024),
+        recv_buffer_bytes: Some(32 * 1024),
+    };
+    options.apply(&stream).unwrap();
+    assert!(stream.nodelay().unwrap());
+    let (send, recv) = sc2_proxy::config::buffer_sizes(&stream).unwrap();
+    assert!(send >= 64 * 1024, "Send buffer of {} bytes", send);
+    assert!(recv >= 32 * 1024, "Receive buffer of {} bytes", recv);
+
+    let options = SocketOptions {
+        nodelay: false,
+        ..SocketOptions::default()
+    };
+    options.apply(&stream).unwrap();
+    assert!(!stream.nodelay().unwrap());
+}
+
+#[test]
+fn test_client_nodelay() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let (proxy_side, _bot) = common::connect_bot();
+    let stream = proxy_side.stream_ref().try_clone().unwrap();
+    assert!(!stream.nodelay().unwrap());
+    sv.add_client(proxy_side);
+    assert!(stream.nodelay().unwrap());
+}
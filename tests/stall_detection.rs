@@ -0,0 +1,166 @@
+mod common;
+
+use protobuf::{parse_from_bytes, Message};
+use sc2_proto::sc2api::{Request, Response};
+use websocket::OwnedMessage;
+
+use sc2_proxy::config::{MatchmakingMode, StalemateOutcome, StallDetection};
+use sc2_proxy::results::{GameEndReason, PlayerOutcomeDetail, PlayerResult, StallDetector};
+use sc2_proxy::sc2::ScoreSnapshot;
+use sc2_proxy::supervisor::Supervisor;
+
+fn rules(stall_window_loops: u32, outcome: StalemateOutcome) -> StallDetection {
+    StallDetection {
+        stall_window_loops,
+        army_value_threshold: 100.0,
+        structures_value_threshold: 50.0,
+        outcome,
+    }
+}
+
+fn score(game_loop: u32, army_value: f32, structures_value: f32) -> ScoreSnapshot {
+    ScoreSnapshot {
+        game_loop,
+        score: game_loop as i32,
+        army_value,
+        structures_value,
+        ..Default::default()
+    }
+}
+
+/// Feed the same series to every participant, returns the first stalled game loop
+fn first_stall(detector: &mut StallDetector, players: usize, series: &[ScoreSnapshot]) -> Option<u32> {
+    for s in series {
+        let mut stalled = false;
+        for player in 0..players {
+            stalled = detector.record(player, s);
+        }
+        if stalled {
+            return Some(s.game_loop);
+        }
+    }
+    None
+}
+
+#[test]
+fn test_no_stall_while_progressing() {
+    let mut detector = StallDetector::new(rules(100, StalemateOutcome::Tie), 2);
+    let series: Vec<_> = (0..50).map(|i| score(i * 10, i as f32 * 150.0, 400.0)).collect();
+    assert_eq!(first_stall(&mut detector, 2, &series), None);
+
+    let mut detector = StallDetector::new(rules(100, StalemateOutcome::Tie), 2);
+    let series: Vec<_> = (0..50).map(|i| score(i * 10, 1000.0, 400.0 + i as f32 * 60.0)).collect();
+    assert_eq!(first_stall(&mut detector, 2, &series), None);
+}
+
+#[test]
+fn test_stall_after_window() {
+    let mut detector = StallDetector::new(rules(100, StalemateOutcome::Tie), 2);
+    let mut series: Vec<_> = (0..10).map(|i| score(i * 10, i as f32 * 200.0, 400.0)).collect();
+    series.extend((10..30).map(|i| score(i * 10, 1800.0, 400.0)));
+    // The last change is on loop 90
+    assert_eq!(first_stall(&mut detector, 2, &series), Some(190));
+    assert!(detector.is_stalled());
+}
+
+#[test]
+fn test_noise_below_threshold_ignored() {
+    let mut detector = StallDetector::new(rules(100, StalemateOutcome::Tie), 1);
+    // Drifting back and forth, never more than the thresholds from the start
+    let series: Vec<_> = (0..20)
+        .map(|i| {
+            let noise = if i % 2 == 0 { 45.0 } else { -45.0 };
+            score(i * 10, 1000.0 + noise, 400.0 + noise / 2.0)
+        })
+        .collect();
+    assert_eq!(first_stall(&mut detector, 1, &series), Some(100));
+
+    // Slow drift adds up to progress
+    let mut detector = StallDetector::new(rules(100, StalemateOutcome::Tie), 1);
+    let series: Vec<_> = (0..20).map(|i| score(i * 10, 1000.0 + i as f32 * 30.0, 400.0)).collect();
+    assert_eq!(first_stall(&mut detector, 1, &series), None);
+}
+
+#[test]
+fn test_stall_requires_every_participant() {
+    let mut detector = StallDetector::new(rules(100, StalemateOutcome::Tie), 2);
+    for i in 0..30 {
+        assert!(!detector.record(0, &score(i * 10, 1000.0, 400.0)));
+        assert!(!detector.record(1, &score(i * 10, i as f32 * 200.0, 400.0)));
+    }
+
+    // Without scores of a participant
+    let mut detector = StallDetector::new(rules(100, StalemateOutcome::Tie), 2);
+    for i in 0..30 {
+        assert!(!detector.record(0, &score(i * 10, 1000.0, 400.0)));
+    }
+}
+
+#[test]
+fn test_stalemate_outcome() {
+    let mut detector = StallDetector::new(rules(10, StalemateOutcome::Tie), 2);
+    detector.record(0, &ScoreSnapshot { score: 500, ..score(0, 0.0, 0.0) });
+    detector.record(1, &ScoreSnapshot { score: 300, ..score(0, 0.0, 0.0) });
+    assert_eq!(detector.results(), vec![PlayerResult::Tie, PlayerResult::Tie]);
+
+    let mut detector = StallDetector::new(rules(10, StalemateOutcome::ScoreTiebreak), 2);
+    detector.record(0, &ScoreSnapshot { score: 300, ..score(0, 0.0, 0.0) });
+    detector.record(1, &ScoreSnapshot { score: 500, ..score(0, 0.0, 0.0) });
+    assert_eq!(detector.results(), vec![PlayerResult::Defeat, PlayerResult::Victory]);
+
+    // Equal scores tie
+    detector.record(0, &ScoreSnapshot { score: 500, ..score(10, 0.0, 0.0) });
+    assert_eq!(detector.results(), vec![PlayerResult::Tie, PlayerResult::Tie]);
+}
+
+#[test]
+fn test_stall_detection_config() {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    assert_eq!(config.match_defaults.time_limits.stall_detection, None);
+    config.match_defaults.time_limits.stall_detection = Some(rules(0, StalemateOutcome::Tie));
+    assert!(config.check().is_err());
+    config.match_defaults.time_limits.stall_detection = Some(StallDetection::default());
+    assert!(config.check().is_ok());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_stalemate_ends_game() {
+    // The army of the fake SC2 never changes
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    config.match_defaults.time_limits.stall_detection = Some(rules(20, StalemateOutcome::Tie));
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("stalebot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+    assert!(common::recv(&mut bot).has_join_game());
+
+    let mut step = Request::new();
+    step.mut_step().set_count(10);
+    let mut obs = Request::new();
+    obs.mut_observation();
+    // The connection is closed after the stalemate
+    'game: for _ in 0..100 {
+        for req in &[&step, &obs] {
+            let bytes = req.write_to_bytes().unwrap();
+            if bot.send_message(&OwnedMessage::Binary(bytes)).is_err() {
+                break 'game;
+            }
+            match bot.recv_message() {
+                Ok(OwnedMessage::Binary(bytes)) => {
+                    let resp = parse_from_bytes::<Response>(&bytes).unwrap();
+                    assert!(resp.get_observation().get_player_result().is_empty(), "Game ended normally");
+                },
+                _ => break 'game,
+            }
+        }
+    }
+    common::wait_games(&mut sv);
+
+    let (_, result) = sv.recent_results().last().expect("No result recorded");
+    assert_eq!(result.end_reason, GameEndReason::Stalemate);
+    assert_eq!(result.player_results, vec![PlayerResult::Tie]);
+    assert_eq!(result.player_details, vec![PlayerOutcomeDetail::NormalResult]);
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use std::process::Command;
+use std::thread::sleep;
+use std::time::Duration;
+
+use sc2_proxy::config::{Config, MatchmakingMode};
+use sc2_proxy::remote_control::message::{Request, Response};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Create a remote controlled lobby with the given bots, and try to start it
+/// Returns the response to the start request
+fn start_lobby(config: Config, names: &[&str], before_start: impl FnOnce()) -> (Supervisor, Response) {
+    let mut sv = Supervisor::new(config);
+    let (mut remote, mut stream) = common::connect_remote();
+    let (id, _bots) = common::remote_lobby(&mut sv, &mut remote, &mut stream, names);
+
+    before_start();
+    let resp = common::remote_request(&mut sv, &mut remote, &mut stream, &Request::StartGame(id));
+    (sv, resp)
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_start_empty_lobby() {
+    let config = common::config(MatchmakingMode::RemoteController);
+    let (sv, resp) = start_lobby(config, &[], || {});
+    assert_eq!(
+        resp,
+        Response::Error("Cannot start game: The lobby has no participants".to_owned())
+    );
+    assert_eq!(sv.lobby_count(), 1);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_start_too_few_participants() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.match_defaults.game.min_participants = 2;
+    let (sv, resp) = start_lobby(config, &["alone"], || {});
+    assert_eq!(
+        resp,
+        Response::Error("Cannot start game: Requires at least 2 participants, has 1".to_owned())
+    );
+    assert_eq!(sv.lobby_count(), 1);
+    assert_eq!(sv.game_count(), 0);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_start_over_map_capacity() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.match_defaults.game.map_capacity = Some(1);
+    let (sv, resp) = start_lobby(config, &["first", "second"], || {});
+    assert_eq!(
+        resp,
+        Response::Error("Cannot start game: Map has 1 slots, but the game has 2 players".to_owned())
+    );
+    assert_eq!(sv.lobby_count(), 1);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_start_interface_not_allowed() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    config.match_defaults.game.allowed_interfaces.raw = false;
+    let (sv, resp) = start_lobby(config, &["rawbot"], || {});
+    assert_eq!(
+        resp,
+        Response::Error(
+            "Cannot start game: Participant 0 requested the raw interface, which is not allowed".to_owned()
+        )
+    );
+    assert_eq!(sv.lobby_count(), 1);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_start_process_not_responding() {
+    let mut config = common::config(MatchmakingMode::RemoteController);
+    common::mark(&mut config, "crash");
+    let (sv, resp) = start_lobby(config, &["crashbot"], || {
+        for pid in common::marked_pids("crash", 1) {
+            Command::new("kill")
+                .arg("-9")
+                .arg(pid.to_string())
+                .status()
+                .expect("Could not kill");
+        }
+        sleep(Duration::from_millis(50));
+    });
+    assert_eq!(
+        resp,
+        Response::Error("Cannot start game: SC2 process of participant 0 is not responding".to_owned())
+    );
+    assert_eq!(sv.lobby_count(), 1);
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use std::fs;
+
+use sc2_proxy::config::{Config, HostSelection, MatchmakingMode};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Config for games against builtin AI, with the fake SC2 environment variables set
+fn config(env: &[(&str, &str)]) -> Config {
+    let mut config = common::config(MatchmakingMode::AgainstBuiltinAI);
+    for (key, value) in env {
+        config.process.env.insert(key.to_string(), value.to_string());
+    }
+    config
+}
+
+/// Connect a bot and try to start a game for it
+/// Returns true if the game was started, playing it to the end
+fn try_start(config: Config) -> bool {
+    let mut sv = Supervisor::new(config);
+    let (proxy_side, mut bot) = common::connect_bot();
+    sv.add_client(proxy_side);
+    common::send(&mut bot, &common::join_request("retrybot"));
+    sv.update_playlist();
+    common::wait_lobbies(&mut sv);
+
+    if sv.game_count() == 0 {
+        // The bot is disconnected instead of receiving a response
+        if let Ok(msg) = bot.recv_message() {
+            assert!(!msg.is_data(), "Unexpected message {:?}", msg);
+        }
+        return false;
+    }
+
+    assert!(common::recv(&mut bot).has_join_game());
+    common::play_until_end(&mut bot);
+    common::wait_games(&mut sv);
+    true
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_create_game_retried() {
+    assert!(try_start(config(&[("FAKE_SC2_CREATE_GAME_FAILURES", "1")])));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_join_game_retried() {
+    assert!(try_start(config(&[("FAKE_SC2_JOIN_GAME_FAILURES", "2")])));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_retries_exhausted() {
+    let mut config = config(&[("FAKE_SC2_CREATE_GAME_FAILURES", "1")]);
+    config.match_defaults.game.start_retries = 0;
+    assert!(!try_start(config));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_non_transient_error_not_retried() {
+    // InvalidMapPath
+    let config = config(&[
+        ("FAKE_SC2_CREATE_GAME_FAILURES", "1"),
+        ("FAKE_SC2_CREATE_GAME_ERROR", "2"),
+    ]);
+    assert!(!try_start(config));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_lobby_start_retried() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let once = dir.path().join("failed");
+    // InvalidMapPath, not retried by the first SC2 process
+    let mut config = config(&[
+        ("FAKE_SC2_CREATE_GAME_FAILURES", "1"),
+        ("FAKE_SC2_CREATE_GAME_ERROR", "2"),
+        ("FAKE_SC2_CREATE_GAME_FAILURES_ONCE", once.to_str().unwrap()),
+    ]);
+    config.match_defaults.game.lobby_start_retries = 1;
+    assert!(try_start(config));
+    assert!(once.exists());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_lobby_start_retries_exhausted() {
+    let mut config = config(&[
+        ("FAKE_SC2_CREATE_GAME_FAILURES", "1"),
+        ("FAKE_SC2_CREATE_GAME_ERROR", "2"),
+    ]);
+    config.match_defaults.game.lobby_start_retries = 2;
+    assert!(!try_start(config));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_lobby_start_retry_closes_host() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let once = dir.path().join("failed");
+    let log = dir.path().join("host_requests.log");
+    let mut config = config(&[]);
+    config.match_defaults.game.host_selection = HostSelection::Dedicated;
+    config.match_defaults.game.lobby_start_retries = 1;
+    // InvalidMapPath, only from the first dedicated host
+    let mut host_process = config.process.clone();
+    for (key, value) in &[
+        ("FAKE_SC2_CREATE_GAME_FAILURES", "1"),
+        ("FAKE_SC2_CREATE_GAME_ERROR", "2"),
+        ("FAKE_SC2_CREATE_GAME_FAILURES_ONCE", once.to_str().unwrap()),
+        ("FAKE_SC2_REQUEST_LOG", log.to_str().unwrap()),
+    ] {
+        host_process.env.insert(key.to_string(), value.to_string());
+    }
+    config.process.spectator_defaults = Some(Box::new(host_process));
+    assert!(try_start(config));
+
+    // The first host was asked to quit before the retry
+    let requests = fs::read_to_string(&log).unwrap();
+    assert_eq!(requests.lines().filter(|&line| line == "quit").count(), 1);
+}
@@ -0,0 +1,64 @@
+use std::fs;
+
+use tempfile::TempDir;
+
+use sc2_proxy::config::{Config, MatchmakingMode, QueueConfig};
+use sc2_proxy::{run_server_config, Error};
+
+#[test]
+fn test_remote_controller_required() {
+    let mut config = Config::new();
+    config.matchmaking.mode = MatchmakingMode::RemoteController;
+    config.remote_controller.enabled = false;
+    match run_server_config(config) {
+        Err(Error::Config(msg)) => assert!(msg.contains("Remote controller disabled"), "{}", msg),
+        other => panic!("Expected a config error, got {:?}", other),
+    }
+
+    // Also when only a queue needs it
+    let mut config = Config::new();
+    config.remote_controller.enabled = false;
+    assert!(config.check_startup().is_ok());
+    let mut queue = QueueConfig::default();
+    queue.matchmaking.mode = MatchmakingMode::RemoteController;
+    config.queues.insert("ladder".to_owned(), queue);
+    assert!(config.check_startup().is_err());
+}
+
+#[test]
+fn test_singleplayer_computers() {
+    let mut config = Config::new();
+    config.matchmaking.mode = MatchmakingMode::Singleplayer;
+    assert!(config.check_startup().is_ok());
+    config.matchmaking.filler_ai.enabled = true;
+    assert!(config.check_startup().is_err());
+    config.matchmaking.allow_singleplayer_computers = true;
+    assert!(config.check_startup().is_ok());
+
+    let mut queue = QueueConfig::default();
+    queue.matchmaking.mode = MatchmakingMode::Singleplayer;
+    queue.matchmaking.cpu_process = Some(Default::default());
+    config.queues.insert("solo".to_owned(), queue);
+    let err = config.check_startup().unwrap_err();
+    assert!(err.starts_with("Queue solo: "), "{}", err);
+}
+
+#[test]
+fn test_record_paths_writable() {
+    let dir = TempDir::new().unwrap();
+    let missing = dir.path().join("missing");
+
+    let mut config = Config::new();
+    config.matchmaking.standings_path = Some(missing.join("standings.json").to_str().unwrap().to_owned());
+    assert!(config.check_startup().is_err());
+    config.matchmaking.standings_path = Some(dir.path().join("standings.json").to_str().unwrap().to_owned());
+    assert!(config.check_startup().is_ok());
+
+    config.match_defaults.record_results.game_info_dir = Some(missing.to_str().unwrap().to_owned());
+    assert!(config.check_startup().is_err());
+    config.match_defaults.record_results.game_info_dir = Some(dir.path().to_str().unwrap().to_owned());
+    assert!(config.check_startup().is_ok());
+
+    // Nothing is left behind
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+}
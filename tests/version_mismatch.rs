@@ -0,0 +1,86 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use protobuf::parse_from_bytes;
+use sc2_proto::sc2api::{Request, Response, ResponseJoinGame_Error};
+use websocket::OwnedMessage;
+
+use sc2_proxy::config::MatchmakingMode;
+use sc2_proxy::proxy::{ClientConnection, ConnectionMeta};
+use sc2_proxy::supervisor::Supervisor;
+
+/// Base build of the fake installation, see `common::fake_install`
+const FAKE_BASE_BUILD: u32 = 99999;
+
+/// Connect a bot to the proxy with the given request URI
+fn connect(sv: &mut Supervisor, uri: &str) -> common::Client {
+    let (proxy_side, bot) = common::connect_bot();
+    let peer_addr = proxy_side.peer_addr().unwrap().to_string();
+    sv.add_connection(ClientConnection {
+        client: proxy_side,
+        meta: ConnectionMeta::from_handshake(peer_addr, uri, None, None),
+    });
+    bot
+}
+
+#[test]
+fn test_expected_base_build_param() {
+    let meta = |uri: &str| ConnectionMeta::from_handshake("127.0.0.1:1".to_owned(), uri, None, None);
+    assert_eq!(meta("/sc2api?base_build=75689").expected_base_build(), Some(75689));
+    assert_eq!(meta("/sc2api?meta=x&base_build=75689").expected_base_build(), Some(75689));
+    assert_eq!(meta("/sc2api?base_build=4.10").expected_base_build(), None);
+    assert_eq!(meta("/sc2api").expected_base_build(), None);
+}
+
+#[test]
+fn test_ping_reports_base_build() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let mut bot = connect(&mut sv, "/sc2api");
+    let mut ping = Request::new();
+    ping.mut_ping();
+    common::send(&mut bot, &ping);
+
+    bot.stream_ref().set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    let start = Instant::now();
+    let resp = loop {
+        sv.update_playlist();
+        if let Ok(OwnedMessage::Binary(bytes)) = bot.recv_message() {
+            break parse_from_bytes::<Response>(&bytes).unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_secs(10), "Ping not answered");
+    };
+    assert_eq!(resp.get_ping().get_base_build(), FAKE_BASE_BUILD);
+}
+
+#[test]
+fn test_join_rejected_on_version_mismatch() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let mut bot = connect(&mut sv, "/sc2api?base_build=75689");
+    common::send(&mut bot, &common::join_request("oldbot"));
+    let start = Instant::now();
+    while !sv.snapshot().playlist.is_empty() {
+        assert!(start.elapsed() < Duration::from_secs(10), "Join request not processed");
+        sv.update_playlist();
+    }
+
+    let resp = common::recv(&mut bot);
+    let join = resp.get_join_game();
+    assert_eq!(join.get_error(), ResponseJoinGame_Error::OtherError);
+    let details = join.get_error_details();
+    assert!(details.contains("75689") && details.contains("99999"), "{}", details);
+}
+
+#[test]
+fn test_join_accepted_on_matching_version() {
+    let mut sv = Supervisor::new(common::config(MatchmakingMode::RemoteController));
+    let uri = format!("/sc2api?base_build={}", FAKE_BASE_BUILD);
+    let mut bot = connect(&mut sv, &uri);
+    common::send(&mut bot, &common::join_request("currentbot"));
+
+    let start = Instant::now();
+    while !sv.snapshot().playlist.iter().any(|e| e.ready) {
+        assert!(start.elapsed() < Duration::from_secs(10), "Join request not processed");
+        sv.update_playlist();
+    }
+}